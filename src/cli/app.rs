@@ -1,11 +1,25 @@
 //! Main CLI application
 
-use crate::config::{parse_config_auto, parse_config_file, validate_config, Config};
-use crate::error::{ConfigError, RtaskError};
-use crate::runner::{Context, Task, Verbosity};
-use clap::{Arg, ArgAction, ArgMatches, Command};
+use crate::cli::{builtin, completion, watch, ErrorFormat};
+use crate::config::{parse_config_auto, parse_config_file, validate_config, Config, RunFrom};
+use crate::error::{ConfigError, ExecutionError, RtaskError, TaskFailure};
+use crate::runner::{
+    append_history, definition_hash, history_path, signals, CaptureLimits, Context, HistoryEntry,
+    Session, Task, TaskHooks, TaskRegistry, Tracer, Verbosity,
+};
+use crate::ui::{message, CatalogMessage, ColorChoice};
+use crate::utils::duration::parse_duration;
+use crate::utils::suggest::closest;
+use crate::utils::time::now_timestamp;
+use clap::builder::PossibleValuesParser;
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use colored::Colorize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// CLI application
 pub struct App {
@@ -15,6 +29,49 @@ pub struct App {
     config: Config,
     /// Config file path
     config_path: PathBuf,
+    /// Flag defaults from the environment
+    env: EnvDefaults,
+    /// Session opened by `--record`/`--replay`, shared by all invocations of a run
+    session: Option<Session>,
+    /// Trace started by `--trace-out`, shared by all invocations of a run
+    trace: Option<Tracer>,
+}
+
+/// Defaults for global flags read from `RTASK_*` environment variables
+///
+/// Flags given on the command line always win over these.
+#[derive(Debug, Clone, Default)]
+pub struct EnvDefaults {
+    /// `RTASK_VERBOSITY`: silent, quiet, normal, verbose, trace (or 0-4)
+    pub verbosity: Option<Verbosity>,
+    /// `RTASK_COLOR`: auto, always, never
+    pub color: Option<ColorChoice>,
+    /// `RTASK_LOG_FILE`: default for `--log-file`
+    pub log_file: Option<PathBuf>,
+}
+
+impl EnvDefaults {
+    /// Read defaults from the process environment
+    pub fn from_env() -> Result<Self, RtaskError> {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    /// Read defaults through `lookup`, ignoring empty values
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, RtaskError> {
+        let get = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        let invalid =
+            |name: &str, error: String| ConfigError::Invalid(format!("{}: {}", name, error));
+
+        Ok(EnvDefaults {
+            verbosity: get("RTASK_VERBOSITY")
+                .map(|v| v.parse().map_err(|e| invalid("RTASK_VERBOSITY", e)))
+                .transpose()?,
+            color: get("RTASK_COLOR")
+                .map(|v| v.parse().map_err(|e| invalid("RTASK_COLOR", e)))
+                .transpose()?,
+            log_file: get("RTASK_LOG_FILE").map(PathBuf::from),
+        })
+    }
 }
 
 impl App {
@@ -29,6 +86,9 @@ impl App {
             command,
             config,
             config_path,
+            env: EnvDefaults::from_env()?,
+            session: None,
+            trace: None,
         })
     }
 
@@ -43,15 +103,89 @@ impl App {
             command,
             config,
             config_path: path,
+            env: EnvDefaults::from_env()?,
+            session: None,
+            trace: None,
         })
     }
 
     /// Run the application with command line arguments
+    ///
+    /// Several tasks can be given in one invocation (`rtask lint test`); they
+    /// run in order, stopping at the first failure unless `--keep-going` is set.
     pub fn run(mut self) -> Result<(), RtaskError> {
-        let matches = self.command.clone().get_matches();
+        let args: Vec<String> = env::args().collect();
+        if args.iter().any(|arg| arg == "--sort") {
+            self.command = sort_tasks(self.command, &self.config);
+        }
+        let invocations = split_invocations(&self.config, &args);
+        if invocations.len() <= 1 {
+            let matches = self.parse_args(&args);
+            return self.run_matches(&args, &matches);
+        }
 
-        // Handle global flags first
-        let verbosity = get_verbosity(&matches);
+        let all_matches: Vec<ArgMatches> = invocations
+            .iter()
+            .map(|argv| self.parse_args(argv))
+            .collect();
+        let keep_going = all_matches.iter().any(|m| m.get_flag("keep-going"));
+
+        let mut failed = Vec::new();
+        for (argv, matches) in invocations.iter().zip(&all_matches) {
+            let name = matches.subcommand_name().unwrap_or_default().to_string();
+            if let Err(err) = self.run_matches(argv, matches) {
+                if !keep_going || signals::check_interrupted().is_err() {
+                    return Err(err);
+                }
+                if get_verbosity(matches, Verbosity::Normal) >= Verbosity::Quiet {
+                    eprintln!(
+                        "{} {}",
+                        "[ERROR]".red(),
+                        message(
+                            "task.failed",
+                            &[("task", &name), ("error", &err.localized())]
+                        )
+                    );
+                }
+                failed.push(name);
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecutionError::TasksFailed {
+                failed,
+                total: all_matches.len(),
+            }
+            .into())
+        }
+    }
+
+    /// Parse an argv with clap, exiting with a usage error on failure
+    ///
+    /// Unknown options get a suggestion drawn from the task's own options and
+    /// the global options, together with the suggested option's usage line.
+    fn parse_args(&self, argv: &[String]) -> ArgMatches {
+        match self.command.clone().try_get_matches_from(argv) {
+            Ok(matches) => matches,
+            Err(err) => {
+                if let Some(message) = unknown_option_message(&self.command, &err, argv) {
+                    eprint!("{}", message);
+                    std::process::exit(err.exit_code());
+                }
+                err.exit()
+            }
+        }
+    }
+
+    /// Run a single invocation (one task or built-in), parsed from `argv`
+    fn run_matches(&mut self, argv: &[String], matches: &ArgMatches) -> Result<(), RtaskError> {
+        // Handle global flags first, falling back to the environment
+        let verbosity = self.verbosity(matches);
+        if let Some(color) = matches.get_one::<String>("color") {
+            color.parse::<ColorChoice>().unwrap_or_default().apply();
+        }
 
         // Check if a task was specified
         let (task_name, task_matches) = match matches.subcommand() {
@@ -64,34 +198,285 @@ impl App {
             }
         };
 
-        // Get the task from config
+        // Built-in subcommands, unless a task shadows the name
+        if !self.config.tasks.contains_key(&task_name) && builtin::is_builtin(&task_name) {
+            return builtin::run_builtin(self, &task_name, argv, task_matches);
+        }
+
+        if self.config.tasks[&task_name].private && !matches.get_flag("include-private") {
+            return Err(ConfigError::PrivateTask(task_name).into());
+        }
+
+        // Re-run the task as its files change, with `--watch` or `watch: true`
+        let always_watch = self.config.tasks[&task_name]
+            .watch
+            .as_ref()
+            .is_some_and(|watch| watch.always);
+        if matches.get_flag("watch") || (always_watch && !matches.get_flag("no-watch")) {
+            return watch::watch_task(self, &task_name, argv, matches);
+        }
+
+        let task = self.prepare_task(&task_name, task_matches)?;
+        if self.session.is_none() {
+            self.session = open_session(matches)?;
+        }
+        let trace_out = matches.get_one::<String>("trace-out").map(PathBuf::from);
+        if trace_out.is_some() && self.trace.is_none() {
+            self.trace = Some(Tracer::new());
+        }
+        let mut ctx = self.build_context(matches, verbosity)?;
+
+        // Execute the task, noting where a failure happened for error reports
+        let started_at = now_timestamp();
+        let start = Instant::now();
+        let result = task.execute(&mut ctx);
+        // Background services started by subtasks end with the run
+        ctx.services.stop_all(&ctx);
+        ctx.print_allowed_failures();
+        self.record_history(&ctx, &task_name, started_at, start.elapsed(), &result);
+        if let (Some(trace), Some(path)) = (&self.trace, &trace_out) {
+            if let Err(e) = trace.write(path) {
+                ctx.print_warning(&message(
+                    "task.trace-not-written",
+                    &[("path", &path.display()), ("error", &e)],
+                ));
+            }
+        }
+
+        result.map_err(|error| self.task_failure(&task_name, &mut ctx, error))
+    }
+
+    /// Verbosity from the global flags, falling back to the environment
+    pub fn verbosity(&self, matches: &ArgMatches) -> Verbosity {
+        get_verbosity(matches, self.env.verbosity.unwrap_or(Verbosity::Normal))
+    }
+
+    /// Build a runtime task from the config with variables from its CLI matches
+    pub fn prepare_task(
+        &self,
+        task_name: &str,
+        task_matches: &ArgMatches,
+    ) -> Result<Task, RtaskError> {
         let task_config = self
             .config
             .tasks
-            .get(&task_name)
-            .ok_or_else(|| ConfigError::TaskNotFound(task_name.clone()))?;
+            .get(task_name)
+            .ok_or_else(|| ConfigError::TaskNotFound(task_name.to_string()))?;
+
+        let mut task = Task::from_config(task_name.to_string(), task_config.clone())?;
+        task.vars = parse_task_vars(task_config, task_matches)?;
+        task.definition = definition_hash(&self.config, task_name);
+        Ok(task)
+    }
+
+    /// Parse task arguments as if `rtask <task_name> <args>` had been invoked
+    pub fn task_matches(&self, task_name: &str, args: &[String]) -> Result<ArgMatches, RtaskError> {
+        let argv = [env!("CARGO_PKG_NAME"), task_name]
+            .into_iter()
+            .map(String::from)
+            .chain(args.iter().cloned());
+        let mut matches = self
+            .command
+            .clone()
+            .try_get_matches_from(argv)
+            .map_err(|e| ConfigError::Invalid(e.to_string().trim_end().to_string()))?;
+
+        match matches.remove_subcommand() {
+            Some((_, task_matches)) => Ok(task_matches),
+            None => Err(ConfigError::TaskNotFound(task_name.to_string()).into()),
+        }
+    }
+
+    /// Wrap a task's execution error with where it happened, for error reports
+    pub fn task_failure(
+        &self,
+        task_name: &str,
+        ctx: &mut Context,
+        error: ExecutionError,
+    ) -> RtaskError {
+        RtaskError::Task(Box::new(TaskFailure {
+            task: task_name.to_string(),
+            command: ctx.failed_command.take(),
+            config_path: Some(self.config_path.clone()),
+            line: self.config.task_lines.get(task_name).copied(),
+            error,
+        }))
+    }
+
+    /// Append a run to the history ledger unless disabled in the config
+    ///
+    /// Failing to write history never fails the task itself.
+    fn record_history(
+        &self,
+        ctx: &Context,
+        task_name: &str,
+        started_at: String,
+        elapsed: Duration,
+        result: &Result<(), ExecutionError>,
+    ) {
+        if self.config.history == Some(false) || ctx.dry_run {
+            return;
+        }
 
-        // Build task with variables from CLI
-        let mut task = Task::from_config(task_name.clone(), task_config.clone())?;
+        let entry = HistoryEntry {
+            task: task_name.to_string(),
+            started_at,
+            duration_ms: elapsed.as_millis() as u64,
+            success: result.is_ok(),
+            exit_code: match result {
+                Ok(()) => Some(0),
+                Err(ExecutionError::CommandFailed(code)) => *code,
+                Err(_) => None,
+            },
+        };
+        let path = history_path(&self.config_dir());
+        if let Err(e) = append_history(&path, &entry) {
+            ctx.print_debug(&format!(
+                "Could not record history in {}: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+
+    /// Directory containing the config file
+    pub fn config_dir(&self) -> PathBuf {
+        match self.config_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    /// Completion candidates for the words typed after the program name
+    pub fn complete(&self, words: &[String]) -> Vec<String> {
+        completion::complete(&self.command, words)
+    }
+
+    /// Get the parsed configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        // Parse options and args from CLI
-        let vars = parse_task_vars(&task_config, task_matches)?;
-        task.vars = vars;
+    /// Get the path of the loaded config file
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
 
+    /// Build the execution context from global flags and config settings
+    pub fn build_context(
+        &self,
+        matches: &ArgMatches,
+        verbosity: Verbosity,
+    ) -> Result<Context, RtaskError> {
         // Create execution context
         let mut ctx = Context::new()
             .with_config_path(self.config_path.clone())
-            .with_verbosity(verbosity);
+            .with_verbosity(verbosity)
+            .with_registry(Arc::new(TaskRegistry::from_config(&self.config)?));
+
+        // Run from the config's directory, so tasks behave the same from any
+        // subdirectory
+        if self.config.run_from.unwrap_or_default() == RunFrom::ConfigDir {
+            if let Some(dir) = self
+                .config_path
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+            {
+                let dir = ctx.working_dir.join(dir);
+                ctx = ctx.with_working_dir(dir);
+            }
+        }
 
-        // Set interpreter if specified in config
+        // Set interpreter if specified in config, picking the host's entry
         if let Some(interpreter) = &self.config.interpreter {
-            ctx = ctx.with_interpreter(interpreter.clone());
+            let interpreter = ctx.interpreter_for(interpreter)?;
+            ctx = ctx.with_interpreter(interpreter);
+        }
+
+        if let Some(capture) = &self.config.capture {
+            let mut limits = CaptureLimits::default();
+            if let Some(max_size) = capture.max_size {
+                limits.max_bytes = max_size;
+            }
+            if let Some(trim) = capture.trim {
+                limits.trim = trim;
+            }
+            ctx = ctx.with_capture_limits(limits);
         }
 
-        // Execute the task
-        task.execute(&mut ctx)?;
+        if let Some(opts) = &self.config.shell_opts {
+            ctx = ctx.with_shell_opts(opts.clone());
+        }
+
+        if let Some(inherit_env) = self.config.inherit_env {
+            ctx = ctx.with_inherit_env(inherit_env);
+        }
+
+        if let Some(strip) = self.config.strip_ansi {
+            ctx = ctx.with_strip_ansi(strip);
+        }
+
+        let hooks = TaskHooks::from_config(&self.config);
+        if !hooks.is_empty() {
+            ctx = ctx.with_hooks(hooks);
+        }
+
+        if let Some(heartbeat) = &self.config.heartbeat {
+            let interval = parse_duration(heartbeat).map_err(|e| {
+                ConfigError::Invalid(format!("Invalid heartbeat '{}': {}", heartbeat, e))
+            })?;
+            ctx = ctx.with_heartbeat(interval);
+        }
+
+        if let Some(log_file) = matches
+            .get_one::<String>("log-file")
+            .map(PathBuf::from)
+            .or_else(|| self.env.log_file.clone())
+        {
+            ctx = ctx.with_log_file(log_file);
+        }
+
+        if let Some(session) = &self.session {
+            ctx = ctx.with_session(session.clone());
+        }
+
+        if let Some(trace) = &self.trace {
+            ctx = ctx.with_trace(trace.clone());
+        }
+
+        if matches.get_flag("skip-finally") {
+            ctx = ctx.with_skip_finally(true);
+        }
+
+        if matches.get_flag("force") {
+            ctx = ctx.with_force(true);
+        }
 
-        Ok(())
+        if matches.get_flag("resume") {
+            ctx = ctx.with_resume(true);
+        }
+
+        if matches.get_flag("keep-going") {
+            ctx = ctx.with_keep_going(true);
+        }
+
+        if let Some(&jobs) = matches.get_one::<u32>("jobs") {
+            ctx = ctx.with_jobs(jobs as usize);
+        }
+
+        if matches.get_flag("dry-run") {
+            ctx = ctx.with_dry_run();
+        }
+
+        // Command echo format: CLI flag overrides the config key
+        if let Some(format) = matches
+            .get_one::<String>("print-format")
+            .or(self.config.print_format.as_ref())
+        {
+            ctx = ctx.with_print_format(format.clone());
+        }
+
+        Ok(ctx)
     }
 }
 
@@ -122,7 +507,7 @@ fn build_command(config: &Config) -> Command {
             Arg::new("silent")
                 .short('s')
                 .long("silent")
-                .help("Print no output")
+                .help("Print no output, including command output")
                 .action(ArgAction::SetTrue)
                 .global(true),
         )
@@ -130,20 +515,144 @@ fn build_command(config: &Config) -> Command {
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
-                .help("Print verbose output")
+                .help("Print verbose output (repeat for more detail, e.g. -vv)")
+                .action(ArgAction::Count)
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("Write suppressed command output to FILE [env: RTASK_LOG_FILE]")
+                .global(true),
+        )
+        .arg(
+            Arg::new("keep-going")
+                .short('k')
+                .long("keep-going")
+                .help("Keep running independent tasks and branches after one fails")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Run up to N prerequisite tasks at the same time")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .global(true),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .help("Run the task again whenever its watched files change")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("no-watch")
+                .long("no-watch")
+                .help("Run a task with watch: true once, without watching")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("watch")
+                .global(true),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Print the commands, conditions and subtasks a task would run, without running them")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["record", "replay"])
+                .global(true),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Record every command run, with its exit status, to a session FILE")
+                .conflicts_with("replay")
+                .global(true),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Answer commands from a recorded session FILE instead of running them")
+                .global(true),
+        )
+        .arg(
+            Arg::new("trace-out")
+                .long("trace-out")
+                .value_name("FILE")
+                .help("Write a Chrome trace of the tasks, run items and commands run to FILE")
+                .global(true),
+        )
+        .arg(
+            Arg::new("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .help("How to print errors")
+                .value_parser(ErrorFormat::NAMES.to_vec())
+                .global(true),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("When to use colors [env: RTASK_COLOR]")
+                .value_parser(ColorChoice::NAMES.to_vec())
+                .global(true),
+        )
+        .arg(
+            Arg::new("include-private")
+                .long("include-private")
+                .help("Allow running private tasks directly")
                 .action(ArgAction::SetTrue)
                 .global(true),
+        )
+        .arg(
+            Arg::new("skip-finally")
+                .long("skip-finally")
+                .help("Skip finally blocks, leaving a failed task's state for inspection")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Run tasks even when their targets are up to date")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Skip the run items a task completed before it last failed")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .help("List tasks alphabetically instead of in definition order")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("print-format")
+                .long("print-format")
+                .value_name("FORMAT")
+                .help("Command echo template ({command}, {dir}, {interpreter}, {task})")
+                .global(true),
         );
 
     // Add subcommands for each task
     for (task_name, task) in &config.tasks {
-        // Skip private tasks
-        if task.private {
-            continue;
-        }
-
+        // Private tasks are parsed (to report them clearly) but hidden
         let mut task_cmd = Command::new(task_name)
-            .about(task.usage.clone().unwrap_or_default());
+            .about(task.usage.clone().unwrap_or_default())
+            .hide(task.private);
 
         // Add long description if available
         if let Some(desc) = &task.description {
@@ -160,7 +669,16 @@ fn build_command(config: &Config) -> Command {
                 .value_name(arg_name.to_uppercase())
                 .help(arg.usage.clone().unwrap_or_default());
 
-            if arg.required {
+            if let Some(hint) = value_hint(&arg.arg_type) {
+                arg_def = arg_def.value_hint(hint);
+            }
+
+            if arg.is_variadic() {
+                arg_def = arg_def
+                    .num_args(arg.min_values()..=arg.max_values())
+                    .action(ArgAction::Append)
+                    .required(arg.min_values() > 0);
+            } else if arg.required {
                 arg_def = arg_def.required(true);
             }
 
@@ -195,8 +713,20 @@ fn build_command(config: &Config) -> Command {
                 "bool" | "boolean" => {
                     opt_def = opt_def.action(ArgAction::SetTrue);
                 }
+                "count" => {
+                    opt_def = opt_def.action(ArgAction::Count);
+                }
                 _ => {
-                    opt_def = opt_def.value_name(&opt_name.to_uppercase());
+                    opt_def = opt_def.value_name(opt_name.to_uppercase());
+
+                    if let Some(hint) = value_hint(&opt.option_type) {
+                        opt_def = opt_def.value_hint(hint);
+                    }
+
+                    if !opt.values.is_empty() {
+                        opt_def =
+                            opt_def.value_parser(PossibleValuesParser::new(opt.values.clone()));
+                    }
 
                     if let Some(default) = &opt.default {
                         opt_def = opt_def.default_value(default);
@@ -214,19 +744,218 @@ fn build_command(config: &Config) -> Command {
         cmd = cmd.subcommand(task_cmd);
     }
 
+    // Add built-in subcommands that are not shadowed by a task
+    for builtin_cmd in builtin::builtin_commands() {
+        if !config.tasks.contains_key(builtin_cmd.get_name()) {
+            cmd = cmd.subcommand(builtin_cmd);
+        }
+    }
+
     cmd
 }
 
-/// Get verbosity level from matches
-fn get_verbosity(matches: &ArgMatches) -> Verbosity {
+/// Shell completion hint for path-like option and arg types
+fn value_hint(type_name: &str) -> Option<ValueHint> {
+    match type_name {
+        "path" => Some(ValueHint::AnyPath),
+        "file" => Some(ValueHint::FilePath),
+        "dir" | "directory" => Some(ValueHint::DirPath),
+        _ => None,
+    }
+}
+
+/// Order task subcommands alphabetically in help, ahead of the built-ins
+fn sort_tasks(mut command: Command, config: &Config) -> Command {
+    let (mut tasks, builtins): (Vec<String>, Vec<String>) = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .partition(|name| config.tasks.contains_key(name));
+    tasks.sort();
+
+    for (order, name) in tasks.iter().chain(&builtins).enumerate() {
+        command = command.mut_subcommand(name, |sub| sub.display_order(order));
+    }
+    command
+}
+
+/// Rewrite clap's unknown-argument error with a suggestion from the task's
+/// options and the global options
+fn unknown_option_message(command: &Command, err: &clap::Error, argv: &[String]) -> Option<String> {
+    if err.kind() != ErrorKind::UnknownArgument {
+        return None;
+    }
+    let invalid = match err.get(ContextKind::InvalidArg)? {
+        ContextValue::String(arg) => arg.clone(),
+        _ => return None,
+    };
+    let name = invalid.strip_prefix("--")?.split('=').next()?;
+
+    let task = argv
+        .iter()
+        .skip(1)
+        .find_map(|word| command.find_subcommand(word.as_str()));
+    let options: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .chain(task.into_iter().flat_map(|task| task.get_arguments()))
+        .filter(|arg| arg.get_long().is_some() && !arg.is_hide_set())
+        .collect();
+
+    let suggestion = closest(name, options.iter().filter_map(|arg| arg.get_long()))?;
+    let option = options
+        .iter()
+        .find(|arg| arg.get_long() == Some(suggestion))?;
+    let usage = option
+        .get_help()
+        .map(|help| format!(" ({})", help))
+        .unwrap_or_default();
+
+    // Keep clap's error and usage lines, replacing its tips with ours
+    let rendered = err.render().to_string();
+    let mut lines = rendered.lines();
+    let headline = lines.next()?;
+    let usage_block: Vec<&str> = lines
+        .skip_while(|line| !line.starts_with("Usage:"))
+        .collect();
+
+    Some(format!(
+        "{}\n\n  tip: did you mean '--{}'?{}\n\n{}\n",
+        headline,
+        suggestion,
+        usage,
+        usage_block.join("\n")
+    ))
+}
+
+/// Open the session named by `--record` or `--replay`, if either is given
+fn open_session(matches: &ArgMatches) -> Result<Option<Session>, RtaskError> {
+    let open = |flag: &str, open: fn(&Path) -> std::io::Result<Session>| {
+        matches
+            .get_one::<String>(flag)
+            .map(|path| {
+                open(Path::new(path)).map_err(|e| {
+                    ConfigError::Invalid(format!("Cannot open session '{}': {}", path, e))
+                })
+            })
+            .transpose()
+    };
+    Ok(open("record", Session::record)?.or(open("replay", Session::replay)?))
+}
+
+/// Global options that take a value
+const GLOBAL_VALUE_OPTIONS: &[&str] = &[
+    "-f",
+    "--file",
+    "--log-file",
+    "-j",
+    "--jobs",
+    "--print-format",
+    "--color",
+    "--error-format",
+    "--record",
+    "--replay",
+    "--trace-out",
+];
+
+/// Split an invocation into the global options given before its subcommand
+/// and the words after the subcommand, as clap parses them
+pub(crate) fn split_at_subcommand(argv: &[String]) -> (&[String], &[String]) {
+    let mut expects_value = false;
+    for (index, word) in argv.iter().enumerate().skip(1) {
+        if expects_value {
+            expects_value = false;
+        } else if word.starts_with('-') {
+            expects_value = !word.contains('=') && GLOBAL_VALUE_OPTIONS.contains(&word.as_str());
+        } else {
+            return (&argv[1..index], &argv[index + 1..]);
+        }
+    }
+    (argv.get(1..).unwrap_or_default(), &[])
+}
+
+/// Split a multi-task command line into one argv per task
+///
+/// Every argv keeps the program name and the global options given before the
+/// first task. A word only starts a new task when it names a public task and
+/// is not the value of an option or a positional argument of the current task.
+fn split_invocations(config: &Config, args: &[String]) -> Vec<Vec<String>> {
+    let is_task = |word: &str| config.tasks.contains_key(word);
+
+    let mut prefix: Vec<String> = args.iter().take(1).cloned().collect();
+    let mut invocations: Vec<Vec<String>> = Vec::new();
+    let mut current: Option<&crate::config::Task> = None;
+    let mut positionals_left = 0;
+    let mut expects_value = false;
+    let mut rest_literal = false;
+
+    for word in args.iter().skip(1) {
+        let target = match invocations.last_mut() {
+            Some(invocation) => invocation,
+            None => &mut prefix,
+        };
+
+        if rest_literal || expects_value {
+            expects_value = false;
+            target.push(word.clone());
+            continue;
+        }
+
+        if word == "--" {
+            rest_literal = true;
+            target.push(word.clone());
+        } else if word.starts_with('-') {
+            expects_value = !word.contains('=')
+                && (GLOBAL_VALUE_OPTIONS.contains(&word.as_str())
+                    || current.is_some_and(|task| option_takes_value(task, word)));
+            target.push(word.clone());
+        } else if current.is_none() && !config.tasks.contains_key(word) && builtin::is_builtin(word)
+        {
+            // Built-ins take the rest of the line, which may name tasks
+            return vec![args.to_vec()];
+        } else if positionals_left == 0 && is_task(word) {
+            let mut argv = prefix.clone();
+            argv.push(word.clone());
+            invocations.push(argv);
+            current = config.tasks.get(word);
+            positionals_left = current.map_or(0, |task| {
+                task.args.values().fold(0, |total: usize, arg| {
+                    total.saturating_add(arg.max_values())
+                })
+            });
+        } else {
+            positionals_left = positionals_left.saturating_sub(1);
+            target.push(word.clone());
+        }
+    }
+
+    if invocations.is_empty() {
+        invocations.push(prefix);
+    }
+    invocations
+}
+
+/// Check whether `word` is a `--long` or `-s` task option that takes a value
+fn option_takes_value(task: &crate::config::Task, word: &str) -> bool {
+    task.options.iter().any(|(name, option)| {
+        let matches_word = word.strip_prefix("--") == Some(name.as_str())
+            || option
+                .short
+                .as_deref()
+                .is_some_and(|short| word.strip_prefix('-') == Some(short));
+        matches_word && !matches!(option.option_type.as_str(), "bool" | "boolean" | "count")
+    })
+}
+
+/// Get verbosity level from matches, using `default` when no flag is given
+fn get_verbosity(matches: &ArgMatches, default: Verbosity) -> Verbosity {
     if matches.get_flag("silent") {
         Verbosity::Silent
     } else if matches.get_flag("quiet") {
         Verbosity::Quiet
-    } else if matches.get_flag("verbose") {
-        Verbosity::Verbose
+    } else if matches.get_count("verbose") > 0 {
+        Verbosity::from_verbose_count(matches.get_count("verbose"))
     } else {
-        Verbosity::Normal
+        default
     }
 }
 
@@ -237,16 +966,32 @@ fn parse_task_vars(
 ) -> Result<HashMap<String, String>, RtaskError> {
     let mut vars = HashMap::new();
 
-    // Parse arguments
+    // Parse arguments; multi-value args are exposed joined with spaces and
+    // one by one as ${name.1}, ${name.2}, ...
     for (arg_name, arg) in &task.args {
-        if let Some(value) = matches.get_one::<String>(arg_name) {
+        if arg.is_variadic() {
+            let values: Vec<&String> = matches
+                .get_many::<String>(arg_name)
+                .map(|values| values.collect())
+                .unwrap_or_default();
+            for (index, value) in values.iter().enumerate() {
+                vars.insert(format!("{}.{}", arg_name, index + 1), (*value).clone());
+            }
+            if !values.is_empty() {
+                let joined: Vec<&str> = values.iter().map(|value| value.as_str()).collect();
+                vars.insert(arg_name.clone(), joined.join(" "));
+            } else if let Some(default) = &arg.default {
+                vars.insert(arg_name.clone(), default.clone());
+            }
+        } else if let Some(value) = matches.get_one::<String>(arg_name) {
             vars.insert(arg_name.clone(), value.clone());
         } else if let Some(default) = &arg.default {
             vars.insert(arg_name.clone(), default.clone());
         }
     }
 
-    // Parse options
+    // Parse options given on the command line; defaults are resolved by the
+    // task when a run item needs them
     for (opt_name, opt) in &task.options {
         let value = match opt.option_type.as_str() {
             "bool" | "boolean" => {
@@ -256,17 +1001,11 @@ fn parse_task_vars(
                     "false".to_string()
                 }
             }
-            _ => {
-                if let Some(v) = matches.get_one::<String>(opt_name) {
-                    v.clone()
-                } else if let Some(default) = &opt.default {
-                    default.clone()
-                } else if let Some(env_var) = &opt.environment {
-                    std::env::var(env_var).unwrap_or_default()
-                } else {
-                    String::new()
-                }
-            }
+            "count" => matches.get_count(opt_name).to_string(),
+            _ => match matches.get_one::<String>(opt_name) {
+                Some(v) => v.clone(),
+                None => continue,
+            },
         };
 
         // Apply rewrite if specified
@@ -286,21 +1025,43 @@ fn parse_task_vars(
 
 /// Run the CLI application with provided arguments
 pub fn run() -> Result<(), RtaskError> {
+    // Environment defaults apply before anything is printed
+    EnvDefaults::from_env()?.color.unwrap_or_default().apply();
+
+    // Ctrl-C stops the running commands, and finally blocks still run
+    signals::install()?;
+
     // Check if --file flag is provided first
     let args: Vec<String> = std::env::args().collect();
     let file_path = extract_file_arg(&args);
 
     let app = if let Some(path) = file_path {
-        App::with_config_file(path)?
+        App::with_config_file(path)
     } else {
-        App::new()?
+        App::new()
+    };
+
+    // Shell completion callback: never fail, just offer nothing
+    if args.get(1).map(String::as_str) == Some(completion::COMPLETE_COMMAND) {
+        if let Ok(app) = &app {
+            for candidate in app.complete(&args[2..]) {
+                println!("{}", candidate);
+            }
+        }
+        return Ok(());
+    }
+
+    // Some built-ins must work even where no config file can be found
+    let app = match app {
+        Ok(app) => app,
+        Err(err) => return builtin::run_standalone(&args).unwrap_or(Err(err)),
     };
 
     app.run()
 }
 
 /// Extract --file argument before clap parsing
-fn extract_file_arg(args: &[String]) -> Option<PathBuf> {
+pub(crate) fn extract_file_arg(args: &[String]) -> Option<PathBuf> {
     for i in 0..args.len() {
         if (args[i] == "--file" || args[i] == "-f") && i + 1 < args.len() {
             return Some(PathBuf::from(&args[i + 1]));
@@ -318,9 +1079,231 @@ mod tests {
         let cmd = Command::new("test")
             .arg(Arg::new("quiet").long("quiet").action(ArgAction::SetTrue))
             .arg(Arg::new("silent").long("silent").action(ArgAction::SetTrue))
-            .arg(Arg::new("verbose").long("verbose").action(ArgAction::SetTrue));
+            .arg(Arg::new("verbose").long("verbose").action(ArgAction::Count));
         let matches = cmd.get_matches_from(vec!["test"]);
-        assert_eq!(get_verbosity(&matches), Verbosity::Normal);
+        assert_eq!(
+            get_verbosity(&matches, Verbosity::Normal),
+            Verbosity::Normal
+        );
+    }
+
+    #[test]
+    fn test_get_verbosity_counted() {
+        let config = Config::default();
+        let matches = build_command(&config).get_matches_from(vec!["rtask", "-vv"]);
+        assert_eq!(get_verbosity(&matches, Verbosity::Normal), Verbosity::Trace);
+
+        let matches = build_command(&config).get_matches_from(vec!["rtask", "-v"]);
+        assert_eq!(
+            get_verbosity(&matches, Verbosity::Normal),
+            Verbosity::Verbose
+        );
+    }
+
+    #[test]
+    fn test_env_defaults() {
+        let env = EnvDefaults::from_lookup(|name| match name {
+            "RTASK_VERBOSITY" => Some("trace".to_string()),
+            "RTASK_COLOR" => Some("never".to_string()),
+            "RTASK_LOG_FILE" => Some("out.log".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(env.verbosity, Some(Verbosity::Trace));
+        assert_eq!(env.color, Some(ColorChoice::Never));
+        assert_eq!(env.log_file, Some(PathBuf::from("out.log")));
+
+        let empty = EnvDefaults::from_lookup(|_| Some(String::new())).unwrap();
+        assert!(empty.verbosity.is_none() && empty.log_file.is_none());
+
+        let err = EnvDefaults::from_lookup(|name| {
+            (name == "RTASK_VERBOSITY").then(|| "loud".to_string())
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("RTASK_VERBOSITY"));
+    }
+
+    #[test]
+    fn test_verbosity_flag_overrides_env_default() {
+        let config = Config::default();
+        let matches = build_command(&config).get_matches_from(vec!["rtask", "--quiet"]);
+        assert_eq!(get_verbosity(&matches, Verbosity::Trace), Verbosity::Quiet);
+
+        let matches = build_command(&config).get_matches_from(vec!["rtask"]);
+        assert_eq!(get_verbosity(&matches, Verbosity::Trace), Verbosity::Trace);
+    }
+
+    #[test]
+    fn test_split_at_subcommand() {
+        let argv: Vec<String> = "rtask --log-file test -v --watch test --release"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let (globals, args) = split_at_subcommand(&argv);
+        assert_eq!(globals, ["--log-file", "test", "-v", "--watch"]);
+        assert_eq!(args, ["--release"]);
+
+        let argv = vec!["rtask".to_string(), "--quiet".to_string()];
+        assert_eq!(split_at_subcommand(&argv), (&argv[1..], &[][..]));
+    }
+
+    #[test]
+    fn test_split_invocations() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  lint:
+    run: echo lint
+  test:
+    options:
+      target:
+        short: t
+    run: echo ${target}
+  greet:
+    args:
+      name: {}
+    run: echo ${name}
+"#,
+            None,
+        )
+        .unwrap();
+        let argv =
+            |words: &str| -> Vec<String> { words.split_whitespace().map(String::from).collect() };
+
+        assert_eq!(
+            split_invocations(
+                &config,
+                &argv("rtask -q lint test --target lint greet test")
+            ),
+            vec![
+                argv("rtask -q lint"),
+                argv("rtask -q test --target lint"),
+                argv("rtask -q greet test"),
+            ]
+        );
+        assert_eq!(
+            split_invocations(
+                &config,
+                &argv("rtask lint -j 2 test --jobs 4 greet -j 3 world")
+            ),
+            vec![
+                argv("rtask lint -j 2"),
+                argv("rtask test --jobs 4"),
+                argv("rtask greet -j 3 world"),
+            ]
+        );
+        assert_eq!(
+            split_invocations(&config, &argv("rtask --help")),
+            vec![argv("rtask --help")]
+        );
+        assert_eq!(
+            split_invocations(&config, &argv("rtask -q bench lint test")),
+            vec![argv("rtask -q bench lint test")]
+        );
+    }
+
+    #[test]
+    fn test_tasks_listed_in_definition_order() {
+        let config = crate::config::parse_config(
+            "tasks:\n  zeta:\n    run: echo\n  alpha:\n    run: echo\n  mid:\n    run: echo\n",
+            None,
+        )
+        .unwrap();
+        let names = |command: &Command| -> Vec<String> {
+            let mut subs: Vec<&Command> = command.get_subcommands().collect();
+            subs.sort_by_key(|sub| sub.get_display_order());
+            subs.iter()
+                .map(|sub| sub.get_name().to_string())
+                .take(3)
+                .collect()
+        };
+
+        let command = build_command(&config);
+        assert_eq!(names(&command), vec!["zeta", "alpha", "mid"]);
+        assert_eq!(
+            names(&sort_tasks(command, &config)),
+            vec!["alpha", "mid", "zeta"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_option_suggestion() {
+        let config = crate::config::parse_config(
+            "tasks:\n  test:\n    options:\n      target:\n        usage: Target triple\n    run: echo\n",
+            None,
+        )
+        .unwrap();
+        let command = build_command(&config);
+
+        let argv = vec![
+            "rtask".to_string(),
+            "test".to_string(),
+            "--taget".to_string(),
+        ];
+        let err = command.clone().try_get_matches_from(&argv).unwrap_err();
+        let message = unknown_option_message(&command, &err, &argv).unwrap();
+        assert!(message.contains("tip: did you mean '--target'? (Target triple)"));
+        assert!(message.contains("Usage:"));
+
+        let argv = vec![
+            "rtask".to_string(),
+            "test".to_string(),
+            "--keep-goin".to_string(),
+        ];
+        let err = command.clone().try_get_matches_from(&argv).unwrap_err();
+        let message = unknown_option_message(&command, &err, &argv).unwrap();
+        assert!(message.contains("did you mean '--keep-going'?"));
+    }
+
+    #[test]
+    fn test_count_option() {
+        let config = crate::config::parse_config(
+            "tasks:\n  test:\n    options:\n      debug:\n        type: count\n        short: d\n    run: echo ${debug}\n",
+            None,
+        )
+        .unwrap();
+        let matches = build_command(&config).get_matches_from(vec!["rtask", "test", "-d", "-d"]);
+        let (_, task_matches) = matches.subcommand().unwrap();
+
+        let vars = parse_task_vars(&config.tasks["test"], task_matches).unwrap();
+        assert_eq!(vars.get("debug").map(String::as_str), Some("2"));
+    }
+
+    #[test]
+    fn test_variadic_arg() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  lint:
+    args:
+      target:
+        required: true
+      files:
+        min: 1
+        max: 3
+    run: echo ${files}
+"#,
+            None,
+        )
+        .unwrap();
+        let command = build_command(&config);
+
+        let matches = command
+            .clone()
+            .get_matches_from(vec!["rtask", "lint", "src", "a.rs", "b.rs"]);
+        let (_, task_matches) = matches.subcommand().unwrap();
+        let vars = parse_task_vars(&config.tasks["lint"], task_matches).unwrap();
+        assert_eq!(vars.get("target").map(String::as_str), Some("src"));
+        assert_eq!(vars.get("files").map(String::as_str), Some("a.rs b.rs"));
+        assert_eq!(vars.get("files.2").map(String::as_str), Some("b.rs"));
+
+        assert!(command
+            .clone()
+            .try_get_matches_from(vec!["rtask", "lint", "src"])
+            .is_err());
+        assert!(command
+            .try_get_matches_from(vec!["rtask", "lint", "src", "a", "b", "c", "d"])
+            .is_err());
     }
 
     #[test]
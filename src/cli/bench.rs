@@ -0,0 +1,119 @@
+//! `bench` subcommand
+//!
+//! Runs tasks repeatedly and summarizes how long they take.
+
+use crate::ui::Catalog;
+use std::time::Duration;
+
+/// Timing summary for repeated runs of one task
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchSummary {
+    pub task: String,
+    pub iterations: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    /// Population standard deviation of the run times
+    pub stddev: Duration,
+}
+
+impl BenchSummary {
+    /// Summarize a set of run times (must not be empty)
+    pub fn new(task: &str, samples: &[Duration]) -> Self {
+        let secs: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+
+        Self {
+            task: task.to_string(),
+            iterations: samples.len(),
+            min: samples.iter().min().copied().unwrap_or_default(),
+            mean: Duration::from_secs_f64(mean),
+            max: samples.iter().max().copied().unwrap_or_default(),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+
+    /// Human-readable report of the summary
+    pub fn render(&self) -> String {
+        format!(
+            "Benchmark: {} ({} iterations)\n  mean {} ± {}  (min {}, max {})\n",
+            self.task,
+            self.iterations,
+            format_elapsed(self.mean),
+            format_elapsed(self.stddev),
+            format_elapsed(self.min),
+            format_elapsed(self.max),
+        )
+    }
+}
+
+/// Describe how two benchmarks compare by mean run time, in the language of
+/// `catalog`
+pub fn compare(catalog: &Catalog, a: &BenchSummary, b: &BenchSummary) -> String {
+    let (fast, slow) = if a.mean <= b.mean { (a, b) } else { (b, a) };
+    let fast_secs = fast.mean.as_secs_f64();
+    if fast_secs == 0.0 {
+        return catalog.format(
+            "bench.too-fast",
+            &[("fast", &fast.task), ("slow", &slow.task)],
+        );
+    }
+    let ratio = format!("{:.2}", slow.mean.as_secs_f64() / fast_secs);
+    catalog.format(
+        "bench.faster",
+        &[
+            ("fast", &fast.task),
+            ("ratio", &ratio),
+            ("slow", &slow.task),
+        ],
+    )
+}
+
+/// Format a run time with precision suited to its magnitude
+fn format_elapsed(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 1.0 {
+        format!("{:.1}ms", secs * 1000.0)
+    } else {
+        format!("{:.3}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = BenchSummary::new("build", &millis(&[100, 200, 300]));
+        assert_eq!(summary.iterations, 3);
+        assert_eq!(summary.min, Duration::from_millis(100));
+        assert_eq!(summary.mean.as_millis(), 200);
+        assert_eq!(summary.max, Duration::from_millis(300));
+        assert_eq!(summary.stddev.as_millis(), 81);
+        assert_eq!(
+            summary.render(),
+            "Benchmark: build (3 iterations)\n  mean 200.0ms ± 81.6ms  (min 100.0ms, max 300.0ms)\n"
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let slow = BenchSummary::new("slow", &millis(&[3000]));
+        let fast = BenchSummary::new("fast", &millis(&[1000]));
+        let english = Catalog::for_locale("en");
+        assert_eq!(
+            compare(&english, &slow, &fast),
+            "fast is 3.00x faster than slow"
+        );
+        assert_eq!(
+            compare(&english, &fast, &slow),
+            "fast is 3.00x faster than slow"
+        );
+    }
+}
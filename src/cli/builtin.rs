@@ -0,0 +1,567 @@
+//! Built-in subcommands
+//!
+//! Built-ins live alongside task subcommands. A task with the same name as a
+//! built-in shadows it, so existing configs keep working as built-ins are added.
+
+use crate::cli::bench::{self, BenchSummary};
+use crate::cli::scaffold::{self, TaskSkeleton};
+use crate::cli::{completion, edit, graph, group, stats, test_runner, watch, App};
+use crate::config::Config;
+use crate::error::{ConfigError, ExecutionError, RtaskError};
+use crate::runner::{self, execute_command, history_path, read_history, Context, Verbosity};
+use crate::ui::{catalog, message};
+use crate::utils::shell;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command as StdCommand;
+use std::time::Instant;
+
+/// Check whether a subcommand name refers to a built-in
+pub fn is_builtin(name: &str) -> bool {
+    builtin_commands().iter().any(|cmd| cmd.get_name() == name)
+}
+
+/// Clap definitions for the built-in subcommands
+pub fn builtin_commands() -> Vec<Command> {
+    let commands = vec![
+        Command::new("edit")
+            .about("Open the config file in $EDITOR, optionally at a task")
+            .arg(Arg::new("task").value_name("TASK").help("Task to jump to")),
+        Command::new("add-task")
+            .about("Append a new task skeleton to the config file")
+            .arg(
+                Arg::new("name")
+                    .value_name("NAME")
+                    .help("Name of the new task")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("usage")
+                    .long("usage")
+                    .value_name("TEXT")
+                    .help("Usage line for the task"),
+            )
+            .arg(
+                Arg::new("run")
+                    .long("run")
+                    .value_name("COMMAND")
+                    .help("Command the task runs"),
+            )
+            .arg(
+                Arg::new("option")
+                    .long("option")
+                    .value_name("NAME")
+                    .help("Declare an option (repeatable)")
+                    .action(ArgAction::Append),
+            ),
+        Command::new("completion")
+            .about("Print a shell completion script")
+            .arg(
+                Arg::new("shell")
+                    .value_name("SHELL")
+                    .help("Shell to generate completions for")
+                    .value_parser(completion::SHELLS.to_vec())
+                    .required(true),
+            ),
+        Command::new("bench")
+            .about("Run tasks repeatedly and report timing statistics")
+            .arg(
+                Arg::new("tasks")
+                    .value_name("TASK")
+                    .help("Task to benchmark; give two to compare them")
+                    .num_args(1..=2)
+                    .required(true),
+            )
+            .arg(
+                Arg::new("iterations")
+                    .short('n')
+                    .long("iterations")
+                    .value_name("N")
+                    .help("Number of runs per task")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .default_value("10"),
+            ),
+        Command::new("exec")
+            .about("Run a one-off command with the project's interpreter and settings")
+            .arg(
+                Arg::new("command")
+                    .value_name("COMMAND")
+                    .help("Command and arguments to run (put them after --)")
+                    .num_args(1..)
+                    .trailing_var_arg(true)
+                    .allow_hyphen_values(true)
+                    .required(true),
+            ),
+        Command::new("config")
+            .about("Inspect the loaded configuration")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("dump")
+                    .about("Print the resolved config, with included files merged")
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Print the config as JSON instead of YAML")
+                            .action(ArgAction::SetTrue),
+                    ),
+            ),
+        Command::new("env")
+            .about("Print the variables a task's commands would see")
+            .arg(
+                Arg::new("task")
+                    .value_name("TASK")
+                    .help("Task to resolve")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("args")
+                    .value_name("ARGS")
+                    .help("Options and args for the task, after --")
+                    .num_args(0..)
+                    .last(true),
+            )
+            .arg(
+                Arg::new("json")
+                    .long("json")
+                    .help("Print variables as JSON instead of export lines")
+                    .action(ArgAction::SetTrue),
+            ),
+        Command::new("shell")
+            .about("Start the interpreter interactively with a task's variables exported")
+            .arg(
+                Arg::new("task")
+                    .value_name("TASK")
+                    .help("Task whose options and args to export"),
+            )
+            .arg(
+                Arg::new("args")
+                    .value_name("ARGS")
+                    .help("Options and args for the task")
+                    .num_args(0..)
+                    .trailing_var_arg(true)
+                    .allow_hyphen_values(true),
+            ),
+        Command::new("watch")
+            .about("Run a task, and again whenever its watched files change")
+            .arg(
+                Arg::new("task")
+                    .value_name("TASK")
+                    .help("Task to run")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("args")
+                    .value_name("ARGS")
+                    .help("Options and args for the task")
+                    .num_args(0..)
+                    .trailing_var_arg(true)
+                    .allow_hyphen_values(true),
+            ),
+        Command::new("up")
+            .about("Start a group of services and show their output")
+            .arg(
+                Arg::new("group")
+                    .value_name("GROUP")
+                    .help("Group from `groups:` in the config")
+                    .required(true),
+            ),
+        Command::new("down")
+            .about("Stop a group of services started with `up`")
+            .arg(
+                Arg::new("group")
+                    .value_name("GROUP")
+                    .help("Group from `groups:` in the config")
+                    .required(true),
+            ),
+        Command::new("test")
+            .about("Run the tests declared under `tests:` in the config")
+            .arg(
+                Arg::new("filter")
+                    .value_name("FILTER")
+                    .help("Only run tests whose name contains FILTER"),
+            ),
+        Command::new("graph")
+            .about("Show the tasks and the tasks they run")
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .help("Output format")
+                    .value_parser(graph::FORMATS.to_vec())
+                    .default_value("text"),
+            ),
+        Command::new("stats")
+            .about("Show run statistics from the task history")
+            .arg(
+                Arg::new("task")
+                    .value_name("TASK")
+                    .help("Only show statistics for this task"),
+            )
+            .arg(
+                Arg::new("json")
+                    .long("json")
+                    .help("Print statistics as JSON")
+                    .action(ArgAction::SetTrue),
+            ),
+    ];
+
+    #[cfg(feature = "self-update")]
+    let commands = [
+        commands,
+        vec![Command::new("self-update")
+            .about("Update rtask to the latest release")
+            .arg(
+                Arg::new("check")
+                    .long("check")
+                    .help("Only check whether an update is available")
+                    .action(ArgAction::SetTrue),
+            )],
+    ]
+    .concat();
+
+    commands
+}
+
+/// Run a built-in subcommand, parsed into `matches` from `invocation`
+pub fn run_builtin(
+    app: &App,
+    name: &str,
+    invocation: &[String],
+    matches: &ArgMatches,
+) -> Result<(), RtaskError> {
+    match name {
+        "edit" => {
+            let line = match matches.get_one::<String>("task") {
+                Some(task) => Some(
+                    *app.config()
+                        .task_lines
+                        .get(task)
+                        .ok_or_else(|| ConfigError::TaskNotFound(task.clone()))?,
+                ),
+                None => None,
+            };
+            edit::edit_config(app.config_path(), line)
+        }
+        "add-task" => {
+            let skeleton = TaskSkeleton {
+                name: matches
+                    .get_one::<String>("name")
+                    .cloned()
+                    .unwrap_or_default(),
+                usage: matches.get_one::<String>("usage").cloned(),
+                run: matches.get_one::<String>("run").cloned(),
+                options: matches
+                    .get_many::<String>("option")
+                    .map(|values| values.cloned().collect())
+                    .unwrap_or_default(),
+            };
+            scaffold::add_task(app.config_path(), &skeleton)?;
+            println!(
+                "{}",
+                message(
+                    "scaffold.added",
+                    &[
+                        ("task", &skeleton.name),
+                        ("file", &app.config_path().display())
+                    ]
+                )
+            );
+            Ok(())
+        }
+        "completion" => {
+            let shell = matches
+                .get_one::<String>("shell")
+                .map(String::as_str)
+                .unwrap_or_default();
+            print_completion(shell)
+        }
+        "bench" => {
+            let iterations = matches.get_one::<u32>("iterations").copied().unwrap_or(10);
+            let mut summaries = Vec::new();
+            for name in matches.get_many::<String>("tasks").into_iter().flatten() {
+                let summary = bench_task(app, name, iterations, matches)?;
+                print!("{}", summary.render());
+                summaries.push(summary);
+            }
+            if let [a, b] = summaries.as_slice() {
+                println!("{}", bench::compare(catalog(), a, b));
+            }
+            Ok(())
+        }
+        "exec" => {
+            let words: Vec<&String> = matches
+                .get_many::<String>("command")
+                .into_iter()
+                .flatten()
+                .collect();
+            let ctx = app.build_context(matches, app.verbosity(matches))?;
+            execute_command(&runner::Command::Simple(shell::join(&words)), &ctx)?;
+            Ok(())
+        }
+        "config" => match matches.subcommand() {
+            Some(("dump", dump_matches)) => {
+                print!(
+                    "{}",
+                    dump_config(app.config(), dump_matches.get_flag("json"))?
+                );
+                Ok(())
+            }
+            _ => Err(ConfigError::TaskNotFound(name.to_string()).into()),
+        },
+        "env" => {
+            let task = matches
+                .get_one::<String>("task")
+                .map(String::as_str)
+                .unwrap_or_default();
+            let args: Vec<String> = matches
+                .get_many::<String>("args")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            let ctx = app.build_context(matches, app.verbosity(matches))?;
+            let task = app.prepare_task(task, &app.task_matches(task, &args)?)?;
+            let vars: BTreeMap<_, _> = task.preview_vars(&ctx)?.into_iter().collect();
+
+            if matches.get_flag("json") {
+                let json = serde_json::to_string_pretty(&vars)
+                    .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+                println!("{}", json);
+            } else {
+                // `name.N` values of multi-value args are not valid shell names
+                for (key, value) in vars.iter().filter(|(key, _)| !key.contains('.')) {
+                    println!("export {}={}", key, shell::quote(value));
+                }
+            }
+            Ok(())
+        }
+        "shell" => {
+            let mut ctx = app.build_context(matches, app.verbosity(matches))?;
+            let vars = match matches.get_one::<String>("task") {
+                Some(task) => {
+                    let args: Vec<String> = matches
+                        .get_many::<String>("args")
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect();
+                    task_vars(app, task, &args, &mut ctx)?
+                }
+                None => HashMap::new(),
+            };
+            run_shell(&ctx, &vars)
+        }
+        "watch" => {
+            let task = matches
+                .get_one::<String>("task")
+                .map(String::as_str)
+                .unwrap_or_default();
+            let args: Vec<String> = matches
+                .get_many::<String>("args")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            watch::watch(app, task, &args, invocation, matches)
+        }
+        "up" | "down" => {
+            let group = matches
+                .get_one::<String>("group")
+                .map(String::as_str)
+                .unwrap_or_default();
+            if name == "up" {
+                group::up(app, group, matches)
+            } else {
+                group::down(app, group, matches)
+            }
+        }
+        "test" => run_tests(app, matches.get_one::<String>("filter")),
+        "graph" => {
+            let graph = graph::build_graph(app.config(), &app.config_dir());
+            if matches
+                .get_one::<String>("format")
+                .is_some_and(|f| f == "json")
+            {
+                let json = serde_json::to_string_pretty(&graph)
+                    .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+                println!("{}", json);
+            } else {
+                print!("{}", graph::render_text(&graph));
+            }
+            Ok(())
+        }
+        "stats" => {
+            let mut entries = read_history(&history_path(&app.config_dir()))?;
+            if let Some(task) = matches.get_one::<String>("task") {
+                entries.retain(|entry| &entry.task == task);
+            }
+            let stats = stats::compute_stats(&entries);
+
+            if matches.get_flag("json") {
+                let json = serde_json::to_string_pretty(&stats)
+                    .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+                println!("{}", json);
+            } else if stats.is_empty() {
+                println!("{}", message("stats.no-history", &[]));
+            } else {
+                print!("{}", stats::render_table(&stats));
+            }
+            Ok(())
+        }
+        #[cfg(feature = "self-update")]
+        "self-update" => crate::cli::self_update::self_update(matches.get_flag("check")),
+        _ => Err(ConfigError::TaskNotFound(name.to_string()).into()),
+    }
+}
+
+/// Run a task `iterations` times with its default options and output silenced
+///
+/// Benchmark runs are not recorded in the task history.
+fn bench_task(
+    app: &App,
+    name: &str,
+    iterations: u32,
+    matches: &ArgMatches,
+) -> Result<BenchSummary, RtaskError> {
+    let task = app.prepare_task(name, &app.task_matches(name, &[])?)?;
+
+    // Every iteration does the work, rather than finding the targets up to date
+    let mut samples = Vec::new();
+    for _ in 0..iterations {
+        let mut ctx = app
+            .build_context(matches, Verbosity::Silent)?
+            .with_force(true);
+        let start = Instant::now();
+        task.execute(&mut ctx)
+            .map_err(|error| app.task_failure(name, &mut ctx, error))?;
+        samples.push(start.elapsed());
+    }
+    Ok(BenchSummary::new(name, &samples))
+}
+
+/// Serialize the resolved config as YAML or JSON
+///
+/// Included tasks are already merged in, so the `include` list is dropped and
+/// the output can be loaded on its own.
+fn dump_config(config: &Config, json: bool) -> Result<String, RtaskError> {
+    let mut resolved = config.clone();
+    resolved.include.clear();
+    if json {
+        let mut out = serde_json::to_string_pretty(&resolved)
+            .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+        out.push('\n');
+        Ok(out)
+    } else {
+        Ok(serde_yaml::to_string(&resolved)?)
+    }
+}
+
+/// Resolve the variables a task's commands would see for the given CLI args,
+/// limiting the host environment in `ctx` as the task does
+fn task_vars(
+    app: &App,
+    name: &str,
+    args: &[String],
+    ctx: &mut Context,
+) -> Result<HashMap<String, String>, RtaskError> {
+    let task = app.prepare_task(name, &app.task_matches(name, args)?)?;
+    task.limit_env(ctx);
+    Ok(task.preview_vars(ctx)?)
+}
+
+/// Spawn the context's interpreter without its command flag and wait for it
+///
+/// The shell's own exit status is not treated as a failure, since it only
+/// reflects the last command typed into it.
+fn run_shell(ctx: &Context, vars: &HashMap<String, String>) -> Result<(), RtaskError> {
+    let program = &ctx.interpreter[0];
+    ctx.print_debug(&format!(
+        "Starting {} with {} variables",
+        program,
+        vars.len()
+    ));
+    let mut command = StdCommand::new(program);
+    command.current_dir(&ctx.working_dir);
+    runner::apply_env(&mut command, ctx);
+    command.envs(vars).status()?;
+    Ok(())
+}
+
+/// Run the config's tests and print a result line for each
+fn run_tests(app: &App, filter: Option<&String>) -> Result<(), RtaskError> {
+    let tests: Vec<_> = app
+        .config()
+        .tests
+        .iter()
+        .filter(|(name, _)| filter.is_none_or(|filter| name.contains(filter.as_str())))
+        .collect();
+    if tests.is_empty() {
+        println!("{}", message("test.none", &[]));
+        return Ok(());
+    }
+
+    let mut failed = Vec::new();
+    for (name, test) in &tests {
+        let outcome = test_runner::run_test(app.config_path(), name, test)?;
+        if outcome.passed() {
+            println!("test {} ... {}", name, "ok".green());
+            continue;
+        }
+
+        println!("test {} ... {}", name, "FAILED".red());
+        for failure in &outcome.failures {
+            println!("    {}", failure);
+        }
+        for line in outcome.stderr.lines() {
+            println!("    | {}", line);
+        }
+        failed.push(outcome.name);
+    }
+
+    let passed = tests.len() - failed.len();
+    println!(
+        "\n{}",
+        message(
+            "test.summary",
+            &[("passed", &passed), ("failed", &failed.len())]
+        )
+    );
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(ExecutionError::TestsFailed {
+            failed,
+            total: tests.len(),
+        }
+        .into())
+    }
+}
+
+/// Run a built-in that does not need a config file, given raw CLI arguments
+///
+/// Returns `None` when the arguments do not name such a built-in.
+pub fn run_standalone(args: &[String]) -> Option<Result<(), RtaskError>> {
+    match args.get(1).map(String::as_str) {
+        Some("completion") => Some(print_completion(
+            args.get(2).map(String::as_str).unwrap_or_default(),
+        )),
+        #[cfg(feature = "self-update")]
+        Some("self-update") => Some(crate::cli::self_update::self_update(
+            args.iter().any(|arg| arg == "--check"),
+        )),
+        _ => None,
+    }
+}
+
+fn print_completion(shell: &str) -> Result<(), RtaskError> {
+    let script = completion::completion_script(shell, env!("CARGO_PKG_NAME")).ok_or_else(|| {
+        ConfigError::Invalid(format!(
+            "Unsupported shell '{}' (expected one of: {})",
+            shell,
+            completion::SHELLS.join(", ")
+        ))
+    })?;
+    print!("{}", script);
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! Shell completion
+//!
+//! Completion scripts are thin wrappers that call back into
+//! `rtask __complete -- <words>`, so candidates always reflect the tasks,
+//! options and option values of the config in the current directory.
+
+use clap::{Arg, Command};
+
+/// Hidden subcommand invoked by the completion scripts
+pub const COMPLETE_COMMAND: &str = "__complete";
+
+/// Shells with a completion script
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Compute completion candidates for `words`
+///
+/// `words` are the command-line words after the program name; the last word
+/// is the (possibly empty) word being completed.
+pub fn complete(command: &Command, words: &[String]) -> Vec<String> {
+    let words = match words.first() {
+        Some(first) if first == "--" => &words[1..],
+        _ => words,
+    };
+    let (current, typed) = match words.split_last() {
+        Some((current, typed)) => (current.as_str(), typed),
+        None => ("", words),
+    };
+
+    let globals: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .collect();
+
+    // Walk the typed words to find the subcommand and any pending option value
+    let mut subcommand: Option<&Command> = None;
+    let mut pending: Option<&Arg> = None;
+    for word in typed {
+        if pending.take().is_some() {
+            continue;
+        }
+        if word.starts_with('-') {
+            let scope = subcommand.unwrap_or(command);
+            pending = find_option(scope, &globals, word).filter(|arg| takes_value(arg));
+        } else if subcommand.is_none() {
+            subcommand = command.find_subcommand(word.as_str());
+            if subcommand.is_none() {
+                return Vec::new();
+            }
+        }
+    }
+
+    let scope = subcommand.unwrap_or(command);
+    let candidates: Vec<String> = if let Some(arg) = pending {
+        possible_values(arg)
+    } else if current.starts_with('-') {
+        scope
+            .get_arguments()
+            .chain(globals.iter().copied())
+            .filter(|arg| !arg.is_hide_set() && !arg.is_positional())
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|l| format!("--{}", l));
+                let short = arg.get_short().map(|s| format!("-{}", s));
+                long.into_iter().chain(short)
+            })
+            .collect()
+    } else if subcommand.is_none() {
+        command
+            .get_subcommands()
+            .filter(|cmd| !cmd.is_hide_set())
+            .map(|cmd| cmd.get_name().to_string())
+            .collect()
+    } else {
+        scope
+            .get_arguments()
+            .filter(|arg| arg.is_positional())
+            .flat_map(possible_values)
+            .collect()
+    };
+
+    let mut candidates: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(current))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// Completion script for `shell`, calling back into `bin`
+pub fn completion_script(shell: &str, bin: &str) -> Option<String> {
+    let script = match shell {
+        "bash" => format!(
+            r#"_{bin}_complete() {{
+    local IFS=$'\n'
+    COMPREPLY=($({bin} {complete} -- "${{COMP_WORDS[@]:1:COMP_CWORD}}" 2>/dev/null))
+}}
+complete -o default -F _{bin}_complete {bin}
+"#,
+            bin = bin,
+            complete = COMPLETE_COMMAND
+        ),
+        "zsh" => format!(
+            r#"#compdef {bin}
+_{bin}() {{
+    local -a candidates
+    candidates=(${{(f)"$({bin} {complete} -- "${{(@)words[2,CURRENT]}}" 2>/dev/null)"}})
+    if (( ${{#candidates}} )); then
+        compadd -a candidates
+    else
+        _files
+    fi
+}}
+compdef _{bin} {bin}
+"#,
+            bin = bin,
+            complete = COMPLETE_COMMAND
+        ),
+        "fish" => format!(
+            "complete -c {bin} -f -a '({bin} {complete} -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'\n",
+            bin = bin,
+            complete = COMPLETE_COMMAND
+        ),
+        _ => return None,
+    };
+    Some(script)
+}
+
+fn find_option<'a>(scope: &'a Command, globals: &[&'a Arg], word: &str) -> Option<&'a Arg> {
+    let word = word.split('=').next().unwrap_or(word);
+    scope
+        .get_arguments()
+        .chain(globals.iter().copied())
+        .find(|arg| match word.strip_prefix("--") {
+            Some(long) => arg.get_long() == Some(long),
+            None => {
+                let mut chars = word.chars().skip(1);
+                matches!((chars.next(), chars.next()), (Some(c), None) if arg.get_short() == Some(c))
+            }
+        })
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::builder::PossibleValuesParser;
+    use clap::ArgAction;
+
+    fn command() -> Command {
+        Command::new("rtask")
+            .arg(Arg::new("file").short('f').long("file").global(true))
+            .subcommand(
+                Command::new("build")
+                    .arg(
+                        Arg::new("profile")
+                            .long("profile")
+                            .short('p')
+                            .value_parser(PossibleValuesParser::new(["debug", "release"])),
+                    )
+                    .arg(
+                        Arg::new("verbose")
+                            .long("verbose")
+                            .action(ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(Command::new("bench"))
+            .subcommand(
+                Command::new("deploy").arg(
+                    Arg::new("env").value_parser(PossibleValuesParser::new(["prod", "staging"])),
+                ),
+            )
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_complete_task_names() {
+        assert_eq!(
+            complete(&command(), &words(&["--", "b"])),
+            vec!["bench", "build"]
+        );
+        assert_eq!(
+            complete(&command(), &words(&["-f", "other.yml", "d"])),
+            vec!["deploy"]
+        );
+    }
+
+    #[test]
+    fn test_complete_task_options() {
+        assert_eq!(
+            complete(&command(), &words(&["build", "--"])),
+            vec!["--file", "--profile", "--verbose"]
+        );
+    }
+
+    #[test]
+    fn test_complete_option_values() {
+        assert_eq!(
+            complete(&command(), &words(&["build", "--profile", "r"])),
+            vec!["release"]
+        );
+        assert_eq!(
+            complete(&command(), &words(&["build", "--verbose", ""])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            complete(&command(), &words(&["deploy", ""])),
+            vec!["prod", "staging"]
+        );
+    }
+
+    #[test]
+    fn test_complete_unknown_task() {
+        assert!(complete(&command(), &words(&["nope", ""])).is_empty());
+    }
+
+    #[test]
+    fn test_completion_script() {
+        let bash = completion_script("bash", "rtask").unwrap();
+        assert!(bash.contains("rtask __complete --"));
+        assert!(bash.contains("complete -o default -F _rtask_complete rtask"));
+        assert!(completion_script("powershell", "rtask").is_none());
+    }
+}
@@ -0,0 +1,109 @@
+//! `edit` subcommand
+//!
+//! Opens the config file in the user's editor, jumping to a task's line when
+//! the editor understands a line argument.
+
+use crate::error::{ExecutionError, RtaskError};
+use std::env;
+use std::path::Path;
+use std::process::Command as StdCommand;
+
+/// Editors that accept `+N path` to open at a line
+const PLUS_LINE_EDITORS: &[&str] = &[
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "micro",
+    "kak",
+    "joe",
+    "ne",
+    "mg",
+];
+
+/// Editors that accept `path:N` to open at a line
+const COLON_LINE_EDITORS: &[&str] = &["subl", "hx", "helix", "zed"];
+
+/// Editors that accept `--goto path:N` to open at a line
+const GOTO_EDITORS: &[&str] = &["code", "codium", "code-insiders", "cursor"];
+
+/// Open the config file in `$VISUAL`/`$EDITOR` (falling back to `vi`)
+pub fn edit_config(path: &Path, line: Option<usize>) -> Result<(), RtaskError> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+
+    let argv = editor_command(&editor, &path.display().to_string(), line);
+    let (program, args) = argv.split_first().expect("editor command is never empty");
+
+    let status = StdCommand::new(program).args(args).status().map_err(|e| {
+        ExecutionError::Environment(format!("Failed to launch '{}': {}", program, e))
+    })?;
+
+    if !status.success() {
+        return Err(ExecutionError::CommandFailed(status.code()).into());
+    }
+
+    Ok(())
+}
+
+/// Build the editor argv, adding a line argument in the editor's syntax when known
+pub fn editor_command(editor: &str, path: &str, line: Option<usize>) -> Vec<String> {
+    let mut argv: Vec<String> = editor.split_whitespace().map(String::from).collect();
+    let program = argv
+        .first()
+        .and_then(|p| Path::new(p).file_name())
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    match line {
+        Some(line) if PLUS_LINE_EDITORS.contains(&program.as_str()) => {
+            argv.push(format!("+{}", line));
+            argv.push(path.to_string());
+        }
+        Some(line) if COLON_LINE_EDITORS.contains(&program.as_str()) => {
+            argv.push(format!("{}:{}", path, line));
+        }
+        Some(line) if GOTO_EDITORS.contains(&program.as_str()) => {
+            argv.push("--goto".to_string());
+            argv.push(format!("{}:{}", path, line));
+        }
+        _ => argv.push(path.to_string()),
+    }
+
+    argv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command_plus_line() {
+        assert_eq!(
+            editor_command("/usr/bin/vim", "rtask.yml", Some(12)),
+            vec!["/usr/bin/vim", "+12", "rtask.yml"]
+        );
+    }
+
+    #[test]
+    fn test_editor_command_goto() {
+        assert_eq!(
+            editor_command("code -w", "rtask.yml", Some(3)),
+            vec!["code", "-w", "--goto", "rtask.yml:3"]
+        );
+    }
+
+    #[test]
+    fn test_editor_command_unknown_editor() {
+        assert_eq!(
+            editor_command("myeditor", "rtask.yml", Some(3)),
+            vec!["myeditor", "rtask.yml"]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! `graph` subcommand
+//!
+//! Describes the tasks of the loaded config and the edges between them, for
+//! people (`--format text`) and for tooling (`--format json`). The JSON is a
+//! [`TaskGraph`]; its `version` changes only when fields are removed or
+//! change meaning, so consumers can ignore fields they do not know.
+
+use crate::config::{Config, Run, SubTask};
+use crate::runner::{cache_state, definition_hash, CacheState};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Version of the JSON schema
+pub const GRAPH_VERSION: u32 = 1;
+
+/// Output formats of `rtask graph`
+pub const FORMATS: [&str; 2] = ["text", "json"];
+
+/// Tasks and the edges between them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskGraph {
+    pub version: u32,
+    /// Tasks in definition order
+    pub nodes: Vec<Node>,
+    /// Edges in definition order of the task they start from
+    pub edges: Vec<Edge>,
+}
+
+/// A task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    pub name: String,
+    pub usage: Option<String>,
+    pub private: bool,
+    pub service: bool,
+    pub options: Vec<NodeOption>,
+    pub args: Vec<NodeArg>,
+    pub cache: NodeCache,
+}
+
+/// An option of a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeOption {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub option_type: String,
+    pub short: Option<String>,
+    pub default: Option<String>,
+    pub required: bool,
+    /// Allowed values; empty when any value is allowed
+    pub values: Vec<String>,
+}
+
+/// A positional argument of a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub arg_type: String,
+    pub default: Option<String>,
+    pub required: bool,
+}
+
+/// Source/target caching of a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeCache {
+    pub state: CacheState,
+    pub source: Vec<String>,
+    pub target: Vec<String>,
+}
+
+/// A task that runs another one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+    /// Config file defining `to`, relative to the config's directory, when it
+    /// is not the loaded config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+/// How one task runs another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeKind {
+    /// A task listed in `deps`
+    Dep,
+    /// A `task:` run item
+    Subtask,
+    /// A `task:` run item in the `finally` block
+    Finally,
+}
+
+/// Build the graph of `config`, whose files are relative to `dir`
+///
+/// A task counts as fresh when its targets are up to date and were built by
+/// its current definition, whatever options that run had.
+pub fn build_graph(config: &Config, dir: &Path) -> TaskGraph {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for (name, task) in &config.tasks {
+        nodes.push(Node {
+            name: name.clone(),
+            usage: task.usage.clone(),
+            private: task.private,
+            service: task.service,
+            options: task
+                .options
+                .iter()
+                .map(|(name, option)| NodeOption {
+                    name: name.clone(),
+                    option_type: option.option_type.clone(),
+                    short: option.short.clone(),
+                    default: option.default.clone(),
+                    required: option.required,
+                    values: option.values.clone(),
+                })
+                .collect(),
+            args: task
+                .args
+                .iter()
+                .map(|(name, arg)| NodeArg {
+                    name: name.clone(),
+                    arg_type: arg.arg_type.clone(),
+                    default: arg.default.clone(),
+                    required: arg.required,
+                })
+                .collect(),
+            cache: NodeCache {
+                state: cache_state(
+                    dir,
+                    name,
+                    &definition_hash(config, name).unwrap_or_default(),
+                    None,
+                    &task.source,
+                    &task.target,
+                ),
+                source: task.source.clone(),
+                target: task.target.clone(),
+            },
+        });
+        for dep in &task.deps {
+            edges.push(Edge {
+                from: name.clone(),
+                to: dep.clone(),
+                kind: EdgeKind::Dep,
+                file: None,
+            });
+        }
+        subtask_edges(name, &task.run, EdgeKind::Subtask, &mut edges);
+        subtask_edges(name, &task.finally, EdgeKind::Finally, &mut edges);
+    }
+    TaskGraph {
+        version: GRAPH_VERSION,
+        nodes,
+        edges,
+    }
+}
+
+/// Add an edge for every subtask in `items`, including parallel branches;
+/// a subtask run more than once gets one edge
+fn subtask_edges(from: &str, items: &[Run], kind: EdgeKind, edges: &mut Vec<Edge>) {
+    for item in items {
+        let Run::Complex(item) = item else {
+            continue;
+        };
+        for subtask in &item.task {
+            let (to, file) = match subtask {
+                SubTask::Simple(name) => (name.clone(), None),
+                SubTask::Complex(detail) => (detail.name.clone(), detail.file.clone()),
+            };
+            let edge = Edge {
+                from: from.to_string(),
+                to,
+                kind,
+                file,
+            };
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+        subtask_edges(from, item.parallel.group(), kind, edges);
+    }
+}
+
+/// Render the graph as one block per task: the task, then the tasks it runs
+pub fn render_text(graph: &TaskGraph) -> String {
+    let mut out = String::new();
+    for node in &graph.nodes {
+        let mut notes = Vec::new();
+        if node.private {
+            notes.push("private");
+        }
+        if node.service {
+            notes.push("service");
+        }
+        match node.cache.state {
+            CacheState::Uncached => {}
+            CacheState::Fresh => notes.push("fresh"),
+            CacheState::Stale => notes.push("stale"),
+        }
+        let _ = match notes.as_slice() {
+            [] => writeln!(out, "{}", node.name),
+            _ => writeln!(out, "{} ({})", node.name, notes.join(", ")),
+        };
+
+        for edge in graph.edges.iter().filter(|edge| edge.from == node.name) {
+            let to = match &edge.file {
+                Some(file) => format!("{} in {}", edge.to, file),
+                None => edge.to.clone(),
+            };
+            let _ = match edge.kind {
+                EdgeKind::Dep => writeln!(out, "  -> {} (dep)", to),
+                EdgeKind::Subtask => writeln!(out, "  -> {}", to),
+                EdgeKind::Finally => writeln!(out, "  -> {} (finally)", to),
+            };
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    const CONFIG: &str = r#"
+tasks:
+  lint:
+    private: true
+    run: cargo clippy
+  test:
+    options:
+      release:
+        type: bool
+        short: r
+    run: cargo test
+  ci:
+    args:
+      target:
+        default: linux
+    deps: [test]
+    run:
+      - task: lint
+      - parallel:
+          - task: test
+          - task: {name: docs, file: docs/rtask.yml}
+      - task: lint
+    finally:
+      - task: {name: cleanup, file: tools.yml}
+"#;
+
+    #[test]
+    fn test_build_graph() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let graph = build_graph(&parse_config(CONFIG, None).unwrap(), dir.path());
+
+        let names: Vec<&str> = graph.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["lint", "test", "ci"]);
+        assert!(graph.nodes[0].private);
+        assert_eq!(graph.nodes[1].options[0].option_type, "bool");
+        assert_eq!(graph.nodes[1].options[0].short.as_deref(), Some("r"));
+        assert_eq!(graph.nodes[2].args[0].default.as_deref(), Some("linux"));
+        assert_eq!(graph.nodes[2].cache.state, CacheState::Uncached);
+
+        let edges: Vec<(&str, EdgeKind, Option<&str>)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.to.as_str(), edge.kind, edge.file.as_deref()))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("test", EdgeKind::Dep, None),
+                ("lint", EdgeKind::Subtask, None),
+                ("test", EdgeKind::Subtask, None),
+                ("docs", EdgeKind::Subtask, Some("docs/rtask.yml")),
+                ("cleanup", EdgeKind::Finally, Some("tools.yml")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_text() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let graph = build_graph(&parse_config(CONFIG, None).unwrap(), dir.path());
+        assert_eq!(
+            render_text(&graph),
+            "lint (private)\ntest\nci\n  -> test (dep)\n  -> lint\n  -> test\n  -> docs in docs/rtask.yml\n  -> cleanup in tools.yml (finally)\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! `up` and `down` subcommands
+//!
+//! `rtask up <group>` starts a group's services in dependency order and stays
+//! in the foreground, printing each service's output behind its name.
+//! `rtask down <group>` stops them from another terminal: `up` writes its pid
+//! to `.rtask/groups/<group>.pid`, `down` drops a stop file next to it, and
+//! `up` stops the services (most recently started first) and exits.
+
+use crate::cli::App;
+use crate::config::group_start_order;
+use crate::error::{ConfigError, ExecutionError, RtaskError};
+use crate::runner::{output_prefix, signals, Context, STATE_DIR};
+use crate::ui::message;
+use clap::ArgMatches;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Directory inside [`STATE_DIR`] holding the pid and stop files
+const GROUPS_DIR: &str = "groups";
+
+/// How often `up` looks for a stop request and `down` for the group stopping
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long `down` waits for the group to stop
+const DOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Files through which `up` and `down` coordinate for one group
+struct GroupState {
+    pid: PathBuf,
+    stop: PathBuf,
+}
+
+impl GroupState {
+    fn new(config_dir: &Path, group: &str) -> Self {
+        let dir = config_dir.join(STATE_DIR).join(GROUPS_DIR);
+        GroupState {
+            pid: dir.join(format!("{}.pid", group)),
+            stop: dir.join(format!("{}.stop", group)),
+        }
+    }
+
+    /// Pid of the `up` process running the group, unless it is known to have
+    /// exited, with whether it is known to be alive
+    fn running_pid(&self) -> Option<(u32, bool)> {
+        let pid = fs::read_to_string(&self.pid).ok()?.trim().parse().ok()?;
+        match process_alive(pid) {
+            Some(false) => None,
+            alive => Some((pid, alive.is_some())),
+        }
+    }
+
+    fn clear(&self) {
+        let _ = fs::remove_file(&self.pid);
+        let _ = fs::remove_file(&self.stop);
+    }
+}
+
+/// Start a group's services and keep them running until `rtask down` or
+/// until they have all exited
+pub fn up(app: &App, group: &str, matches: &ArgMatches) -> Result<(), RtaskError> {
+    let config = app
+        .config()
+        .groups
+        .get(group)
+        .ok_or_else(|| ConfigError::GroupNotFound(group.to_string()))?;
+    let order = group_start_order(group, config)?;
+
+    let state = GroupState::new(&app.config_dir(), group);
+    match state.running_pid() {
+        Some((pid, true)) => {
+            return Err(ExecutionError::Environment(format!(
+                "Group '{}' is already up (pid {})",
+                group, pid
+            ))
+            .into());
+        }
+        Some((pid, false)) => {
+            return Err(ExecutionError::Environment(format!(
+                "Group '{}' may already be up (pid {}); remove {} if it is not",
+                group,
+                pid,
+                state.pid.display()
+            ))
+            .into());
+        }
+        None => {}
+    }
+    state.clear();
+    if let Some(dir) = state.pid.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&state.pid, process::id().to_string())?;
+
+    let mut ctx = app
+        .build_context(matches, app.verbosity(matches))?
+        .with_detach_services(true);
+    let result = start_services(app, &order, &mut ctx).and_then(|()| {
+        ctx.print_info(&message("group.up", &[("group", &group)]));
+        wait_for_stop(&ctx, &state)
+    });
+    ctx.services.stop_all(&ctx);
+    state.clear();
+    result
+}
+
+/// Stop a group started by `rtask up` in another process
+pub fn down(app: &App, group: &str, matches: &ArgMatches) -> Result<(), RtaskError> {
+    if !app.config().groups.contains_key(group) {
+        return Err(ConfigError::GroupNotFound(group.to_string()).into());
+    }
+    let state = GroupState::new(&app.config_dir(), group);
+    let Some((pid, _)) = state.running_pid() else {
+        // Left behind by an `up` that was killed
+        state.clear();
+        return Err(ExecutionError::GroupNotUp(group.to_string()).into());
+    };
+
+    fs::write(&state.stop, "")?;
+    let deadline = Instant::now() + DOWN_TIMEOUT;
+    while state.pid.exists() {
+        if Instant::now() >= deadline {
+            return Err(ExecutionError::Environment(format!(
+                "Group '{}' (pid {}) did not stop in time",
+                group, pid
+            ))
+            .into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let ctx = app.build_context(matches, app.verbosity(matches))?;
+    ctx.print_info(&message("group.down", &[("group", &group)]));
+    Ok(())
+}
+
+/// Run each service task in order, labelling its output with its name
+fn start_services(app: &App, order: &[String], ctx: &mut Context) -> Result<(), RtaskError> {
+    let width = order.iter().map(String::len).max().unwrap_or_default();
+    for (index, name) in order.iter().enumerate() {
+        ctx.output_prefix = Some(output_prefix(name, index, width));
+        let task = app.prepare_task(name, &app.task_matches(name, &[])?)?;
+        task.execute(ctx)?;
+    }
+    ctx.output_prefix = None;
+    Ok(())
+}
+
+/// Wait until `down` asks the group to stop or every service has exited,
+/// failing if any service's last run failed
+fn wait_for_stop(ctx: &Context, state: &GroupState) -> Result<(), RtaskError> {
+    while ctx.services.running() {
+        if state.stop.exists() {
+            return Ok(());
+        }
+        signals::check_interrupted()?;
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let results = ctx.services.wait_all();
+    let total = results.len();
+    let failed: Vec<String> = results
+        .into_iter()
+        .filter(|(_, result)| result.is_err())
+        .map(|(name, _)| name)
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(ExecutionError::TasksFailed { failed, total }.into())
+    }
+}
+
+/// Whether a process with this pid exists, if that can be told
+fn process_alive(pid: u32) -> Option<bool> {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return Some(false);
+        };
+        if pid <= 0 {
+            return Some(false);
+        }
+        // Signal 0 only checks for the process; one of another user's
+        // processes is reported as not permitted
+        let found = unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+        Some(found)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_state_paths_and_stale_pid() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = GroupState::new(temp_dir.path(), "dev");
+        assert!(state.pid.ends_with(".rtask/groups/dev.pid"));
+        assert!(state.stop.ends_with(".rtask/groups/dev.stop"));
+        assert_eq!(state.running_pid(), None);
+
+        fs::create_dir_all(state.pid.parent().unwrap()).unwrap();
+        fs::write(&state.pid, process::id().to_string()).unwrap();
+        assert_eq!(state.running_pid(), Some((process::id(), cfg!(unix))));
+
+        #[cfg(unix)]
+        {
+            let mut exited = process::Command::new("true").spawn().unwrap();
+            exited.wait().unwrap();
+            fs::write(&state.pid, exited.id().to_string()).unwrap();
+            assert_eq!(state.running_pid(), None);
+        }
+
+        state.clear();
+        assert!(!state.pid.exists());
+    }
+}
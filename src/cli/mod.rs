@@ -4,9 +4,20 @@
 //! and shell completion.
 
 pub mod app;
-
-// Module declarations (to be implemented in later phases)
-// pub mod completion;
+pub mod bench;
+pub mod builtin;
+pub mod completion;
+pub mod edit;
+pub mod graph;
+pub mod group;
+pub mod report;
+pub mod scaffold;
+#[cfg(feature = "self-update")]
+pub mod self_update;
+pub mod stats;
+pub mod test_runner;
+pub mod watch;
 
 // Re-export main types
 pub use app::*;
+pub use report::{report_error, ErrorFormat};
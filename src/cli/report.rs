@@ -0,0 +1,404 @@
+//! Error reporting
+//!
+//! Errors are printed as a single `Error: ...` line by default, or as a JSON
+//! object with `--error-format json` so editors and CI can annotate failures.
+//! Both use the message catalog's language; the JSON carries the message ID
+//! for tools that should not depend on it.
+
+use crate::config::{find_config_file, task_line_numbers, When};
+use crate::error::{ConfigError, RtaskError, TaskFailure};
+use crate::ui::{message, CatalogMessage, Diagnostic};
+use crate::utils::suggest::closest;
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How errors are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    /// Accepted spellings, for help text and completion
+    pub const NAMES: &'static [&'static str] = &["text", "json"];
+
+    /// Find `--error-format` in raw arguments (errors can happen before clap runs)
+    pub fn from_args(args: &[String]) -> Self {
+        args.iter()
+            .enumerate()
+            .find_map(|(i, arg)| match arg.strip_prefix("--error-format") {
+                Some("") => args.get(i + 1).map(String::as_str),
+                Some(rest) => rest.strip_prefix('='),
+                None => None,
+            })
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            other => Err(format!("invalid error format '{}'", other)),
+        }
+    }
+}
+
+/// Machine-readable description of an error
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// Error category: config, yaml, execution, interpolation or io
+    pub kind: &'static str,
+    /// Stable catalog ID of the message, e.g. `config.task-not-found`
+    pub id: &'static str,
+    pub message: String,
+    pub task: Option<String>,
+    pub command: Option<String>,
+    pub exit_code: i32,
+    pub location: Option<Location>,
+}
+
+/// Position in a config file
+#[derive(Debug, Serialize)]
+pub struct Location {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl ErrorReport {
+    /// Describe `err`; `config_path` is used for errors that do not carry a file
+    pub fn new(err: &RtaskError, config_path: Option<&Path>) -> Self {
+        let kind = match err {
+            RtaskError::Config(_) => "config",
+            RtaskError::Yaml(_) => "yaml",
+            RtaskError::Execution(_) | RtaskError::Task(_) => "execution",
+            RtaskError::Interpolation(_) => "interpolation",
+            RtaskError::Io(_) => "io",
+        };
+
+        let mut report = ErrorReport {
+            kind,
+            id: err.message_id(),
+            message: err.localized(),
+            task: None,
+            command: None,
+            exit_code: err.exit_code(),
+            location: None,
+        };
+
+        match err {
+            RtaskError::Task(failure) => {
+                let TaskFailure {
+                    task,
+                    command,
+                    config_path,
+                    line,
+                    ..
+                } = failure.as_ref();
+                report.task = Some(task.clone());
+                report.command = command.clone();
+                report.location = Some(Location {
+                    file: config_path.clone(),
+                    line: *line,
+                    column: None,
+                });
+            }
+            RtaskError::Yaml(yaml) => {
+                report.location = Some(Location {
+                    file: config_path.map(Path::to_path_buf),
+                    line: yaml.location().map(|l| l.line()),
+                    column: yaml.location().map(|l| l.column()),
+                });
+            }
+            RtaskError::Config(ConfigError::IncludeFile {
+                path,
+                location: Some(location),
+                ..
+            }) => {
+                report.location = Some(Location {
+                    file: Some(path.clone()),
+                    line: Some(location.line()),
+                    column: Some(location.column()),
+                });
+            }
+            RtaskError::Config(_) => {
+                report.location = config_path.map(|file| Location {
+                    file: Some(file.to_path_buf()),
+                    line: None,
+                    column: None,
+                });
+            }
+            _ => {}
+        }
+
+        report
+    }
+}
+
+/// Build a source diagnostic for config errors that can be located in `source`
+///
+/// `source` is the file the error points into: see [`located_file`].
+pub fn diagnose(err: &RtaskError, file: &Path, source: &str) -> Option<Diagnostic> {
+    let file = file.display().to_string();
+    match err {
+        RtaskError::Yaml(yaml) => {
+            let message = yaml_message(&yaml.to_string());
+            Some(yaml_diagnostic(message, &yaml.location()?, file, source))
+        }
+        RtaskError::Config(ConfigError::IncludeFile {
+            location: Some(location),
+            ..
+        }) => {
+            let message = yaml_message(&err.localized());
+            Some(yaml_diagnostic(message, location, file, source))
+        }
+        RtaskError::Config(ConfigError::UnknownWhenKey {
+            task,
+            key,
+            expected,
+        }) => {
+            let (line, column) = find_key(source, task, key)?;
+            let hint = match closest(key, When::KEYS.iter().copied()) {
+                Some(suggestion) => format!("did you mean `{}`?", suggestion),
+                None => format!("expected one of: {}", expected),
+            };
+            Some(Diagnostic {
+                message: err.localized(),
+                file,
+                line,
+                column,
+                len: key.chars().count(),
+                hint: Some(hint),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The file a located error points into, when it is not the main config
+pub fn located_file(err: &RtaskError) -> Option<&Path> {
+    match err {
+        RtaskError::Config(ConfigError::IncludeFile {
+            path,
+            location: Some(_),
+            ..
+        }) => Some(path),
+        _ => None,
+    }
+}
+
+/// Point at the token serde_yaml reported `message` at
+fn yaml_diagnostic(
+    message: String,
+    location: &serde_yaml::Location,
+    file: String,
+    source: &str,
+) -> Diagnostic {
+    let len = source
+        .lines()
+        .nth(location.line().saturating_sub(1))
+        .map_or(1, |text| token_len(text, location.column()));
+    Diagnostic {
+        hint: unknown_name_hint(&message),
+        message,
+        file,
+        line: location.line(),
+        column: location.column(),
+        len,
+    }
+}
+
+/// Strip serde_yaml's trailing "at line X column Y", which the snippet shows
+fn yaml_message(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Suggest a fix for serde's "unknown field `x`, expected one of `a`, `b`"
+fn unknown_name_hint(message: &str) -> Option<String> {
+    let rest = message
+        .split_once("unknown field `")
+        .or_else(|| message.split_once("unknown variant `"))?
+        .1;
+    let (name, expected) = rest.split_once('`')?;
+    let candidates: Vec<&str> = expected.split('`').skip(1).step_by(2).collect();
+    closest(name, candidates).map(|suggestion| format!("did you mean `{}`?", suggestion))
+}
+
+/// Length of the token starting at 1-based `column`
+fn token_len(line: &str, column: usize) -> usize {
+    line.chars()
+        .skip(column.saturating_sub(1))
+        .take_while(|c| !c.is_whitespace() && *c != ':' && *c != ',')
+        .count()
+}
+
+/// Find `key:` within the definition of `task`, returning 1-based line and column
+fn find_key(source: &str, task: &str, key: &str) -> Option<(usize, usize)> {
+    let start = *task_line_numbers(source).get(task)?;
+    let pattern = format!("{}:", key);
+    source
+        .lines()
+        .enumerate()
+        .skip(start)
+        .find_map(|(index, line)| {
+            let column = line.find(&pattern)?;
+            let before = &line[..column];
+            before
+                .chars()
+                .all(|c| c.is_whitespace() || c == '-' || c == '{' || c == ',')
+                .then_some((index + 1, column + 1))
+        })
+}
+
+/// Print `err` to stderr in the format requested on the command line
+pub fn report_error(err: &RtaskError) {
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = || super::app::extract_file_arg(&args).or_else(|| find_config_file().ok());
+
+    match ErrorFormat::from_args(&args) {
+        ErrorFormat::Text => {
+            let file = located_file(err).map(Path::to_path_buf);
+            let rendered = file.or_else(config_path).and_then(|path| {
+                let source = fs::read_to_string(&path).ok()?;
+                diagnose(err, &path, &source).map(|d| d.render(&source))
+            });
+            match rendered {
+                Some(rendered) => eprint!("{}", rendered),
+                None => eprintln!(
+                    "{} {}",
+                    message("error.label", &[]).red().bold(),
+                    err.localized()
+                ),
+            }
+        }
+        ErrorFormat::Json => {
+            let config_path = config_path();
+            let report = ErrorReport::new(err, config_path.as_deref());
+            match serde_json::to_string(&report) {
+                Ok(json) => eprintln!("{}", json),
+                Err(_) => eprintln!("{} {}", message("error.label", &[]), err.localized()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use crate::error::ExecutionError;
+
+    fn args(words: &str) -> Vec<String> {
+        words.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_error_format_from_args() {
+        assert_eq!(
+            ErrorFormat::from_args(&args("rtask build")),
+            ErrorFormat::Text
+        );
+        assert_eq!(
+            ErrorFormat::from_args(&args("rtask --error-format json build")),
+            ErrorFormat::Json
+        );
+        assert_eq!(
+            ErrorFormat::from_args(&args("rtask build --error-format=json")),
+            ErrorFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_task_failure_report() {
+        let err = RtaskError::Task(Box::new(TaskFailure {
+            task: "build".to_string(),
+            command: Some("make all".to_string()),
+            config_path: Some(PathBuf::from("rtask.yml")),
+            line: Some(4),
+            error: ExecutionError::CommandFailed(Some(2)),
+        }));
+
+        let json = serde_json::to_value(ErrorReport::new(&err, None)).unwrap();
+        assert_eq!(json["kind"], "execution");
+        assert_eq!(json["id"], "execution.command-failed");
+        assert_eq!(json["task"], "build");
+        assert_eq!(json["command"], "make all");
+        assert_eq!(json["exit_code"], 2);
+        assert_eq!(json["location"]["file"], "rtask.yml");
+        assert_eq!(json["location"]["line"], 4);
+    }
+
+    #[test]
+    fn test_diagnose_unknown_when_key() {
+        let source = "tasks:\n  build:\n    run:\n      - command: make\n        when:\n          - not_equal: {left: a, right: b}\n";
+        let config = parse_config(source, None).unwrap();
+        let err: RtaskError = crate::config::validate_config(&config).unwrap_err().into();
+
+        let diagnostic = diagnose(&err, Path::new("rtask.yml"), source).unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (6, 13));
+        assert_eq!(diagnostic.len, 9);
+        assert_eq!(
+            diagnostic.hint.as_deref(),
+            Some("did you mean `not-equal`?")
+        );
+    }
+
+    #[test]
+    fn test_diagnose_yaml_error() {
+        let source = "tasks:\n  build:\n    usage: [x]\n";
+        let err = parse_config(source, None).unwrap_err();
+
+        let diagnostic = diagnose(&err, Path::new("rtask.yml"), source).unwrap();
+        assert!(!diagnostic.message.contains(" at line "));
+        assert_eq!(diagnostic.line, 3);
+    }
+
+    #[test]
+    fn test_diagnose_error_in_included_file() {
+        let source = "tasks:\n  build:\n    usage: [x]\n";
+        let yaml = parse_config(source, None).unwrap_err();
+        let err: RtaskError = ConfigError::include_failed(Path::new("more.yml"), &yaml).into();
+
+        assert_eq!(located_file(&err), Some(Path::new("more.yml")));
+        let diagnostic = diagnose(&err, Path::new("more.yml"), source).unwrap();
+        assert!(diagnostic.message.contains("more.yml"));
+        assert!(!diagnostic.message.contains(" at line "));
+        assert_eq!(diagnostic.line, 3);
+
+        let json = serde_json::to_value(ErrorReport::new(&err, None)).unwrap();
+        assert_eq!(json["location"]["file"], "more.yml");
+        assert_eq!(json["location"]["line"], 3);
+    }
+
+    #[test]
+    fn test_unknown_name_hint() {
+        assert_eq!(
+            unknown_name_hint("unknown field `finaly`, expected one of `run`, `finally`")
+                .as_deref(),
+            Some("did you mean `finally`?")
+        );
+    }
+
+    #[test]
+    fn test_yaml_error_report_has_position() {
+        let err = parse_config("tasks:\n  a: [\n", None).unwrap_err();
+        let json =
+            serde_json::to_value(ErrorReport::new(&err, Some(Path::new("rtask.yml")))).unwrap();
+        assert_eq!(json["kind"], "yaml");
+        assert_eq!(json["location"]["file"], "rtask.yml");
+        assert!(json["location"]["line"].as_u64().is_some());
+    }
+}
@@ -0,0 +1,207 @@
+//! `add-task` subcommand
+//!
+//! Appends a task skeleton to an existing config file. The file is edited as
+//! text so that comments, key order and formatting are left untouched.
+
+use crate::config::parse_config;
+use crate::error::{ConfigError, RtaskError};
+use std::fs;
+use std::path::Path;
+
+/// Placeholder command for a freshly scaffolded task
+pub const PLACEHOLDER_RUN: &str = "echo \"TODO\"";
+
+/// Description of the task to scaffold
+#[derive(Debug, Clone, Default)]
+pub struct TaskSkeleton {
+    /// Task name
+    pub name: String,
+    /// Usage line
+    pub usage: Option<String>,
+    /// Command to run (defaults to a placeholder)
+    pub run: Option<String>,
+    /// Option names to declare
+    pub options: Vec<String>,
+}
+
+/// Append a task skeleton to the config file at `path`
+pub fn add_task(path: &Path, skeleton: &TaskSkeleton) -> Result<(), RtaskError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError::Invalid(format!("Failed to read file: {}", e)))?;
+
+    let updated = insert_task(&contents, skeleton)?;
+
+    // Make sure the result still parses before touching the file
+    parse_config(&updated, None)?;
+    fs::write(path, updated)?;
+
+    Ok(())
+}
+
+/// Insert a task skeleton into the `tasks:` mapping of a YAML document
+pub fn insert_task(yaml: &str, skeleton: &TaskSkeleton) -> Result<String, RtaskError> {
+    let existing = parse_config(yaml, None)?;
+    if existing.tasks.contains_key(&skeleton.name) {
+        return Err(ConfigError::DuplicateTask(skeleton.name.clone()).into());
+    }
+
+    let lines: Vec<&str> = yaml.lines().collect();
+    let tasks_line = lines.iter().position(|line| {
+        let line = line.trim_end();
+        line == "tasks:" || line == "tasks: {}"
+    });
+
+    let mut output: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+
+    let Some(tasks_line) = tasks_line else {
+        // No tasks mapping yet: start one at the end of the file
+        while output.last().is_some_and(|line| line.trim().is_empty()) {
+            output.pop();
+        }
+        if !output.is_empty() {
+            output.push(String::new());
+        }
+        output.push("tasks:".to_string());
+        output.extend(render_task(skeleton, 2));
+        return Ok(join_lines(output));
+    };
+
+    if output[tasks_line].trim_end() == "tasks: {}" {
+        output[tasks_line] = "tasks:".to_string();
+    }
+
+    // Find the indentation of existing tasks and the last line of the block
+    let mut indent = None;
+    let mut last = tasks_line;
+    for (index, line) in lines.iter().enumerate().skip(tasks_line + 1) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let line_indent = line.len() - trimmed.len();
+        if line_indent == 0 {
+            break;
+        }
+        if !trimmed.starts_with('#') {
+            indent.get_or_insert(line_indent);
+        }
+        last = index;
+    }
+
+    let mut block = Vec::new();
+    if last > tasks_line {
+        block.push(String::new());
+    }
+    block.extend(render_task(skeleton, indent.unwrap_or(2)));
+
+    output.splice(last + 1..last + 1, block);
+    Ok(join_lines(output))
+}
+
+/// Render the skeleton lines at the given task indentation
+fn render_task(skeleton: &TaskSkeleton, indent: usize) -> Vec<String> {
+    let pad = |level: usize| " ".repeat(indent * level);
+    let usage = skeleton
+        .usage
+        .clone()
+        .unwrap_or_else(|| format!("TODO: describe {}", skeleton.name));
+    let run = skeleton.run.as_deref().unwrap_or(PLACEHOLDER_RUN);
+
+    let mut lines = vec![
+        format!("{}{}:", pad(1), scalar(&skeleton.name)),
+        format!("{}usage: {}", pad(2), scalar(&usage)),
+    ];
+
+    if !skeleton.options.is_empty() {
+        lines.push(format!("{}options:", pad(2)));
+        for option in &skeleton.options {
+            lines.push(format!("{}{}:", pad(3), scalar(option)));
+            lines.push(format!(
+                "{}usage: {}",
+                pad(4),
+                scalar(&format!("TODO: describe {}", option))
+            ));
+        }
+    }
+
+    lines.push(format!("{}run: {}", pad(2), scalar(run)));
+    lines
+}
+
+/// Render a string as a YAML scalar, quoting only when needed
+fn scalar(value: &str) -> String {
+    serde_yaml::to_string(value)
+        .map(|s| s.trim_end().to_string())
+        .unwrap_or_else(|_| format!("{:?}", value))
+}
+
+fn join_lines(lines: Vec<String>) -> String {
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skeleton(name: &str) -> TaskSkeleton {
+        TaskSkeleton {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_insert_task_preserves_comments() {
+        let yaml = "# My tasks\nname: app\n\ntasks:\n  # Build it\n  build:\n    run: make\n\n# Trailing\ninterpreter: [bash, -c]\n";
+        let updated = insert_task(yaml, &skeleton("deploy")).unwrap();
+
+        assert!(updated.starts_with(
+            "# My tasks\nname: app\n\ntasks:\n  # Build it\n  build:\n    run: make\n\n  deploy:\n"
+        ));
+        assert!(updated.ends_with("\n\n# Trailing\ninterpreter: [bash, -c]\n"));
+
+        let config = parse_config(&updated, None).unwrap();
+        assert!(config.tasks.contains_key("deploy"));
+        assert_eq!(config.task_lines.get("deploy"), Some(&9));
+    }
+
+    #[test]
+    fn test_insert_task_with_options_and_indent() {
+        let yaml = "tasks:\n    build:\n        run: make\n";
+        let updated = insert_task(
+            yaml,
+            &TaskSkeleton {
+                name: "greet".to_string(),
+                usage: Some("Say hi".to_string()),
+                run: Some("echo ${name}".to_string()),
+                options: vec!["name".to_string()],
+            },
+        )
+        .unwrap();
+
+        assert!(updated.contains(
+            "\n    greet:\n        usage: Say hi\n        options:\n            name:\n"
+        ));
+        let config = parse_config(&updated, None).unwrap();
+        let task = &config.tasks["greet"];
+        assert!(task.options.contains_key("name"));
+    }
+
+    #[test]
+    fn test_insert_task_without_tasks_key() {
+        let updated = insert_task("name: app\n", &skeleton("hello")).unwrap();
+        assert_eq!(
+            updated,
+            "name: app\n\ntasks:\n  hello:\n    usage: 'TODO: describe hello'\n    run: echo \"TODO\"\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_task_duplicate() {
+        let yaml = "tasks:\n  build:\n    run: make\n";
+        let err = insert_task(yaml, &skeleton("build")).unwrap_err();
+        assert!(err.to_string().contains("already defined"));
+    }
+}
@@ -0,0 +1,232 @@
+//! `self-update` subcommand
+//!
+//! Replaces the running binary with the latest GitHub release for this
+//! platform. Only built with the `self-update` feature.
+
+use crate::error::{ExecutionError, RtaskError};
+use crate::ui::message;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// GitHub repository that publishes release binaries
+pub const RELEASE_REPO: &str = "wensheng/rtask";
+
+/// A GitHub release, as returned by the releases API
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+/// A downloadable file attached to a release
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+/// Check for a newer release and install it over the current executable
+pub fn self_update(check_only: bool) -> Result<(), RtaskError> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = get(&format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        RELEASE_REPO
+    ))?
+    .into_json()
+    .map_err(|e| update_error(format!("Invalid release metadata: {}", e)))?;
+
+    if !is_newer(&release.tag_name, current)? {
+        println!("{}", message("update.up-to-date", &[("version", &current)]));
+        return Ok(());
+    }
+
+    let latest = release.tag_name.trim_start_matches('v');
+    if check_only {
+        println!(
+            "{}",
+            message(
+                "update.available",
+                &[("latest", &latest), ("current", &current)]
+            )
+        );
+        return Ok(());
+    }
+
+    let asset_name = platform_asset_name();
+    let asset = find_asset(&release, &asset_name)
+        .ok_or_else(|| update_error(format!("No release binary named '{}'", asset_name)))?;
+    let checksum_asset = find_checksum_asset(&release, &asset_name)
+        .ok_or_else(|| update_error(format!("No checksum published for '{}'", asset_name)))?;
+
+    println!("{}", message("update.downloading", &[("version", &latest)]));
+    let binary = download(&asset.browser_download_url)?;
+    let checksums =
+        String::from_utf8_lossy(&download(&checksum_asset.browser_download_url)?).into_owned();
+
+    let expected = expected_checksum(&checksums, &asset_name)
+        .ok_or_else(|| update_error(format!("Checksum for '{}' not found", asset_name)))?;
+    let actual = sha256_hex(&binary);
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(update_error(format!(
+            "Checksum mismatch for '{}': expected {}, got {}",
+            asset_name, expected, actual
+        )));
+    }
+
+    let exe = env::current_exe()?;
+    replace_executable(&exe, &binary)?;
+    println!(
+        "{}",
+        message(
+            "update.updated",
+            &[("current", &current), ("latest", &latest)]
+        )
+    );
+
+    Ok(())
+}
+
+/// Name of the release asset for the running platform
+pub fn platform_asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!("rtask-{}-{}{}", env::consts::ARCH, env::consts::OS, suffix)
+}
+
+/// Compare a release tag (e.g. "v0.2.0") with the current version
+pub fn is_newer(tag: &str, current: &str) -> Result<bool, RtaskError> {
+    let parse = |v: &str| {
+        semver::Version::parse(v.trim_start_matches('v'))
+            .map_err(|e| update_error(format!("Invalid version '{}': {}", v, e)))
+    };
+    Ok(parse(tag)? > parse(current)?)
+}
+
+/// Look up the checksum for `asset_name` in `sha256sum`-style output
+///
+/// Accepts both a bare digest (single-file `.sha256`) and `<digest>  <name>` lines.
+pub fn expected_checksum(checksums: &str, asset_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let digest = parts.next()?;
+        match parts.next() {
+            Some(name) if name.trim_start_matches('*') == asset_name => Some(digest.to_string()),
+            None if checksums.trim().lines().count() == 1 => Some(digest.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Hex-encoded SHA-256 digest of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn find_asset<'a>(release: &'a Release, name: &str) -> Option<&'a Asset> {
+    release.assets.iter().find(|asset| asset.name == name)
+}
+
+fn find_checksum_asset<'a>(release: &'a Release, name: &str) -> Option<&'a Asset> {
+    find_asset(release, &format!("{}.sha256", name))
+        .or_else(|| find_asset(release, "SHA256SUMS"))
+        .or_else(|| find_asset(release, "sha256sums.txt"))
+}
+
+fn get(url: &str) -> Result<ureq::Response, RtaskError> {
+    ureq::get(url)
+        .set("User-Agent", concat!("rtask/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|e| update_error(format!("Request to {} failed: {}", url, e)))
+}
+
+fn download(url: &str) -> Result<Vec<u8>, RtaskError> {
+    let mut data = Vec::new();
+    get(url)?.into_reader().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Write the new binary next to the current one, then swap it into place
+fn replace_executable(exe: &Path, binary: &[u8]) -> Result<(), RtaskError> {
+    let staged = sibling(exe, "new");
+    fs::write(&staged, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Windows cannot overwrite a running executable, but it can rename it
+    if cfg!(windows) {
+        let old = sibling(exe, "old");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+
+    fs::rename(&staged, exe)?;
+    Ok(())
+}
+
+fn sibling(exe: &Path, suffix: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    exe.with_file_name(name)
+}
+
+fn update_error(message: String) -> RtaskError {
+    ExecutionError::Environment(format!("self-update: {}", message)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v0.2.0", "0.1.0").unwrap());
+        assert!(!is_newer("v0.1.0", "0.1.0").unwrap());
+        assert!(!is_newer("0.0.9", "0.1.0").unwrap());
+        assert!(is_newer("garbage", "0.1.0").is_err());
+    }
+
+    #[test]
+    fn test_expected_checksum() {
+        let sums = "abc123  rtask-x86_64-linux\ndef456 *rtask-aarch64-macos\n";
+        assert_eq!(
+            expected_checksum(sums, "rtask-aarch64-macos").as_deref(),
+            Some("def456")
+        );
+        assert_eq!(expected_checksum(sums, "rtask-x86_64-windows.exe"), None);
+        assert_eq!(
+            expected_checksum("abc123\n", "anything").as_deref(),
+            Some("abc123")
+        );
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_replace_executable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let exe = dir.path().join("rtask");
+        fs::write(&exe, b"old").unwrap();
+
+        replace_executable(&exe, b"new").unwrap();
+
+        assert_eq!(fs::read(&exe).unwrap(), b"new");
+        assert!(!sibling(&exe, "new").exists());
+    }
+}
@@ -0,0 +1,164 @@
+//! `stats` subcommand
+//!
+//! Aggregates the history ledger into per-task statistics.
+
+use crate::runner::HistoryEntry;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Aggregated statistics for one task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskStats {
+    pub task: String,
+    pub runs: usize,
+    pub failures: usize,
+    /// Fraction of runs that failed (0.0 - 1.0)
+    pub failure_rate: f64,
+    pub mean_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Compute per-task statistics, sorted by task name
+pub fn compute_stats(entries: &[HistoryEntry]) -> Vec<TaskStats> {
+    let mut by_task: BTreeMap<&str, Vec<&HistoryEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_task.entry(&entry.task).or_default().push(entry);
+    }
+
+    by_task
+        .into_iter()
+        .map(|(task, runs)| {
+            let mut durations: Vec<u64> = runs.iter().map(|e| e.duration_ms).collect();
+            durations.sort_unstable();
+            let failures = runs.iter().filter(|e| !e.success).count();
+            let total: u64 = durations.iter().sum();
+
+            TaskStats {
+                task: task.to_string(),
+                runs: runs.len(),
+                failures,
+                failure_rate: failures as f64 / runs.len() as f64,
+                mean_ms: total / runs.len() as u64,
+                p50_ms: percentile(&durations, 50),
+                p90_ms: percentile(&durations, 90),
+                p99_ms: percentile(&durations, 99),
+                max_ms: durations.last().copied().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Render statistics as an aligned table
+pub fn render_table(stats: &[TaskStats]) -> String {
+    let header = ["TASK", "RUNS", "FAIL%", "MEAN", "P50", "P90", "P99", "MAX"];
+    let rows: Vec<[String; 8]> = stats
+        .iter()
+        .map(|s| {
+            [
+                s.task.clone(),
+                s.runs.to_string(),
+                format!("{:.1}", s.failure_rate * 100.0),
+                format_ms(s.mean_ms),
+                format_ms(s.p50_ms),
+                format_ms(s.p90_ms),
+                format_ms(s.p99_ms),
+                format_ms(s.max_ms),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                if i == 0 {
+                    format!("{:<width$}", cell, width = width)
+                } else {
+                    format!("{:>width$}", cell, width = width)
+                }
+            })
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    };
+
+    push_row(header.to_vec());
+    for row in &rows {
+        push_row(row.iter().map(String::as_str).collect());
+    }
+    out
+}
+
+fn format_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(task: &str, duration_ms: u64, success: bool) -> HistoryEntry {
+        HistoryEntry {
+            task: task.to_string(),
+            started_at: String::new(),
+            duration_ms,
+            success,
+            exit_code: None,
+        }
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let mut entries: Vec<HistoryEntry> =
+            (1..=10).map(|i| entry("build", i * 100, i != 3)).collect();
+        entries.push(entry("lint", 50, true));
+
+        let stats = compute_stats(&entries);
+        assert_eq!(stats.len(), 2);
+
+        let build = &stats[0];
+        assert_eq!(build.task, "build");
+        assert_eq!(build.runs, 10);
+        assert_eq!(build.failures, 1);
+        assert!((build.failure_rate - 0.1).abs() < f64::EPSILON);
+        assert_eq!(build.mean_ms, 550);
+        assert_eq!(build.p50_ms, 500);
+        assert_eq!(build.p90_ms, 900);
+        assert_eq!(build.p99_ms, 1000);
+        assert_eq!(stats[1].task, "lint");
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(&compute_stats(&[entry("build", 1500, true)]));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "TASK   RUNS  FAIL%  MEAN   P50   P90   P99   MAX");
+        assert_eq!(lines[1], "build     1    0.0  1.5s  1.5s  1.5s  1.5s  1.5s");
+    }
+}
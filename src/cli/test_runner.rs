@@ -0,0 +1,130 @@
+//! `test` subcommand
+//!
+//! Runs the `tests:` declared in the config. Each test invokes rtask itself in
+//! a subprocess, so exit codes and stdout are checked exactly as a user of the
+//! task would see them.
+
+use crate::config::{TaskTest, TestExpectation};
+use crate::runner::exit_code;
+use regex::RegexBuilder;
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Result of running one test
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    /// One message per unmet expectation; empty when the test passed
+    pub failures: Vec<String>,
+    /// Captured stderr of the run, shown when the test fails
+    pub stderr: String,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Run a test against the config at `config_path`
+pub fn run_test(config_path: &Path, name: &str, test: &TaskTest) -> io::Result<TestOutcome> {
+    let output = Command::new(env::current_exe()?)
+        .arg("--file")
+        .arg(config_path)
+        .args(["--color", "never", &test.task])
+        .args(&test.args)
+        .output()?;
+
+    let base = config_path.parent().unwrap_or(Path::new(""));
+    Ok(TestOutcome {
+        name: name.to_string(),
+        failures: check_expectations(
+            &test.expect,
+            exit_code(&output.status),
+            &String::from_utf8_lossy(&output.stdout),
+            base,
+        ),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+/// Compare a finished run with what the test expects
+pub fn check_expectations(
+    expect: &TestExpectation,
+    code: Option<i32>,
+    stdout: &str,
+    base: &Path,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    let expected_code = expect.exit_code.unwrap_or(0);
+    if code != Some(expected_code) {
+        failures.push(match code {
+            Some(code) => format!("expected exit code {}, got {}", expected_code, code),
+            None => format!(
+                "expected exit code {}, but the run was killed",
+                expected_code
+            ),
+        });
+    }
+
+    if let Some(needle) = &expect.stdout_contains {
+        if !stdout.contains(needle.as_str()) {
+            failures.push(format!("stdout does not contain {:?}", needle));
+        }
+    }
+
+    if let Some(pattern) = &expect.stdout_matches {
+        match RegexBuilder::new(pattern).multi_line(true).build() {
+            Ok(re) if re.is_match(stdout) => {}
+            Ok(_) => failures.push(format!("stdout does not match /{}/", pattern)),
+            Err(e) => failures.push(format!("invalid pattern /{}/: {}", pattern, e)),
+        }
+    }
+
+    for file in &expect.files_exist {
+        if !base.join(file).exists() {
+            failures.push(format!("file '{}' does not exist", file));
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_expectations() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("out.txt"), "").unwrap();
+
+        let expect = TestExpectation {
+            exit_code: None,
+            stdout_contains: Some("Hello".to_string()),
+            stdout_matches: Some(r"^Hello, \w+!$".to_string()),
+            files_exist: vec!["out.txt".to_string()],
+        };
+        assert!(check_expectations(&expect, Some(0), "Hello, World!\n", dir.path()).is_empty());
+
+        let expect = TestExpectation {
+            exit_code: Some(2),
+            stdout_contains: Some("Bye".to_string()),
+            stdout_matches: Some("^Bye".to_string()),
+            files_exist: vec!["missing.txt".to_string()],
+        };
+        assert_eq!(
+            check_expectations(&expect, Some(0), "Hello", dir.path()),
+            vec![
+                "expected exit code 2, got 0",
+                "stdout does not contain \"Bye\"",
+                "stdout does not match /^Bye/",
+                "file 'missing.txt' does not exist",
+            ]
+        );
+    }
+}
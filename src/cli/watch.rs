@@ -0,0 +1,239 @@
+//! `watch` subcommand
+//!
+//! Runs a task, then runs it again whenever one of its watched files changes,
+//! until interrupted. The files come from the task's `watch` block, falling
+//! back to its `source` patterns. `rtask --watch <task>` and tasks with
+//! `watch: true` watch the same way.
+//!
+//! Each run is a separate `rtask <task>` process with the same global flags,
+//! so a run can be stopped part-way when the task's `in-flight` policy is
+//! `restart`. Changes are only acted on once the files have been quiet for
+//! the debounce interval, so saving many files at once triggers one run.
+
+use crate::cli::{split_at_subcommand, App};
+use crate::config::InFlightPolicy;
+use crate::error::{ConfigError, RtaskError};
+use crate::runner::{signals, stop_process, terminate, STATE_DIR};
+use crate::ui::{catalog, message, Catalog};
+use crate::utils::duration::parse_duration;
+use crate::utils::watch::{gitignore_patterns, FileWatcher};
+use clap::ArgMatches;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command as StdCommand};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the watched files are scanned
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Default quiet period before a change triggers a run
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Run `task_name` with `args` each time its watched files change
+///
+/// Runs get the global options given before the subcommand of
+/// `invocation`, the command line `matches` were parsed from.
+pub fn watch(
+    app: &App,
+    task_name: &str,
+    args: &[String],
+    invocation: &[String],
+    matches: &ArgMatches,
+) -> Result<(), RtaskError> {
+    let config = app
+        .config()
+        .tasks
+        .get(task_name)
+        .ok_or_else(|| ConfigError::TaskNotFound(task_name.to_string()))?;
+    if config.private && !matches.get_flag("include-private") {
+        return Err(ConfigError::PrivateTask(task_name.to_string()).into());
+    }
+    let mut settings = config.watch.clone().unwrap_or_default();
+    if settings.paths.is_empty() {
+        settings.paths = config.source.clone();
+    }
+    if settings.paths.is_empty() {
+        return Err(ConfigError::Invalid(format!(
+            "Task '{}' has nothing to watch (add watch paths or source files)",
+            task_name
+        ))
+        .into());
+    }
+
+    let root = app.config_dir();
+    let mut exclude = settings.exclude.clone();
+    exclude.push(format!("{}/**", STATE_DIR));
+    if settings.gitignore.unwrap_or(true) {
+        if let Ok(contents) = fs::read_to_string(root.join(".gitignore")) {
+            exclude.extend(gitignore_patterns(&contents));
+        }
+    }
+    let mut watcher =
+        FileWatcher::new(&root, &settings.paths, &exclude).map_err(ConfigError::Invalid)?;
+    let debounce = match &settings.debounce {
+        Some(debounce) => parse_duration(debounce).map_err(ConfigError::Invalid)?,
+        None => DEFAULT_DEBOUNCE,
+    };
+    let policy = settings.in_flight.unwrap_or_default();
+
+    let ctx = app.build_context(matches, app.verbosity(matches))?;
+    let (globals, _) = split_at_subcommand(invocation);
+    let argv = run_argv(globals, task_name, args);
+    let watching = message("watch.watching", &[("paths", &settings.paths.join(", "))]);
+
+    let mut run = Some(start_run(&argv)?);
+    let mut queued = false;
+    let mut changed: Vec<PathBuf> = Vec::new();
+    let mut last_change = Instant::now();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        // Each run gets the signal too, and runs its finally items
+        if let Err(e) = signals::check_interrupted() {
+            if let Some(child) = &mut run {
+                let signal = e.interrupted_by().unwrap_or(signals::SIGINT);
+                stop_process(child, false, signal);
+            }
+            return Err(e.into());
+        }
+
+        if let Some(child) = &mut run {
+            if !matches!(child.try_wait(), Ok(None)) {
+                run = None;
+                if queued {
+                    queued = false;
+                    run = Some(start_run(&argv)?);
+                } else {
+                    ctx.print_info(&watching);
+                }
+            }
+        }
+
+        let new_changes = watcher.changes();
+        if !new_changes.is_empty() {
+            changed.extend(new_changes);
+            last_change = Instant::now();
+            continue;
+        }
+        if changed.is_empty() || last_change.elapsed() < debounce {
+            continue;
+        }
+
+        changed.sort();
+        changed.dedup();
+        let summary = describe(catalog(), &std::mem::take(&mut changed));
+        match (&mut run, policy) {
+            (None, _) => {
+                ctx.print_info(&message(
+                    "watch.rerun",
+                    &[("changes", &summary), ("task", &task_name)],
+                ));
+                run = Some(start_run(&argv)?);
+            }
+            (Some(_), InFlightPolicy::Queue) => {
+                ctx.print_info(&message("watch.queued", &[("changes", &summary)]));
+                queued = true;
+            }
+            (Some(child), InFlightPolicy::Restart) => {
+                ctx.print_info(&message(
+                    "watch.restart",
+                    &[("changes", &summary), ("task", &task_name)],
+                ));
+                terminate(child, false);
+                run = Some(start_run(&argv)?);
+            }
+            (Some(_), InFlightPolicy::Skip) => {
+                ctx.print_debug(&format!("{}; ignored while the task runs", summary));
+            }
+        }
+    }
+}
+
+/// Watch a task invoked as `rtask --watch <task>`, or one with `watch: true`
+///
+/// The task's args are the words after its name in `invocation`, the
+/// command line `matches` were parsed from.
+pub fn watch_task(
+    app: &App,
+    task_name: &str,
+    invocation: &[String],
+    matches: &ArgMatches,
+) -> Result<(), RtaskError> {
+    let (_, args) = split_at_subcommand(invocation);
+    watch(app, task_name, args, invocation, matches)
+}
+
+/// Arguments for one run: the global flags the watch was started with,
+/// followed by the task and its args
+///
+/// Runs get `--no-watch` so that a task with `watch: true` runs once.
+fn run_argv(globals: &[String], task_name: &str, args: &[String]) -> Vec<String> {
+    globals
+        .iter()
+        .filter(|arg| *arg != "--watch")
+        .cloned()
+        .chain(["--no-watch".to_string(), task_name.to_string()])
+        .chain(args.iter().cloned())
+        .collect()
+}
+
+/// Start one run of the task as a child `rtask` process
+fn start_run(argv: &[String]) -> Result<Child, RtaskError> {
+    Ok(StdCommand::new(env::current_exe()?).args(argv).spawn()?)
+}
+
+/// Summary of changed files for the status line, in the language of
+/// `catalog`
+fn describe(catalog: &Catalog, changed: &[PathBuf]) -> String {
+    match changed {
+        [] => catalog.format("watch.nothing-changed", &[]),
+        [path] => catalog.format("watch.changed", &[("path", &path.display())]),
+        [path, rest @ ..] => catalog.format(
+            "watch.changed-more",
+            &[("path", &path.display()), ("count", &rest.len())],
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_changes() {
+        let paths = [PathBuf::from("src/main.rs"), PathBuf::from("src/lib.rs")];
+        let english = Catalog::for_locale("en");
+        assert_eq!(describe(&english, &paths[..1]), "Changed: src/main.rs");
+        assert_eq!(
+            describe(&english, &paths),
+            "Changed: src/main.rs and 1 more"
+        );
+    }
+
+    #[test]
+    fn test_run_argv_keeps_global_flags() {
+        let globals: Vec<String> = ["--file", "ci.yml", "-q"].map(String::from).to_vec();
+        assert_eq!(
+            run_argv(&globals, "build", &["--release".to_string()]),
+            ["--file", "ci.yml", "-q", "--no-watch", "build", "--release"]
+        );
+
+        let globals: Vec<String> = ["--watch", "-q"].map(String::from).to_vec();
+        assert_eq!(
+            run_argv(&globals, "build", &["--release".to_string()]),
+            ["-q", "--no-watch", "build", "--release"]
+        );
+
+        // A global option's value may be the subcommand's name
+        let invocation: Vec<String> = ["rtask", "--log-file", "watch", "watch", "build"]
+            .map(String::from)
+            .to_vec();
+        let (globals, _) = split_at_subcommand(&invocation);
+        assert_eq!(
+            run_argv(globals, "build", &[]),
+            ["--log-file", "watch", "--no-watch", "build"]
+        );
+    }
+}
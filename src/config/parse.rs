@@ -1,9 +1,10 @@
 //! Configuration file parsing and discovery
 
-use crate::config::types::{Config, Task};
+use crate::config::types::{Config, Run, SubTask, Task};
 use crate::error::{ConfigError, ConfigResult, RtaskError};
+use crate::utils::fs::{FileSystem, OsFs};
+use std::collections::HashMap;
 use std::env;
-use std::fs;
 use std::path::{Path, PathBuf};
 
 /// Default configuration file names to search for
@@ -18,6 +19,11 @@ pub fn find_config_file() -> ConfigResult<PathBuf> {
 
 /// Find the configuration file starting from a specific directory
 pub fn find_config_file_from(start_dir: PathBuf) -> ConfigResult<PathBuf> {
+    find_config_file_with(&OsFs, start_dir)
+}
+
+/// Find the configuration file starting from a directory of the given file system
+pub fn find_config_file_with(fs: &dyn FileSystem, start_dir: PathBuf) -> ConfigResult<PathBuf> {
     let mut current_dir = start_dir.clone();
     let mut searched_paths = Vec::new();
 
@@ -26,7 +32,7 @@ pub fn find_config_file_from(start_dir: PathBuf) -> ConfigResult<PathBuf> {
             let config_path = current_dir.join(file_name);
             searched_paths.push(config_path.display().to_string());
 
-            if config_path.exists() && config_path.is_file() {
+            if fs.is_file(&config_path) {
                 return Ok(config_path);
             }
         }
@@ -44,26 +50,163 @@ pub fn find_config_file_from(start_dir: PathBuf) -> ConfigResult<PathBuf> {
 
 /// Parse a configuration file from a path
 pub fn parse_config_file(path: &Path) -> Result<Config, RtaskError> {
-    let contents = fs::read_to_string(path)
+    parse_config_file_with(&OsFs, path)
+}
+
+/// Parse a configuration file from a path on the given file system
+pub fn parse_config_file_with(fs: &dyn FileSystem, path: &Path) -> Result<Config, RtaskError> {
+    let contents = fs
+        .read_to_string(path)
         .map_err(|e| ConfigError::Invalid(format!("Failed to read file: {}", e)))?;
 
-    parse_config(&contents, Some(path))
+    parse_config_with(fs, &contents, Some(path))
 }
 
 /// Parse configuration from a string
 pub fn parse_config(yaml: &str, config_path: Option<&Path>) -> Result<Config, RtaskError> {
+    parse_config_with(&OsFs, yaml, config_path)
+}
+
+/// Parse configuration from a string, loading includes from the given file system
+pub fn parse_config_with(
+    fs: &dyn FileSystem,
+    yaml: &str,
+    config_path: Option<&Path>,
+) -> Result<Config, RtaskError> {
+    parse_config_nested(fs, yaml, config_path, &mut Vec::new())
+}
+
+/// Parse configuration, tracking the chain of files being included
+fn parse_config_nested(
+    fs: &dyn FileSystem,
+    yaml: &str,
+    config_path: Option<&Path>,
+    including: &mut Vec<PathBuf>,
+) -> Result<Config, RtaskError> {
     let mut config: Config = serde_yaml::from_str(yaml)?;
+    config.task_lines = task_line_numbers(yaml);
 
     // Process includes if present
     if let Some(base_path) = config_path {
-        process_includes(&mut config, base_path)?;
+        process_includes(fs, &mut config, base_path)?;
+        including.push(base_path.to_path_buf());
+        let merged = merge_included_configs(fs, &mut config, base_path, including);
+        including.pop();
+        merged?;
     }
 
     Ok(config)
 }
 
+/// Merge the tasks of the config files listed under the top-level `include`
+fn merge_included_configs(
+    fs: &dyn FileSystem,
+    config: &mut Config,
+    config_path: &Path,
+    including: &mut Vec<PathBuf>,
+) -> Result<(), RtaskError> {
+    let base_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+
+    for include in config.include.clone() {
+        let path = base_dir.join(include.path());
+        let include_error = |error: String| ConfigError::IncludeFile {
+            path: path.clone(),
+            error,
+            location: None,
+        };
+        if including.contains(&path) {
+            return Err(include_error("file includes itself".to_string()).into());
+        }
+
+        let contents = fs
+            .read_to_string(&path)
+            .map_err(|e| include_error(e.to_string()))?;
+        let included = parse_config_nested(fs, &contents, Some(&path), including)
+            .map_err(|e| ConfigError::include_failed(&path, &e))?;
+
+        let namespaced = |name: &str| match include.namespace() {
+            Some(namespace) => format!("{}:{}", namespace, name),
+            None => name.to_string(),
+        };
+        let local_names: Vec<String> = included.tasks.keys().cloned().collect();
+        for (name, mut task) in included.tasks {
+            let name = namespaced(&name);
+            if config.tasks.contains_key(&name) {
+                return Err(ConfigError::DuplicateTask(name).into());
+            }
+            // Subtasks of an included task refer to tasks of its own file
+            if include.namespace().is_some() {
+                for run in task.run.iter_mut().chain(task.finally.iter_mut()) {
+                    rename_subtasks(run, &local_names, &namespaced);
+                }
+            }
+            config.tasks.insert(name, task);
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite references to the given tasks in a run item's subtasks
+fn rename_subtasks(run: &mut Run, names: &[String], rename: &dyn Fn(&str) -> String) {
+    let Run::Complex(item) = run else {
+        return;
+    };
+    for subtask in &mut item.task {
+        let name = match subtask {
+            SubTask::Simple(name) => name,
+            SubTask::Complex(detail) if detail.file.is_none() => &mut detail.name,
+            SubTask::Complex(_) => continue,
+        };
+        if names.contains(name) {
+            *name = rename(name);
+        }
+    }
+}
+
+/// Locate the line (1-based) where each task is defined
+///
+/// This is a lightweight scan of the block-style `tasks:` mapping rather than a
+/// full YAML parse, which is enough to point editors and diagnostics at a task.
+pub fn task_line_numbers(yaml: &str) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+    let mut in_tasks = false;
+    let mut task_indent = None;
+
+    for (index, line) in yaml.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+
+        if indent == 0 {
+            in_tasks = trimmed.trim_end() == "tasks:";
+            continue;
+        }
+        if !in_tasks {
+            continue;
+        }
+
+        let expected = *task_indent.get_or_insert(indent);
+        if indent != expected {
+            continue;
+        }
+        if let Some((key, _)) = trimmed.split_once(':') {
+            let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+            lines.insert(key.to_string(), index + 1);
+        }
+    }
+
+    lines
+}
+
 /// Process include directives in tasks
-fn process_includes(config: &mut Config, config_path: &Path) -> Result<(), RtaskError> {
+fn process_includes(
+    fs: &dyn FileSystem,
+    config: &mut Config,
+    config_path: &Path,
+) -> Result<(), RtaskError> {
     let base_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
 
     let task_names: Vec<String> = config.tasks.keys().cloned().collect();
@@ -74,7 +217,7 @@ fn process_includes(config: &mut Config, config_path: &Path) -> Result<(), Rtask
                 // Read and parse the included file
                 let full_include_path = base_dir.join(include_path);
 
-                let included_task = load_included_task(&full_include_path)?;
+                let included_task = load_included_task(fs, &full_include_path)?;
 
                 // Replace the task with the included content
                 config.tasks.insert(task_name.clone(), included_task);
@@ -86,20 +229,17 @@ fn process_includes(config: &mut Config, config_path: &Path) -> Result<(), Rtask
 }
 
 /// Load a task from an included file
-fn load_included_task(path: &Path) -> Result<Task, RtaskError> {
-    let contents = fs::read_to_string(path).map_err(|e| {
-        ConfigError::IncludeFile {
+fn load_included_task(fs: &dyn FileSystem, path: &Path) -> Result<Task, RtaskError> {
+    let contents = fs
+        .read_to_string(path)
+        .map_err(|e| ConfigError::IncludeFile {
             path: path.to_path_buf(),
             error: e.to_string(),
-        }
-    })?;
+            location: None,
+        })?;
 
-    let task: Task = serde_yaml::from_str(&contents).map_err(|e| {
-        ConfigError::IncludeFile {
-            path: path.to_path_buf(),
-            error: e.to_string(),
-        }
-    })?;
+    let task: Task = serde_yaml::from_str(&contents)
+        .map_err(|e| ConfigError::include_failed(path, &e.into()))?;
 
     Ok(task)
 }
@@ -114,6 +254,7 @@ pub fn parse_config_auto() -> Result<(Config, PathBuf), RtaskError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Interpreter;
     use std::fs;
     use tempfile::TempDir;
 
@@ -130,6 +271,26 @@ tasks:
         assert!(config.tasks.contains_key("hello"));
     }
 
+    #[test]
+    fn test_task_line_numbers() {
+        let yaml = r#"name: app
+
+tasks:
+  # The build task
+  build:
+    usage: Build
+    run: make
+  "test":
+    run:
+      - cargo test
+interpreter: [bash, -c]
+"#;
+        let config = parse_config(yaml, None).unwrap();
+        assert_eq!(config.task_lines.get("build"), Some(&5));
+        assert_eq!(config.task_lines.get("test"), Some(&8));
+        assert_eq!(config.task_lines.len(), 2);
+    }
+
     #[test]
     fn test_find_config_in_current_dir() {
         let temp_dir = TempDir::new().unwrap();
@@ -178,6 +339,73 @@ tasks:
         assert!(matches!(result, Err(ConfigError::NotFound(_))));
     }
 
+    #[test]
+    fn test_discovery_and_includes_on_memory_fs() {
+        use crate::utils::fs::MemoryFs;
+
+        let fs = MemoryFs::new()
+            .with_file(
+                "/repo/rtask.yml",
+                "tasks:\n  build:\n    include: tasks/build.yml\n",
+            )
+            .with_file("/repo/tasks/build.yml", "usage: Build it\nrun: make\n");
+
+        let path = find_config_file_with(&fs, PathBuf::from("/repo/src/deep")).unwrap();
+        assert_eq!(path, Path::new("/repo/rtask.yml"));
+
+        let config = parse_config_file_with(&fs, &path).unwrap();
+        assert_eq!(config.tasks["build"].usage.as_deref(), Some("Build it"));
+
+        let missing = MemoryFs::new().with_file(
+            "/repo/rtask.yml",
+            "tasks:\n  build:\n    include: nope.yml\n",
+        );
+        assert!(matches!(
+            parse_config_file_with(&missing, &path),
+            Err(RtaskError::Config(ConfigError::IncludeFile { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_namespaced_includes() {
+        use crate::utils::fs::MemoryFs;
+
+        let fs = MemoryFs::new()
+            .with_file(
+                "/repo/rtask.yml",
+                "include:\n  - common.yml\n  - {path: ci/rtask.yml, as: ci}\ntasks:\n  build:\n    run: make\n",
+            )
+            .with_file("/repo/common.yml", "tasks:\n  fmt:\n    run: cargo fmt\n")
+            .with_file(
+                "/repo/ci/rtask.yml",
+                "tasks:\n  lint:\n    run:\n      - task: fmt\n  fmt:\n    run: cargo fmt --check\n",
+            );
+
+        let config = parse_config_file_with(&fs, Path::new("/repo/rtask.yml")).unwrap();
+        let names: Vec<&str> = config.tasks.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["build", "fmt", "ci:lint", "ci:fmt"]);
+        match &config.tasks["ci:lint"].run[0] {
+            Run::Complex(item) => {
+                assert!(matches!(&item.task[0], SubTask::Simple(name) if name == "ci:fmt"))
+            }
+            other => panic!("unexpected run item {:?}", other),
+        }
+
+        let clash = MemoryFs::new()
+            .with_file(
+                "/repo/rtask.yml",
+                "include: [common.yml]\ntasks:\n  fmt:\n    run: echo\n",
+            )
+            .with_file("/repo/common.yml", "tasks:\n  fmt:\n    run: cargo fmt\n");
+        assert!(matches!(
+            parse_config_file_with(&clash, Path::new("/repo/rtask.yml")),
+            Err(RtaskError::Config(ConfigError::DuplicateTask(name))) if name == "fmt"
+        ));
+
+        let cycle = MemoryFs::new().with_file("/repo/rtask.yml", "include: [rtask.yml]\n");
+        assert!(parse_config_file_with(&cycle, Path::new("/repo/rtask.yml")).is_err());
+    }
+
     #[test]
     fn test_parse_config_with_name_and_usage() {
         let yaml = r#"
@@ -205,7 +433,76 @@ tasks:
         let config = parse_config(yaml, None).unwrap();
         assert_eq!(
             config.interpreter,
-            Some(vec!["bash".to_string(), "-c".to_string()])
+            Some(Interpreter::Command(vec![
+                "bash".to_string(),
+                "-c".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_per_os_interpreter() {
+        let yaml = r#"
+interpreter:
+  linux: [bash, -c]
+  windows: [powershell, -Command]
+  default: [[zsh, -c], [sh, -c]]
+tasks:
+  hello:
+    interpreter: [python3, -c]
+    run: print("hello")
+"#;
+        let config = parse_config(yaml, None).unwrap();
+        let interpreter = config.interpreter.unwrap();
+        assert_eq!(interpreter.for_os("linux"), [["bash", "-c"]]);
+        assert_eq!(interpreter.for_os("windows"), [["powershell", "-Command"]]);
+        assert_eq!(interpreter.for_os("macos"), [["zsh", "-c"], ["sh", "-c"]]);
+        assert_eq!(
+            config.tasks["hello"].interpreter,
+            Some(Interpreter::Command(vec![
+                "python3".to_string(),
+                "-c".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_shell_opts() {
+        let yaml = r#"
+shell-opts: -eu -o pipefail
+tasks:
+  hello:
+    run:
+      - command:
+          exec: grep -q x missing.txt; echo done
+          shell-opts: []
+      - command:
+          exec: echo hi
+          shell-opts: [-x]
+"#;
+        let config = parse_config(yaml, None).unwrap();
+        assert_eq!(
+            config.shell_opts,
+            Some(vec![
+                "-eu".to_string(),
+                "-o".to_string(),
+                "pipefail".to_string()
+            ])
         );
+        let opts: Vec<_> = config.tasks["hello"]
+            .run
+            .iter()
+            .map(|run| match run {
+                crate::config::Run::Complex(item) => match &item.command[0] {
+                    crate::config::Command::Complex(detail) => detail.shell_opts.clone(),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(opts, [Some(vec![]), Some(vec!["-x".to_string()])]);
+
+        let err = parse_config("shell-opts: \"'-e\"\ntasks: {}\n", None).unwrap_err();
+        assert!(err.to_string().contains("invalid shell-opts"), "{}", err);
     }
 }
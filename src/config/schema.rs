@@ -2,25 +2,195 @@
 //!
 //! This module provides validation logic for configuration files.
 
-use crate::config::types::{Config, Task};
+use crate::config::types::{
+    Command, Config, Interpreter, ProbeWait, Run, ServiceGroup, Task, TaskTest, When,
+};
 use crate::error::{ConfigError, ConfigResult};
+use crate::runner::referenced_vars;
+use crate::utils::duration::parse_duration;
+use crate::utils::net::parse_http_url;
+use crate::utils::shell;
+use globset::Glob;
+use indexmap::IndexMap;
+use regex::Regex;
 use std::collections::HashSet;
 
 /// Validate a complete configuration
 pub fn validate_config(config: &Config) -> ConfigResult<()> {
+    if let Some(interpreter) = &config.interpreter {
+        validate_interpreter(interpreter, "the config")?;
+    }
+
     // Validate each task
     for (name, task) in &config.tasks {
         validate_task(name, task)?;
     }
+    for run in config.before_each.iter().chain(&config.after_each) {
+        validate_run_item("before-each/after-each", run)?;
+    }
 
     // Check for circular dependencies between tasks
     detect_circular_task_dependencies(config)?;
 
+    for (name, test) in &config.tests {
+        validate_test(config, name, test)?;
+    }
+
+    for (name, group) in &config.groups {
+        validate_group(config, name, group)?;
+    }
+
+    Ok(())
+}
+
+/// Check that a group lists service tasks and can be started in some order
+fn validate_group(config: &Config, name: &str, group: &ServiceGroup) -> ConfigResult<()> {
+    if group.services.is_empty() {
+        return Err(ConfigError::Invalid(format!(
+            "Group '{}' has no services",
+            name
+        )));
+    }
+    for service in group.services.keys() {
+        match config.tasks.get(service) {
+            Some(task) if task.service => {}
+            Some(_) => {
+                return Err(ConfigError::Invalid(format!(
+                    "Task '{}' in group '{}' is not a service (set service: true)",
+                    service, name
+                )))
+            }
+            None => return Err(ConfigError::TaskNotFound(service.clone())),
+        }
+        if let Some(other) = group
+            .after(service)
+            .iter()
+            .find(|other| !group.services.contains_key(*other))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "Service '{}' in group '{}' starts after '{}', which is not in the group",
+                service, name, other
+            )));
+        }
+    }
+    group_start_order(name, group).map(|_| ())
+}
+
+/// Order in which a group's services start: each after the services it waits
+/// for, otherwise in definition order
+pub fn group_start_order(name: &str, group: &ServiceGroup) -> ConfigResult<Vec<String>> {
+    let mut order: Vec<String> = Vec::new();
+    while order.len() < group.services.len() {
+        let next = group.services.keys().find(|service| {
+            !order.contains(service)
+                && group
+                    .after(service)
+                    .iter()
+                    .all(|other| order.contains(other))
+        });
+        match next {
+            Some(service) => order.push(service.clone()),
+            None => {
+                let waiting: Vec<&str> = group
+                    .services
+                    .keys()
+                    .filter(|service| !order.contains(service))
+                    .map(String::as_str)
+                    .collect();
+                return Err(ConfigError::CircularDependency(format!(
+                    "{} (after in group '{}')",
+                    waiting.join(", "),
+                    name
+                )));
+            }
+        }
+    }
+    Ok(order)
+}
+
+/// Check arg types, and that positional args can be bound unambiguously:
+/// required args come before optional ones, and only the last arg takes
+/// several values
+fn validate_args(task_name: &str, task: &Task) -> ConfigResult<()> {
+    let mut optional: Option<&str> = None;
+    for (index, (arg_name, arg)) in task.args.iter().enumerate() {
+        validate_option_type(&arg.arg_type)?;
+        if matches!(arg.arg_type.as_str(), "bool" | "boolean" | "count") {
+            return Err(ConfigError::Invalid(format!(
+                "Arg '{}' in task '{}' cannot have type '{}'",
+                arg_name, task_name, arg.arg_type
+            )));
+        }
+        if arg.min_values() > arg.max_values() {
+            return Err(ConfigError::Invalid(format!(
+                "Arg '{}' in task '{}' has min greater than max",
+                arg_name, task_name
+            )));
+        }
+        if arg.is_variadic() && index + 1 < task.args.len() {
+            return Err(ConfigError::Invalid(format!(
+                "Arg '{}' in task '{}' takes several values, so it must be the last arg",
+                arg_name, task_name
+            )));
+        }
+
+        if arg.min_values() == 0 {
+            optional.get_or_insert(arg_name);
+        } else if let Some(optional) = optional {
+            return Err(ConfigError::Invalid(format!(
+                "Required arg '{}' in task '{}' cannot follow optional arg '{}'",
+                arg_name, task_name, optional
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check that every interpreter names a program
+fn validate_interpreter(interpreter: &Interpreter, owner: &str) -> ConfigResult<()> {
+    let empty = match interpreter {
+        Interpreter::Command(command) => command.is_empty(),
+        Interpreter::Fallback(commands) => {
+            commands.is_empty() || commands.iter().any(Vec::is_empty)
+        }
+        Interpreter::PerOs(map) => {
+            return map
+                .values()
+                .try_for_each(|interpreter| validate_interpreter(interpreter, owner));
+        }
+    };
+    if empty {
+        return Err(ConfigError::Invalid(format!(
+            "Interpreter in {} must name a program",
+            owner
+        )));
+    }
+    Ok(())
+}
+
+/// Validate a test case against the tasks it exercises
+fn validate_test(config: &Config, name: &str, test: &TaskTest) -> ConfigResult<()> {
+    if !config.tasks.contains_key(&test.task) {
+        return Err(ConfigError::UnknownTestTask {
+            test: name.to_string(),
+            task: test.task.clone(),
+        });
+    }
+
+    if let Some(pattern) = &test.expect.stdout_matches {
+        Regex::new(pattern).map_err(|e| {
+            ConfigError::Invalid(format!(
+                "Invalid stdout-matches '{}' in test '{}': {}",
+                pattern, name, e
+            ))
+        })?;
+    }
+
     Ok(())
 }
 
 /// Validate a single task
-pub fn validate_task(_name: &str, task: &Task) -> ConfigResult<()> {
+pub fn validate_task(name: &str, task: &Task) -> ConfigResult<()> {
     // Check source/target consistency
     if !task.source.is_empty() && task.target.is_empty() {
         return Err(ConfigError::SourceWithoutTarget);
@@ -36,28 +206,474 @@ pub fn validate_task(_name: &str, task: &Task) -> ConfigResult<()> {
         }
     }
 
+    validate_args(name, task)?;
+    validate_matrix(name, &task.matrix)?;
+    if let Some(var) = task
+        .matrix
+        .keys()
+        .find(|var| task.args.contains_key(*var) || task.options.contains_key(*var))
+    {
+        return Err(ConfigError::Invalid(format!(
+            "Matrix variable '{}' in task '{}' is also an arg or option",
+            var, name
+        )));
+    }
+    for pattern in task.env_allow.iter().chain(&task.env_deny) {
+        Glob::new(pattern).map_err(|e| {
+            ConfigError::Invalid(format!(
+                "Invalid environment pattern '{}' in task '{}': {}",
+                pattern, name, e
+            ))
+        })?;
+    }
+    if let Some(interpreter) = &task.interpreter {
+        validate_interpreter(interpreter, &format!("task '{}'", name))?;
+    }
+    if let Some(timeout) = &task.timeout {
+        parse_duration(timeout).map_err(|e| {
+            ConfigError::Invalid(format!(
+                "Invalid timeout '{}' in task '{}': {}",
+                timeout, name, e
+            ))
+        })?;
+    }
+
     // Validate option types
-    for (_opt_name, option) in &task.options {
+    for (option_name, option) in &task.options {
         validate_option_type(&option.option_type)?;
+
+        if let Some(default) = &option.default {
+            if !option.values.is_empty() && !option.values.contains(default) {
+                return Err(ConfigError::Invalid(format!(
+                    "Default '{}' of option '{}' in task '{}' is not one of: {}",
+                    default,
+                    option_name,
+                    name,
+                    option.values.join(", ")
+                )));
+            }
+        }
+    }
+
+    detect_circular_option_defaults(name, task)?;
+    validate_service(name, task)?;
+
+    if let Some(watch) = &task.watch {
+        // Without paths, the task's source files are watched
+        if watch.paths.is_empty() && task.source.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "Watch in task '{}' must list at least one path, or the task a source",
+                name
+            )));
+        }
+        for pattern in watch.paths.iter().chain(watch.exclude.iter()) {
+            if let Err(e) = Glob::new(pattern) {
+                return Err(ConfigError::Invalid(format!(
+                    "Invalid watch pattern '{}' in task '{}': {}",
+                    pattern, name, e
+                )));
+            }
+        }
+        if let Some(debounce) = &watch.debounce {
+            if let Err(e) = parse_duration(debounce) {
+                return Err(ConfigError::Invalid(format!(
+                    "Invalid watch debounce '{}' in task '{}': {}",
+                    debounce, name, e
+                )));
+            }
+        }
+    }
+
+    if let Some(group) = &task.concurrency_group {
+        // The name becomes a lock file name when held across processes
+        let valid = !group.name().is_empty()
+            && group
+                .name()
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(ConfigError::Invalid(format!(
+                "Invalid concurrency group '{}' in task '{}': use letters, digits, '-', '_' and '.'",
+                group.name(),
+                name
+            )));
+        }
+    }
+
+    // Validate when conditions and commands in run and finally items
+    for when in &task.when {
+        validate_when(name, when)?;
+    }
+    for run in task.run.iter().chain(task.finally.iter()) {
+        validate_run_item(name, run)?;
+    }
+
+    Ok(())
+}
+
+/// Validate the service settings of a task
+///
+/// A service runs exactly one command, which is the process that is supervised.
+fn validate_service(name: &str, task: &Task) -> ConfigResult<()> {
+    let invalid = |message: &str| {
+        Err(ConfigError::Invalid(format!(
+            "Service task '{}' {}",
+            name, message
+        )))
+    };
+    if !task.service {
+        if task.ready.is_some() || task.restart.is_some() || task.max_restarts.is_some() {
+            return Err(ConfigError::Invalid(format!(
+                "ready, restart and max-restarts in task '{}' require service: true",
+                name
+            )));
+        }
+        return Ok(());
+    }
+
+    let single_command = match task.run.as_slice() {
+        [Run::SimpleCommand(_)] => true,
+        [Run::Complex(item)] => {
+            item.command.len() == 1
+                && item.pipe.is_empty()
+                && item.parallel.is_unset()
+                && item.task.is_empty()
+        }
+        _ => false,
+    };
+    if !single_command {
+        return invalid("must run exactly one command");
+    }
+    if !task.finally.is_empty() {
+        return invalid("cannot have a finally block");
+    }
+    if !task.matrix.is_empty() {
+        return invalid("cannot have a matrix");
+    }
+
+    if let Some(ready) = &task.ready {
+        let checks = [
+            ready.port.is_some(),
+            ready.log.is_some(),
+            ready.command.is_some(),
+        ];
+        if checks.iter().filter(|set| **set).count() != 1 {
+            return invalid("must set exactly one of port, log or command in ready");
+        }
+        if let Some(log) = &ready.log {
+            if let Err(e) = Regex::new(log) {
+                return invalid(&format!(
+                    "has an invalid ready log pattern '{}': {}",
+                    log, e
+                ));
+            }
+        }
+        if let Some(timeout) = &ready.timeout {
+            if let Err(e) = parse_duration(timeout) {
+                return invalid(&format!(
+                    "has an invalid ready timeout '{}': {}",
+                    timeout, e
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate a run item, including the branches of a parallel group
+fn validate_run_item(task_name: &str, run: &Run) -> ConfigResult<()> {
+    if let Run::Complex(item) = run {
+        for when in &item.when {
+            validate_when(task_name, when)?;
+        }
+        validate_matrix(task_name, &item.matrix)?;
+        for command in item.command.iter().chain(&item.pipe) {
+            validate_command(task_name, command)?;
+        }
+        let captures = item.pipe.iter().any(
+            |command| matches!(command, Command::Complex(detail) if detail.output_var.is_some()),
+        );
+        if captures {
+            return Err(ConfigError::Invalid(format!(
+                "Pipe stages in task '{}' cannot set output-var",
+                task_name
+            )));
+        }
+        let timed = item
+            .pipe
+            .iter()
+            .any(|command| matches!(command, Command::Complex(detail) if detail.timeout.is_some()));
+        if timed {
+            return Err(ConfigError::Invalid(format!(
+                "Pipe stages in task '{}' cannot set a timeout; the task's timeout applies",
+                task_name
+            )));
+        }
+        let retried = item
+            .pipe
+            .iter()
+            .any(|command| matches!(command, Command::Complex(detail) if detail.retries.is_some()));
+        if retried {
+            return Err(ConfigError::Invalid(format!(
+                "Pipe stages in task '{}' cannot set retries",
+                task_name
+            )));
+        }
+        let allowed = item.pipe.iter().any(|command| {
+            matches!(
+                command,
+                Command::Complex(detail) if detail.allow_failure || detail.background || detail.tty
+            )
+        });
+        if allowed {
+            return Err(ConfigError::Invalid(format!(
+                "Pipe stages in task '{}' cannot set allow-failure, background or tty",
+                task_name
+            )));
+        }
+        if item.fail_fast.is_some() && item.parallel.is_unset() {
+            return Err(ConfigError::Invalid(format!(
+                "fail-fast in task '{}' only applies to a parallel group",
+                task_name
+            )));
+        }
+        let sets_vars = item.command.iter().any(
+            |command| matches!(command, Command::Complex(detail) if detail.output_var.is_some()),
+        );
+        if item.parallel.is_flag() && sets_vars {
+            return Err(ConfigError::Invalid(format!(
+                "Commands run with parallel: true in task '{}' cannot set output-var",
+                task_name
+            )));
+        }
+        for branch in item.parallel.group() {
+            validate_run_item(task_name, branch)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that every matrix variable has at least one value
+fn validate_matrix(task_name: &str, matrix: &IndexMap<String, Vec<String>>) -> ConfigResult<()> {
+    if let Some((var, _)) = matrix.iter().find(|(_, values)| values.is_empty()) {
+        return Err(ConfigError::Invalid(format!(
+            "Matrix variable '{}' in task '{}' has no values",
+            var, task_name
+        )));
+    }
+    Ok(())
+}
+
+/// Validate a single command definition
+fn validate_command(task_name: &str, command: &Command) -> ConfigResult<()> {
+    if let Command::Complex(detail) = command {
+        if let Some(filter) = &detail.filter {
+            Regex::new(filter).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "Invalid filter '{}' in task '{}': {}",
+                    filter, task_name, e
+                ))
+            })?;
+        }
+        // Interpolated values (e.g. a `duration` option) are checked at run time
+        if let Some(heartbeat) = detail.heartbeat.as_ref().filter(|h| !h.contains("${")) {
+            parse_duration(heartbeat).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "Invalid heartbeat '{}' in task '{}': {}",
+                    heartbeat, task_name, e
+                ))
+            })?;
+        }
+        if let Some(timeout) = detail.timeout.as_ref().filter(|t| !t.contains("${")) {
+            parse_duration(timeout).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "Invalid timeout '{}' in task '{}': {}",
+                    timeout, task_name, e
+                ))
+            })?;
+        }
+        if detail.background {
+            let settings = [
+                ("output-var", detail.output_var.is_some()),
+                ("timeout", detail.timeout.is_some()),
+                ("retries", detail.retries.is_some()),
+                ("filter", detail.filter.is_some()),
+                ("stdout", detail.stdout.is_some()),
+                ("stderr", detail.stderr.is_some()),
+                ("log", detail.log.is_some()),
+            ];
+            if let Some((setting, _)) = settings.iter().find(|(_, set)| *set) {
+                return Err(ConfigError::Invalid(format!(
+                    "Background command '{}' in task '{}' cannot set {}",
+                    detail.exec, task_name, setting
+                )));
+            }
+        }
+        if detail.tty {
+            let settings = [
+                ("background", detail.background),
+                ("stdout", detail.stdout.is_some()),
+                ("stderr", detail.stderr.is_some()),
+            ];
+            if let Some((setting, _)) = settings.iter().find(|(_, set)| *set) {
+                return Err(ConfigError::Invalid(format!(
+                    "Command '{}' in task '{}' cannot set both tty and {}",
+                    detail.exec, task_name, setting
+                )));
+            }
+        }
+        if let Some(delay) = detail.retry_delay.as_ref().filter(|d| !d.contains("${")) {
+            parse_duration(delay).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "Invalid retry-delay '{}' in task '{}': {}",
+                    delay, task_name, e
+                ))
+            })?;
+        }
+        if detail.shell == Some(false) {
+            let words = shell::split(&detail.exec).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "Invalid command '{}' in task '{}': {}",
+                    detail.exec, task_name, e
+                ))
+            })?;
+            if words.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "Command in task '{}' has shell: false but no program to run",
+                    task_name
+                )));
+            }
+        }
+        if detail.output_var.is_some() && detail.stdout.is_some() {
+            return Err(ConfigError::Invalid(format!(
+                "Command in task '{}' cannot set both output-var and stdout",
+                task_name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a single when condition, rejecting unknown keys and empty blocks
+fn validate_when(task_name: &str, when: &When) -> ConfigResult<()> {
+    if let Some(key) = when.unknown.keys().next() {
+        return Err(ConfigError::UnknownWhenKey {
+            task: task_name.to_string(),
+            key: key.clone(),
+            expected: When::KEYS.join(", "),
+        });
+    }
+
+    if when.is_empty() {
+        return Err(ConfigError::EmptyWhen(task_name.to_string()));
+    }
+
+    let invalid = |key: &str, error: String| {
+        Err(ConfigError::Invalid(format!(
+            "Invalid {} condition in task '{}': {}",
+            key, task_name, error
+        )))
+    };
+    if let Some(check) = &when.port_open {
+        let address = check.address();
+        // Interpolated addresses are only known when the condition runs
+        let valid = address.contains("${")
+            || address
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !valid {
+            return invalid(
+                "port-open",
+                format!("'{}' is not a port or host:port", address),
+            );
+        }
+        if let Err(e) = validate_probe_wait(&check.wait()) {
+            return invalid("port-open", e);
+        }
+    }
+    if let Some(check) = &when.http_ok {
+        if !check.url().contains("${") {
+            if let Err(e) = parse_http_url(check.url()) {
+                return invalid("http-ok", e);
+            }
+        }
+        if let Err(e) = validate_probe_wait(&check.wait()) {
+            return invalid("http-ok", e);
+        }
     }
 
     Ok(())
 }
 
+/// Check the durations of a network condition's wait settings
+fn validate_probe_wait(wait: &ProbeWait) -> Result<(), String> {
+    for value in wait.timeout.iter().chain(wait.interval.iter()) {
+        parse_duration(value).map_err(|e| format!("invalid duration '{}': {}", value, e))?;
+    }
+    Ok(())
+}
+
 /// Validate an option type string
 fn validate_option_type(option_type: &str) -> ConfigResult<()> {
     match option_type {
-        "string" | "bool" | "boolean" | "int" | "integer" | "float" => Ok(()),
+        "string" | "bool" | "boolean" | "int" | "integer" | "float" | "path" | "file" | "dir"
+        | "directory" | "duration" | "url" | "count" => Ok(()),
         _ => Err(ConfigError::Invalid(format!(
-            "Invalid option type: {}. Must be one of: string, bool, int, float",
+            "Invalid option type: {}. Must be one of: string, bool, int, float, path, file, dir, duration, url, count",
             option_type
         ))),
     }
 }
 
+/// Detect option defaults that reference each other in a loop
+fn detect_circular_option_defaults(task_name: &str, task: &Task) -> ConfigResult<()> {
+    let mut visited = HashSet::new();
+    for option_name in task.options.keys() {
+        let mut stack = Vec::new();
+        check_option_cycle(task_name, task, option_name, &mut visited, &mut stack)?;
+    }
+    Ok(())
+}
+
+/// Recursively follow the options referenced by an option's default
+fn check_option_cycle(
+    task_name: &str,
+    task: &Task,
+    option_name: &str,
+    visited: &mut HashSet<String>,
+    stack: &mut Vec<String>,
+) -> ConfigResult<()> {
+    if stack.iter().any(|name| name == option_name) {
+        stack.push(option_name.to_string());
+        return Err(ConfigError::CircularDependency(format!(
+            "{} (option defaults in task '{}')",
+            stack.join(" -> "),
+            task_name
+        )));
+    }
+    if visited.contains(option_name) {
+        return Ok(());
+    }
+
+    stack.push(option_name.to_string());
+    let default = task.options[option_name]
+        .default
+        .as_deref()
+        .unwrap_or_default();
+    for reference in referenced_vars(default) {
+        if task.options.contains_key(&reference) {
+            check_option_cycle(task_name, task, &reference, visited, stack)?;
+        }
+    }
+    stack.pop();
+    visited.insert(option_name.to_string());
+
+    Ok(())
+}
+
 /// Detect circular dependencies in task subtask relationships
 fn detect_circular_task_dependencies(config: &Config) -> ConfigResult<()> {
-    for (task_name, _) in &config.tasks {
+    for task_name in config.tasks.keys() {
         let mut visited = HashSet::new();
         let mut stack = Vec::new();
         check_task_cycle(config, task_name, &mut visited, &mut stack)?;
@@ -91,6 +707,10 @@ fn check_task_cycle(
     // Add to stack
     stack.push(task_name.to_string());
 
+    for dep in &task.deps {
+        check_task_cycle(config, dep, visited, stack)?;
+    }
+
     // Check all subtasks
     for run in &task.run {
         let subtasks = match run {
@@ -98,9 +718,11 @@ fn check_task_cycle(
             crate::config::types::Run::Complex(item) => item
                 .task
                 .iter()
-                .map(|st| match st {
-                    crate::config::types::SubTask::Simple(name) => name.clone(),
-                    crate::config::types::SubTask::Complex(detail) => detail.name.clone(),
+                .filter_map(|st| match st {
+                    crate::config::types::SubTask::Simple(name) => Some(name.clone()),
+                    // Tasks in other files are checked when that file is loaded
+                    crate::config::types::SubTask::Complex(detail) if detail.file.is_some() => None,
+                    crate::config::types::SubTask::Complex(detail) => Some(detail.name.clone()),
                 })
                 .collect(),
         };
@@ -117,9 +739,11 @@ fn check_task_cycle(
             crate::config::types::Run::Complex(item) => item
                 .task
                 .iter()
-                .map(|st| match st {
-                    crate::config::types::SubTask::Simple(name) => name.clone(),
-                    crate::config::types::SubTask::Complex(detail) => detail.name.clone(),
+                .filter_map(|st| match st {
+                    crate::config::types::SubTask::Simple(name) => Some(name.clone()),
+                    // Tasks in other files are checked when that file is loaded
+                    crate::config::types::SubTask::Complex(detail) if detail.file.is_some() => None,
+                    crate::config::types::SubTask::Complex(detail) => Some(detail.name.clone()),
                 })
                 .collect(),
         };
@@ -140,6 +764,7 @@ fn check_task_cycle(
 mod tests {
     use super::*;
     use crate::config::types::{Arg, Run, RunItem, SubTask, TaskOption};
+    use indexmap::IndexMap;
     use std::collections::HashMap;
 
     #[test]
@@ -147,8 +772,9 @@ mod tests {
         let mut config = Config {
             name: None,
             usage: None,
-            tasks: HashMap::new(),
+            tasks: IndexMap::new(),
             interpreter: None,
+            ..Default::default()
         };
 
         let task = Task {
@@ -156,13 +782,14 @@ mod tests {
             description: None,
             private: false,
             quiet: false,
-            args: HashMap::new(),
-            options: HashMap::new(),
+            args: IndexMap::new(),
+            options: IndexMap::new(),
             run: vec![],
             finally: vec![],
             source: vec!["src.txt".to_string()],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         config.tasks.insert("test".to_string(), task);
@@ -177,11 +804,12 @@ mod tests {
         let mut config = Config {
             name: None,
             usage: None,
-            tasks: HashMap::new(),
+            tasks: IndexMap::new(),
             interpreter: None,
+            ..Default::default()
         };
 
-        let mut args = HashMap::new();
+        let mut args = IndexMap::new();
         args.insert(
             "name".to_string(),
             Arg {
@@ -189,10 +817,11 @@ mod tests {
                 default: None,
                 required: false,
                 private: false,
+                ..Default::default()
             },
         );
 
-        let mut options = HashMap::new();
+        let mut options = IndexMap::new();
         options.insert(
             "name".to_string(),
             TaskOption {
@@ -203,6 +832,7 @@ mod tests {
                 required: false,
                 rewrite: None,
                 environment: None,
+                values: vec![],
                 private: false,
             },
         );
@@ -219,6 +849,7 @@ mod tests {
             source: vec![],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         config.tasks.insert("test".to_string(), task);
@@ -238,6 +869,7 @@ mod tests {
             required: false,
             rewrite: None,
             environment: None,
+            values: vec![],
             private: false,
         };
 
@@ -258,8 +890,9 @@ mod tests {
         let mut config = Config {
             name: None,
             usage: None,
-            tasks: HashMap::new(),
+            tasks: IndexMap::new(),
             interpreter: None,
+            ..Default::default()
         };
 
         // Create task A that depends on task B
@@ -268,18 +901,20 @@ mod tests {
             description: None,
             private: false,
             quiet: false,
-            args: HashMap::new(),
-            options: HashMap::new(),
-            run: vec![Run::Complex(RunItem {
+            args: IndexMap::new(),
+            options: IndexMap::new(),
+            run: vec![Run::Complex(Box::new(RunItem {
                 when: vec![],
                 command: vec![],
                 task: vec![SubTask::Simple("b".to_string())],
                 set_environment: HashMap::new(),
-            })],
+                ..Default::default()
+            }))],
             finally: vec![],
             source: vec![],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         // Create task B that depends on task A (circular!)
@@ -288,18 +923,20 @@ mod tests {
             description: None,
             private: false,
             quiet: false,
-            args: HashMap::new(),
-            options: HashMap::new(),
-            run: vec![Run::Complex(RunItem {
+            args: IndexMap::new(),
+            options: IndexMap::new(),
+            run: vec![Run::Complex(Box::new(RunItem {
                 when: vec![],
                 command: vec![],
                 task: vec![SubTask::Simple("a".to_string())],
                 set_environment: HashMap::new(),
-            })],
+                ..Default::default()
+            }))],
             finally: vec![],
             source: vec![],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         config.tasks.insert("a".to_string(), task_a);
@@ -313,13 +950,454 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_validate_deps() {
+        let validate = |yaml: &str| {
+            let config: Config = serde_yaml::from_str(yaml).unwrap();
+            validate_config(&config)
+        };
+
+        assert!(validate("tasks:\n  a:\n    deps: b\n  b:\n    run: echo\n").is_ok());
+        assert!(matches!(
+            validate("tasks:\n  a:\n    deps: [b]\n"),
+            Err(ConfigError::TaskNotFound(_))
+        ));
+        assert!(matches!(
+            validate("tasks:\n  a:\n    deps: [b]\n  b:\n    run:\n      - task: a\n"),
+            Err(ConfigError::CircularDependency(_))
+        ));
+    }
+
+    #[test]
+    fn test_detect_circular_option_defaults() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  deploy:
+    options:
+      stage:
+        default: prod
+      bucket:
+        default: ${region}-${stage}
+      region:
+        default: ${zone}
+      zone:
+        default: ${bucket}-zone
+    run: echo ${bucket}
+"#,
+            None,
+        )
+        .unwrap();
+
+        match validate_config(&config) {
+            Err(ConfigError::CircularDependency(chain)) => {
+                assert_eq!(
+                    chain,
+                    "bucket -> region -> zone -> bucket (option defaults in task 'deploy')"
+                );
+            }
+            other => panic!("expected circular dependency, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_empty_interpreter() {
+        let config = crate::config::parse_config(
+            "interpreter:\n  linux: []\ntasks:\n  a:\n    run: echo\n",
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            validate_config(&config),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_unknown_when_key() {
+        let yaml = r#"
+tasks:
+  test:
+    run:
+      - when:
+          - enviroment-set: HOME
+        command: echo "home"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let result = validate_config(&config);
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnknownWhenKey { ref key, .. }) if key == "enviroment-set"
+        ));
+    }
+
+    #[test]
+    fn test_validate_empty_when() {
+        let yaml = r#"
+tasks:
+  test:
+    finally:
+      - when:
+          - {}
+        command: echo "cleanup"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let result = validate_config(&config);
+        assert!(matches!(result, Err(ConfigError::EmptyWhen(_))));
+    }
+
+    #[test]
+    fn test_validate_invalid_filter() {
+        let yaml = r#"
+tasks:
+  test:
+    run:
+      - command:
+          exec: make
+          filter: "[unclosed"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let result = validate_config(&config);
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_validate_env_patterns() {
+        let yaml = "tasks:\n  t:\n    env-deny: [\"AWS_[\"]\n    run: echo\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let result = validate_config(&config);
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_validate_service() {
+        let invalid = |task: &str| {
+            let yaml = format!("tasks:\n  t:\n{}", task);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "    service: true\n    ready: {port: 8080, timeout: 5s}\n    run: ./server\n"
+        ));
+        assert!(invalid("    ready: {port: 8080}\n    run: ./server\n"));
+        assert!(invalid("    restart: always\n    run: ./server\n"));
+        assert!(invalid("    service: true\n    run: [./a, ./b]\n"));
+        assert!(invalid(
+            "    service: true\n    run: ./server\n    finally: [echo]\n"
+        ));
+        assert!(invalid(
+            "    service: true\n    ready: {port: 80, log: up}\n    run: ./server\n"
+        ));
+        assert!(invalid(
+            "    service: true\n    ready: {log: \"(\"}\n    run: ./server\n"
+        ));
+        assert!(invalid(
+            "    service: true\n    ready: {port: 80, timeout: soon}\n    run: ./server\n"
+        ));
+    }
+
+    #[test]
+    fn test_group_start_order() {
+        let services = "tasks:\n  db: {service: true, run: db}\n  api: {service: true, run: api}\n  web: {service: true, run: web}\n";
+        let group = |group: &str| -> ConfigResult<Vec<String>> {
+            let yaml = format!("{}groups:\n  dev:\n    services:\n{}", services, group);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            validate_config(&config)?;
+            group_start_order("dev", &config.groups["dev"])
+        };
+
+        assert_eq!(
+            group("      web: {after: [api]}\n      api: {after: [db]}\n      db:\n").unwrap(),
+            ["db", "api", "web"]
+        );
+        assert_eq!(group("      web:\n      db:\n").unwrap(), ["web", "db"]);
+        assert!(matches!(
+            group("      db: {after: [api]}\n      api: {after: [db]}\n"),
+            Err(ConfigError::CircularDependency(_))
+        ));
+        assert!(matches!(
+            group("      api: {after: [db]}\n"),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            group("      cache:\n"),
+            Err(ConfigError::TaskNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_network_conditions() {
+        let invalid = |when: &str| {
+            let yaml = format!(
+                "tasks:\n  t:\n    run:\n      - when:\n          - {}\n        command: echo\n",
+                when
+            );
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid("port-open: 5432"));
+        assert!(!invalid("port-open: \"db:5432\""));
+        assert!(!invalid("port-open: \"localhost:${port}\""));
+        assert!(!invalid(
+            "port-open: {host: db, port: 5432, timeout: 30s, interval: 500ms}"
+        ));
+        assert!(!invalid("http-ok: http://localhost:8080/health"));
+        assert!(!invalid(
+            "http-ok: {url: \"http://localhost/\", timeout: 1m}"
+        ));
+        assert!(invalid("port-open: db"));
+        assert!(invalid("port-open: {port: 5432, timeout: soon}"));
+        assert!(invalid("http-ok: https://example.com/health"));
+        assert!(invalid(
+            "http-ok: {url: \"http://localhost/\", interval: often}"
+        ));
+    }
+
+    #[test]
+    fn test_validate_watch() {
+        let invalid = |watch: &str| {
+            let yaml = format!("tasks:\n  t:\n    watch: {}\n    run: echo\n", watch);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid("{paths: [\"src/**\"], exclude: [\"src/gen/**\"]}"));
+        assert!(invalid("{exclude: [\"target/**\"]}"));
+        assert!(invalid("{paths: [\"src/[\"]}"));
+        assert!(!invalid(
+            "{paths: [src], debounce: 1s, gitignore: false, in-flight: restart}"
+        ));
+        assert!(invalid("{paths: [src], debounce: later}"));
+        assert!(invalid("true"));
+        assert!(!invalid("false"));
+
+        let yaml =
+            "tasks:\n  t:\n    watch: true\n    source: [src]\n    target: [out]\n    run: echo\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.tasks["t"]
+            .watch
+            .as_ref()
+            .is_some_and(|watch| watch.always));
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_concurrency_group() {
+        let invalid = |group: &str| {
+            let yaml = format!(
+                "tasks:\n  t:\n    concurrency-group: {}\n    run: echo\n",
+                group
+            );
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid("deploy"));
+        assert!(!invalid("{name: db.migrate, across-processes: true}"));
+        assert!(invalid("\"\""));
+        assert!(invalid("../deploy"));
+    }
+
+    #[test]
+    fn test_validate_parallel_flag() {
+        let invalid = |item: &str| {
+            let yaml = format!("tasks:\n  t:\n    run:\n      - {}\n", item);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "{parallel: true, fail-fast: false, command: [a, b]}"
+        ));
+        assert!(invalid("{parallel: false, fail-fast: false, command: a}"));
+        assert!(invalid(
+            "{parallel: true, command: [a, {exec: b, output-var: B}]}"
+        ));
+    }
+
+    #[test]
+    fn test_validate_timeout() {
+        let invalid = |task: &str| {
+            let yaml = format!("tasks:\n  t:\n{}", task);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "    timeout: 10m\n    run: [{command: {exec: a, timeout: 30s}}]\n"
+        ));
+        assert!(!invalid(
+            "    run: [{command: {exec: a, timeout: '${limit}'}}]\n"
+        ));
+        assert!(invalid("    timeout: soon\n    run: a\n"));
+        assert!(invalid(
+            "    run: [{command: {exec: a, timeout: 30 sec}}]\n"
+        ));
+        assert!(invalid("    run: [{pipe: [a, {exec: b, timeout: 1s}]}]\n"));
+    }
+
+    #[test]
+    fn test_validate_retries() {
+        let invalid = |command: &str| {
+            let yaml = format!("tasks:\n  t:\n    run: [{}]\n", command);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "{command: {exec: a, retries: 3, retry-delay: 500ms}}"
+        ));
+        assert!(!invalid(
+            "{command: {exec: a, retries: 3, retry-delay: '${delay}'}}"
+        ));
+        assert!(invalid(
+            "{command: {exec: a, retries: 3, retry-delay: a while}}"
+        ));
+        assert!(invalid("{pipe: [a, {exec: b, retries: 2}]}"));
+    }
+
+    #[test]
+    fn test_validate_allow_failure() {
+        let invalid = |item: &str| {
+            let yaml = format!("tasks:\n  t:\n    run: [{}]\n", item);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid("{command: {exec: a, allow-failure: true}}"));
+        assert!(!invalid("{pipe: [a, b], allow-failure: true}"));
+        assert!(invalid("{pipe: [a, {exec: b, allow-failure: true}]}"));
+    }
+
+    #[test]
+    fn test_validate_background() {
+        let invalid = |item: &str| {
+            let yaml = format!("tasks:\n  t:\n    run: [{}]\n", item);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "{command: {exec: serve, background: true, dir: web}}"
+        ));
+        assert!(invalid(
+            "{command: {exec: serve, background: true, output-var: OUT}}"
+        ));
+        assert!(invalid(
+            "{command: {exec: serve, background: true, timeout: 1m}}"
+        ));
+        assert!(invalid("{pipe: [a, {exec: b, background: true}]}"));
+    }
+
+    #[test]
+    fn test_validate_tty() {
+        let invalid = |item: &str| {
+            let yaml = format!("tasks:\n  t:\n    run: [{}]\n", item);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "{command: {exec: npm test, tty: true, filter: FAIL}}"
+        ));
+        assert!(!invalid(
+            "{command: {exec: git diff, tty: true, output-var: DIFF}}"
+        ));
+        assert!(invalid(
+            "{command: {exec: npm test, tty: true, stdout: out.txt}}"
+        ));
+        assert!(invalid(
+            "{command: {exec: serve, tty: true, background: true}}"
+        ));
+        assert!(invalid("{pipe: [{exec: ls, tty: true}, wc -l]}"));
+    }
+
+    #[test]
+    fn test_validate_matrix() {
+        let invalid = |task: &str| {
+            let yaml = format!("tasks:\n  t:\n{}", task);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "    matrix: {os: [linux, macos]}\n    run: echo ${os}\n"
+        ));
+        assert!(!invalid(
+            "    run: [{matrix: {py: [3.11, '3.12']}, command: 'tox -e py${py}'}]\n"
+        ));
+        assert!(invalid("    matrix: {os: []}\n    run: echo ${os}\n"));
+        assert!(invalid("    run: [{matrix: {os: []}, command: echo}]\n"));
+        assert!(invalid(
+            "    options: {os: {}}\n    matrix: {os: [linux]}\n    run: echo\n"
+        ));
+        assert!(invalid(
+            "    service: true\n    matrix: {port: [1, 2]}\n    run: serve\n"
+        ));
+    }
+
+    #[test]
+    fn test_validate_arg_order() {
+        let invalid = |args: &str| {
+            let yaml = format!("tasks:\n  t:\n    args:\n{}    run: echo\n", args);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            matches!(validate_config(&config), Err(ConfigError::Invalid(_)))
+        };
+
+        assert!(!invalid(
+            "      a: {required: true}\n      b: {}\n      rest: {min: 0}\n"
+        ));
+        assert!(invalid("      a: {}\n      b: {required: true}\n"));
+        assert!(invalid("      rest: {min: 1}\n      b: {}\n"));
+        assert!(invalid("      rest: {min: 3, max: 2}\n"));
+        assert!(!invalid("      port: {type: int}\n"));
+        assert!(invalid("      port: {type: bool}\n"));
+        assert!(invalid("      port: {type: number}\n"));
+    }
+
+    #[test]
+    fn test_validate_tests() {
+        let yaml = r#"
+tasks:
+  build:
+    run: echo build
+tests:
+  builds:
+    task: biuld
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(
+            validate_config(&config),
+            Err(ConfigError::UnknownTestTask { ref task, .. }) if task == "biuld"
+        ));
+
+        let yaml = r#"
+tasks:
+  build:
+    run: echo build
+tests:
+  builds:
+    task: build
+    expect:
+      stdout-matches: "(unclosed"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(
+            validate_config(&config),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
     #[test]
     fn test_validate_valid_config() {
         let mut config = Config {
             name: Some("test-app".to_string()),
             usage: Some("Test application".to_string()),
-            tasks: HashMap::new(),
+            tasks: IndexMap::new(),
             interpreter: None,
+            ..Default::default()
         };
 
         let task = Task {
@@ -327,13 +1405,14 @@ mod tests {
             description: None,
             private: false,
             quiet: false,
-            args: HashMap::new(),
-            options: HashMap::new(),
+            args: IndexMap::new(),
+            options: IndexMap::new(),
             run: vec![Run::SimpleCommand("echo test".to_string())],
             finally: vec![],
             source: vec![],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         config.tasks.insert("test".to_string(), task);
@@ -2,11 +2,12 @@
 //!
 //! This module defines the data structures that represent a tusk.yml configuration file.
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Top-level configuration structure
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Config {
     /// Application name (optional)
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -16,17 +17,287 @@ pub struct Config {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub usage: Option<String>,
 
-    /// Tasks defined in the configuration
+    /// Tasks defined in the configuration, in definition order
     #[serde(default)]
-    pub tasks: HashMap<String, Task>,
+    pub tasks: IndexMap<String, Task>,
+
+    /// Global interpreter to use for commands (e.g., ["sh", "-c"]), or one
+    /// per operating system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<Interpreter>,
+
+    /// Template for the command echo line (e.g., "[RUN] {command}")
+    #[serde(
+        rename = "print-format",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub print_format: Option<String>,
+
+    /// Limits for command output captured into variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureConfig>,
+
+    /// Strip ANSI escape sequences from logged and captured output (default true)
+    #[serde(
+        rename = "strip-ansi",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub strip_ansi: Option<bool>,
 
-    /// Global interpreter to use for commands (e.g., ["sh", "-c"])
+    /// Flags passed to the interpreter before its command flag (e.g.,
+    /// `-eu -o pipefail`), as a string or a list
+    #[serde(
+        rename = "shell-opts",
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_shell_opts"
+    )]
+    pub shell_opts: Option<Vec<String>>,
+
+    /// Directory commands run from (default: the config file's directory)
+    #[serde(rename = "run-from", default, skip_serializing_if = "Option::is_none")]
+    pub run_from: Option<RunFrom>,
+
+    /// Host environment variables commands inherit (default all)
+    #[serde(
+        rename = "inherit-env",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub inherit_env: Option<InheritEnv>,
+
+    /// Default heartbeat interval for all commands (e.g., "1m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<String>,
+
+    /// Record task runs in `.rtask/history.jsonl` (default true)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub interpreter: Option<Vec<String>>,
+    pub history: Option<bool>,
+
+    /// Other config files whose tasks are merged into this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<ConfigInclude>,
+
+    /// Tests for the tasks in this file, run with `rtask test`
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tests: IndexMap<String, TaskTest>,
+
+    /// Groups of service tasks started together with `rtask up`
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub groups: IndexMap<String, ServiceGroup>,
+
+    /// Run items run before every task invoked from the command line
+    #[serde(
+        rename = "before-each",
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_run_items"
+    )]
+    pub before_each: Vec<Run>,
+
+    /// Run items run after every task invoked from the command line, even
+    /// when it failed
+    #[serde(
+        rename = "after-each",
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_run_items"
+    )]
+    pub after_each: Vec<Run>,
+
+    /// Also run `before-each` and `after-each` around subtasks and deps
+    /// (default false)
+    #[serde(
+        rename = "each-includes-subtasks",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub each_includes_subtasks: Option<bool>,
+
+    /// 1-based line number of each task definition in the source file
+    #[serde(skip)]
+    pub task_lines: HashMap<String, usize>,
+}
+
+/// A config file to merge, optionally under a namespace
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ConfigInclude {
+    /// Path relative to the including file
+    Path(String),
+
+    /// Path with a namespace prefixed to each task (`ci` gives `ci:lint`)
+    Detail {
+        path: String,
+        #[serde(rename = "as", default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+    },
+}
+
+impl ConfigInclude {
+    pub fn path(&self) -> &str {
+        match self {
+            ConfigInclude::Path(path) | ConfigInclude::Detail { path, .. } => path,
+        }
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            ConfigInclude::Path(_) => None,
+            ConfigInclude::Detail { namespace, .. } => namespace.as_deref(),
+        }
+    }
+}
+
+/// Interpreter that runs commands, either everywhere or per operating system
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Interpreter {
+    /// Same program and flags on every host (`[bash, -c]`)
+    Command(Vec<String>),
+
+    /// Candidates tried in order, using the first whose program is installed
+    /// (`[[bash, -c], [sh, -c]]`)
+    Fallback(Vec<Vec<String>>),
+
+    /// Keyed by OS name as in `std::env::consts::OS` (`linux`, `macos`,
+    /// `windows`, ...), with an optional `default` entry for other hosts
+    PerOs(IndexMap<String, Interpreter>),
+}
+
+impl Interpreter {
+    /// The candidate interpreters for `os`, in order of preference
+    pub fn for_os(&self, os: &str) -> Vec<&[String]> {
+        match self {
+            Interpreter::Command(command) => vec![command.as_slice()],
+            Interpreter::Fallback(commands) => commands.iter().map(Vec::as_slice).collect(),
+            Interpreter::PerOs(map) => map
+                .get(os)
+                .or_else(|| map.get("default"))
+                .map(|interpreter| interpreter.for_os(os))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Directory that commands run from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunFrom {
+    /// The directory containing the config file, wherever rtask is invoked
+    #[default]
+    ConfigDir,
+
+    /// The directory rtask is invoked from
+    Cwd,
+}
+
+/// Which host environment variables commands inherit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InheritEnv {
+    /// Everything rtask was started with
+    #[default]
+    All,
+
+    /// Nothing; commands only see the task's variables
+    None,
+
+    /// Only essentials such as `PATH`, `HOME`, `TERM` and the locale
+    Allowlist,
+}
+
+/// Limits for captured command output
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CaptureConfig {
+    /// Maximum number of bytes kept from captured output
+    #[serde(rename = "max-size", skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+
+    /// Strip surrounding whitespace from captured output (default true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim: Option<bool>,
+}
+
+/// Service tasks started and stopped together with `rtask up` / `rtask down`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ServiceGroup {
+    /// Usage description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+
+    /// Service tasks in the group, started in definition order unless
+    /// `after` says otherwise
+    #[serde(default)]
+    pub services: IndexMap<String, Option<GroupService>>,
+}
+
+/// A service's place in a group
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct GroupService {
+    /// Services of the group that must be ready before this one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+impl ServiceGroup {
+    /// Services a service waits for
+    pub fn after(&self, service: &str) -> &[String] {
+        match self.services.get(service) {
+            Some(Some(entry)) => &entry.after,
+            _ => &[],
+        }
+    }
+}
+
+/// A test case: run a task with arguments and check the outcome
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TaskTest {
+    /// Task to run
+    pub task: String,
+
+    /// Command-line arguments passed to the task
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+
+    /// Expected outcome
+    #[serde(default)]
+    pub expect: TestExpectation,
+}
+
+/// Expected outcome of a test run
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TestExpectation {
+    /// Expected exit code (default 0)
+    #[serde(rename = "exit-code", default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    /// Text the task's stdout must contain
+    #[serde(
+        rename = "stdout-contains",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stdout_contains: Option<String>,
+
+    /// Regular expression the task's stdout must match (`^` and `$` match at
+    /// line boundaries)
+    #[serde(
+        rename = "stdout-matches",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stdout_matches: Option<String>,
+
+    /// Files that must exist after the run, relative to the config file
+    #[serde(rename = "files-exist", default, skip_serializing_if = "Vec::is_empty")]
+    pub files_exist: Vec<String>,
 }
 
 /// A task definition
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Task {
     /// Usage description for help text
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -44,18 +315,40 @@ pub struct Task {
     #[serde(default)]
     pub quiet: bool,
 
-    /// Positional arguments for the task
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub args: HashMap<String, Arg>,
+    /// Positional arguments for the task, bound to command-line values in
+    /// the order they are defined
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub args: IndexMap<String, Arg>,
 
     /// Named options (flags) for the task
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub options: HashMap<String, TaskOption>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub options: IndexMap<String, TaskOption>,
+
+    /// Conditions that must be met for the task to run at all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<When>,
+
+    /// Prerequisite tasks, run before `run` and at most once per run
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_task_names"
+    )]
+    pub deps: Vec<String>,
 
     /// Run items to execute
     #[serde(default, deserialize_with = "deserialize_run_items")]
     pub run: Vec<Run>,
 
+    /// Variables with lists of values; the run items run once per
+    /// combination of values
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        deserialize_with = "deserialize_matrix"
+    )]
+    pub matrix: IndexMap<String, Vec<String>>,
+
     /// Finally block - always executes, even on error
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub finally: Vec<Run>,
@@ -71,6 +364,187 @@ pub struct Task {
     /// Include another file as task definition
     #[serde(skip_serializing_if = "Option::is_none")]
     pub include: Option<String>,
+
+    /// File that receives a timestamped copy of all command output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+
+    /// Interpreter for this task's commands, overriding the global one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<Interpreter>,
+
+    /// Host environment variables this task's commands inherit, overriding
+    /// the global setting
+    #[serde(
+        rename = "inherit-env",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub inherit_env: Option<InheritEnv>,
+
+    /// Host variables (glob patterns such as `AWS_*`) this task's commands
+    /// inherit on top of `inherit-env`, which then defaults to `none`
+    #[serde(rename = "env-allow", default, skip_serializing_if = "Vec::is_empty")]
+    pub env_allow: Vec<String>,
+
+    /// Host variables (glob patterns) this task's commands never inherit
+    #[serde(rename = "env-deny", default, skip_serializing_if = "Vec::is_empty")]
+    pub env_deny: Vec<String>,
+
+    /// Run as a long-running service: start the task's command, wait until
+    /// it is ready and keep it running
+    #[serde(default)]
+    pub service: bool,
+
+    /// How to tell that a service is ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<ReadyCheck>,
+
+    /// When to restart a service whose process exits (default never)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
+
+    /// Stop restarting a service after this many restarts
+    #[serde(
+        rename = "max-restarts",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_restarts: Option<u32>,
+
+    /// Files `rtask watch` re-runs this task for; `true` watches the
+    /// task's `source` whenever it runs
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_watch"
+    )]
+    pub watch: Option<WatchConfig>,
+
+    /// Tasks sharing a concurrency group never run at the same time
+    #[serde(
+        rename = "concurrency-group",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub concurrency_group: Option<ConcurrencyGroup>,
+
+    /// Scheduling hint: among tasks ready to start in parallel, higher
+    /// priorities start first (default 0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+
+    /// Default timeout of the task's commands (e.g., "10m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+/// A task's concurrency group: a name, or a mapping that can also hold the
+/// group across rtask processes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ConcurrencyGroup {
+    Name(String),
+    Detail(ConcurrencyGroupDetail),
+}
+
+/// A `concurrency-group` written as a mapping
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConcurrencyGroupDetail {
+    pub name: String,
+
+    /// Also wait for members running in other rtask processes on this machine
+    #[serde(rename = "across-processes", default)]
+    pub across_processes: bool,
+}
+
+impl ConcurrencyGroup {
+    pub fn name(&self) -> &str {
+        match self {
+            ConcurrencyGroup::Name(name) => name,
+            ConcurrencyGroup::Detail(detail) => &detail.name,
+        }
+    }
+
+    pub fn across_processes(&self) -> bool {
+        match self {
+            ConcurrencyGroup::Name(_) => false,
+            ConcurrencyGroup::Detail(detail) => detail.across_processes,
+        }
+    }
+}
+
+/// Files a task is re-run for by `rtask watch`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct WatchConfig {
+    /// Glob patterns of watched files, relative to the config file
+    #[serde(default)]
+    pub paths: Vec<String>,
+
+    /// Glob patterns of files that never trigger a re-run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    /// Wait until files have stopped changing for this long (default 300ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debounce: Option<String>,
+
+    /// Also skip files ignored by the `.gitignore` next to the config file
+    /// (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gitignore: Option<bool>,
+
+    /// What a change during a run does (default queue)
+    #[serde(rename = "in-flight", default, skip_serializing_if = "Option::is_none")]
+    pub in_flight: Option<InFlightPolicy>,
+
+    /// Watch whenever the task runs, not only with `rtask watch` or
+    /// `--watch` (as `watch: true` does for the task's source)
+    #[serde(default)]
+    pub always: bool,
+}
+
+/// What a change does while the watched task is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InFlightPolicy {
+    /// Run again once the current run finishes
+    #[default]
+    Queue,
+    /// Stop the current run and start over
+    Restart,
+    /// Ignore the change
+    Skip,
+}
+
+/// Readiness check for a service task; set one of `port`, `log` or `command`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ReadyCheck {
+    /// TCP port on localhost that accepts connections once the service is ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// Regex matching an output line the service prints once ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+
+    /// Command that succeeds once the service is ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// How long to wait for readiness (default 30s)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+/// When a service's process is restarted after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
 }
 
 /// A run item - can be a command, subtask, or environment setter
@@ -81,11 +555,11 @@ pub enum Run {
     SimpleCommand(String),
 
     /// Complex run item with conditionals and multiple actions
-    Complex(RunItem),
+    Complex(Box<RunItem>),
 }
 
 /// A complex run item with conditions and actions
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct RunItem {
     /// Conditions that must be met for this run item to execute
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -99,6 +573,14 @@ pub struct RunItem {
     )]
     pub command: Vec<Command>,
 
+    /// Commands run as a pipeline, each one's stdout feeding the next one's stdin
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_commands"
+    )]
+    pub pipe: Vec<Command>,
+
     /// Subtasks to execute
     #[serde(
         default,
@@ -107,6 +589,15 @@ pub struct RunItem {
     )]
     pub task: Vec<SubTask>,
 
+    /// Run items executed concurrently, each on its own copy of the variables,
+    /// or `true` to run this item's own commands and subtasks concurrently
+    #[serde(default, skip_serializing_if = "Parallel::is_unset")]
+    pub parallel: Parallel,
+
+    /// Cancel the other parallel branches when one fails (default: true)
+    #[serde(rename = "fail-fast", default, skip_serializing_if = "Option::is_none")]
+    pub fail_fast: Option<bool>,
+
     /// Environment variables to set
     #[serde(
         rename = "set-environment",
@@ -114,6 +605,78 @@ pub struct RunItem {
         skip_serializing_if = "HashMap::is_empty"
     )]
     pub set_environment: HashMap<String, OptionString>,
+
+    /// Suppress the command echo for every command in this item
+    #[serde(default)]
+    pub quiet: bool,
+
+    /// Keep running the task when this item fails
+    #[serde(rename = "allow-failure", default)]
+    pub allow_failure: bool,
+
+    /// Variables with lists of values; the item runs once per combination
+    /// of values
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        deserialize_with = "deserialize_matrix"
+    )]
+    pub matrix: IndexMap<String, Vec<String>>,
+}
+
+/// The `parallel` key of a run item
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Parallel {
+    /// `parallel: true`: the item's commands, pipeline and subtasks are the branches
+    Flag(bool),
+
+    /// A parallel group of run items
+    Group(Vec<Run>),
+}
+
+impl Default for Parallel {
+    fn default() -> Self {
+        Parallel::Group(Vec::new())
+    }
+}
+
+impl Parallel {
+    /// The run items of a parallel group (empty for the flag)
+    pub fn group(&self) -> &[Run] {
+        match self {
+            Parallel::Group(items) => items,
+            Parallel::Flag(_) => &[],
+        }
+    }
+
+    /// Whether `parallel: true` is set
+    pub fn is_flag(&self) -> bool {
+        matches!(self, Parallel::Flag(true))
+    }
+
+    /// Whether neither the flag nor a group is given
+    pub fn is_unset(&self) -> bool {
+        !self.is_flag() && self.group().is_empty()
+    }
+}
+
+impl<'de> Deserialize<'de> for Parallel {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        use serde_yaml::Value;
+
+        let value = Value::deserialize(deserializer)?;
+        match value {
+            Value::Bool(flag) => Ok(Parallel::Flag(flag)),
+            _ => deserialize_run_items(value)
+                .map(Parallel::Group)
+                .map_err(D::Error::custom),
+        }
+    }
 }
 
 /// A command to execute
@@ -124,13 +687,14 @@ pub enum Command {
     Simple(String),
 
     /// Complex command with additional options
-    Complex(CommandDetail),
+    Complex(Box<CommandDetail>),
 }
 
 /// Detailed command specification
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CommandDetail {
-    /// The command to execute
+    /// The command to execute; a list of words is joined with shell quoting
+    #[serde(deserialize_with = "deserialize_exec")]
     pub exec: String,
 
     /// What to print when running (defaults to exec)
@@ -144,6 +708,79 @@ pub struct CommandDetail {
     /// Working directory for the command
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dir: Option<String>,
+
+    /// Variable that receives the command's captured stdout
+    #[serde(rename = "output-var", skip_serializing_if = "Option::is_none")]
+    pub output_var: Option<String>,
+
+    /// Regex selecting which output lines are shown on the console
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+
+    /// File that receives the command's stdout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+
+    /// File that receives the command's stderr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+
+    /// Append to the redirection files instead of truncating them
+    #[serde(default)]
+    pub append: bool,
+
+    /// File that receives a timestamped copy of the output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+
+    /// Interval between "still running" lines (e.g., "30s")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<String>,
+
+    /// Kill the command if it runs longer than this (e.g., "30s")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+
+    /// How many times to re-run the command after it fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+
+    /// Wait before the first retry (e.g., "1s"), doubling for each one after
+    #[serde(
+        rename = "retry-delay",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retry_delay: Option<String>,
+
+    /// Keep running the task when this command fails
+    #[serde(rename = "allow-failure", default)]
+    pub allow_failure: bool,
+
+    /// Leave the command running while the task goes on; it is stopped when
+    /// the task ends
+    #[serde(default)]
+    pub background: bool,
+
+    /// Run the command in a pseudo-terminal, so it keeps its colors and can
+    /// prompt even when rtask pipes its output
+    #[serde(default)]
+    pub tty: bool,
+
+    /// Run through the interpreter (default true); `false` splits `exec`
+    /// into words and spawns them directly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<bool>,
+
+    /// Interpreter flags for this command, replacing the global `shell-opts`
+    /// (an empty list runs it without any)
+    #[serde(
+        rename = "shell-opts",
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_shell_opts"
+    )]
+    pub shell_opts: Option<Vec<String>>,
 }
 
 /// A reference to a subtask to execute
@@ -163,13 +800,18 @@ pub struct SubTaskDetail {
     /// Name of the task to run
     pub name: String,
 
+    /// Config file defining the task, relative to this config's directory;
+    /// the task runs with that file's directory as its working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
     /// Options to pass to the subtask
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub options: HashMap<String, String>,
 }
 
 /// A conditional expression
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct When {
     /// Check if values are equal
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -179,6 +821,14 @@ pub struct When {
     #[serde(rename = "not-equal", skip_serializing_if = "Option::is_none")]
     pub not_equal: Option<WhenComparison>,
 
+    /// Check if left is greater than right (numerically for numbers)
+    #[serde(rename = "greater-than", skip_serializing_if = "Option::is_none")]
+    pub greater_than: Option<WhenComparison>,
+
+    /// Check if left is less than right (numerically for numbers)
+    #[serde(rename = "less-than", skip_serializing_if = "Option::is_none")]
+    pub less_than: Option<WhenComparison>,
+
     /// Check if a command succeeds
     #[serde(skip_serializing_if = "Option::is_none")]
     pub command: Option<String>,
@@ -202,6 +852,143 @@ pub struct When {
     /// Check if option is not set
     #[serde(rename = "option-not-set", skip_serializing_if = "Option::is_none")]
     pub option_not_set: Option<String>,
+
+    /// Check if a TCP port accepts connections
+    #[serde(rename = "port-open", skip_serializing_if = "Option::is_none")]
+    pub port_open: Option<PortCheck>,
+
+    /// Check if an http:// URL answers with a 2xx status
+    #[serde(rename = "http-ok", skip_serializing_if = "Option::is_none")]
+    pub http_ok: Option<HttpCheck>,
+
+    /// Unrecognized keys, kept so validation can report typos
+    #[serde(flatten, skip_serializing)]
+    pub unknown: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl When {
+    /// Keys accepted inside a when block
+    pub const KEYS: &'static [&'static str] = &[
+        "equal",
+        "not-equal",
+        "greater-than",
+        "less-than",
+        "command",
+        "exists",
+        "env-set",
+        "env-not-set",
+        "option-set",
+        "option-not-set",
+        "port-open",
+        "http-ok",
+    ];
+
+    /// Check whether no condition is set at all
+    pub fn is_empty(&self) -> bool {
+        self.equal.is_none()
+            && self.not_equal.is_none()
+            && self.greater_than.is_none()
+            && self.less_than.is_none()
+            && self.command.is_none()
+            && self.exists.is_none()
+            && self.env_set.is_none()
+            && self.env_not_set.is_none()
+            && self.option_set.is_none()
+            && self.option_not_set.is_none()
+            && self.port_open.is_none()
+            && self.http_ok.is_none()
+    }
+}
+
+/// Target of a `port-open` condition: a port on localhost, `host:port`, or a
+/// mapping that can also wait for the port to open
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PortCheck {
+    Port(u16),
+    Address(String),
+    Detail(PortCheckDetail),
+}
+
+/// A `port-open` condition written as a mapping
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PortCheckDetail {
+    /// Host to connect to (default localhost)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    pub port: u16,
+
+    #[serde(flatten)]
+    pub wait: ProbeWait,
+}
+
+/// Target of an `http-ok` condition: a URL, or a mapping that can also wait
+/// for the URL to answer
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HttpCheck {
+    Url(String),
+    Detail(HttpCheckDetail),
+}
+
+/// An `http-ok` condition written as a mapping
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HttpCheckDetail {
+    pub url: String,
+
+    #[serde(flatten)]
+    pub wait: ProbeWait,
+}
+
+/// How long a network condition keeps retrying before it counts as false
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ProbeWait {
+    /// Keep retrying for this long (default: check once)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+
+    /// Pause between attempts (default 1s)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+}
+
+impl PortCheck {
+    /// `host:port` to connect to
+    pub fn address(&self) -> String {
+        match self {
+            PortCheck::Port(port) => format!("localhost:{}", port),
+            PortCheck::Address(address) => address.clone(),
+            PortCheck::Detail(detail) => format!(
+                "{}:{}",
+                detail.host.as_deref().unwrap_or("localhost"),
+                detail.port
+            ),
+        }
+    }
+
+    pub fn wait(&self) -> ProbeWait {
+        match self {
+            PortCheck::Detail(detail) => detail.wait.clone(),
+            _ => ProbeWait::default(),
+        }
+    }
+}
+
+impl HttpCheck {
+    pub fn url(&self) -> &str {
+        match self {
+            HttpCheck::Url(url) => url,
+            HttpCheck::Detail(detail) => &detail.url,
+        }
+    }
+
+    pub fn wait(&self) -> ProbeWait {
+        match self {
+            HttpCheck::Url(_) => ProbeWait::default(),
+            HttpCheck::Detail(detail) => detail.wait.clone(),
+        }
+    }
 }
 
 /// A comparison for when conditions
@@ -245,6 +1032,10 @@ pub struct TaskOption {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub environment: Option<String>,
 
+    /// Allowed values, offered by shell completion
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+
     /// Private option (hidden from help)
     #[serde(default)]
     pub private: bool,
@@ -272,6 +1063,55 @@ pub struct Arg {
     /// Private argument (hidden from help)
     #[serde(default)]
     pub private: bool,
+
+    /// Value type, checked before the task runs (string, int, float, path,
+    /// file, dir, duration or url)
+    #[serde(rename = "type", default = "default_option_type")]
+    pub arg_type: String,
+
+    /// Minimum number of values; setting `min` or `max` makes the argument
+    /// take several values (only allowed for the last argument)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<usize>,
+
+    /// Maximum number of values (unbounded if only `min` is set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<usize>,
+}
+
+impl Default for Arg {
+    fn default() -> Self {
+        Arg {
+            usage: None,
+            default: None,
+            required: false,
+            private: false,
+            arg_type: default_option_type(),
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl Arg {
+    /// Whether the argument takes a variable number of values
+    pub fn is_variadic(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    /// Fewest values the argument accepts
+    pub fn min_values(&self) -> usize {
+        self.min.unwrap_or(usize::from(self.required))
+    }
+
+    /// Most values the argument accepts
+    pub fn max_values(&self) -> usize {
+        match self.max {
+            Some(max) => max,
+            None if self.is_variadic() => usize::MAX,
+            None => 1,
+        }
+    }
 }
 
 /// An optional string value (used for environment variables)
@@ -337,6 +1177,128 @@ where
     }
 }
 
+/// Custom deserializer for `exec`, which is a command line or a list of words
+fn deserialize_exec<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_yaml::Value;
+
+    let value = Value::deserialize(deserializer)?;
+
+    match value {
+        Value::String(s) => Ok(s),
+        // Quoted so that splitting the line gives back the same words
+        Value::Sequence(_) => {
+            let words = Vec::<String>::deserialize(value).map_err(D::Error::custom)?;
+            Ok(crate::utils::shell::join(&words))
+        }
+        _ => Err(D::Error::custom("exec must be a string or a list of words")),
+    }
+}
+
+/// Deserialize interpreter flags given as a shell-quoted string or a list
+fn deserialize_shell_opts<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_yaml::Value;
+
+    let value = Value::deserialize(deserializer)?;
+
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => crate::utils::shell::split(&s)
+            .map(Some)
+            .map_err(|e| D::Error::custom(format!("invalid shell-opts '{}': {}", s, e))),
+        Value::Sequence(_) => Vec::<String>::deserialize(value)
+            .map(Some)
+            .map_err(D::Error::custom),
+        _ => Err(D::Error::custom(
+            "shell-opts must be a string or a list of flags",
+        )),
+    }
+}
+
+/// Deserialize task names given as a single name or a list
+fn deserialize_task_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_yaml::Value;
+
+    let value = Value::deserialize(deserializer)?;
+
+    match value {
+        Value::String(s) => Ok(vec![s]),
+        Value::Sequence(_) => Vec::<String>::deserialize(value).map_err(D::Error::custom),
+        Value::Null => Ok(Vec::new()),
+        _ => Err(D::Error::custom(
+            "deps must be a task name or a list of names",
+        )),
+    }
+}
+
+/// Deserialize a watch block, or `true` to watch the task's source whenever
+/// it runs
+fn deserialize_watch<'de, D>(deserializer: D) -> Result<Option<WatchConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_yaml::Value;
+
+    let value = Value::deserialize(deserializer)?;
+
+    match value {
+        Value::Bool(true) => Ok(Some(WatchConfig {
+            always: true,
+            ..WatchConfig::default()
+        })),
+        Value::Bool(false) | Value::Null => Ok(None),
+        Value::Mapping(_) => WatchConfig::deserialize(value)
+            .map(Some)
+            .map_err(D::Error::custom),
+        _ => Err(D::Error::custom("watch must be true or a mapping")),
+    }
+}
+
+/// Deserialize a matrix, whose variables each take a value or a list of
+/// values; numbers and booleans are read as their text
+fn deserialize_matrix<'de, D>(deserializer: D) -> Result<IndexMap<String, Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_yaml::Value;
+
+    let scalar = |name: &str, value: Value| match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(D::Error::custom(format!(
+            "matrix values of '{}' must be strings, numbers or booleans",
+            name
+        ))),
+    };
+    let matrix = IndexMap::<String, Value>::deserialize(deserializer)?;
+    let mut expanded = IndexMap::new();
+    for (name, value) in matrix {
+        let values = match value {
+            Value::Sequence(seq) => seq
+                .into_iter()
+                .map(|value| scalar(&name, value))
+                .collect::<Result<_, _>>()?,
+            value => vec![scalar(&name, value)?],
+        };
+        expanded.insert(name, values);
+    }
+    Ok(expanded)
+}
+
 /// Custom deserializer for subtasks that handles both single values and arrays
 fn deserialize_subtasks<'de, D>(deserializer: D) -> Result<Vec<SubTask>, D::Error>
 where
@@ -405,6 +1367,29 @@ tasks:
         assert!(task.options.contains_key("name"));
     }
 
+    #[test]
+    fn test_deserialize_matrix() {
+        let yaml = r#"
+tasks:
+  test:
+    matrix:
+      python: [3.11, "3.12"]
+      debug: [true, false]
+      os: linux
+    run: tox -e py${python}
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let matrix = &config.tasks.get("test").unwrap().matrix;
+        let vars: Vec<&str> = matrix.keys().map(String::as_str).collect();
+        assert_eq!(vars, vec!["python", "debug", "os"]);
+        assert_eq!(matrix["python"], vec!["3.11", "3.12"]);
+        assert_eq!(matrix["debug"], vec!["true", "false"]);
+        assert_eq!(matrix["os"], vec!["linux"]);
+
+        let nested = "tasks:\n  t:\n    matrix: {os: [[linux]]}\n    run: echo\n";
+        assert!(serde_yaml::from_str::<Config>(nested).is_err());
+    }
+
     #[test]
     fn test_deserialize_when_conditions() {
         let yaml = r#"
@@ -421,4 +1406,25 @@ tasks:
         let task = config.tasks.get("conditional").unwrap();
         assert_eq!(task.run.len(), 1);
     }
+
+    #[test]
+    fn test_deserialize_when_unknown_keys() {
+        let yaml = r#"
+tasks:
+  conditional:
+    run:
+      - when:
+          - enviroment-set: HOME
+        command: echo "home"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let task = config.tasks.get("conditional").unwrap();
+        match &task.run[0] {
+            Run::Complex(item) => {
+                assert!(item.when[0].is_empty());
+                assert!(item.when[0].unknown.contains_key("enviroment-set"));
+            }
+            _ => panic!("expected complex run item"),
+        }
+    }
 }
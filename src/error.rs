@@ -1,7 +1,14 @@
 //! Error types for Rtask
+//!
+//! The `Display` text of every error is its English catalog message; the
+//! [`CatalogMessage`] impls give the message IDs used to print them in other
+//! languages. Detail strings built elsewhere, such as the text of
+//! [`ConfigError::Invalid`], are not translated.
 
+use crate::runner::signals::signal_name;
+use crate::ui::{Catalog, CatalogMessage};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 /// Result type alias for Rtask operations
@@ -18,6 +25,10 @@ pub enum RtaskError {
     #[error("Execution error: {0}")]
     Execution(#[from] ExecutionError),
 
+    /// Task execution errors annotated with the failing task
+    #[error("Execution error: {}", .0.error)]
+    Task(Box<TaskFailure>),
+
     /// Variable interpolation errors
     #[error("Interpolation error: {0}")]
     Interpolation(#[from] InterpolationError),
@@ -31,6 +42,42 @@ pub enum RtaskError {
     Yaml(#[from] serde_yaml::Error),
 }
 
+/// Where and how a task failed
+#[derive(Debug)]
+pub struct TaskFailure {
+    /// Name of the task that was invoked
+    pub task: String,
+    /// The command that failed, if the failure came from a command
+    pub command: Option<String>,
+    /// Config file defining the task
+    pub config_path: Option<PathBuf>,
+    /// Line of the task definition in the config file
+    pub line: Option<usize>,
+    /// The underlying error
+    pub error: ExecutionError,
+}
+
+impl RtaskError {
+    /// Process exit code for this error
+    ///
+    /// A failed command passes its own exit code through so wrapper scripts can
+    /// tell failures apart, a timed out one exits with 124 like `timeout(1)`,
+    /// and an interrupted run with 128 + the signal number, as shells do;
+    /// everything else exits with 1.
+    pub fn exit_code(&self) -> i32 {
+        self.execution_error().map_or(1, ExecutionError::exit_code)
+    }
+
+    /// The execution error behind this error, if any
+    pub fn execution_error(&self) -> Option<&ExecutionError> {
+        match self {
+            RtaskError::Execution(err) => Some(err),
+            RtaskError::Task(failure) => Some(&failure.error),
+            _ => None,
+        }
+    }
+}
+
 /// Configuration parsing and validation errors
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -52,11 +99,56 @@ pub enum ConfigError {
     #[error("Task '{0}' is not defined")]
     TaskNotFound(String),
 
+    #[error("Group '{0}' is not defined")]
+    GroupNotFound(String),
+
+    #[error("Task '{0}' is private and only runs as a subtask (use --include-private to run it directly)")]
+    PrivateTask(String),
+
+    #[error("Task '{0}' is already defined")]
+    DuplicateTask(String),
+
     #[error("Circular dependency detected: {0}")]
     CircularDependency(String),
 
+    #[error(
+        "Unknown key '{key}' in when condition of task '{task}' (expected one of: {expected})"
+    )]
+    UnknownWhenKey {
+        task: String,
+        key: String,
+        expected: String,
+    },
+
+    #[error("Test '{test}' runs undefined task '{task}'")]
+    UnknownTestTask { test: String, task: String },
+
+    #[error("Empty when condition in task '{0}'")]
+    EmptyWhen(String),
+
     #[error("Failed to include file '{path}': {error}")]
-    IncludeFile { path: PathBuf, error: String },
+    IncludeFile {
+        path: PathBuf,
+        error: String,
+        /// Position of the YAML error in the included file, if that is what failed
+        location: Option<serde_yaml::Location>,
+    },
+}
+
+impl ConfigError {
+    /// Failure to include `path` because of `err`, keeping the position of a
+    /// YAML error within the file
+    pub fn include_failed(path: &Path, err: &RtaskError) -> Self {
+        let location = match err {
+            RtaskError::Yaml(yaml) => yaml.location(),
+            _ => None,
+        };
+        ConfigError::IncludeFile {
+            path: path.to_path_buf(),
+            error: err.to_string(),
+            location,
+        }
+    }
 }
 
 /// Task execution errors
@@ -65,6 +157,31 @@ pub enum ExecutionError {
     #[error("Command failed with exit code {0:?}")]
     CommandFailed(Option<i32>),
 
+    #[error("Command timed out after {0}")]
+    Timeout(String),
+
+    #[error("Interrupted by {}", signal_name(*.0))]
+    Interrupted(i32),
+
+    #[error("{} of {total} tasks failed: {}", failed.len(), failed.join(", "))]
+    TasksFailed { failed: Vec<String>, total: usize },
+
+    #[error("{} of {total} tests failed: {}", failed.len(), failed.join(", "))]
+    TestsFailed { failed: Vec<String>, total: usize },
+
+    #[error("{} of {total} combinations of '{item}' failed: {}", failed.len(), failed.join(", "))]
+    CombinationsFailed {
+        item: String,
+        failed: Vec<String>,
+        total: usize,
+    },
+
+    #[error("{} parallel branches failed: {}", .0.len(), join_errors(.0))]
+    ParallelFailed(Vec<ExecutionError>),
+
+    #[error("Task recursion detected: {0}")]
+    RecursiveTask(String),
+
     #[error("Failed condition: {0}")]
     FailedCondition(String),
 
@@ -74,6 +191,30 @@ pub enum ExecutionError {
     #[error("Invalid option value for '{name}': {error}")]
     InvalidOption { name: String, error: String },
 
+    #[error("Invalid value for argument '{name}': {error}")]
+    InvalidArg { name: String, error: String },
+
+    #[error("Type mismatch in when condition: {0}")]
+    TypeMismatch(String),
+
+    #[error("Interpreter not found: {0}")]
+    InterpreterNotFound(String),
+
+    #[error("Internal error while running task '{task}': {message}")]
+    Panicked { task: String, message: String },
+
+    #[error("Cannot run subtask '{task}': {error}")]
+    Subtask { task: String, error: String },
+
+    #[error("Service '{name}' did not become ready: {reason}")]
+    ServiceNotReady { name: String, reason: String },
+
+    #[error("Group '{0}' is not up")]
+    GroupNotUp(String),
+
+    #[error("Replay error: {0}")]
+    Replay(String),
+
     #[error("Cache error: {0}")]
     Cache(String),
 
@@ -94,6 +235,247 @@ pub enum InterpolationError {
     RecursiveInterpolation,
 }
 
+impl ExecutionError {
+    /// The signal that interrupted the run, if this error, or one of the
+    /// parallel failures it collects, comes from an interrupt
+    pub fn interrupted_by(&self) -> Option<i32> {
+        match self {
+            ExecutionError::Interrupted(signal) => Some(*signal),
+            ExecutionError::ParallelFailed(errors) => {
+                errors.iter().find_map(ExecutionError::interrupted_by)
+            }
+            _ => None,
+        }
+    }
+
+    /// Exit code standing for this failure, as described for
+    /// [`RtaskError::exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        if let Some(signal) = self.interrupted_by() {
+            return 128 + signal;
+        }
+        match self {
+            ExecutionError::CommandFailed(Some(code)) if *code != 0 => *code,
+            ExecutionError::Timeout(_) => 124,
+            _ => 1,
+        }
+    }
+}
+
+fn join_errors(errors: &[ExecutionError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl CatalogMessage for RtaskError {
+    fn message_parts(&self, catalog: &Catalog) -> (&'static str, Vec<(&'static str, String)>) {
+        let (id, error) = match self {
+            RtaskError::Config(err) => ("error.config", err.localized_in(catalog)),
+            RtaskError::Execution(err) => ("error.execution", err.localized_in(catalog)),
+            RtaskError::Task(failure) => ("error.execution", failure.error.localized_in(catalog)),
+            RtaskError::Interpolation(err) => ("error.interpolation", err.localized_in(catalog)),
+            RtaskError::Io(err) => ("error.io", err.to_string()),
+            RtaskError::Yaml(err) => ("error.yaml", err.to_string()),
+        };
+        (id, vec![("error", error)])
+    }
+
+    /// ID of the underlying error rather than of its category prefix
+    fn message_id(&self) -> &'static str {
+        match self {
+            RtaskError::Config(err) => err.message_id(),
+            RtaskError::Execution(err) => err.message_id(),
+            RtaskError::Task(failure) => failure.error.message_id(),
+            RtaskError::Interpolation(err) => err.message_id(),
+            RtaskError::Io(_) => "error.io",
+            RtaskError::Yaml(_) => "error.yaml",
+        }
+    }
+}
+
+impl CatalogMessage for ConfigError {
+    fn message_parts(&self, _catalog: &Catalog) -> (&'static str, Vec<(&'static str, String)>) {
+        match self {
+            ConfigError::NotFound(searched) => {
+                ("config.not-found", vec![("searched", searched.clone())])
+            }
+            ConfigError::Invalid(message) => ("config.invalid", vec![("message", message.clone())]),
+            ConfigError::SourceWithoutTarget => ("config.source-without-target", vec![]),
+            ConfigError::TargetWithoutSource => ("config.target-without-source", vec![]),
+            ConfigError::DuplicateNames(name) => {
+                ("config.duplicate-names", vec![("name", name.clone())])
+            }
+            ConfigError::TaskNotFound(task) => {
+                ("config.task-not-found", vec![("task", task.clone())])
+            }
+            ConfigError::GroupNotFound(group) => {
+                ("config.group-not-found", vec![("group", group.clone())])
+            }
+            ConfigError::PrivateTask(task) => ("config.private-task", vec![("task", task.clone())]),
+            ConfigError::DuplicateTask(task) => {
+                ("config.duplicate-task", vec![("task", task.clone())])
+            }
+            ConfigError::CircularDependency(chain) => {
+                ("config.circular-dependency", vec![("chain", chain.clone())])
+            }
+            ConfigError::UnknownWhenKey {
+                task,
+                key,
+                expected,
+            } => (
+                "config.unknown-when-key",
+                vec![
+                    ("task", task.clone()),
+                    ("key", key.clone()),
+                    ("expected", expected.clone()),
+                ],
+            ),
+            ConfigError::UnknownTestTask { test, task } => (
+                "config.unknown-test-task",
+                vec![("test", test.clone()), ("task", task.clone())],
+            ),
+            ConfigError::EmptyWhen(task) => ("config.empty-when", vec![("task", task.clone())]),
+            ConfigError::IncludeFile { path, error, .. } => (
+                "config.include-file",
+                vec![
+                    ("path", path.display().to_string()),
+                    ("error", error.clone()),
+                ],
+            ),
+        }
+    }
+}
+
+impl CatalogMessage for ExecutionError {
+    fn message_parts(&self, catalog: &Catalog) -> (&'static str, Vec<(&'static str, String)>) {
+        match self {
+            ExecutionError::CommandFailed(code) => (
+                "execution.command-failed",
+                vec![("code", format!("{:?}", code))],
+            ),
+            ExecutionError::Timeout(duration) => {
+                ("execution.timeout", vec![("duration", duration.clone())])
+            }
+            ExecutionError::Interrupted(signal) => (
+                "execution.interrupted",
+                vec![("signal", signal_name(*signal))],
+            ),
+            ExecutionError::TasksFailed { failed, total } => (
+                "execution.tasks-failed",
+                vec![
+                    ("count", failed.len().to_string()),
+                    ("total", total.to_string()),
+                    ("tasks", failed.join(", ")),
+                ],
+            ),
+            ExecutionError::CombinationsFailed {
+                item,
+                failed,
+                total,
+            } => (
+                "execution.combinations-failed",
+                vec![
+                    ("count", failed.len().to_string()),
+                    ("total", total.to_string()),
+                    ("item", item.clone()),
+                    ("combinations", failed.join(", ")),
+                ],
+            ),
+            ExecutionError::TestsFailed { failed, total } => (
+                "execution.tests-failed",
+                vec![
+                    ("count", failed.len().to_string()),
+                    ("total", total.to_string()),
+                    ("tests", failed.join(", ")),
+                ],
+            ),
+            ExecutionError::ParallelFailed(errors) => (
+                "execution.parallel-failed",
+                vec![
+                    ("count", errors.len().to_string()),
+                    (
+                        "errors",
+                        errors
+                            .iter()
+                            .map(|err| err.localized_in(catalog))
+                            .collect::<Vec<_>>()
+                            .join("; "),
+                    ),
+                ],
+            ),
+            ExecutionError::RecursiveTask(chain) => {
+                ("execution.recursive-task", vec![("chain", chain.clone())])
+            }
+            ExecutionError::FailedCondition(condition) => (
+                "execution.failed-condition",
+                vec![("condition", condition.clone())],
+            ),
+            ExecutionError::MissingOption(option) => {
+                ("execution.missing-option", vec![("option", option.clone())])
+            }
+            ExecutionError::InvalidOption { name, error } => (
+                "execution.invalid-option",
+                vec![("option", name.clone()), ("error", error.clone())],
+            ),
+            ExecutionError::InvalidArg { name, error } => (
+                "execution.invalid-arg",
+                vec![("arg", name.clone()), ("error", error.clone())],
+            ),
+            ExecutionError::TypeMismatch(message) => (
+                "execution.type-mismatch",
+                vec![("message", message.clone())],
+            ),
+            ExecutionError::InterpreterNotFound(interpreter) => (
+                "execution.interpreter-not-found",
+                vec![("interpreter", interpreter.clone())],
+            ),
+            ExecutionError::Panicked { task, message } => (
+                "execution.panicked",
+                vec![("task", task.clone()), ("message", message.clone())],
+            ),
+            ExecutionError::Subtask { task, error } => (
+                "execution.subtask",
+                vec![("task", task.clone()), ("error", error.clone())],
+            ),
+            ExecutionError::ServiceNotReady { name, reason } => (
+                "execution.service-not-ready",
+                vec![("service", name.clone()), ("reason", reason.clone())],
+            ),
+            ExecutionError::GroupNotUp(group) => {
+                ("execution.group-not-up", vec![("group", group.clone())])
+            }
+            ExecutionError::Replay(message) => {
+                ("execution.replay", vec![("message", message.clone())])
+            }
+            ExecutionError::Cache(message) => {
+                ("execution.cache", vec![("message", message.clone())])
+            }
+            ExecutionError::Environment(message) => {
+                ("execution.environment", vec![("message", message.clone())])
+            }
+        }
+    }
+}
+
+impl CatalogMessage for InterpolationError {
+    fn message_parts(&self, _catalog: &Catalog) -> (&'static str, Vec<(&'static str, String)>) {
+        match self {
+            InterpolationError::UndefinedVariable(variable) => (
+                "interpolation.undefined-variable",
+                vec![("variable", variable.clone())],
+            ),
+            InterpolationError::InvalidSyntax(message) => (
+                "interpolation.invalid-syntax",
+                vec![("message", message.clone())],
+            ),
+            InterpolationError::RecursiveInterpolation => ("interpolation.recursive", vec![]),
+        }
+    }
+}
+
 /// Specialized result type for configuration operations
 pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
 
@@ -108,3 +490,138 @@ pub type InterpolationResult<T> = std::result::Result<T, InterpolationError>;
 pub fn is_failed_condition(err: &ExecutionError) -> bool {
     matches!(err, ExecutionError::FailedCondition(_))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english_messages_match_display() {
+        let config = [
+            ConfigError::NotFound("rtask.yml".to_string()),
+            ConfigError::Invalid("bad".to_string()),
+            ConfigError::SourceWithoutTarget,
+            ConfigError::TargetWithoutSource,
+            ConfigError::DuplicateNames("n".to_string()),
+            ConfigError::TaskNotFound("t".to_string()),
+            ConfigError::GroupNotFound("g".to_string()),
+            ConfigError::PrivateTask("t".to_string()),
+            ConfigError::DuplicateTask("t".to_string()),
+            ConfigError::CircularDependency("a -> b -> a".to_string()),
+            ConfigError::UnknownWhenKey {
+                task: "t".to_string(),
+                key: "k".to_string(),
+                expected: "os, env".to_string(),
+            },
+            ConfigError::UnknownTestTask {
+                test: "smoke".to_string(),
+                task: "t".to_string(),
+            },
+            ConfigError::EmptyWhen("t".to_string()),
+            ConfigError::IncludeFile {
+                path: PathBuf::from("more.yml"),
+                error: "missing".to_string(),
+                location: None,
+            },
+        ];
+        let execution = [
+            ExecutionError::CommandFailed(Some(2)),
+            ExecutionError::CommandFailed(None),
+            ExecutionError::Timeout("30s".to_string()),
+            ExecutionError::Interrupted(2),
+            ExecutionError::TasksFailed {
+                failed: vec!["a".to_string(), "b".to_string()],
+                total: 3,
+            },
+            ExecutionError::TestsFailed {
+                failed: vec!["smoke".to_string()],
+                total: 1,
+            },
+            ExecutionError::CombinationsFailed {
+                item: "build".to_string(),
+                failed: vec!["os=linux".to_string()],
+                total: 2,
+            },
+            ExecutionError::ParallelFailed(vec![
+                ExecutionError::CommandFailed(Some(1)),
+                ExecutionError::GroupNotUp("dev".to_string()),
+            ]),
+            ExecutionError::RecursiveTask("a -> a".to_string()),
+            ExecutionError::FailedCondition("os".to_string()),
+            ExecutionError::MissingOption("o".to_string()),
+            ExecutionError::InvalidOption {
+                name: "o".to_string(),
+                error: "e".to_string(),
+            },
+            ExecutionError::InvalidArg {
+                name: "a".to_string(),
+                error: "e".to_string(),
+            },
+            ExecutionError::TypeMismatch("m".to_string()),
+            ExecutionError::InterpreterNotFound("fish".to_string()),
+            ExecutionError::Panicked {
+                task: "t".to_string(),
+                message: "m".to_string(),
+            },
+            ExecutionError::Subtask {
+                task: "t".to_string(),
+                error: "e".to_string(),
+            },
+            ExecutionError::ServiceNotReady {
+                name: "db".to_string(),
+                reason: "timed out".to_string(),
+            },
+            ExecutionError::GroupNotUp("dev".to_string()),
+            ExecutionError::Replay("m".to_string()),
+            ExecutionError::Cache("m".to_string()),
+            ExecutionError::Environment("m".to_string()),
+        ];
+        let interpolation = [
+            InterpolationError::UndefinedVariable("v".to_string()),
+            InterpolationError::InvalidSyntax("m".to_string()),
+            InterpolationError::RecursiveInterpolation,
+        ];
+
+        let mut errors: Vec<RtaskError> = Vec::new();
+        errors.extend(config.into_iter().map(RtaskError::from));
+        errors.extend(execution.into_iter().map(RtaskError::from));
+        errors.extend(interpolation.into_iter().map(RtaskError::from));
+        errors.push(RtaskError::Task(Box::new(TaskFailure {
+            task: "build".to_string(),
+            command: None,
+            config_path: None,
+            line: None,
+            error: ExecutionError::CommandFailed(Some(1)),
+        })));
+        errors.push(io::Error::other("disk full").into());
+
+        let english = Catalog::for_locale("en");
+        for err in &errors {
+            assert_eq!(err.localized_in(&english), err.to_string());
+        }
+    }
+
+    #[test]
+    fn test_message_id_names_the_underlying_error() {
+        let err = RtaskError::from(ConfigError::TaskNotFound("t".to_string()));
+        assert_eq!(err.message_id(), "config.task-not-found");
+        assert_eq!(
+            RtaskError::from(io::Error::other("x")).message_id(),
+            "error.io"
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let code = |err: ExecutionError| RtaskError::from(err).exit_code();
+        assert_eq!(code(ExecutionError::CommandFailed(Some(3))), 3);
+        assert_eq!(code(ExecutionError::CommandFailed(None)), 1);
+        assert_eq!(code(ExecutionError::Timeout("1s".to_string())), 124);
+        assert_eq!(code(ExecutionError::Interrupted(2)), 130);
+        let parallel = ExecutionError::ParallelFailed(vec![
+            ExecutionError::CommandFailed(Some(1)),
+            ExecutionError::Interrupted(15),
+        ]);
+        assert_eq!(code(parallel), 143);
+    }
+}
@@ -2,7 +2,7 @@ use std::process;
 
 fn main() {
     if let Err(e) = rtask::cli::run() {
-        eprintln!("Error: {}", e);
-        process::exit(1);
+        rtask::cli::report_error(&e);
+        process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,251 @@
+//! Source/target caching
+//!
+//! A task that declares `source` and `target` patterns is up to date when
+//! every target pattern matches at least one file, the oldest target is no
+//! older than the newest source, and the task is unchanged since it last
+//! built them. Patterns are globs relative to the config file's directory.
+//!
+//! "Unchanged" is decided by a cache key: a hash of the task's definition
+//! (including the config settings that shape its commands) and the option
+//! values it was run with. The definition hash and key of each task's last
+//! successful run are kept in `.rtask/cache.json`, so editing a task's
+//! commands makes its targets stale.
+
+use crate::config::Config;
+use crate::runner::STATE_DIR;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File name of the cache entries inside [`STATE_DIR`]
+pub const CACHE_FILE: &str = "cache.json";
+
+/// Task fields that do not change what a task does
+const DESCRIPTIVE_FIELDS: [&str; 5] = ["usage", "description", "private", "watch", "priority"];
+
+/// Whether a task's targets are up to date with its sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheState {
+    /// The task declares no source and target files
+    Uncached,
+    /// The targets were built by this definition of the task and are at
+    /// least as new as every source
+    Fresh,
+    /// A target is missing or older than a source, or the task changed
+    /// since the targets were built
+    Stale,
+}
+
+/// Hash of what task `name` does: its definition without descriptive fields,
+/// plus the config's interpreter, shell options, run directory and
+/// environment policy
+pub fn definition_hash(config: &Config, name: &str) -> Option<String> {
+    let task = config.tasks.get(name)?;
+    let mut task = serde_json::to_value(task).ok()?;
+    if let Some(fields) = task.as_object_mut() {
+        for field in DESCRIPTIVE_FIELDS {
+            fields.remove(field);
+        }
+    }
+    // serde_json sorts object keys, so equal definitions serialize the same
+    let definition = serde_json::json!({
+        "task": task,
+        "interpreter": config.interpreter,
+        "shell-opts": config.shell_opts,
+        "run-from": config.run_from,
+        "inherit-env": config.inherit_env,
+    });
+    Some(format!("{:016x}", fnv1a(definition.to_string().as_bytes())))
+}
+
+/// Cache key of a run of a task with definition hash `definition` and the
+/// option and arg values `vars`
+pub fn cache_key(definition: &str, vars: &HashMap<String, String>) -> String {
+    let vars: BTreeMap<&String, &String> = vars.iter().collect();
+    let input = serde_json::json!([definition, vars]).to_string();
+    format!("{:016x}", fnv1a(input.as_bytes()))
+}
+
+/// Path of the cache entries for a config living in `config_dir`
+pub fn cache_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(STATE_DIR).join(CACHE_FILE)
+}
+
+/// What the last successful run of a task was
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Definition hash of the task
+    pub definition: String,
+    /// Cache key of the run
+    pub key: String,
+}
+
+/// The last successful run of each task; a missing or unreadable file reads
+/// as empty
+pub fn read_cache_entries(config_dir: &Path) -> BTreeMap<String, CacheEntry> {
+    fs::read_to_string(cache_path(config_dir))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Remember `entry` as the last successful run of `task`
+pub fn record_cache_entry(config_dir: &Path, task: &str, entry: CacheEntry) -> io::Result<()> {
+    let mut entries = read_cache_entries(config_dir);
+    if entries.get(task) == Some(&entry) {
+        return Ok(());
+    }
+    entries.insert(task.to_string(), entry);
+    let path = cache_path(config_dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&entries)? + "\n")
+}
+
+/// Cache state of `task`, which has definition hash `definition` and these
+/// `source` and `target` patterns
+///
+/// With a `key`, the targets must come from a run with that key; without
+/// one, from any run of the same definition.
+pub fn cache_state(
+    dir: &Path,
+    task: &str,
+    definition: &str,
+    key: Option<&str>,
+    source: &[String],
+    target: &[String],
+) -> CacheState {
+    if source.is_empty() || target.is_empty() {
+        return CacheState::Uncached;
+    }
+    let built_by_this = read_cache_entries(dir).get(task).is_some_and(|entry| {
+        entry.definition == definition && key.is_none_or(|key| entry.key == key)
+    });
+    if !built_by_this {
+        return CacheState::Stale;
+    }
+
+    let mut oldest_target: Option<SystemTime> = None;
+    for pattern in target {
+        let times = modified_times(dir, pattern);
+        let Some(oldest) = times.iter().min() else {
+            return CacheState::Stale;
+        };
+        oldest_target = Some(oldest_target.map_or(*oldest, |t| t.min(*oldest)));
+    }
+    let newest_source = source
+        .iter()
+        .flat_map(|pattern| modified_times(dir, pattern))
+        .max();
+
+    match (oldest_target, newest_source) {
+        (Some(target), Some(source)) if target < source => CacheState::Stale,
+        _ => CacheState::Fresh,
+    }
+}
+
+/// Modification times of the files matching `pattern` under `dir`
+fn modified_times(dir: &Path, pattern: &str) -> Vec<SystemTime> {
+    let full = dir.join(pattern);
+    let Ok(paths) = glob::glob(&full.to_string_lossy()) else {
+        return Vec::new();
+    };
+    paths
+        .flatten()
+        .filter_map(|path| path.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .filter_map(|metadata| metadata.modified().ok())
+        .collect()
+}
+
+/// 64-bit FNV-1a, a hash that stays the same across builds and platforms
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn touch(dir: &Path, path: &str, age: Duration) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn test_cache_state() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = vec!["src/*.c".to_string()];
+        let target = vec!["build/app".to_string()];
+        let state = |key| cache_state(dir.path(), "build", "d1", key, &source, &target);
+        let hour = Duration::from_secs(3600);
+
+        assert_eq!(
+            cache_state(dir.path(), "build", "d1", None, &[], &[]),
+            CacheState::Uncached
+        );
+        touch(dir.path(), "src/main.c", hour * 2);
+        touch(dir.path(), "build/app", hour);
+        assert_eq!(state(None), CacheState::Stale);
+
+        let entry = CacheEntry {
+            definition: "d1".to_string(),
+            key: "k1".to_string(),
+        };
+        record_cache_entry(dir.path(), "build", entry).unwrap();
+        assert_eq!(state(None), CacheState::Fresh);
+        assert_eq!(state(Some("k1")), CacheState::Fresh);
+        assert_eq!(state(Some("k2")), CacheState::Stale);
+        assert_eq!(
+            cache_state(dir.path(), "build", "d2", None, &source, &target),
+            CacheState::Stale
+        );
+
+        touch(dir.path(), "src/util.c", Duration::ZERO);
+        assert_eq!(state(None), CacheState::Stale);
+    }
+
+    #[test]
+    fn test_definition_hash() {
+        let hash = |yaml: &str| definition_hash(&parse_config(yaml, None).unwrap(), "build");
+
+        let base = hash("tasks:\n  build:\n    usage: Build\n    run: make\n");
+        assert!(base.is_some());
+        assert_eq!(
+            base,
+            hash("tasks:\n  build:\n    usage: Build it\n    run: make\n")
+        );
+        assert_ne!(base, hash("tasks:\n  build:\n    run: make all\n"));
+        assert_ne!(
+            base,
+            hash("shell-opts: -e\ntasks:\n  build:\n    run: make\n")
+        );
+        assert_eq!(hash("tasks:\n  other:\n    run: make\n"), None);
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_vars() {
+        let release = HashMap::from([("release".to_string(), "true".to_string())]);
+        assert_eq!(
+            cache_key("abc", &release),
+            cache_key("abc", &release.clone())
+        );
+        assert_ne!(
+            cache_key("abc", &release),
+            cache_key("abc", &HashMap::new())
+        );
+        assert_ne!(cache_key("abc", &release), cache_key("abd", &release));
+    }
+}
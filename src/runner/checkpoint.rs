@@ -0,0 +1,128 @@
+//! Checkpoints for `--resume`
+//!
+//! While the invoked task runs, the number of its run items that have
+//! succeeded so far is kept in `.rtask/checkpoints.json`, together with the
+//! task's cache key (see [`cache_key`](crate::runner::cache_key)) and the
+//! variables those run items set. A run with `--resume` skips that many run
+//! items and restores their variables, provided the task, the config
+//! settings that shape it and its option values are unchanged. A successful
+//! run removes the task's checkpoint.
+//!
+//! Updates hold an exclusive lock on `.rtask/checkpoints.lock`, so rtask
+//! processes sharing a config do not lose each other's checkpoints.
+
+use crate::runner::STATE_DIR;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File name of the checkpoints inside [`STATE_DIR`]
+pub const CHECKPOINT_FILE: &str = "checkpoints.json";
+
+/// File name of the lock held while updating the checkpoints
+const LOCK_FILE: &str = "checkpoints.lock";
+
+/// How far the last run of a task got
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Cache key of the run
+    pub key: String,
+    /// Number of leading run items that succeeded
+    pub completed: usize,
+    /// Variables set (or removed, as `None`) by those run items
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, Option<String>>,
+}
+
+/// Path of the checkpoints for a config living in `config_dir`
+pub fn checkpoint_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(STATE_DIR).join(CHECKPOINT_FILE)
+}
+
+/// Checkpoint of each task; a missing or unreadable file reads as empty
+fn read_checkpoints(config_dir: &Path) -> BTreeMap<String, Checkpoint> {
+    fs::read_to_string(checkpoint_path(config_dir))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `update` to the checkpoints under the lock, writing them back if
+/// it reports a change
+fn update_checkpoints(
+    config_dir: &Path,
+    update: impl FnOnce(&mut BTreeMap<String, Checkpoint>) -> bool,
+) -> io::Result<()> {
+    let dir = config_dir.join(STATE_DIR);
+    fs::create_dir_all(&dir)?;
+    let lock = File::create(dir.join(LOCK_FILE))?;
+    lock.lock()?;
+
+    let mut checkpoints = read_checkpoints(config_dir);
+    if !update(&mut checkpoints) {
+        return Ok(());
+    }
+    // Readers never see a half-written file
+    let path = checkpoint_path(config_dir);
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, serde_json::to_string_pretty(&checkpoints)? + "\n")?;
+    fs::rename(partial, path)
+}
+
+/// Checkpoint of the last run of `task` with cache key `key`, if it has one
+pub fn read_checkpoint(config_dir: &Path, task: &str, key: &str) -> Option<Checkpoint> {
+    read_checkpoints(config_dir)
+        .remove(task)
+        .filter(|checkpoint| checkpoint.key == key)
+}
+
+/// Remember `checkpoint` as how far the current run of `task` got
+pub fn record_checkpoint(config_dir: &Path, task: &str, checkpoint: Checkpoint) -> io::Result<()> {
+    update_checkpoints(config_dir, |checkpoints| {
+        if checkpoints.get(task) == Some(&checkpoint) {
+            return false;
+        }
+        checkpoints.insert(task.to_string(), checkpoint);
+        true
+    })
+}
+
+/// Forget the checkpoint of `task`, if it has one
+pub fn clear_checkpoint(config_dir: &Path, task: &str) -> io::Result<()> {
+    // Most runs have nothing to clear and need not take the lock
+    if !read_checkpoints(config_dir).contains_key(task) {
+        return Ok(());
+    }
+    update_checkpoints(config_dir, |checkpoints| checkpoints.remove(task).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let completed = |task, key| read_checkpoint(dir.path(), task, key).map(|c| c.completed);
+        assert_eq!(completed("deploy", "k1"), None);
+
+        let checkpoint = |completed| Checkpoint {
+            key: "k1".to_string(),
+            completed,
+            vars: BTreeMap::from([("VERSION".to_string(), Some("1.2".to_string()))]),
+        };
+        record_checkpoint(dir.path(), "deploy", checkpoint(3)).unwrap();
+        record_checkpoint(dir.path(), "build", checkpoint(1)).unwrap();
+        assert_eq!(
+            read_checkpoint(dir.path(), "deploy", "k1"),
+            Some(checkpoint(3))
+        );
+        assert_eq!(completed("deploy", "k2"), None);
+
+        clear_checkpoint(dir.path(), "deploy").unwrap();
+        assert_eq!(completed("deploy", "k1"), None);
+        assert_eq!(completed("build", "k1"), Some(1));
+    }
+}
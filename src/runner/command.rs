@@ -2,12 +2,598 @@
 //!
 //! This module handles executing shell commands.
 
+use crate::config::RestartPolicy;
 use crate::error::{ExecutionError, ExecutionResult};
-use crate::runner::{interpolate, Command, Context};
-use std::process::{Command as StdCommand, Stdio};
+use crate::runner::service::{stop_leftovers, stop_process, terminate};
+use crate::runner::{
+    interpolate, pty, read_limited, signals, stream_lines, strip_ansi, terminal, traced,
+    CancelToken, Captured, Command, Context, LineSink, RecordedCommand, Service, ServiceSpec,
+    Stream, Verbosity, DEFAULT_READY_TIMEOUT,
+};
+use crate::ui::{format_command_echo, message, CatalogMessage, CommandEcho, Heartbeat, Spinner};
+use crate::utils::duration::{format_duration, parse_duration};
+use crate::utils::shell;
+use regex::Regex;
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command as StdCommand, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// Execute a command in the given context
+/// How often a running command checks whether it was cancelled or timed out
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// Execute a command in the given context, retrying it as configured
 pub fn execute_command(cmd: &Command, ctx: &Context) -> ExecutionResult<()> {
+    with_retries(cmd, ctx, || {
+        trace_command(cmd, ctx, || execute_once(cmd, ctx))
+    })
+}
+
+/// Start a `background: true` command through the context's executor and
+/// leave it running
+///
+/// Replayed sessions only echo it.
+pub fn start_background(cmd: &Command, ctx: &Context) -> ExecutionResult<()> {
+    let (process, working_dir) = prepare_command(cmd, ctx)?;
+    if ctx.session.as_ref().is_some_and(|s| s.is_replay()) {
+        return Ok(());
+    }
+    ctx.executor.background(cmd, ctx, process, &working_dir)
+}
+
+/// Start the process of a `background: true` command
+///
+/// The process is supervised like a service without a readiness check and
+/// stopped when the task that started it ends.
+pub fn spawn_background(cmd: &Command, ctx: &Context, process: StdCommand) -> ExecutionResult<()> {
+    let spec = ServiceSpec {
+        ready: None,
+        timeout: DEFAULT_READY_TIMEOUT,
+        restart: RestartPolicy::Never,
+        max_restarts: None,
+    };
+    let name = interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+    let service = Service::start(&name, process, &spec, ctx)?.as_background_command();
+    ctx.background.add(service);
+    Ok(())
+}
+
+/// Execute a command once
+fn execute_once(cmd: &Command, ctx: &Context) -> ExecutionResult<()> {
+    let (command, working_dir) = prepare_command(cmd, ctx)?;
+    let Some(session) = &ctx.session else {
+        return ctx.executor.execute(cmd, ctx, command, &working_dir);
+    };
+
+    let mut entry = RecordedCommand::new(&command, &working_dir);
+    if session.is_replay() {
+        return session.next(&entry)?.result();
+    }
+    let result = ctx.executor.execute(cmd, ctx, command, &working_dir);
+    entry.set_result(&result);
+    session.append(&entry)?;
+    result
+}
+
+/// Run a prepared command with its redirections, filters and logs applied
+pub(crate) fn spawn_command(
+    cmd: &Command,
+    ctx: &Context,
+    mut command: StdCommand,
+    working_dir: &Path,
+) -> ExecutionResult<()> {
+    let redirects = open_redirects(cmd, ctx, working_dir)?;
+    let deadline = command_timeout(cmd, ctx)?.map(Deadline::after);
+    let _heartbeat = start_heartbeat(cmd, ctx)?;
+
+    // Stream line by line when output must be filtered, teed, cleaned
+    // before reaching a log file, prefixed, or handed to an observer, or
+    // relayed from a pseudo-terminal; a `tty` command whose output goes
+    // straight to rtask's terminal just uses that one
+    let tee = tee_log_path(cmd, ctx, working_dir)?;
+    let clean_log = ctx.strip_ansi && ctx.suppresses_child_output() && ctx.log_file.is_some();
+    let tty = cmd.uses_tty() && pty::SUPPORTED;
+    let relayed = tty && (ctx.suppresses_child_output() || !io::stdout().is_terminal());
+    let streamed = ctx.output_prefix.is_some() || ctx.observer.is_some() || relayed;
+    if cmd.filter().is_some() || tee.is_some() || clean_log || streamed {
+        let filter = cmd.filter().map(Regex::new).transpose().map_err(|e| {
+            ExecutionError::InvalidOption {
+                name: "filter".to_string(),
+                error: e.to_string(),
+            }
+        })?;
+        let sink = LineSink {
+            filter,
+            console: !ctx.suppresses_child_output(),
+            log: open_log_file(ctx)?,
+            tee: tee.as_deref().map(open_append).transpose()?,
+            strip_ansi: ctx.strip_ansi,
+            observer: ctx.observer.clone(),
+            command: RecordedCommand::new(&command, working_dir).command,
+            prefix: ctx.output_prefix.clone().unwrap_or_default(),
+        };
+        return run_streamed(command, sink, redirects, ctx, deadline, tty);
+    }
+
+    // Set up stdio, with explicit redirections taking precedence
+    command.stdin(Stdio::inherit());
+    let (stdout, stderr) = if ctx.suppresses_child_output() {
+        suppressed_stdio(ctx)?
+    } else {
+        (Stdio::inherit(), Stdio::inherit())
+    };
+    let spinner = if redirects.stdout.is_some() {
+        start_spinner(cmd, ctx)
+    } else {
+        None
+    };
+    command.stdout(redirects.stdout.map_or(stdout, Stdio::from));
+    command.stderr(redirects.stderr.map_or(stderr, Stdio::from));
+
+    // Execute the command
+    let status = spawn_child(&mut command, ctx).and_then(|(mut child, foreground)| {
+        wait_child(&mut child, ctx, deadline, foreground.as_ref())
+    });
+    drop(spinner);
+    let status = status?;
+
+    // Check exit status
+    if !status.success() {
+        return Err(ExecutionError::CommandFailed(exit_code(&status)));
+    }
+
+    Ok(())
+}
+
+/// Run commands as a pipeline, each stage's stdout feeding the next stage's stdin
+///
+/// The pipeline is echoed as one line. Like `set -o pipefail`, it fails with
+/// the exit code of the last stage that failed.
+pub fn execute_pipeline(stages: &[Command], ctx: &Context) -> ExecutionResult<()> {
+    let processes = stages
+        .iter()
+        .map(|cmd| build_process(cmd, ctx))
+        .collect::<ExecutionResult<Vec<_>>>()?;
+    let print_str = stages
+        .iter()
+        .map(|cmd| interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string()))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    if let Some((_, dir)) = processes.first() {
+        if !stages.iter().all(Command::is_quiet) || ctx.dry_run {
+            echo_command(&print_str, dir, ctx);
+        }
+    }
+    traced(
+        ctx.trace.as_ref(),
+        "command",
+        || print_str,
+        || run_pipeline(ctx, processes),
+    )
+}
+
+/// Run prepared pipeline stages, recording or replaying them in a session
+fn run_pipeline(ctx: &Context, processes: Vec<(StdCommand, PathBuf)>) -> ExecutionResult<()> {
+    let Some(session) = &ctx.session else {
+        return ctx.executor.pipeline(ctx, processes);
+    };
+
+    let mut entry = RecordedCommand::pipeline(&processes);
+    if session.is_replay() {
+        return session.next(&entry)?.result();
+    }
+    let result = ctx.executor.pipeline(ctx, processes);
+    entry.set_result(&result);
+    session.append(&entry)?;
+    result
+}
+
+/// Spawn prepared pipeline stages connected by pipes and wait for all of them
+pub(crate) fn spawn_pipeline(
+    ctx: &Context,
+    processes: Vec<(StdCommand, PathBuf)>,
+) -> ExecutionResult<()> {
+    let deadline = ctx
+        .timeout
+        .filter(|timeout| !timeout.is_zero())
+        .map(Deadline::after);
+    let last = processes.len().saturating_sub(1);
+    let mut children = Vec::new();
+    let mut readers = Vec::new();
+    let mut previous_stdout: Option<std::process::ChildStdout> = None;
+    let mut foreground = None;
+
+    for (index, (mut command, dir)) in processes.into_iter().enumerate() {
+        command.stdin(previous_stdout.take().map_or(Stdio::inherit(), Stdio::from));
+        let sink = console_sink(ctx, &command, &dir);
+        let (stdout, stderr) = if ctx.suppresses_child_output() {
+            suppressed_stdio(ctx)?
+        } else if sink.is_some() {
+            (Stdio::piped(), Stdio::piped())
+        } else {
+            (Stdio::inherit(), Stdio::inherit())
+        };
+        command.stdout(if index == last {
+            stdout
+        } else {
+            Stdio::piped()
+        });
+        command.stderr(stderr);
+
+        // Only the first stage reads from the terminal
+        let spawned = if index == 0 {
+            spawn_child(&mut command, ctx)
+        } else {
+            spawn_in_background(&mut command).map(|child| (child, None))
+        };
+        match spawned {
+            Ok((mut child, stage_foreground)) => {
+                foreground = foreground.or(stage_foreground);
+                previous_stdout = child.stdout.take();
+                if let Some(sink) = sink {
+                    if index == last {
+                        if let Some(stdout) = previous_stdout.take() {
+                            readers.push(stream_lines(stdout, Stream::Stdout, Arc::clone(&sink)));
+                        }
+                    }
+                    if let Some(stderr) = child.stderr.take() {
+                        readers.push(stream_lines(stderr, Stream::Stderr, sink));
+                    }
+                }
+                children.push(child);
+            }
+            Err(_) => {
+                for mut child in children {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(ExecutionError::CommandFailed(None));
+            }
+        }
+    }
+
+    // A stage that timed out stops the stages after it too
+    let mut failure = None;
+    let mut children = children.into_iter();
+    while let Some(mut child) = children.next() {
+        match wait_child(&mut child, ctx, deadline, foreground.as_ref()) {
+            Ok(status) if !status.success() => {
+                failure = Some(ExecutionError::CommandFailed(exit_code(&status)));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                for mut child in children.by_ref() {
+                    terminate(&mut child, own_process_group());
+                }
+                failure = Some(e);
+            }
+        }
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+    failure.map_or(Ok(()), Err)
+}
+
+/// Execute a command and capture its stdout, bounded by the context's capture limits
+pub fn capture_command(cmd: &Command, ctx: &Context) -> ExecutionResult<String> {
+    with_retries(cmd, ctx, || {
+        trace_command(cmd, ctx, || capture_once(cmd, ctx))
+    })
+}
+
+/// Run one attempt at `cmd` as a span of the trace, if one is recorded
+fn trace_command<T>(
+    cmd: &Command,
+    ctx: &Context,
+    attempt: impl FnOnce() -> ExecutionResult<T>,
+) -> ExecutionResult<T> {
+    let name = || interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+    traced(ctx.trace.as_ref(), "command", name, attempt)
+}
+
+/// Execute a command once, capturing its stdout
+///
+/// The capture limits apply whichever executor ran the command, and to
+/// output replayed from a session.
+fn capture_once(cmd: &Command, ctx: &Context) -> ExecutionResult<String> {
+    let (command, working_dir) = prepare_command(cmd, ctx)?;
+    let Some(session) = &ctx.session else {
+        let output = ctx.executor.capture(cmd, ctx, command, &working_dir)?;
+        return Ok(limit_capture(cmd, ctx, &output));
+    };
+
+    let mut entry = RecordedCommand::new(&command, &working_dir);
+    if session.is_replay() {
+        let recorded = session.next(&entry)?;
+        recorded.result()?;
+        let output = recorded.stdout.unwrap_or_default();
+        return Ok(limit_capture(cmd, ctx, &output));
+    }
+    let result = ctx
+        .executor
+        .capture(cmd, ctx, command, &working_dir)
+        .map(|output| limit_capture(cmd, ctx, &output));
+    entry.set_result(&result);
+    entry.stdout = result.as_ref().ok().cloned();
+    session.append(&entry)?;
+    result
+}
+
+/// Hold captured output to the context's capture limits
+fn limit_capture(cmd: &Command, ctx: &Context, output: &str) -> String {
+    let captured = ctx.capture_limits.apply(output);
+    if captured.truncated {
+        ctx.print_debug(&format!(
+            "Captured output of '{}' truncated to {} bytes",
+            cmd.print(),
+            ctx.capture_limits.max_bytes
+        ));
+    }
+    captured.text
+}
+
+/// Run a prepared command with its stdout piped back to rtask
+pub(crate) fn spawn_captured(
+    cmd: &Command,
+    ctx: &Context,
+    mut command: StdCommand,
+    working_dir: &Path,
+) -> ExecutionResult<String> {
+    let redirects = open_redirects(cmd, ctx, working_dir)?;
+    let deadline = command_timeout(cmd, ctx)?.map(Deadline::after);
+    let _heartbeat = start_heartbeat(cmd, ctx)?;
+
+    // In a pseudo-terminal, stderr is captured along with stdout
+    if cmd.uses_tty() && pty::SUPPORTED {
+        let (mut child, output, input) = spawn_tty(&mut command)?;
+        let spinner = start_spinner(cmd, ctx);
+        let limits = ctx.capture_limits.clone();
+        let reader = thread::spawn(move || read_limited(output, &limits));
+        let status = wait_child(&mut child, ctx, deadline, None);
+        drop(input);
+        return finish_capture(cmd, ctx, status, reader, spinner, None);
+    }
+
+    let mut observer = None;
+    command.stdin(Stdio::inherit());
+    command.stdout(Stdio::piped());
+    if let Some(file) = redirects.stderr {
+        command.stderr(file);
+    } else if ctx.suppresses_child_output() {
+        let (_, stderr) = suppressed_stdio(ctx)?;
+        command.stderr(stderr);
+    } else if let Some(sink) = console_sink(ctx, &command, working_dir) {
+        command.stderr(Stdio::piped());
+        observer = Some(sink);
+    } else {
+        command.stderr(Stdio::inherit());
+    }
+
+    let (mut child, foreground) = spawn_child(&mut command, ctx)?;
+    let stderr_reader = observer.and_then(|sink| {
+        let stderr = child.stderr.take()?;
+        Some(stream_lines(stderr, Stream::Stderr, sink))
+    });
+
+    let spinner = start_spinner(cmd, ctx);
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or(ExecutionError::CommandFailed(None))?;
+    let limits = ctx.capture_limits.clone();
+    let reader = thread::spawn(move || read_limited(stdout, &limits));
+    let status = wait_child(&mut child, ctx, deadline, foreground.as_ref());
+    drop(foreground);
+    finish_capture(cmd, ctx, status, reader, spinner, stderr_reader)
+}
+
+/// Collect the output of a captured command once it has exited
+fn finish_capture(
+    cmd: &Command,
+    ctx: &Context,
+    status: ExecutionResult<ExitStatus>,
+    reader: thread::JoinHandle<io::Result<Captured>>,
+    spinner: Option<Spinner>,
+    stderr_reader: Option<thread::JoinHandle<()>>,
+) -> ExecutionResult<String> {
+    let captured = reader
+        .join()
+        .unwrap_or_else(|_| Err(std::io::Error::other("reader panicked")))
+        .map_err(|e| ExecutionError::Environment(format!("Failed to read command output: {}", e)));
+    drop(spinner);
+    if let Some(reader) = stderr_reader {
+        let _ = reader.join();
+    }
+    let (status, captured) = (status?, captured?);
+
+    if captured.truncated {
+        ctx.print_debug(&format!(
+            "Captured output of '{}' truncated to {} bytes",
+            cmd.print(),
+            ctx.capture_limits.max_bytes
+        ));
+    }
+
+    if !status.success() {
+        return Err(ExecutionError::CommandFailed(exit_code(&status)));
+    }
+
+    if ctx.strip_ansi {
+        return Ok(strip_ansi(&captured.text).into_owned());
+    }
+
+    Ok(captured.text)
+}
+
+/// Show a spinner while a command runs without visible output
+///
+/// Only shown at normal verbosity or above, and not for quiet commands; the
+/// spinner itself checks for a terminal.
+fn start_spinner(cmd: &Command, ctx: &Context) -> Option<Spinner> {
+    if ctx.quiet || ctx.verbosity < Verbosity::Normal {
+        return None;
+    }
+    let label = interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+    Spinner::start(&label)
+}
+
+/// Start a heartbeat for a command if it (or the context) sets an interval
+fn start_heartbeat(cmd: &Command, ctx: &Context) -> ExecutionResult<Option<Heartbeat>> {
+    if ctx.verbosity == Verbosity::Silent {
+        return Ok(None);
+    }
+
+    let interval = match cmd.heartbeat() {
+        Some(value) => {
+            let value =
+                interpolate(value, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+                    name: "heartbeat".to_string(),
+                    error: e.to_string(),
+                })?;
+            Some(
+                parse_duration(&value).map_err(|e| ExecutionError::InvalidOption {
+                    name: "heartbeat".to_string(),
+                    error: e,
+                })?,
+            )
+        }
+        None => ctx.heartbeat,
+    };
+
+    Ok(interval
+        .filter(|interval| !interval.is_zero())
+        .map(|interval| {
+            let label =
+                interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+            Heartbeat::start(&label, interval)
+        }))
+}
+
+/// Run `attempt` until it succeeds or the command's retries run out
+///
+/// Only failed and timed out commands are retried, and not once the
+/// context's parallel branch is cancelled. The delay before each retry
+/// doubles the one before it.
+fn with_retries<T>(
+    cmd: &Command,
+    ctx: &Context,
+    mut attempt: impl FnMut() -> ExecutionResult<T>,
+) -> ExecutionResult<T> {
+    let retries = cmd.retries();
+    if retries == 0 {
+        return attempt();
+    }
+    let mut delay = match cmd.retry_delay() {
+        Some(value) => {
+            let value =
+                interpolate(value, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+                    name: "retry-delay".to_string(),
+                    error: e.to_string(),
+                })?;
+            parse_duration(&value).map_err(|e| ExecutionError::InvalidOption {
+                name: "retry-delay".to_string(),
+                error: e,
+            })?
+        }
+        None => Duration::ZERO,
+    };
+
+    let mut retried = 0;
+    loop {
+        let error = match attempt() {
+            Err(e @ (ExecutionError::CommandFailed(_) | ExecutionError::Timeout(_))) => e,
+            result => return result,
+        };
+        let cancelled = ctx.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        if retried == retries || cancelled {
+            return Err(error);
+        }
+        retried += 1;
+        let command =
+            interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+        ctx.print_warning(&message(
+            "command.retrying",
+            &[
+                ("command", &command),
+                ("error", &error.localized()),
+                ("retry", &retried.to_string()),
+                ("retries", &retries.to_string()),
+            ],
+        ));
+        thread::sleep(delay);
+        delay = delay.saturating_mul(2);
+    }
+}
+
+/// The timeout of a command: its own, or else the running task's
+fn command_timeout(cmd: &Command, ctx: &Context) -> ExecutionResult<Option<Duration>> {
+    let timeout = match cmd.timeout() {
+        Some(value) => {
+            let value =
+                interpolate(value, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+                    name: "timeout".to_string(),
+                    error: e.to_string(),
+                })?;
+            Some(
+                parse_duration(&value).map_err(|e| ExecutionError::InvalidOption {
+                    name: "timeout".to_string(),
+                    error: e,
+                })?,
+            )
+        }
+        None => ctx.timeout,
+    };
+
+    Ok(timeout.filter(|timeout| !timeout.is_zero()))
+}
+
+/// Interpolate a command, echo it, and build the process with working
+/// directory, interpreter, and environment applied (stdio is left to the caller)
+///
+/// Returns the process together with its resolved working directory.
+pub(crate) fn prepare_command(
+    cmd: &Command,
+    ctx: &Context,
+) -> ExecutionResult<(StdCommand, PathBuf)> {
+    let (command, working_dir) = build_process(cmd, ctx)?;
+
+    // Print the command if not quiet; a dry run shows every command
+    if !cmd.is_quiet() || ctx.dry_run {
+        let print_str =
+            interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
+        echo_command(&print_str, &working_dir, ctx);
+    }
+
+    Ok((command, working_dir))
+}
+
+/// Print the command echo line, unless the context is quiet
+fn echo_command(print_str: &str, working_dir: &Path, ctx: &Context) {
+    if (ctx.quiet && !ctx.dry_run) || ctx.verbosity < Verbosity::Normal {
+        return;
+    }
+    let dir_str = working_dir.display().to_string();
+    let interpreter_str = ctx.interpreter.join(" ");
+    let echo = CommandEcho {
+        command: print_str,
+        dir: &dir_str,
+        interpreter: &interpreter_str,
+        task: ctx.current_task().map(String::as_str).unwrap_or_default(),
+    };
+    eprintln!(
+        "{}{}",
+        ctx.indent(),
+        format_command_echo(&ctx.print_format, &echo)
+    );
+}
+
+/// Interpolate a command and build its process, without echoing it
+fn build_process(cmd: &Command, ctx: &Context) -> ExecutionResult<(StdCommand, PathBuf)> {
     // Get the command string and interpolate variables
     let exec_str = interpolate(cmd.exec(), &ctx.vars).map_err(|e| {
         ExecutionError::InvalidOption {
@@ -16,12 +602,6 @@ pub fn execute_command(cmd: &Command, ctx: &Context) -> ExecutionResult<()> {
         }
     })?;
 
-    // Print the command if not quiet
-    if !cmd.is_quiet() && ctx.verbosity >= crate::runner::context::Verbosity::Normal {
-        let print_str = interpolate(cmd.print(), &ctx.vars).unwrap_or_else(|_| cmd.print().to_string());
-        eprintln!("[RUN] {}", print_str);
-    }
-
     // Determine working directory
     let working_dir = if let Some(dir) = cmd.dir() {
         let interpolated_dir = interpolate(dir, &ctx.vars).map_err(|e| {
@@ -30,50 +610,448 @@ pub fn execute_command(cmd: &Command, ctx: &Context) -> ExecutionResult<()> {
                 error: e.to_string(),
             }
         })?;
-        ctx.working_dir.join(interpolated_dir)
+        ctx.resolve_path(&interpolated_dir)
     } else {
         ctx.working_dir.clone()
     };
 
     // Build the command
-    let mut command = StdCommand::new(&ctx.interpreter[0]);
+    let mut command = if cmd.uses_shell() {
+        let mut command = StdCommand::new(&ctx.interpreter[0]);
 
-    // Add interpreter args (e.g., "-c" for sh/bash)
-    if ctx.interpreter.len() > 1 {
-        command.args(&ctx.interpreter[1..]);
-    }
+        // Shell options (e.g., "-e", "-u") go before the command flag
+        if shell::is_posix_shell(&ctx.interpreter[0]) {
+            command.args(cmd.shell_opts().unwrap_or(&ctx.shell_opts));
+        }
 
-    // Add the actual command to execute
-    command.arg(&exec_str);
+        // Add interpreter args (e.g., "-c" for sh/bash)
+        if ctx.interpreter.len() > 1 {
+            command.args(&ctx.interpreter[1..]);
+        }
+
+        // Add the actual command to execute
+        command.arg(&exec_str);
+        command
+    } else {
+        direct_command(cmd, ctx)?
+    };
 
     // Set working directory
     command.current_dir(&working_dir);
 
-    // Set up stdio
-    command.stdin(Stdio::inherit());
-    command.stdout(Stdio::inherit());
-    command.stderr(Stdio::inherit());
+    apply_env(&mut command, ctx);
 
-    // Set environment variables from context
-    for (key, value) in &ctx.vars {
-        command.env(key, value);
+    ctx.print_trace(&format!(
+        "Spawning {:?} in {}",
+        command,
+        working_dir.display()
+    ));
+
+    Ok((command, working_dir))
+}
+
+/// Build a process from the words of a `shell: false` command, bypassing the
+/// interpreter
+///
+/// The line is split before variables are interpolated, so a value containing
+/// spaces or quotes stays a single argument.
+fn direct_command(cmd: &Command, ctx: &Context) -> ExecutionResult<StdCommand> {
+    let invalid = |error: String| ExecutionError::InvalidOption {
+        name: "command".to_string(),
+        error,
+    };
+    let words = shell::split(cmd.exec())
+        .map_err(invalid)?
+        .iter()
+        .map(|word| interpolate(word, &ctx.vars).map_err(|e| invalid(e.to_string())))
+        .collect::<ExecutionResult<Vec<String>>>()?;
+    let (program, args) = words
+        .split_first()
+        .ok_or_else(|| invalid("command is empty".to_string()))?;
+
+    let mut command = StdCommand::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Exit code of a finished process, using the shell convention of 128 + signal
+/// number for processes killed by a signal
+pub fn exit_code(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Some(128 + signal);
+        }
     }
+    status.code()
+}
 
-    // Execute the command
-    let status = command.status().map_err(|_e| {
-        ExecutionError::CommandFailed(None)
-    })?;
+/// Files a command's output streams are redirected to
+#[derive(Default)]
+struct Redirects {
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+/// Open the `stdout`/`stderr` redirection files of a command, relative to its
+/// working directory
+fn open_redirects(cmd: &Command, ctx: &Context, working_dir: &Path) -> ExecutionResult<Redirects> {
+    let Some(detail) = cmd.detail() else {
+        return Ok(Redirects::default());
+    };
+
+    let resolve = |name: &str, path: &str| -> ExecutionResult<PathBuf> {
+        let path = interpolate(path, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+            name: name.to_string(),
+            error: e.to_string(),
+        })?;
+        Ok(working_dir.join(path))
+    };
+    let open = |path: &Path| -> ExecutionResult<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(detail.append)
+            .truncate(!detail.append)
+            .open(path)
+            .map_err(|e| {
+                ExecutionError::Environment(format!(
+                    "Failed to open '{}' for redirection: {}",
+                    path.display(),
+                    e
+                ))
+            })
+    };
+
+    let stdout_path = detail
+        .stdout
+        .as_deref()
+        .map(|p| resolve("stdout", p))
+        .transpose()?;
+    let stderr_path = detail
+        .stderr
+        .as_deref()
+        .map(|p| resolve("stderr", p))
+        .transpose()?;
+
+    let stdout = stdout_path.as_deref().map(open).transpose()?;
+    let stderr = match (&stderr_path, &stdout_path, &stdout) {
+        // Both streams to the same file share one handle so writes interleave
+        (Some(err), Some(out), Some(file)) if err == out => {
+            Some(file.try_clone().map_err(|e| {
+                ExecutionError::Environment(format!("Failed to open '{}': {}", err.display(), e))
+            })?)
+        }
+        (Some(err), _, _) => Some(open(err)?),
+        (None, _, _) => None,
+    };
+
+    Ok(Redirects { stdout, stderr })
+}
+
+/// Run a command with piped output, routing each line through the sink
+///
+/// Streams with an explicit redirection bypass the sink and go straight to
+/// their file. With `tty`, the command runs in a pseudo-terminal instead, and
+/// everything it writes arrives as stdout.
+fn run_streamed(
+    mut command: StdCommand,
+    sink: LineSink,
+    redirects: Redirects,
+    ctx: &Context,
+    deadline: Option<Deadline>,
+    tty: bool,
+) -> ExecutionResult<()> {
+    let sink = Arc::new(sink);
+    let mut readers = Vec::new();
+    let mut input = None;
+    let mut foreground = None;
+    let mut child = if tty {
+        let (child, output, forwarder) = spawn_tty(&mut command)?;
+        readers.push(stream_lines(output, Stream::Stdout, Arc::clone(&sink)));
+        input = Some(forwarder);
+        child
+    } else {
+        command.stdin(Stdio::inherit());
+        command.stdout(redirects.stdout.map_or_else(Stdio::piped, Stdio::from));
+        command.stderr(redirects.stderr.map_or_else(Stdio::piped, Stdio::from));
+        let (mut child, taken) = spawn_child(&mut command, ctx)?;
+        foreground = taken;
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(stream_lines(stdout, Stream::Stdout, Arc::clone(&sink)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(stream_lines(stderr, Stream::Stderr, Arc::clone(&sink)));
+        }
+        child
+    };
+
+    let status = wait_child(&mut child, ctx, deadline, foreground.as_ref());
+    drop(foreground);
+    drop(input);
+    for reader in readers {
+        let _ = reader.join();
+    }
+    let status = status?;
 
-    // Check exit status
     if !status.success() {
-        return Err(ExecutionError::CommandFailed(status.code()));
+        return Err(ExecutionError::CommandFailed(exit_code(&status)));
     }
 
     Ok(())
 }
 
+/// When a running command times out
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline {
+    timeout: Duration,
+    at: Instant,
+}
+
+impl Deadline {
+    /// A deadline `timeout` from now
+    pub(crate) fn after(timeout: Duration) -> Self {
+        Deadline {
+            timeout,
+            at: Instant::now() + timeout,
+        }
+    }
+}
+
+/// Wait for a child process, terminating it if the context's parallel branch
+/// is cancelled or the deadline passes first
+///
+/// An interrupt is passed on to the process, which is then waited for. What
+/// the process left running in its group is stopped once it exits. While
+/// it holds the terminal, rtask stops along with it, and a Ctrl-C that ends
+/// it counts as an interrupt.
+pub(crate) fn wait_child(
+    child: &mut Child,
+    ctx: &Context,
+    deadline: Option<Deadline>,
+    foreground: Option<&terminal::Foreground>,
+) -> ExecutionResult<ExitStatus> {
+    let failed = |_| ExecutionError::CommandFailed(None);
+    if ctx.cancel.is_none() && deadline.is_none() && !signals::installed() {
+        return child.wait().map_err(failed);
+    }
+    let group = own_process_group();
+    let interrupts = signals::interrupts();
+    // Quick commands are noticed quickly; long ones settle at the poll interval
+    let mut pause = Duration::from_millis(1);
+    loop {
+        let status = child.try_wait().map_err(failed)?;
+        if let (Some(foreground), Some(status)) = (foreground, &status) {
+            if foreground.interrupted(status) {
+                signals::record(signals::SIGINT);
+            }
+        }
+        // A Ctrl-C reaches the process too, which may exit on its own first
+        if let Some(signal) = signals::interrupted_since(interrupts) {
+            match status {
+                None => stop_process(child, group, signal),
+                Some(_) if group => stop_leftovers(child),
+                Some(_) => {}
+            }
+            return Err(ExecutionError::Interrupted(signal));
+        }
+        if let Some(status) = status {
+            if group {
+                stop_leftovers(child);
+            }
+            return Ok(status);
+        }
+        if let Some(foreground) = foreground {
+            foreground.follow_stop(child);
+        }
+        if ctx.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            terminate(child, group);
+            return child.wait().map_err(failed);
+        }
+        if let Some(deadline) = deadline.filter(|deadline| Instant::now() >= deadline.at) {
+            terminate(child, group);
+            return Err(ExecutionError::Timeout(format_duration(deadline.timeout)));
+        }
+        thread::sleep(pause);
+        pause = (pause * 2).min(CANCEL_POLL);
+    }
+}
+
+/// Whether commands run in a process group of their own, so that stopping
+/// one reaches every process it started, even those its shell left running
+/// in the background
+///
+/// Only when rtask handles interrupts itself, since a Ctrl-C in the terminal
+/// no longer reaches the group otherwise. A command running on its own is
+/// handed the terminal instead (see [`terminal`]); commands running side by
+/// side are not, as they would compete for it.
+///
+/// Windows has no process groups here (commands are not put in a job
+/// object), so stopping a command stops only the process rtask started.
+pub(crate) fn own_process_group() -> bool {
+    signals::installed()
+}
+
+/// Spawn a prepared command, in a process group of its own if commands get
+/// one, handing it the terminal if it runs on its own and rtask has it
+fn spawn_child(
+    command: &mut StdCommand,
+    ctx: &Context,
+) -> ExecutionResult<(Child, Option<terminal::Foreground>)> {
+    let takes_terminal = own_process_group() && ctx.cancel.is_none() && terminal::in_foreground();
+    if takes_terminal {
+        terminal::take_over(command);
+    }
+    let child = spawn_in_background(command)?;
+    let foreground = takes_terminal.then(|| terminal::Foreground::new(&child));
+    Ok((child, foreground))
+}
+
+/// Spawn a prepared command, in a process group of its own if commands get
+/// one, leaving the terminal to rtask
+fn spawn_in_background(command: &mut StdCommand) -> ExecutionResult<Child> {
+    #[cfg(unix)]
+    if own_process_group() {
+        std::os::unix::process::CommandExt::process_group(command, 0);
+    }
+    command
+        .spawn()
+        .map_err(|_| ExecutionError::CommandFailed(None))
+}
+
+/// Spawn a `tty: true` command in a pseudo-terminal of its own
+fn spawn_tty(
+    command: &mut StdCommand,
+) -> ExecutionResult<(Child, pty::PtyOutput, pty::InputForwarder)> {
+    pty::spawn(command).map_err(|e| {
+        ExecutionError::Environment(format!("Cannot run command in a pseudo-terminal: {}", e))
+    })
+}
+
+/// Sink that streams a process's console output to the context's observer
+/// or with its output prefix, if it has either
+fn console_sink(ctx: &Context, command: &StdCommand, dir: &Path) -> Option<Arc<LineSink>> {
+    if ctx.observer.is_none() && ctx.output_prefix.is_none() {
+        return None;
+    }
+    Some(Arc::new(LineSink {
+        console: true,
+        observer: ctx.observer.clone(),
+        command: RecordedCommand::new(command, dir).command,
+        prefix: ctx.output_prefix.clone().unwrap_or_default(),
+        ..Default::default()
+    }))
+}
+
+/// Open the context's log file for appending, if one is configured
+fn open_log_file(ctx: &Context) -> ExecutionResult<Option<Arc<Mutex<File>>>> {
+    ctx.log_file.as_deref().map(open_append).transpose()
+}
+
+/// Resolve the tee log for a command: its own `log` field, else the task's
+fn tee_log_path(
+    cmd: &Command,
+    ctx: &Context,
+    working_dir: &Path,
+) -> ExecutionResult<Option<PathBuf>> {
+    match cmd.log() {
+        Some(log) => {
+            let path = interpolate(log, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+                name: "log".to_string(),
+                error: e.to_string(),
+            })?;
+            Ok(Some(working_dir.join(path)))
+        }
+        None => Ok(ctx.tee_log.clone()),
+    }
+}
+
+/// Open a file for appending, shared between output reader threads
+fn open_append(path: &Path) -> ExecutionResult<Arc<Mutex<File>>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| {
+            ExecutionError::Environment(format!(
+                "Failed to open log file '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+    Ok(Arc::new(Mutex::new(file)))
+}
+
+/// Build stdout/stderr handles for suppressed output: the log file if one is
+/// configured, otherwise the null device
+fn suppressed_stdio(ctx: &Context) -> ExecutionResult<(Stdio, Stdio)> {
+    match open_log_file(ctx)? {
+        Some(log) => {
+            let file = log
+                .lock()
+                .map_err(|_| ExecutionError::Environment("Log file lock poisoned".to_string()))?;
+            let out_file = file.try_clone().map_err(|e| {
+                ExecutionError::Environment(format!("Failed to open log file: {}", e))
+            })?;
+            let err_file = file.try_clone().map_err(|e| {
+                ExecutionError::Environment(format!("Failed to open log file: {}", e))
+            })?;
+            Ok((Stdio::from(out_file), Stdio::from(err_file)))
+        }
+        None => Ok((Stdio::null(), Stdio::null())),
+    }
+}
+
 /// Check if a command succeeds (for when conditions)
 pub fn check_command(cmd_str: &str, ctx: &Context) -> ExecutionResult<bool> {
+    let (command, exec_str) = check_process(cmd_str, ctx)?;
+
+    // Identical probes run once per invocation
+    let key = CheckKey::new(&command, &ctx.working_dir);
+    if let Some(&success) = ctx.check_cache.lock().unwrap().get(&key) {
+        ctx.print_trace(&format!("Reusing result of check '{}'", exec_str));
+        return Ok(success);
+    }
+    let success = run_check(command, ctx)?;
+    ctx.check_cache.lock().unwrap().insert(key, success);
+    Ok(success)
+}
+
+/// What makes two checks the same: the process as prepared, with its
+/// interpreter, arguments, environment and directory
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CheckKey {
+    program: OsString,
+    args: Vec<OsString>,
+    env: Vec<(OsString, Option<OsString>)>,
+    dir: PathBuf,
+}
+
+impl CheckKey {
+    fn new(command: &StdCommand, dir: &Path) -> Self {
+        CheckKey {
+            program: command.get_program().to_os_string(),
+            args: command.get_args().map(OsStr::to_os_string).collect(),
+            env: command
+                .get_envs()
+                .map(|(key, value)| (key.to_os_string(), value.map(OsStr::to_os_string)))
+                .collect(),
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+/// Check if a command succeeds right now, bypassing the memoized results
+/// (for polling, e.g. service readiness)
+pub fn probe_command(cmd_str: &str, ctx: &Context) -> ExecutionResult<bool> {
+    let (command, _) = check_process(cmd_str, ctx)?;
+    run_check(command, ctx)
+}
+
+/// Build the silent process for a check, returning it with its interpolated command
+fn check_process(cmd_str: &str, ctx: &Context) -> ExecutionResult<(StdCommand, String)> {
     // Interpolate the command
     let exec_str = interpolate(cmd_str, &ctx.vars).map_err(|e| {
         ExecutionError::InvalidOption {
@@ -82,8 +1060,11 @@ pub fn check_command(cmd_str: &str, ctx: &Context) -> ExecutionResult<bool> {
         }
     })?;
 
-    // Build the command
+    // Build the command, with shell options as for other commands
     let mut command = StdCommand::new(&ctx.interpreter[0]);
+    if shell::is_posix_shell(&ctx.interpreter[0]) {
+        command.args(&ctx.shell_opts);
+    }
 
     if ctx.interpreter.len() > 1 {
         command.args(&ctx.interpreter[1..]);
@@ -91,22 +1072,51 @@ pub fn check_command(cmd_str: &str, ctx: &Context) -> ExecutionResult<bool> {
 
     command.arg(&exec_str);
     command.current_dir(&ctx.working_dir);
+    apply_env(&mut command, ctx);
 
     // Suppress output
     command.stdout(Stdio::null());
     command.stderr(Stdio::null());
 
-    // Execute and check status
-    let status = command.status().map_err(|_| {
-        ExecutionError::CommandFailed(None)
-    })?;
+    Ok((command, exec_str))
+}
 
-    Ok(status.success())
+/// Limit the host environment a process inherits, then set variables from
+/// the context
+pub fn apply_env(command: &mut StdCommand, ctx: &Context) {
+    if ctx.filters_env() {
+        command.env_clear();
+        command.envs(
+            std::env::vars_os()
+                .filter(|(key, _)| key.to_str().is_some_and(|key| ctx.inherits_env(key))),
+        );
+    }
+    for (key, value) in &ctx.vars {
+        command.env(key, value);
+    }
+}
+
+/// Run a check process, recording it to or replaying it from the session,
+/// and report whether it succeeded
+fn run_check(command: StdCommand, ctx: &Context) -> ExecutionResult<bool> {
+    let Some(session) = &ctx.session else {
+        return Ok(ctx.executor.check(command, &ctx.working_dir)? == Some(0));
+    };
+
+    let mut entry = RecordedCommand::new(&command, &ctx.working_dir);
+    if session.is_replay() {
+        return Ok(session.next(&entry)?.result().is_ok());
+    }
+    entry.exit_code = ctx.executor.check(command, &ctx.working_dir)?;
+    session.append(&entry)?;
+    Ok(entry.exit_code == Some(0))
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use crate::runner::CommandDetail;
     use std::collections::HashMap;
 
     #[test]
@@ -140,6 +1150,353 @@ mod tests {
         assert!(matches!(result, Err(ExecutionError::CommandFailed(_))));
     }
 
+    #[test]
+    fn test_cancelled_branch_terminates_command() {
+        use crate::runner::CancelToken;
+        use std::time::Instant;
+
+        let token = CancelToken::default();
+        let mut ctx = Context::new();
+        ctx.cancel = Some(token.clone());
+        let cancel = std::thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        });
+
+        let started = Instant::now();
+        let result = execute_command(&Command::Simple("sleep 10".to_string()), &ctx);
+        cancel.join().unwrap();
+        assert!(matches!(result, Err(ExecutionError::CommandFailed(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_timeout_kills_command() {
+        use std::time::Instant;
+
+        let timed = |exec: &str, timeout: &str| {
+            Command::Complex(Box::new(CommandDetail {
+                exec: exec.to_string(),
+                quiet: true,
+                timeout: Some(timeout.to_string()),
+                ..Default::default()
+            }))
+        };
+        let ctx = Context::new();
+
+        let started = Instant::now();
+        let result = execute_command(&timed("sleep 10", "200ms"), &ctx);
+        assert!(matches!(result, Err(ExecutionError::Timeout(ref after)) if after == "200ms"));
+        let result = capture_command(&timed("sleep 10", "200ms"), &ctx);
+        assert!(matches!(result, Err(ExecutionError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert!(execute_command(&timed("true", "5s"), &ctx).is_ok());
+
+        // Commands without their own timeout get the task's
+        let mut ctx = Context::new();
+        ctx.timeout = Some(Duration::from_millis(200));
+        let result = execute_command(&Command::Simple("sleep 10".to_string()), &ctx);
+        assert!(matches!(result, Err(ExecutionError::Timeout(_))));
+    }
+
+    #[test]
+    fn test_retries_rerun_failed_command() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+        // Fails until it has run three times
+        let flaky = |retries: u32| {
+            Command::Complex(Box::new(CommandDetail {
+                exec: "echo x >> tries; test $(wc -l < tries) -ge 3".to_string(),
+                quiet: true,
+                retries,
+                retry_delay: Some("10ms".to_string()),
+                ..Default::default()
+            }))
+        };
+
+        let result = execute_command(&flaky(1), &ctx);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::CommandFailed(Some(1)))
+        ));
+        std::fs::remove_file(temp_dir.path().join("tries")).unwrap();
+        execute_command(&flaky(2), &ctx).unwrap();
+
+        let tries = std::fs::read_to_string(temp_dir.path().join("tries")).unwrap();
+        assert_eq!(tries.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_silent_output_goes_to_log_file() {
+        use crate::runner::Verbosity;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("out.log");
+        let ctx = Context::new()
+            .with_verbosity(Verbosity::Silent)
+            .with_log_file(log_path.clone());
+        let cmd = Command::Simple("echo captured; echo oops >&2".to_string());
+
+        execute_command(&cmd, &ctx).unwrap();
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        assert!(contents.contains("captured"));
+        assert!(contents.contains("oops"));
+    }
+
+    #[test]
+    fn test_filtered_command_logs_all_lines() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("out.log");
+        let ctx = Context::new().with_log_file(log_path.clone());
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "echo keep; echo drop".to_string(),
+            quiet: true,
+            filter: Some("^keep$".to_string()),
+            ..Default::default()
+        }));
+
+        execute_command(&cmd, &ctx).unwrap();
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        assert!(contents.contains("keep"));
+        assert!(contents.contains("drop"));
+    }
+
+    #[test]
+    fn test_tee_log_with_timestamps() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "echo teed".to_string(),
+            quiet: true,
+            log: Some("tee.log".to_string()),
+            ..Default::default()
+        }));
+
+        execute_command(&cmd, &ctx).unwrap();
+
+        let contents = std::fs::read_to_string(temp_dir.path().join("tee.log")).unwrap();
+        assert!(contents.starts_with('['));
+        assert!(contents.trim_end().ends_with("Z] teed"));
+    }
+
+    #[test]
+    fn test_invalid_filter() {
+        let ctx = Context::new();
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "true".to_string(),
+            quiet: true,
+            filter: Some("(".to_string()),
+            ..Default::default()
+        }));
+
+        let result = execute_command(&cmd, &ctx);
+        assert!(matches!(result, Err(ExecutionError::InvalidOption { .. })));
+    }
+
+    #[test]
+    fn test_redirect_stdout_and_stderr() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "echo out; echo err >&2".to_string(),
+            quiet: true,
+            stdout: Some("out.log".to_string()),
+            stderr: Some("err.log".to_string()),
+            ..Default::default()
+        }));
+
+        execute_command(&cmd, &ctx).unwrap();
+
+        let out = std::fs::read_to_string(temp_dir.path().join("out.log")).unwrap();
+        let err = std::fs::read_to_string(temp_dir.path().join("err.log")).unwrap();
+        assert_eq!(out, "out\n");
+        assert_eq!(err, "err\n");
+    }
+
+    #[test]
+    fn test_redirect_append() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "echo line".to_string(),
+            quiet: true,
+            stdout: Some("out.log".to_string()),
+            append: true,
+            ..Default::default()
+        }));
+
+        execute_command(&cmd, &ctx).unwrap();
+        execute_command(&cmd, &ctx).unwrap();
+
+        let out = std::fs::read_to_string(temp_dir.path().join("out.log")).unwrap();
+        assert_eq!(out, "line\nline\n");
+    }
+
+    #[test]
+    fn test_capture_command() {
+        let ctx = Context::new();
+        let cmd = Command::Simple("echo captured".to_string());
+
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "captured");
+    }
+
+    #[test]
+    fn test_capture_command_strips_ansi() {
+        let cmd = Command::Simple(r"printf '\033[31mred\033[0m'".to_string());
+
+        let ctx = Context::new();
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "red");
+
+        let ctx = Context::new().with_strip_ansi(false);
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "\x1b[31mred\x1b[0m");
+    }
+
+    #[derive(Default)]
+    struct Lines(Mutex<Vec<String>>);
+
+    impl crate::runner::OutputObserver for Lines {
+        fn stdout_line(&self, command: &str, line: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}: out {}", command, line));
+        }
+
+        fn stderr_line(&self, command: &str, line: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}: err {}", command, line));
+        }
+    }
+
+    #[test]
+    fn test_observer_receives_output_lines() {
+        let lines = Arc::new(Lines::default());
+        let ctx = Context::new().with_observer(lines.clone());
+
+        execute_command(&Command::Simple("echo one; echo two".to_string()), &ctx).unwrap();
+        let cmd = Command::Simple("echo kept; echo warn >&2".to_string());
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "kept");
+        let stages = vec![
+            Command::Simple("echo b; echo a".to_string()),
+            Command::Simple("sort".to_string()),
+        ];
+        execute_pipeline(&stages, &ctx).unwrap();
+
+        assert_eq!(
+            *lines.0.lock().unwrap(),
+            vec![
+                "echo one; echo two: out one",
+                "echo one; echo two: out two",
+                "echo kept; echo warn >&2: err warn",
+                "sort: out a",
+                "sort: out b",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipeline_feeds_stages() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+        let stages = vec![
+            Command::Simple("printf 'b\\na\\nb\\n'".to_string()),
+            Command::Complex(Box::new(CommandDetail {
+                exec: "sort -u".to_string(),
+                direct: true,
+                ..Default::default()
+            })),
+            Command::Simple("tr a-z A-Z > out.txt".to_string()),
+        ];
+
+        execute_pipeline(&stages, &ctx).unwrap();
+        let out = std::fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+        assert_eq!(out, "A\nB\n");
+    }
+
+    #[test]
+    fn test_pipeline_fails_with_last_failing_stage() {
+        let ctx = Context::new().with_verbosity(Verbosity::Silent);
+        let stages = vec![
+            Command::Simple("exit 3".to_string()),
+            Command::Simple("cat; exit 4".to_string()),
+            Command::Simple("cat".to_string()),
+        ];
+
+        assert!(matches!(
+            execute_pipeline(&stages, &ctx),
+            Err(ExecutionError::CommandFailed(Some(4)))
+        ));
+    }
+
+    #[test]
+    fn test_command_without_shell_keeps_arguments_whole() {
+        let mut vars = HashMap::new();
+        vars.insert("msg".to_string(), "a b; echo injected".to_string());
+        let ctx = Context::new().with_vars(vars);
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "printf '%s|%s' ${msg} \"$HOME\"".to_string(),
+            direct: true,
+            ..Default::default()
+        }));
+
+        assert_eq!(
+            capture_command(&cmd, &ctx).unwrap(),
+            "a b; echo injected|$HOME"
+        );
+    }
+
+    #[test]
+    fn test_shell_opts_apply_unless_overridden() {
+        let ctx = Context::new().with_shell_opts(vec!["-e".to_string()]);
+        let cmd = Command::Simple("false; echo after".to_string());
+        assert!(capture_command(&cmd, &ctx).is_err());
+
+        let cmd = Command::Complex(Box::new(CommandDetail {
+            exec: "false; echo after".to_string(),
+            shell_opts: Some(Vec::new()),
+            ..Default::default()
+        }));
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "after");
+    }
+
+    #[test]
+    fn test_shell_opts_apply_to_checks_and_shells_only() {
+        let ctx = Context::new().with_shell_opts(vec!["-e".to_string()]);
+        assert!(!check_command("false; true", &ctx).unwrap());
+
+        let mut ctx = ctx;
+        ctx.interpreter = vec!["printf".to_string(), "%s|".to_string()];
+        let cmd = Command::Simple("plain".to_string());
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "plain|");
+    }
+
+    #[test]
+    fn test_capture_command_respects_limit() {
+        let mut ctx = Context::new();
+        ctx.capture_limits.max_bytes = 4;
+        let cmd = Command::Simple("echo 0123456789".to_string());
+
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "0123");
+    }
+
     #[test]
     fn test_check_command_success() {
         let ctx = Context::new();
@@ -169,4 +1526,43 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), true);
     }
+
+    #[test]
+    fn test_check_command_is_memoized() {
+        let executor = Arc::new(crate::runner::MockExecutor::new());
+        let mut vars = HashMap::new();
+        vars.insert("tool".to_string(), "docker".to_string());
+        let ctx = Context::new()
+            .with_vars(vars)
+            .with_executor(executor.clone());
+
+        assert!(check_command("which ${tool}", &ctx).unwrap());
+        assert!(check_command("which docker", &ctx).unwrap());
+        assert!(check_command("which podman", &ctx).unwrap());
+        assert_eq!(executor.commands(), vec!["which docker", "which podman"]);
+    }
+
+    #[test]
+    fn test_capture_limits_apply_to_any_executor() {
+        let executor = Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("version", 0, "  0123456789\n");
+        let mut ctx = Context::new().with_executor(executor);
+        ctx.capture_limits.max_bytes = 6;
+
+        let cmd = Command::Simple("version".to_string());
+        assert_eq!(capture_command(&cmd, &ctx).unwrap(), "0123");
+    }
+
+    #[test]
+    fn test_check_memo_depends_on_variables() {
+        let executor = Arc::new(crate::runner::MockExecutor::new());
+        executor.respond(r#"test "$os" = ubuntu"#, 1, "");
+        let mut ctx = Context::new().with_executor(executor.clone());
+
+        for os in ["debian", "ubuntu", "debian"] {
+            ctx.set_var("os".to_string(), os.to_string());
+            check_command(r#"test "$os" = ubuntu"#, &ctx).unwrap();
+        }
+        assert_eq!(executor.commands().len(), 2);
+    }
 }
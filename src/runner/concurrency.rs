@@ -0,0 +1,154 @@
+//! Concurrency groups
+//!
+//! Tasks that name the same `concurrency-group` never run at the same time.
+//! Within one rtask process the groups are held in a [`ConcurrencyLocks`]
+//! shared by every branch of the run; a group held across processes is
+//! additionally backed by an exclusive lock on `.rtask/locks/<group>.lock`,
+//! which the operating system releases even if rtask is killed.
+
+use crate::runner::STATE_DIR;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Directory inside [`STATE_DIR`] holding the cross-process lock files
+const LOCKS_DIR: &str = "locks";
+
+/// Concurrency groups held by the tasks of this run
+#[derive(Debug, Clone, Default)]
+pub struct ConcurrencyLocks(Arc<(Mutex<HashSet<String>>, Condvar)>);
+
+impl ConcurrencyLocks {
+    /// Hold `group` until the returned guard is dropped
+    ///
+    /// When another task holds the group, `on_wait` is called once and this
+    /// blocks until it is released. With `config_dir`, the group is also
+    /// locked against other rtask processes using that directory.
+    pub fn acquire(
+        &self,
+        group: &str,
+        config_dir: Option<&Path>,
+        on_wait: impl FnOnce(),
+    ) -> io::Result<GroupGuard> {
+        let mut on_wait = Some(on_wait);
+        let (held, released) = &*self.0;
+        let mut held = held.lock().unwrap_or_else(PoisonError::into_inner);
+        while held.contains(group) {
+            if let Some(on_wait) = on_wait.take() {
+                on_wait();
+            }
+            held = released.wait(held).unwrap_or_else(PoisonError::into_inner);
+        }
+        held.insert(group.to_string());
+        drop(held);
+
+        // From here the guard releases the in-process hold, even if locking
+        // the file fails
+        let mut guard = GroupGuard {
+            locks: self.clone(),
+            group: group.to_string(),
+            file: None,
+        };
+        if let Some(config_dir) = config_dir {
+            let dir = config_dir.join(STATE_DIR).join(LOCKS_DIR);
+            fs::create_dir_all(&dir)?;
+            let file = File::create(dir.join(format!("{}.lock", group)))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(fs::TryLockError::WouldBlock) => {
+                    if let Some(on_wait) = on_wait.take() {
+                        on_wait();
+                    }
+                    file.lock()?;
+                }
+                Err(fs::TryLockError::Error(e)) => return Err(e),
+            }
+            guard.file = Some(file);
+        }
+        Ok(guard)
+    }
+}
+
+/// A held concurrency group, released on drop
+#[derive(Debug)]
+pub struct GroupGuard {
+    locks: ConcurrencyLocks,
+    group: String,
+    /// Open lock file when the group is held across processes; closing it
+    /// releases the lock
+    file: Option<File>,
+}
+
+impl GroupGuard {
+    /// Name of the held group
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        self.file.take();
+        let (held, released) = &*self.locks.0;
+        held.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.group);
+        released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_group_is_exclusive() {
+        let locks = ConcurrencyLocks::default();
+        let guard = locks.acquire("deploy", None, || {}).unwrap();
+        let _other = locks
+            .acquire("build", None, || panic!("not contended"))
+            .unwrap();
+
+        let waiting = Arc::new(Mutex::new(false));
+        let handle = {
+            let locks = locks.clone();
+            let waiting = Arc::clone(&waiting);
+            thread::spawn(move || {
+                let _guard = locks
+                    .acquire("deploy", None, || *waiting.lock().unwrap() = true)
+                    .unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(*waiting.lock().unwrap());
+        assert!(!handle.is_finished());
+
+        drop(guard);
+        handle.join().unwrap();
+        drop(
+            locks
+                .acquire("deploy", None, || panic!("released"))
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_group_lock_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let guard = ConcurrencyLocks::default()
+            .acquire("deploy", Some(dir.path()), || {})
+            .unwrap();
+        let path = dir.path().join(".rtask/locks/deploy.lock");
+        assert!(matches!(
+            File::open(&path).unwrap().try_lock(),
+            Err(fs::TryLockError::WouldBlock)
+        ));
+
+        drop(guard);
+        assert!(File::open(&path).unwrap().try_lock().is_ok());
+    }
+}
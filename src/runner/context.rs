@@ -2,29 +2,219 @@
 //!
 //! The context tracks all the state needed during task execution.
 
+use crate::config::{InheritEnv, Interpreter};
+use crate::error::{ExecutionError, ExecutionResult};
+use crate::runner::{
+    signals, CancelToken, CaptureLimits, CheckKey, CommandExecutor, ConcurrencyLocks,
+    DryRunExecutor, FailurePolicy, JobSlots, OutputObserver, RunOnce, Services, Session,
+    SystemExecutor, TaskHooks, TaskRegistry, Tracer, Value,
+};
+use crate::ui::{message, CatalogMessage, DEFAULT_PRINT_FORMAT};
+use crate::utils::which::which;
+use colored::Colorize;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Variable holding the exit code of the last command a task ran
+pub const EXIT_CODE_VAR: &str = "exit_code";
+
+/// Variable holding the last command a task ran, as interpolated
+pub const LAST_COMMAND_VAR: &str = "last_command";
+
+/// Variable holding the name of the task that `before-each` and
+/// `after-each` items run around
+pub const TASK_NAME_VAR: &str = "task_name";
 
 /// Execution context that tracks state during task execution
 pub struct Context {
     /// Current working directory
     pub working_dir: PathBuf,
 
+    /// Directory rtask was started in
+    pub invocation_dir: PathBuf,
+
     /// Configuration file path
     pub config_path: Option<PathBuf>,
 
     /// Variables (from options, args, set-environment, etc.)
     pub vars: HashMap<String, String>,
 
+    /// Typed values of bool/int/float options, used by when comparisons
+    pub values: HashMap<String, Value>,
+
     /// Custom interpreter (e.g., ["bash", "-c"])
     pub interpreter: Vec<String>,
 
+    /// The config's interpreter, used by tasks without one of their own
+    pub config_interpreter: Vec<String>,
+
+    /// Flags inserted after the interpreter's program (e.g., ["-e", "-u"])
+    pub shell_opts: Vec<String>,
+
+    /// Host operating system, used to pick per-OS interpreters
+    pub os: String,
+
+    /// Host environment variables passed on to commands
+    pub inherit_env: InheritEnv,
+
+    /// Patterns of host variables inherited regardless of `inherit_env`
+    pub env_allow: EnvPatterns,
+
+    /// Patterns of host variables never inherited
+    pub env_deny: EnvPatterns,
+
     /// Stack of tasks being executed (for detecting recursion)
     pub task_stack: Vec<String>,
 
     /// Verbosity level
     pub verbosity: Verbosity,
+
+    /// Suppress command echo for the run item currently executing
+    pub quiet: bool,
+
+    /// Template used when echoing commands
+    pub print_format: String,
+
+    /// File that receives child output when it is suppressed on the console
+    pub log_file: Option<PathBuf>,
+
+    /// Limits applied when command output is captured into a variable
+    pub capture_limits: CaptureLimits,
+
+    /// File that receives a timestamped copy of output for the current task
+    pub tee_log: Option<PathBuf>,
+
+    /// Strip ANSI escape sequences from logged and captured output
+    pub strip_ansi: bool,
+
+    /// Default interval for "still running" heartbeat lines
+    pub heartbeat: Option<Duration>,
+
+    /// Default timeout of commands, set from the running task's `timeout`
+    pub timeout: Option<Duration>,
+
+    /// Skip finally blocks, leaving a failed task's state behind for inspection
+    pub skip_finally: bool,
+
+    /// Run tasks even when their targets are up to date (`--force`)
+    pub force: bool,
+
+    /// Skip the run items the invoked task completed in its last, failed
+    /// run (`--resume`)
+    pub resume: bool,
+
+    /// Interrupts that arrived before the running `finally` block started;
+    /// only later ones stop it
+    pub handled_interrupts: usize,
+
+    /// The command that caused the current failure, for error reports
+    pub failed_command: Option<String>,
+
+    /// Session that commands are recorded to or replayed from
+    pub session: Option<Session>,
+
+    /// Trace that tasks, run items and commands are timed in (`--trace-out`)
+    pub trace: Option<Tracer>,
+
+    /// Runs the processes for commands (real processes unless replaced)
+    pub executor: Arc<dyn CommandExecutor>,
+
+    /// Receives command output lines instead of the console, when set
+    pub observer: Option<Arc<dyn OutputObserver>>,
+
+    /// Results of `when` command checks, keyed on the prepared process;
+    /// shared with branch contexts
+    pub check_cache: Arc<Mutex<HashMap<CheckKey, bool>>>,
+
+    /// Background services started by subtasks, stopped when the run ends
+    pub services: Services,
+
+    /// Background commands started by the running task, stopped when it
+    /// ends; shared with branch contexts
+    pub background: Services,
+
+    /// Keep every started service in `services` instead of waiting for it,
+    /// as `rtask up` does
+    pub detach_services: bool,
+
+    /// Label printed before each line of command and service output, set
+    /// for concurrent branches and services
+    pub output_prefix: Option<String>,
+
+    /// Concurrency groups held by the tasks of this run; shared with branch
+    /// contexts
+    pub concurrency: ConcurrencyLocks,
+
+    /// Concurrency groups held by the enclosing tasks, which their subtasks
+    /// run inside of instead of waiting for
+    pub held_groups: Vec<String>,
+
+    /// Tasks that `task:` run items can start; shared with branch contexts
+    pub registry: Arc<TaskRegistry>,
+
+    /// Cancellation of the parallel branch this context runs in; a running
+    /// command is terminated when a sibling branch fails
+    pub cancel: Option<CancelToken>,
+
+    /// How many prerequisite tasks may run at the same time (`--jobs`)
+    pub jobs: usize,
+
+    /// Slots for the prerequisite tasks running at the same time, shared
+    /// with branch contexts
+    pub job_slots: JobSlots,
+
+    /// Whether this context runs a scheduled job, holding one of the slots
+    pub holds_job_slot: bool,
+
+    /// Let independent deps, branches and subtasks finish after one fails,
+    /// reporting every failure at the end (`--keep-going`)
+    pub keep_going: bool,
+
+    /// Run items run around tasks (`before-each` and `after-each`)
+    pub hooks: Option<Arc<TaskHooks>>,
+
+    /// Tasks run as `deps` so far, which do not run again; shared with
+    /// branch contexts
+    pub deps_run: RunOnce,
+
+    /// Print what would run without running it (`--dry-run`)
+    pub dry_run: bool,
+
+    /// Failures that `allow-failure` let the run continue past; shared with
+    /// branch contexts
+    pub allowed_failures: AllowedFailures,
+}
+
+/// A failure that `allow-failure` let the run continue past
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedFailure {
+    pub task: String,
+    pub command: String,
+    pub error: String,
+}
+
+/// Failures allowed so far in a run, for the summary at its end
+#[derive(Debug, Clone, Default)]
+pub struct AllowedFailures(Arc<Mutex<Vec<AllowedFailure>>>);
+
+impl AllowedFailures {
+    /// Record an allowed failure
+    pub fn push(&self, failure: AllowedFailure) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(failure);
+    }
+
+    /// The failures allowed so far, in the order they happened
+    pub fn list(&self) -> Vec<AllowedFailure> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
 }
 
 /// Verbosity levels for output
@@ -34,18 +224,149 @@ pub enum Verbosity {
     Quiet = 1,
     Normal = 2,
     Verbose = 3,
+    Trace = 4,
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    /// Parse a level name (`quiet`, `trace`, ...) or its number (0-4)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "silent" | "0" => Ok(Verbosity::Silent),
+            "quiet" | "1" => Ok(Verbosity::Quiet),
+            "normal" | "2" => Ok(Verbosity::Normal),
+            "verbose" | "3" => Ok(Verbosity::Verbose),
+            "trace" | "4" => Ok(Verbosity::Trace),
+            other => Err(format!(
+                "invalid verbosity '{}' (expected silent, quiet, normal, verbose or trace)",
+                other
+            )),
+        }
+    }
+}
+
+impl Verbosity {
+    /// Map a `-v` count onto a level above `Normal`
+    pub fn from_verbose_count(count: u8) -> Self {
+        match count {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::Trace,
+        }
+    }
 }
 
 impl Context {
     /// Create a new context with default settings
     pub fn new() -> Self {
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         Context {
-            working_dir: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            working_dir: cwd.clone(),
+            invocation_dir: cwd,
             config_path: None,
             vars: HashMap::new(),
+            values: HashMap::new(),
             interpreter: vec!["sh".to_string(), "-c".to_string()],
+            config_interpreter: vec!["sh".to_string(), "-c".to_string()],
+            shell_opts: Vec::new(),
+            os: env::consts::OS.to_string(),
+            inherit_env: InheritEnv::All,
+            env_allow: EnvPatterns::default(),
+            env_deny: EnvPatterns::default(),
             task_stack: Vec::new(),
             verbosity: Verbosity::Normal,
+            quiet: false,
+            print_format: DEFAULT_PRINT_FORMAT.to_string(),
+            log_file: None,
+            capture_limits: CaptureLimits::default(),
+            tee_log: None,
+            strip_ansi: true,
+            heartbeat: None,
+            timeout: None,
+            skip_finally: false,
+            force: false,
+            resume: false,
+            handled_interrupts: 0,
+            failed_command: None,
+            session: None,
+            trace: None,
+            executor: Arc::new(SystemExecutor),
+            observer: None,
+            check_cache: Arc::new(Mutex::new(HashMap::new())),
+            services: Services::default(),
+            background: Services::default(),
+            detach_services: false,
+            output_prefix: None,
+            concurrency: ConcurrencyLocks::default(),
+            held_groups: Vec::new(),
+            registry: Arc::default(),
+            cancel: None,
+            jobs: 1,
+            job_slots: JobSlots::default(),
+            holds_job_slot: false,
+            keep_going: false,
+            hooks: None,
+            deps_run: RunOnce::default(),
+            dry_run: false,
+            allowed_failures: AllowedFailures::default(),
+        }
+    }
+
+    /// Copy this context for a concurrently running branch
+    ///
+    /// The branch starts with the same variables and settings; changes it
+    /// makes are not seen by this context.
+    pub fn branch(&self) -> Self {
+        Context {
+            working_dir: self.working_dir.clone(),
+            invocation_dir: self.invocation_dir.clone(),
+            config_path: self.config_path.clone(),
+            vars: self.vars.clone(),
+            values: self.values.clone(),
+            interpreter: self.interpreter.clone(),
+            config_interpreter: self.config_interpreter.clone(),
+            shell_opts: self.shell_opts.clone(),
+            os: self.os.clone(),
+            inherit_env: self.inherit_env,
+            env_allow: self.env_allow.clone(),
+            env_deny: self.env_deny.clone(),
+            task_stack: self.task_stack.clone(),
+            verbosity: self.verbosity,
+            quiet: self.quiet,
+            print_format: self.print_format.clone(),
+            log_file: self.log_file.clone(),
+            capture_limits: self.capture_limits.clone(),
+            tee_log: self.tee_log.clone(),
+            strip_ansi: self.strip_ansi,
+            heartbeat: self.heartbeat,
+            timeout: self.timeout,
+            skip_finally: self.skip_finally,
+            force: self.force,
+            resume: self.resume,
+            handled_interrupts: self.handled_interrupts,
+            failed_command: None,
+            session: self.session.clone(),
+            trace: self.trace.clone(),
+            executor: Arc::clone(&self.executor),
+            observer: self.observer.clone(),
+            check_cache: Arc::clone(&self.check_cache),
+            services: self.services.clone(),
+            background: self.background.clone(),
+            detach_services: self.detach_services,
+            output_prefix: self.output_prefix.clone(),
+            concurrency: self.concurrency.clone(),
+            held_groups: self.held_groups.clone(),
+            registry: Arc::clone(&self.registry),
+            cancel: self.cancel.clone(),
+            jobs: self.jobs,
+            job_slots: self.job_slots.clone(),
+            holds_job_slot: self.holds_job_slot,
+            keep_going: self.keep_going,
+            hooks: self.hooks.clone(),
+            deps_run: self.deps_run.clone(),
+            dry_run: self.dry_run,
+            allowed_failures: self.allowed_failures.clone(),
         }
     }
 
@@ -61,14 +382,99 @@ impl Context {
         self
     }
 
+    /// Time tasks, run items and commands in `trace`
+    pub fn with_trace(mut self, trace: Tracer) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Record commands to, or replay them from, a session
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Run commands through a different executor, such as a [`MockExecutor`]
+    ///
+    /// [`MockExecutor`]: crate::runner::MockExecutor
+    pub fn with_executor(mut self, executor: Arc<dyn CommandExecutor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Print commands, `when` decisions and subtasks instead of running them
+    ///
+    /// Commands go to a [`DryRunExecutor`], and every command is echoed,
+    /// quiet ones included.
+    ///
+    /// [`DryRunExecutor`]: crate::runner::DryRunExecutor
+    pub fn with_dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self.executor = Arc::new(DryRunExecutor);
+        self
+    }
+
+    /// Set the tasks that subtasks are looked up in
+    pub fn with_registry(mut self, registry: Arc<TaskRegistry>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Set how many prerequisite tasks may run at the same time
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self.job_slots = JobSlots::new(jobs);
+        self
+    }
+
+    /// The slots of the scheduled job this context runs, for it to lend
+    /// while it waits for other jobs
+    pub fn held_job_slot(&self) -> Option<&JobSlots> {
+        self.holds_job_slot.then_some(&self.job_slots)
+    }
+
+    /// Keep running independent work after a failure
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Run `hooks` around tasks
+    pub fn with_hooks(mut self, hooks: TaskHooks) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    /// How a group of independent branches with the given `fail-fast:`
+    /// setting handles a failure; unless set, `--keep-going` collects them
+    pub fn failure_policy(&self, fail_fast: Option<bool>) -> FailurePolicy {
+        match fail_fast {
+            None if self.keep_going => FailurePolicy::CollectAll,
+            _ => FailurePolicy::from_fail_fast(fail_fast),
+        }
+    }
+
+    /// Keep started services running in the background instead of waiting for them
+    pub fn with_detach_services(mut self, detach: bool) -> Self {
+        self.detach_services = detach;
+        self
+    }
+
+    /// Send command output to `observer` line by line instead of the console
+    pub fn with_observer(mut self, observer: Arc<dyn OutputObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
     /// Set variables
     pub fn with_vars(mut self, vars: HashMap<String, String>) -> Self {
         self.vars = vars;
         self
     }
 
-    /// Set a single variable
+    /// Set a single variable (dropping any typed value of the same name)
     pub fn set_var(&mut self, key: String, value: String) {
+        self.values.remove(&key);
         self.vars.insert(key, value);
     }
 
@@ -77,18 +483,160 @@ impl Context {
         self.vars.get(key)
     }
 
-    /// Set the interpreter
+    /// Set a typed value, keeping its string form available for interpolation
+    pub fn set_value(&mut self, key: String, value: Value) {
+        self.vars.insert(key.clone(), value.to_string());
+        self.values.insert(key, value);
+    }
+
+    /// Get a typed value
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// Set the interpreter, for the config's tasks without one of their own
     pub fn with_interpreter(mut self, interpreter: Vec<String>) -> Self {
+        self.config_interpreter = interpreter.clone();
         self.interpreter = interpreter;
         self
     }
 
+    /// Set the host operating system
+    pub fn with_os(mut self, os: &str) -> Self {
+        self.os = os.to_string();
+        self
+    }
+
+    /// The first installed interpreter configured for the host operating system
+    pub fn interpreter_for(&self, interpreter: &Interpreter) -> ExecutionResult<Vec<String>> {
+        let candidates = interpreter.for_os(&self.os);
+        if candidates.is_empty() {
+            return Err(ExecutionError::InterpreterNotFound(format!(
+                "none configured for '{}' (add a '{}' or 'default' entry)",
+                self.os, self.os
+            )));
+        }
+
+        candidates
+            .iter()
+            .find(|candidate| which(&candidate[0]).is_some())
+            .map(|candidate| candidate.to_vec())
+            .ok_or_else(|| {
+                let programs: Vec<String> =
+                    candidates.iter().map(|c| format!("'{}'", c[0])).collect();
+                ExecutionError::InterpreterNotFound(match programs.as_slice() {
+                    [program] => format!("{} is not installed or not on the PATH", program),
+                    _ => format!(
+                        "none of {} is installed or on the PATH",
+                        programs.join(", ")
+                    ),
+                })
+            })
+    }
+
     /// Set verbosity level
     pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
         self.verbosity = verbosity;
         self
     }
 
+    /// Set the command echo template
+    pub fn with_print_format(mut self, format: String) -> Self {
+        self.print_format = format;
+        self
+    }
+
+    /// Set the file that receives suppressed child output
+    pub fn with_log_file(mut self, path: PathBuf) -> Self {
+        self.log_file = Some(path);
+        self
+    }
+
+    /// Set the limits applied to captured output
+    pub fn with_capture_limits(mut self, limits: CaptureLimits) -> Self {
+        self.capture_limits = limits;
+        self
+    }
+
+    /// Set whether ANSI escapes are stripped from logged and captured output
+    pub fn with_strip_ansi(mut self, strip: bool) -> Self {
+        self.strip_ansi = strip;
+        self
+    }
+
+    /// Set the default heartbeat interval
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Set the flags passed to the interpreter before its command flag
+    pub fn with_shell_opts(mut self, opts: Vec<String>) -> Self {
+        self.shell_opts = opts;
+        self
+    }
+
+    /// Set which host environment variables commands inherit
+    pub fn with_inherit_env(mut self, inherit_env: InheritEnv) -> Self {
+        self.inherit_env = inherit_env;
+        self
+    }
+
+    /// Set whether finally blocks are skipped
+    pub fn with_skip_finally(mut self, skip: bool) -> Self {
+        self.skip_finally = skip;
+        self
+    }
+
+    /// Set whether tasks run even when their targets are up to date
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Set whether the invoked task resumes after its last completed run item
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Fail with [`ExecutionError::Interrupted`] if an interrupt arrived that
+    /// the running `finally` block has not already seen
+    pub fn check_interrupted(&self) -> ExecutionResult<()> {
+        match signals::interrupted_since(self.handled_interrupts) {
+            Some(signal) => Err(ExecutionError::Interrupted(signal)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether commands get a filtered copy of the host environment rather
+    /// than all of it
+    pub fn filters_env(&self) -> bool {
+        self.inherit_env != InheritEnv::All || !self.env_deny.is_empty()
+    }
+
+    /// Whether the host variable `key` is passed on to commands
+    ///
+    /// `env_deny` wins over `env_allow`, which wins over the policy.
+    pub fn inherits_env(&self, key: &str) -> bool {
+        if self.env_deny.is_match(key) {
+            return false;
+        }
+        if self.env_allow.is_match(key) {
+            return true;
+        }
+        match self.inherit_env {
+            InheritEnv::All => true,
+            InheritEnv::None => false,
+            InheritEnv::Allowlist => is_essential_env(key),
+        }
+    }
+
+    /// Whether child process output should be kept off the console
+    pub fn suppresses_child_output(&self) -> bool {
+        self.verbosity == Verbosity::Silent
+    }
+
     /// Push a task onto the execution stack
     pub fn push_task(&mut self, task_name: String) {
         self.task_stack.push(task_name);
@@ -114,52 +662,216 @@ impl Context {
         self.task_stack.clone()
     }
 
-    /// Get the directory for the config file (or current dir)
+    /// Get the directory for the config file (or the working dir without one)
     pub fn config_dir(&self) -> PathBuf {
-        self.config_path
-            .as_ref()
-            .and_then(|p| p.parent())
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| self.working_dir.clone())
+        match &self.config_path {
+            Some(path) => self
+                .invocation_dir
+                .join(path.parent().unwrap_or_else(|| Path::new(""))),
+            None => self.working_dir.clone(),
+        }
+    }
+
+    /// Resolve a `dir` or `exists` path
+    ///
+    /// `{config}/...` is relative to the config file's directory and
+    /// `{cwd}/...` to the directory rtask was started in; other relative paths
+    /// are relative to the working directory.
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        let base = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .filter(|rest| rest.is_empty() || rest.starts_with(['/', '\\']))
+                .map(|rest| rest.trim_start_matches(['/', '\\']))
+        };
+        if let Some(rest) = base("{config}") {
+            self.config_dir().join(rest)
+        } else if let Some(rest) = base("{cwd}") {
+            self.invocation_dir.join(rest)
+        } else {
+            self.working_dir.join(path)
+        }
+    }
+
+    /// Indentation for rtask's own lines while a subtask runs: two spaces per
+    /// level below the task that was invoked
+    ///
+    /// Child process output is inherited and printed as-is.
+    pub fn indent(&self) -> String {
+        "  ".repeat(self.task_stack.len().saturating_sub(1))
     }
 
     /// Print info message
     pub fn print_info(&self, message: &str) {
         if self.verbosity >= Verbosity::Normal {
-            eprintln!("[INFO] {}", message);
+            eprintln!("{}{} {}", self.indent(), "[INFO]".cyan(), message);
         }
     }
 
     /// Print error message
     pub fn print_error(&self, message: &str) {
         if self.verbosity >= Verbosity::Quiet {
-            eprintln!("[ERROR] {}", message);
+            eprintln!("{}{} {}", self.indent(), "[ERROR]".red(), message);
+        }
+    }
+
+    /// Print warning message
+    pub fn print_warning(&self, message: &str) {
+        if self.verbosity >= Verbosity::Quiet {
+            eprintln!("{}{} {}", self.indent(), "[WARN]".yellow().bold(), message);
         }
     }
 
     /// Print debug message (only in verbose mode)
     pub fn print_debug(&self, message: &str) {
         if self.verbosity >= Verbosity::Verbose {
-            eprintln!("[DEBUG] {}", message);
+            eprintln!("{}{} {}", self.indent(), "[DEBUG]".dimmed(), message);
+        }
+    }
+
+    /// Print trace message (only at trace verbosity)
+    pub fn print_trace(&self, message: &str) {
+        if self.verbosity >= Verbosity::Trace {
+            eprintln!("{}{} {}", self.indent(), "[TRACE]".dimmed(), message);
+        }
+    }
+
+    /// Make the outcome of `command` available to the steps after it as
+    /// `${exit_code}` (an integer in `when` comparisons) and `${last_command}`
+    pub fn set_command_result<T>(&mut self, command: String, result: &ExecutionResult<T>) {
+        let code = result.as_ref().err().map_or(0, ExecutionError::exit_code);
+        self.set_value(EXIT_CODE_VAR.to_string(), Value::Integer(code.into()));
+        self.set_var(LAST_COMMAND_VAR.to_string(), command);
+    }
+
+    /// Record a failure that `allow-failure` lets the run continue past,
+    /// reporting it when verbose
+    pub fn allow_failure(&self, command: String, error: &ExecutionError) {
+        let error = error.localized();
+        if self.verbosity >= Verbosity::Verbose {
+            self.print_warning(&message(
+                "task.failure-allowed",
+                &[("command", &command), ("error", &error)],
+            ));
+        }
+        self.allowed_failures.push(AllowedFailure {
+            task: self.current_task().cloned().unwrap_or_default(),
+            command,
+            error,
+        });
+    }
+
+    /// Print the failures allowed in this run, if any
+    pub fn print_allowed_failures(&self) {
+        let failures = self.allowed_failures.list();
+        if failures.is_empty() {
+            return;
+        }
+        self.print_warning(&message(
+            "task.allowed-failures",
+            &[("count", &failures.len().to_string())],
+        ));
+        for failure in failures {
+            self.print_warning(&message(
+                "task.allowed-failure",
+                &[
+                    ("task", &failure.task),
+                    ("command", &failure.command),
+                    ("error", &failure.error),
+                ],
+            ));
         }
     }
 
     /// Print task start message
     pub fn print_task_start(&self, task_name: &str) {
-        self.print_info(&format!("Running task: {}", task_name));
+        self.print_info(&message("task.running", &[("task", &task_name.bold())]));
     }
 
-    /// Print task complete message
+    /// Print task complete message (call before popping the task)
+    ///
+    /// Subtasks announce when they finish so their output is easy to tell
+    /// apart from the parent's; the invoked task only does so when verbose.
     pub fn print_task_complete(&self, task_name: &str) {
-        self.print_debug(&format!("Task completed: {}", task_name));
+        let finished = message("task.finished", &[("task", &task_name.bold())]);
+        if self.task_stack.len() > 1 {
+            self.print_info(&finished);
+        } else {
+            self.print_debug(&finished);
+        }
     }
 
     /// Print task skip message
     pub fn print_task_skip(&self, task_name: &str, reason: &str) {
-        self.print_debug(&format!("Skipping task '{}': {}", task_name, reason));
+        self.print_info(&message(
+            "task.skipped",
+            &[("task", &task_name), ("reason", &reason)],
+        ));
     }
 }
 
+/// Glob patterns over environment variable names, compiled once
+#[derive(Debug, Clone, Default)]
+pub struct EnvPatterns {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl EnvPatterns {
+    /// Compile `patterns` (invalid ones never match; they are rejected when
+    /// the config is validated)
+    pub fn new(patterns: Vec<String>) -> Self {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            if let Ok(glob) = Glob::new(pattern) {
+                builder.add(glob);
+            }
+        }
+        let set = builder.build().unwrap_or_else(|_| GlobSet::empty());
+        EnvPatterns { patterns, set }
+    }
+
+    /// These patterns followed by `more`
+    pub fn extended(&self, more: &[String]) -> Self {
+        EnvPatterns::new(self.patterns.iter().chain(more).cloned().collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether `key` matches any of the patterns
+    pub fn is_match(&self, key: &str) -> bool {
+        self.set.is_match(key)
+    }
+}
+
+/// Host variables kept by `inherit-env: allowlist`: what shells and common
+/// tools need to find programs, the user's home, the terminal and the locale
+fn is_essential_env(key: &str) -> bool {
+    const ESSENTIAL: &[&str] = &[
+        "PATH",
+        "HOME",
+        "USER",
+        "LOGNAME",
+        "SHELL",
+        "TERM",
+        "LANG",
+        "TZ",
+        "TMPDIR",
+        // Windows
+        "PATHEXT",
+        "SYSTEMROOT",
+        "COMSPEC",
+        "TEMP",
+        "TMP",
+        "USERPROFILE",
+        "APPDATA",
+        "LOCALAPPDATA",
+    ];
+    let upper = key.to_ascii_uppercase();
+    ESSENTIAL.contains(&upper.as_str()) || upper.starts_with("LC_")
+}
+
 impl Default for Context {
     fn default() -> Self {
         Self::new()
@@ -179,6 +891,77 @@ mod tests {
         assert!(ctx.task_stack.is_empty());
     }
 
+    #[test]
+    fn test_indent_follows_task_depth() {
+        let mut ctx = Context::new();
+        assert_eq!(ctx.indent(), "");
+        ctx.push_task("release".to_string());
+        assert_eq!(ctx.indent(), "");
+        ctx.push_task("build".to_string());
+        ctx.push_task("assets".to_string());
+        assert_eq!(ctx.indent(), "    ");
+        ctx.pop_task();
+        assert_eq!(ctx.indent(), "  ");
+    }
+
+    #[test]
+    fn test_inherits_env() {
+        let mut ctx = Context::new();
+        assert!(!ctx.filters_env());
+        assert!(ctx.inherits_env("AWS_SECRET_ACCESS_KEY"));
+
+        ctx.env_deny = EnvPatterns::new(vec!["AWS_*".to_string()]);
+        assert!(ctx.filters_env());
+        assert!(!ctx.inherits_env("AWS_SECRET_ACCESS_KEY"));
+        assert!(ctx.inherits_env("EDITOR"));
+
+        ctx.inherit_env = InheritEnv::Allowlist;
+        ctx.env_allow = EnvPatterns::new(vec!["AWS_REGION".to_string(), "AWS_*".to_string()]);
+        assert!(ctx.inherits_env("PATH"));
+        assert!(ctx.inherits_env("LC_ALL"));
+        assert!(!ctx.inherits_env("EDITOR"));
+        assert!(!ctx.inherits_env("AWS_REGION"));
+    }
+
+    #[test]
+    fn test_resolve_path_bases() {
+        let mut ctx = Context::new()
+            .with_working_dir(PathBuf::from("/work"))
+            .with_config_path(PathBuf::from("/project/rtask.yml"));
+        ctx.invocation_dir = PathBuf::from("/project/src");
+
+        assert_eq!(ctx.resolve_path("dist"), PathBuf::from("/work/dist"));
+        assert_eq!(ctx.resolve_path("/abs/dist"), PathBuf::from("/abs/dist"));
+        assert_eq!(
+            ctx.resolve_path("{config}/dist"),
+            PathBuf::from("/project/dist")
+        );
+        assert_eq!(ctx.resolve_path("{config}"), PathBuf::from("/project"));
+        assert_eq!(
+            ctx.resolve_path("{cwd}/out"),
+            PathBuf::from("/project/src/out")
+        );
+        assert_eq!(
+            ctx.resolve_path("{configs}/x"),
+            PathBuf::from("/work/{configs}/x")
+        );
+    }
+
+    #[test]
+    fn test_verbosity_from_str() {
+        assert_eq!("trace".parse::<Verbosity>().unwrap(), Verbosity::Trace);
+        assert_eq!("1".parse::<Verbosity>().unwrap(), Verbosity::Quiet);
+        assert!("loud".parse::<Verbosity>().is_err());
+    }
+
+    #[test]
+    fn test_verbosity_from_verbose_count() {
+        assert_eq!(Verbosity::from_verbose_count(0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_verbose_count(1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_verbose_count(2), Verbosity::Trace);
+        assert_eq!(Verbosity::from_verbose_count(3), Verbosity::Trace);
+    }
+
     #[test]
     fn test_context_with_vars() {
         let mut vars = HashMap::new();
@@ -217,6 +1000,7 @@ mod tests {
 
     #[test]
     fn test_verbosity_levels() {
+        assert!(Verbosity::Trace > Verbosity::Verbose);
         assert!(Verbosity::Verbose > Verbosity::Normal);
         assert!(Verbosity::Normal > Verbosity::Quiet);
         assert!(Verbosity::Quiet > Verbosity::Silent);
@@ -233,4 +1017,10 @@ mod tests {
         let ctx = Context::new().with_verbosity(Verbosity::Verbose);
         assert_eq!(ctx.verbosity, Verbosity::Verbose);
     }
+
+    #[test]
+    fn test_with_print_format() {
+        let ctx = Context::new().with_print_format("$ {command}".to_string());
+        assert_eq!(ctx.print_format, "$ {command}");
+    }
 }
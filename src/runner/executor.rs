@@ -0,0 +1,300 @@
+//! Pluggable process execution
+//!
+//! Commands are prepared (interpolated, echoed, given their directory and
+//! environment) by the `command` module and then handed to the context's
+//! [`CommandExecutor`]. The [`SystemExecutor`] spawns real processes; the
+//! [`MockExecutor`] only records what would have run, so library users and
+//! tests can assert on commands without touching the system. The
+//! [`DryRunExecutor`] behind `--dry-run` runs nothing at all.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use crate::runner::{
+    exit_code, spawn_background, spawn_captured, spawn_command, spawn_pipeline, Command, Context,
+    RecordedCommand,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+use std::sync::Mutex;
+
+/// Runs prepared processes on behalf of the command module
+pub trait CommandExecutor: Send + Sync {
+    /// Run a command with its output shown, logged or redirected as configured
+    fn execute(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<()>;
+
+    /// Run a command and return its stdout
+    fn capture(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<String>;
+
+    /// Start a `background: true` command and leave it running until the
+    /// task that started it ends
+    fn background(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<()>;
+
+    /// Run a `when` command with its output discarded and return its exit
+    /// code, or `None` if it was killed
+    fn check(&self, process: StdCommand, dir: &Path) -> ExecutionResult<Option<i32>>;
+
+    /// Run processes connected stdout to stdin, each in its own directory
+    fn pipeline(&self, ctx: &Context, stages: Vec<(StdCommand, PathBuf)>) -> ExecutionResult<()>;
+}
+
+/// Executor that spawns real processes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemExecutor;
+
+impl CommandExecutor for SystemExecutor {
+    fn execute(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<()> {
+        spawn_command(cmd, ctx, process, dir)
+    }
+
+    fn capture(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<String> {
+        spawn_captured(cmd, ctx, process, dir)
+    }
+
+    fn background(
+        &self,
+        cmd: &Command,
+        ctx: &Context,
+        process: StdCommand,
+        _dir: &Path,
+    ) -> ExecutionResult<()> {
+        spawn_background(cmd, ctx, process)
+    }
+
+    fn check(&self, mut process: StdCommand, _dir: &Path) -> ExecutionResult<Option<i32>> {
+        let status = process
+            .status()
+            .map_err(|_| ExecutionError::CommandFailed(None))?;
+        Ok(exit_code(&status))
+    }
+
+    fn pipeline(&self, ctx: &Context, stages: Vec<(StdCommand, PathBuf)>) -> ExecutionResult<()> {
+        spawn_pipeline(ctx, stages)
+    }
+}
+
+/// Executor for `--dry-run`: every command succeeds without running
+///
+/// Commands are still echoed by the command module; captured output is empty
+/// and `when` commands count as passing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DryRunExecutor;
+
+impl CommandExecutor for DryRunExecutor {
+    fn execute(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        _process: StdCommand,
+        _dir: &Path,
+    ) -> ExecutionResult<()> {
+        Ok(())
+    }
+
+    fn capture(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        _process: StdCommand,
+        _dir: &Path,
+    ) -> ExecutionResult<String> {
+        Ok(String::new())
+    }
+
+    fn background(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        _process: StdCommand,
+        _dir: &Path,
+    ) -> ExecutionResult<()> {
+        Ok(())
+    }
+
+    fn check(&self, _process: StdCommand, _dir: &Path) -> ExecutionResult<Option<i32>> {
+        Ok(Some(0))
+    }
+
+    fn pipeline(&self, _ctx: &Context, _stages: Vec<(StdCommand, PathBuf)>) -> ExecutionResult<()> {
+        Ok(())
+    }
+}
+
+/// Executor that records commands instead of running them
+///
+/// Every command succeeds with empty output unless a response was set for
+/// its (interpolated) command string with [`MockExecutor::respond`].
+#[derive(Debug, Default)]
+pub struct MockExecutor {
+    calls: Mutex<Vec<RecordedCommand>>,
+    responses: Mutex<HashMap<String, (i32, String)>>,
+}
+
+impl MockExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `command` with an exit code and stdout
+    pub fn respond(&self, command: &str, exit_code: i32, stdout: &str) -> &Self {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.insert(command.to_string(), (exit_code, stdout.to_string()));
+        }
+        self
+    }
+
+    /// Commands run so far, in order, with their directory and environment
+    pub fn calls(&self) -> Vec<RecordedCommand> {
+        self.calls
+            .lock()
+            .map(|calls| calls.clone())
+            .unwrap_or_default()
+    }
+
+    /// Just the command strings run so far
+    pub fn commands(&self) -> Vec<String> {
+        self.calls().into_iter().map(|call| call.command).collect()
+    }
+
+    fn call(&self, process: &StdCommand, dir: &Path) -> RecordedCommand {
+        let mut call = RecordedCommand::new(process, dir);
+        let (code, stdout) = self
+            .responses
+            .lock()
+            .ok()
+            .and_then(|responses| responses.get(&call.command).cloned())
+            .unwrap_or_default();
+        call.exit_code = Some(code);
+        call.stdout = Some(stdout);
+
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(call.clone());
+        }
+        call
+    }
+}
+
+impl CommandExecutor for MockExecutor {
+    fn execute(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<()> {
+        self.call(&process, dir).result()
+    }
+
+    fn capture(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<String> {
+        let call = self.call(&process, dir);
+        call.result()?;
+        Ok(call.stdout.unwrap_or_default())
+    }
+
+    fn background(
+        &self,
+        _cmd: &Command,
+        _ctx: &Context,
+        process: StdCommand,
+        dir: &Path,
+    ) -> ExecutionResult<()> {
+        self.call(&process, dir).result()
+    }
+
+    fn check(&self, process: StdCommand, dir: &Path) -> ExecutionResult<Option<i32>> {
+        Ok(self.call(&process, dir).exit_code)
+    }
+
+    /// Each stage is recorded as its own call; the last failing stage decides
+    fn pipeline(&self, _ctx: &Context, stages: Vec<(StdCommand, PathBuf)>) -> ExecutionResult<()> {
+        let results: Vec<_> = stages
+            .iter()
+            .map(|(process, dir)| self.call(process, dir).result())
+            .collect();
+        results.into_iter().rfind(Result::is_err).unwrap_or(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{capture_command, check_command, execute_command, Verbosity};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mock_executor_records_commands() {
+        let mock = Arc::new(MockExecutor::new());
+        mock.respond("git rev-parse HEAD", 0, "abc123")
+            .respond("make", 2, "");
+
+        let mut vars = HashMap::new();
+        vars.insert("target".to_string(), "release".to_string());
+        let ctx = Context::new()
+            .with_verbosity(Verbosity::Silent)
+            .with_working_dir("/work".into())
+            .with_vars(vars)
+            .with_executor(mock.clone());
+
+        let rev = Command::Simple("git rev-parse HEAD".to_string());
+        assert_eq!(capture_command(&rev, &ctx).unwrap(), "abc123");
+        assert!(check_command("test -f ${target}", &ctx).unwrap());
+        let build = Command::Simple("make ${target}".to_string());
+        assert!(execute_command(&build, &ctx).is_ok());
+        let fail = Command::Simple("make".to_string());
+        assert!(matches!(
+            execute_command(&fail, &ctx),
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+
+        assert_eq!(
+            mock.commands(),
+            vec![
+                "git rev-parse HEAD",
+                "test -f release",
+                "make release",
+                "make"
+            ]
+        );
+        let calls = mock.calls();
+        assert_eq!(calls[2].dir, Path::new("/work"));
+        assert_eq!(
+            calls[2].env.get("target").map(String::as_str),
+            Some("release")
+        );
+    }
+}
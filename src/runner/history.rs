@@ -0,0 +1,92 @@
+//! Run history ledger
+//!
+//! Every task invocation appends one JSON line to `.rtask/history.jsonl` next
+//! to the config file. The ledger feeds `rtask stats`.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Directory (relative to the config file) holding rtask's state
+pub const STATE_DIR: &str = ".rtask";
+
+/// File name of the history ledger inside [`STATE_DIR`]
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// One recorded task run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub task: String,
+    /// RFC 3339 UTC start time
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// Path of the history ledger for a config living in `config_dir`
+pub fn history_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(STATE_DIR).join(HISTORY_FILE)
+}
+
+/// Append an entry to the ledger, creating it if needed
+pub fn append_history(path: &Path, entry: &HistoryEntry) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let line = serde_json::to_string(entry)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+/// Read all entries from the ledger, skipping lines that do not parse
+///
+/// A missing ledger reads as empty.
+pub fn read_history(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append_and_read_history() {
+        let dir = TempDir::new().unwrap();
+        let path = history_path(dir.path());
+        assert!(read_history(&path).unwrap().is_empty());
+
+        let entry = HistoryEntry {
+            task: "build".to_string(),
+            started_at: "2024-05-01T12:00:00.000Z".to_string(),
+            duration_ms: 1200,
+            success: false,
+            exit_code: Some(2),
+        };
+        append_history(&path, &entry).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        append_history(&path, &entry).unwrap();
+
+        assert_eq!(read_history(&path).unwrap(), vec![entry.clone(), entry]);
+    }
+}
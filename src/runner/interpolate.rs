@@ -82,6 +82,14 @@ pub fn interpolate_strict(
     Ok(result)
 }
 
+/// Names of the variables referenced as `${name}` in a string, in order
+pub fn referenced_vars(s: &str) -> Vec<String> {
+    let re = Regex::new(r"\$\{([^}]+)\}").unwrap();
+    re.captures_iter(s)
+        .map(|caps| caps[1].to_string())
+        .collect()
+}
+
 /// Interpolate all values in a HashMap
 pub fn interpolate_map(
     map: &HashMap<String, String>,
@@ -204,6 +212,15 @@ mod tests {
         assert_eq!(result[1], "static.txt");
     }
 
+    #[test]
+    fn test_referenced_vars() {
+        assert_eq!(
+            referenced_vars("${region}-${name}/${region}"),
+            vec!["region", "name", "region"]
+        );
+        assert!(referenced_vars("no vars, $HOME").is_empty());
+    }
+
     #[test]
     fn test_empty_variable_name() {
         let vars = HashMap::new();
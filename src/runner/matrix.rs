@@ -0,0 +1,74 @@
+//! Matrix expansion (`matrix:` on a task or run item)
+//!
+//! A matrix maps variable names to lists of values. The task's run items, or
+//! the run item, run once per combination of those values, with the values
+//! of the combination set as variables.
+
+use indexmap::IndexMap;
+
+/// The value of each matrix variable in one run, in definition order
+pub type Combination = Vec<(String, String)>;
+
+/// Every combination of the values in `matrix`, the first variable varying
+/// slowest
+pub fn matrix_combinations(matrix: &IndexMap<String, Vec<String>>) -> Vec<Combination> {
+    matrix
+        .iter()
+        .fold(vec![Vec::new()], |combinations, (name, values)| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((name.clone(), value.clone()));
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+/// Name of a combination in output, such as `os=linux, arch=amd64`
+pub fn describe_combination(combination: &Combination) -> String {
+    let values: Vec<String> = combination
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    values.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_combinations() {
+        let mut matrix = IndexMap::new();
+        matrix.insert(
+            "os".to_string(),
+            vec!["linux".to_string(), "macos".to_string()],
+        );
+        matrix.insert(
+            "arch".to_string(),
+            vec!["amd64".to_string(), "arm64".to_string()],
+        );
+
+        let described: Vec<String> = matrix_combinations(&matrix)
+            .iter()
+            .map(describe_combination)
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                "os=linux, arch=amd64",
+                "os=linux, arch=arm64",
+                "os=macos, arch=amd64",
+                "os=macos, arch=arm64",
+            ]
+        );
+
+        // A variable without values leaves nothing to run
+        matrix.insert("libc".to_string(), Vec::new());
+        assert!(matrix_combinations(&matrix).is_empty());
+    }
+}
@@ -3,21 +3,51 @@
 //! This module handles the execution of tasks, including command running,
 //! conditional logic, and dependency resolution.
 
+pub mod cache;
+pub mod checkpoint;
 pub mod command;
+pub mod concurrency;
 pub mod context;
+pub mod executor;
+pub mod history;
 pub mod interpolate;
+pub mod matrix;
+pub mod output;
+pub mod parallel;
+pub mod pty;
+pub mod registry;
+pub mod scheduler;
+pub mod service;
+pub mod session;
+pub mod signals;
 pub mod task;
+pub mod terminal;
+pub mod trace;
+pub mod value;
 pub mod when;
 
 // Module declarations (to be implemented in later phases)
 // pub mod run;
 // pub mod option;
-// pub mod cache;
 // pub mod dependencies;
 
 // Re-export main types
+pub use cache::*;
+pub use checkpoint::*;
 pub use command::*;
+pub use concurrency::*;
 pub use context::*;
+pub use executor::*;
+pub use history::*;
 pub use interpolate::*;
+pub use matrix::*;
+pub use output::*;
+pub use parallel::*;
+pub use registry::*;
+pub use scheduler::*;
+pub use service::*;
+pub use session::*;
 pub use task::*;
+pub use trace::*;
+pub use value::*;
 pub use when::*;
@@ -0,0 +1,295 @@
+//! Captured and streamed command output
+//!
+//! This module bounds how much child output is kept in memory when a
+//! command's stdout is captured into a variable, and routes streamed output
+//! line by line when it cannot simply be inherited.
+
+use crate::utils::time::now_timestamp;
+use colored::{Color, Colorize};
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+
+/// Default maximum number of bytes kept from captured output (1 MiB)
+pub const DEFAULT_CAPTURE_MAX_BYTES: usize = 1024 * 1024;
+
+/// Colors cycled through for output prefixes
+const PREFIX_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Prefix for the output lines of `name`, the `index`th of several services
+/// or concurrent branches, padded so the names line up at `width`
+pub fn output_prefix(name: &str, index: usize, width: usize) -> String {
+    let label = format!("{:width$} |", name, width = width);
+    format!(
+        "{} ",
+        label.color(PREFIX_COLORS[index % PREFIX_COLORS.len()])
+    )
+}
+
+/// Limits applied to captured output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureLimits {
+    /// Maximum number of bytes kept; the rest is read and discarded
+    pub max_bytes: usize,
+
+    /// Strip surrounding whitespace (including the trailing newline)
+    pub trim: bool,
+}
+
+impl Default for CaptureLimits {
+    fn default() -> Self {
+        CaptureLimits {
+            max_bytes: DEFAULT_CAPTURE_MAX_BYTES,
+            trim: true,
+        }
+    }
+}
+
+impl CaptureLimits {
+    /// Apply the limits to output that was read in full
+    pub fn apply(&self, output: &str) -> Captured {
+        read_limited(output.as_bytes(), self).expect("reading from memory cannot fail")
+    }
+}
+
+/// Output captured from a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captured {
+    /// Captured text after truncation and trimming
+    pub text: String,
+
+    /// Whether output beyond the byte limit was discarded
+    pub truncated: bool,
+}
+
+/// Read a stream to the end, keeping at most `limits.max_bytes` bytes
+///
+/// The remainder of the stream is drained so the writer never blocks on a full pipe.
+pub fn read_limited<R: Read>(mut reader: R, limits: &CaptureLimits) -> io::Result<Captured> {
+    let mut kept = Vec::new();
+    (&mut reader)
+        .take(limits.max_bytes as u64)
+        .read_to_end(&mut kept)?;
+
+    let discarded = io::copy(&mut reader, &mut io::sink())?;
+
+    let text = String::from_utf8_lossy(&kept);
+    let text = if limits.trim {
+        text.trim().to_string()
+    } else {
+        text.into_owned()
+    };
+
+    Ok(Captured {
+        text,
+        truncated: discarded > 0,
+    })
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, OSC titles) from text
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let re = ANSI.get_or_init(|| {
+        Regex::new(r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(?:\x07|\x1b\\)|[@-Z\\-_])").unwrap()
+    });
+    re.replace_all(text, "")
+}
+
+/// Which child stream a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives command output line by line instead of the console
+///
+/// Register one with [`Context::with_observer`] when embedding rtask to show
+/// or store output your own way. Lines arrive on reader threads, and several
+/// commands may be streaming at once (e.g. in a parallel group). `command` is
+/// the interpolated command string, as recorded in sessions.
+///
+/// [`Context::with_observer`]: crate::runner::Context::with_observer
+pub trait OutputObserver: Send + Sync {
+    /// A line the command wrote to stdout, without its line ending
+    fn stdout_line(&self, _command: &str, _line: &str) {}
+
+    /// A line the command wrote to stderr, without its line ending
+    fn stderr_line(&self, _command: &str, _line: &str) {}
+}
+
+impl fmt::Debug for dyn OutputObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutputObserver")
+    }
+}
+
+/// Destination for streamed output lines
+///
+/// Every line goes to the log file when one is set; only lines matching the
+/// filter (or all lines, without a filter) are shown on the console, or
+/// handed to the observer when there is one.
+#[derive(Debug, Clone, Default)]
+pub struct LineSink {
+    /// Only lines matching this pattern are shown on the console
+    pub filter: Option<Regex>,
+
+    /// Whether lines are echoed to the console at all
+    pub console: bool,
+
+    /// File that receives every line
+    pub log: Option<Arc<Mutex<File>>>,
+
+    /// File that receives every line prefixed with a timestamp
+    pub tee: Option<Arc<Mutex<File>>>,
+
+    /// Strip ANSI escape sequences from lines written to files
+    pub strip_ansi: bool,
+
+    /// Receives console lines in place of stdout/stderr
+    pub observer: Option<Arc<dyn OutputObserver>>,
+
+    /// Command the lines belong to, as reported to the observer
+    pub command: String,
+
+    /// Label printed before each console line
+    pub prefix: String,
+}
+
+impl LineSink {
+    /// Route a single line (without its trailing newline)
+    pub fn write_line(&self, stream: Stream, line: &str) {
+        let file_line = if self.strip_ansi {
+            strip_ansi(line)
+        } else {
+            Cow::Borrowed(line)
+        };
+
+        if let Some(log) = &self.log {
+            if let Ok(mut file) = log.lock() {
+                let _ = writeln!(file, "{}", file_line);
+            }
+        }
+
+        if let Some(tee) = &self.tee {
+            if let Ok(mut file) = tee.lock() {
+                let _ = writeln!(file, "[{}] {}", now_timestamp(), file_line);
+            }
+        }
+
+        if self.console && self.filter.as_ref().is_none_or(|re| re.is_match(line)) {
+            match (&self.observer, stream) {
+                (Some(observer), Stream::Stdout) => observer.stdout_line(&self.command, line),
+                (Some(observer), Stream::Stderr) => observer.stderr_line(&self.command, line),
+                (None, Stream::Stdout) => println!("{}{}", self.prefix, line),
+                (None, Stream::Stderr) => eprintln!("{}{}", self.prefix, line),
+            }
+        }
+    }
+}
+
+/// Read a child stream on a background thread, passing each line to the sink
+pub fn stream_lines<R>(reader: R, stream: Stream, sink: Arc<LineSink>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf);
+                    sink.write_line(stream, line.trim_end_matches(['\n', '\r']));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_within_limit() {
+        let captured = read_limited("hello\n".as_bytes(), &CaptureLimits::default()).unwrap();
+        assert_eq!(captured.text, "hello");
+        assert!(!captured.truncated);
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let limits = CaptureLimits {
+            max_bytes: 3,
+            trim: false,
+        };
+        let captured = read_limited("abcdef".as_bytes(), &limits).unwrap();
+        assert_eq!(captured.text, "abc");
+        assert!(captured.truncated);
+    }
+
+    #[test]
+    fn test_read_without_trim() {
+        let limits = CaptureLimits {
+            trim: false,
+            ..Default::default()
+        };
+        let captured = read_limited(" padded \n".as_bytes(), &limits).unwrap();
+        assert_eq!(captured.text, " padded \n");
+    }
+
+    #[test]
+    fn test_stream_lines_logs_everything() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("out.log");
+        let sink = Arc::new(LineSink {
+            filter: Some(Regex::new("ERROR").unwrap()),
+            console: false,
+            log: Some(Arc::new(Mutex::new(File::create(&log_path).unwrap()))),
+            tee: None,
+            strip_ansi: true,
+            ..Default::default()
+        });
+
+        stream_lines(
+            "ok\n\x1b[31mERROR\x1b[0m bad\r\nlast".as_bytes(),
+            Stream::Stdout,
+            sink,
+        )
+        .join()
+        .unwrap();
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        assert_eq!(contents, "ok\nERROR bad\nlast\n");
+    }
+
+    #[test]
+    fn test_output_prefix_pads_names() {
+        colored::control::set_override(false);
+        assert_eq!(output_prefix("db", 0, 5), "db    | ");
+        assert_eq!(output_prefix("api", 1, 3), "api | ");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[1;32mgreen\x1b[0m"), "green");
+        assert_eq!(strip_ansi("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+}
@@ -0,0 +1,256 @@
+//! Running branches concurrently
+//!
+//! Shared by everything that fans out work (parallel run items, parallel
+//! deps). Each branch runs on its own thread; the [`FailurePolicy`] decides
+//! whether the first failure cancels the remaining branches or whether all
+//! branches finish and every failure is reported together.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// What to do with sibling branches when one fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Cancel siblings on the first failure and report that failure
+    #[default]
+    FailFast,
+    /// Let every branch finish, then report all failures together
+    CollectAll,
+}
+
+impl FailurePolicy {
+    /// Policy for a `fail-fast:` setting (unset means fail fast)
+    pub fn from_fail_fast(fail_fast: Option<bool>) -> Self {
+        match fail_fast {
+            Some(false) => FailurePolicy::CollectAll,
+            _ => FailurePolicy::FailFast,
+        }
+    }
+}
+
+/// Cooperative cancellation flag handed to each branch
+///
+/// Branches should check it before starting expensive work such as spawning
+/// a process; commands already running in a branch whose context carries
+/// the token are terminated.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Request cancellation
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Request cancellation, returning whether it had already been requested
+    fn cancel_again(&self) -> bool {
+        self.0.swap(true, Ordering::SeqCst)
+    }
+}
+
+/// A unit of work run by [`run_parallel`]
+pub type Branch<'a> = Box<dyn FnOnce(&CancelToken) -> ExecutionResult<()> + Send + 'a>;
+
+/// Run all branches concurrently and combine their results per `policy`
+pub fn run_parallel(branches: Vec<Branch<'_>>, policy: FailurePolicy) -> ExecutionResult<()> {
+    let token = CancelToken::default();
+
+    let failures: Vec<ExecutionError> = thread::scope(|scope| {
+        let handles: Vec<_> = branches
+            .into_iter()
+            .map(|branch| {
+                let token = token.clone();
+                scope.spawn(move || {
+                    if token.is_cancelled() {
+                        return Ok(());
+                    }
+                    let result = branch(&token);
+                    // A branch failing after a sibling's failure was most
+                    // likely terminated by it; report the sibling's failure
+                    if result.is_err() && policy == FailurePolicy::FailFast && token.cancel_again()
+                    {
+                        return Ok(());
+                    }
+                    result
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| match handle.join() {
+                Ok(result) => result.err(),
+                Err(_) => Some(ExecutionError::Environment(
+                    "parallel branch panicked".to_string(),
+                )),
+            })
+            .collect()
+    });
+
+    combine(policy, failures)
+}
+
+/// Run branches one after another in order, with the same failure handling
+/// as [`run_parallel`]
+///
+/// Used where output must stay deterministic, such as a recorded session.
+pub fn run_in_order(branches: Vec<Branch<'_>>, policy: FailurePolicy) -> ExecutionResult<()> {
+    let token = CancelToken::default();
+    let mut failures = Vec::new();
+    for branch in branches {
+        if let Err(e) = branch(&token) {
+            failures.push(e);
+            if policy == FailurePolicy::FailFast {
+                break;
+            }
+        }
+    }
+    combine(policy, failures)
+}
+
+/// Order in which to start work that is ready at the same time: highest
+/// priority first, ties in the order given
+///
+/// Under a job limit, starting the tasks that head long chains first keeps
+/// the limit busy to the end of the run; `priority:` lets a config say which
+/// those are.
+pub fn start_order(priorities: &[i32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..priorities.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(priorities[index]));
+    order
+}
+
+/// Turn the failures of a group of branches into one result
+pub(crate) fn combine(
+    policy: FailurePolicy,
+    mut failures: Vec<ExecutionError>,
+) -> ExecutionResult<()> {
+    match (policy, failures.len()) {
+        (_, 0) => Ok(()),
+        (FailurePolicy::FailFast, _) | (_, 1) => Err(failures.remove(0)),
+        (FailurePolicy::CollectAll, _) => Err(ExecutionError::ParallelFailed(failures)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    fn fail(code: i32) -> Branch<'static> {
+        Box::new(move |_| Err(ExecutionError::CommandFailed(Some(code))))
+    }
+
+    #[test]
+    fn test_all_succeed() {
+        let count = AtomicUsize::new(0);
+        let branches: Vec<Branch> = (0..4)
+            .map(|_| -> Branch {
+                Box::new(|_| {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                })
+            })
+            .collect();
+
+        assert!(run_parallel(branches, FailurePolicy::FailFast).is_ok());
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_fail_fast_cancels_waiting_siblings() {
+        let slow: Branch = Box::new(|token: &CancelToken| {
+            for _ in 0..100 {
+                if token.is_cancelled() {
+                    return Ok(());
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("sibling was not cancelled");
+        });
+
+        let result = run_parallel(vec![fail(2), slow], FailurePolicy::FailFast);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+    }
+
+    #[test]
+    fn test_fail_fast_reports_the_failure_that_cancelled() {
+        let terminated: Branch = Box::new(|token: &CancelToken| {
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(ExecutionError::CommandFailed(Some(143)))
+        });
+
+        let result = run_parallel(vec![terminated, fail(2)], FailurePolicy::FailFast);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+    }
+
+    #[test]
+    fn test_collect_all_reports_every_failure() {
+        let finished = AtomicBool::new(false);
+        let slow: Branch = Box::new(|_| {
+            thread::sleep(Duration::from_millis(50));
+            finished.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+
+        let result = run_parallel(vec![fail(2), slow, fail(3)], FailurePolicy::CollectAll);
+        assert!(finished.load(Ordering::SeqCst));
+        match result {
+            Err(ExecutionError::ParallelFailed(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected aggregated failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_in_order_stops_at_first_failure() {
+        let count = AtomicUsize::new(0);
+        let ok = || -> Branch {
+            Box::new(|_| {
+                count.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+
+        let result = run_in_order(vec![ok(), fail(2), ok()], FailurePolicy::FailFast);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let result = run_in_order(vec![fail(2), ok(), fail(3)], FailurePolicy::CollectAll);
+        assert!(matches!(result, Err(ExecutionError::ParallelFailed(ref e)) if e.len() == 2));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_start_order_by_priority() {
+        assert_eq!(start_order(&[0, 10, 0, -1, 10]), vec![1, 4, 0, 2, 3]);
+        assert!(start_order(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_policy_from_fail_fast() {
+        assert_eq!(FailurePolicy::from_fail_fast(None), FailurePolicy::FailFast);
+        assert_eq!(
+            FailurePolicy::from_fail_fast(Some(false)),
+            FailurePolicy::CollectAll
+        );
+    }
+}
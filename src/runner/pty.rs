@@ -0,0 +1,280 @@
+//! Pseudo-terminals for `tty: true` commands
+//!
+//! A command spawned by [`spawn`] runs as the leader of a new session whose
+//! controlling terminal is a fresh pseudo-terminal, so it sees a terminal on
+//! stdout and stderr even though rtask reads its output. When rtask's stdin
+//! is a terminal, the command's stdin is the pseudo-terminal too: what is
+//! typed is forwarded to it, with rtask's terminal in raw mode meanwhile so
+//! keys such as Ctrl-C reach the command as typed. Otherwise the command
+//! reads rtask's stdin directly, leaving what it does not read to the
+//! commands after it.
+//!
+//! Pseudo-terminals need a unix system; elsewhere `tty: true` commands run
+//! with pipes like any other command.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::process::{Child, Command as StdCommand};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Whether commands can be given a pseudo-terminal on this system
+pub const SUPPORTED: bool = cfg!(unix);
+
+/// Terminal size (rows, columns) used when rtask itself has no terminal
+#[cfg(unix)]
+const DEFAULT_SIZE: (u16, u16) = (24, 80);
+
+/// How often, in milliseconds, the input forwarder checks whether the command
+/// has ended
+#[cfg(unix)]
+const INPUT_POLL_MS: i32 = 50;
+
+/// Set while a command has rtask's stdin, which only one command gets at a time
+static INPUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Everything a command writes to its terminal
+///
+/// Reading ends once the command, and any process it left holding the
+/// terminal, has exited.
+pub struct PtyOutput(File);
+
+impl Read for PtyOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // Linux reports a terminal whose last user has gone as an I/O error
+            #[cfg(unix)]
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Forwards rtask's stdin to a command's terminal until dropped, then puts
+/// rtask's own terminal back the way it was
+pub struct InputForwarder {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    /// Mode of rtask's terminal before it was switched to raw mode
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl Drop for InputForwarder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        let _ = thread.join();
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: `saved` was filled in by tcgetattr on the same descriptor
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+        INPUT_TAKEN.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Spawn `command` in a new pseudo-terminal, returning the process, its
+/// output and the forwarding of rtask's stdin to it
+///
+/// The terminal gets the size of rtask's own terminal, and leaves line
+/// endings as the command writes them, as a pipe would.
+#[cfg(unix)]
+pub fn spawn(command: &mut StdCommand) -> io::Result<(Child, PtyOutput, InputForwarder)> {
+    use std::io::IsTerminal;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    let (master, slave) = open_pty()?;
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        command.stdin(Stdio::from(slave.try_clone()?));
+    } else {
+        command.stdin(Stdio::inherit());
+    }
+    command.stdout(Stdio::from(slave.try_clone()?));
+    command.stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe calls between fork and exec; stdout is
+    // the terminal in either case, so it becomes the controlling terminal
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1
+                || libc::ioctl(libc::STDOUT_FILENO, libc::TIOCSCTTY as _, 0) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let spawned = command.spawn();
+    // Without rtask's copies of the terminal, reading it ends with the command
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let child = spawned?;
+    let input = if interactive {
+        forward_input(master.try_clone()?)
+    } else {
+        InputForwarder {
+            stop: Arc::default(),
+            thread: None,
+            saved: None,
+        }
+    };
+    Ok((child, PtyOutput(master), input))
+}
+
+#[cfg(not(unix))]
+pub fn spawn(_command: &mut StdCommand) -> io::Result<(Child, PtyOutput, InputForwarder)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pseudo-terminals need a unix system",
+    ))
+}
+
+/// Open a pseudo-terminal, returning its master and slave sides
+#[cfg(unix)]
+fn open_pty() -> io::Result<(File, File)> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let mut size = terminal_size();
+    let (mut master, mut slave) = (0, 0);
+    // SAFETY: openpty fills in two descriptors that nothing else owns
+    let (master, slave) = unsafe {
+        let opened = libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::addr_of_mut!(size),
+        );
+        if opened == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        (File::from_raw_fd(master), File::from_raw_fd(slave))
+    };
+    // SAFETY: plain fcntl and termios calls on descriptors owned above
+    unsafe {
+        for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let mut mode: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut mode) == 0 {
+            mode.c_oflag &= !libc::ONLCR;
+            libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &mode);
+        }
+    }
+    Ok((master, slave))
+}
+
+/// Size of the terminal rtask runs in, if any
+#[cfg(unix)]
+fn terminal_size() -> libc::winsize {
+    let mut size = libc::winsize {
+        ws_row: DEFAULT_SIZE.0,
+        ws_col: DEFAULT_SIZE.1,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO] {
+        let mut found = size;
+        // SAFETY: TIOCGWINSZ only writes a winsize
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut found) } == 0 && found.ws_col > 0 {
+            size = found;
+            break;
+        }
+    }
+    size
+}
+
+/// Copy what is typed in rtask's terminal to the terminal `master` on a
+/// background thread
+///
+/// Only one command at a time is given rtask's input; others get none.
+#[cfg(unix)]
+fn forward_input(master: File) -> InputForwarder {
+    use std::io::Write;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut forwarder = InputForwarder {
+        stop: Arc::clone(&stop),
+        thread: None,
+        saved: None,
+    };
+    if INPUT_TAKEN.swap(true, Ordering::SeqCst) {
+        return forwarder;
+    }
+    forwarder.saved = enter_raw_mode();
+    forwarder.thread = Some(std::thread::spawn(move || {
+        let mut master = master;
+        let mut buf = [0u8; 1024];
+        while !stop.load(Ordering::SeqCst) {
+            let mut stdin = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll and read on stdin into a buffer of the given length
+            let read = unsafe {
+                match libc::poll(&mut stdin, 1, INPUT_POLL_MS) {
+                    0 => continue,
+                    -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {
+                        continue
+                    }
+                    -1 => break,
+                    _ => libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()),
+                }
+            };
+            let read = match usize::try_from(read) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            if master.write_all(&buf[..read]).is_err() {
+                break;
+            }
+        }
+    }));
+    forwarder
+}
+
+/// Switch rtask's terminal to raw input, returning its previous mode
+///
+/// Output processing is kept, so rtask's own lines still start at the left
+/// margin.
+#[cfg(unix)]
+fn enter_raw_mode() -> Option<libc::termios> {
+    // SAFETY: termios calls on stdin with structs they fill in themselves
+    unsafe {
+        let mut saved: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) == -1 {
+            return None;
+        }
+        let mut raw = saved;
+        libc::cfmakeraw(&mut raw);
+        raw.c_oflag = saved.c_oflag;
+        (libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) == 0).then_some(saved)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_sees_a_terminal() {
+        let mut command = StdCommand::new("sh");
+        command.args(["-c", "test -t 1 && test -t 2 && printf 'a\\nb\\n'"]);
+        let (mut child, mut output, input) = spawn(&mut command).unwrap();
+        let status = child.wait().unwrap();
+        drop(input);
+
+        let mut text = String::new();
+        output.read_to_string(&mut text).unwrap();
+        assert!(status.success());
+        assert_eq!(text, "a\nb\n");
+    }
+}
@@ -0,0 +1,55 @@
+//! Task registry
+//!
+//! The runtime tasks of a config by name, so run items can start the tasks
+//! they reference with `task:`.
+
+use crate::config::Config;
+use crate::error::ConfigResult;
+use crate::runner::{definition_hash, Task};
+use std::collections::HashMap;
+
+/// Runtime tasks of a config, without variables
+#[derive(Debug, Clone, Default)]
+pub struct TaskRegistry {
+    tasks: HashMap<String, Task>,
+}
+
+impl TaskRegistry {
+    /// Build the runtime task of every task in `config`
+    pub fn from_config(config: &Config) -> ConfigResult<Self> {
+        let mut tasks = HashMap::new();
+        for (name, task_config) in &config.tasks {
+            let mut task = Task::from_config(name.clone(), task_config.clone())?;
+            task.definition = definition_hash(config, name);
+            tasks.insert(name.clone(), task);
+        }
+        Ok(TaskRegistry { tasks })
+    }
+
+    /// The task called `name`
+    pub fn get(&self, name: &str) -> Option<&Task> {
+        self.tasks.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    #[test]
+    fn test_registry_from_config() {
+        let config = parse_config(
+            "tasks:\n  build:\n    private: true\n    run: make\n  test:\n    run: make test\n",
+            None,
+        )
+        .unwrap();
+        let registry = TaskRegistry::from_config(&config).unwrap();
+
+        let build = registry.get("build").unwrap();
+        assert!(build.private);
+        assert_eq!(build.definition, definition_hash(&config, "build"));
+        assert!(registry.get("test").is_some());
+        assert!(registry.get("deploy").is_none());
+    }
+}
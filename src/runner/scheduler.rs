@@ -0,0 +1,420 @@
+//! Dependency-aware scheduling
+//!
+//! Runs a set of jobs whose prerequisites form a DAG on a bounded number of
+//! threads (`--jobs`). A job starts once all of its prerequisites succeeded;
+//! among jobs ready at the same time, higher priorities start first.
+//! [`JobSlots`] bound the jobs of nested schedules together, and
+//! [`RunOnce`] keeps prerequisites that several tasks share from running
+//! more than once in a run.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use crate::runner::{combine, start_order, Branch, CancelToken, FailurePolicy};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// How often a schedule with ready jobs looks for slots that other
+/// schedules gave back
+const SLOT_POLL: Duration = Duration::from_millis(20);
+
+/// A unit of work for [`schedule`]
+pub struct Job<'a> {
+    /// Indices of the jobs that must succeed before this one starts
+    pub prerequisites: Vec<usize>,
+    /// Scheduling hint among jobs ready at the same time
+    pub priority: i32,
+    pub work: Branch<'a>,
+}
+
+/// The number of jobs that may run at once across every schedule of a run
+/// (`--jobs`); shared with branch contexts
+///
+/// A job waiting for other jobs, in a nested schedule or for a prerequisite
+/// another thread runs, lends its slot to them meanwhile.
+#[derive(Debug, Clone)]
+pub struct JobSlots {
+    free: Arc<Mutex<usize>>,
+    freed: Arc<Condvar>,
+}
+
+impl JobSlots {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(limit.max(1))),
+            freed: Arc::new(Condvar::new()),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if *free == 0 {
+            return false;
+        }
+        *free -= 1;
+        true
+    }
+
+    fn acquire(&self) {
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        while *free == 0 {
+            free = self.freed.wait(free).unwrap_or_else(|e| e.into_inner());
+        }
+        *free -= 1;
+    }
+
+    fn release(&self) {
+        *self.free.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.freed.notify_one();
+    }
+
+    /// Run `wait` with the caller's slot given back, taking one again after
+    pub fn lend<T>(&self, wait: impl FnOnce() -> T) -> T {
+        self.release();
+        let result = wait();
+        self.acquire();
+        result
+    }
+}
+
+impl Default for JobSlots {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// Whether a prerequisite task was claimed by a caller, and, once it ran,
+/// whether it succeeded
+#[derive(Debug, Default)]
+struct Claim {
+    claimed: AtomicBool,
+    succeeded: OnceLock<bool>,
+}
+
+/// Settles a claim as failed if its task panicked, so waiters go on
+struct Settle<'a>(&'a OnceLock<bool>);
+
+impl Drop for Settle<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.set(false);
+    }
+}
+
+/// Prerequisite tasks that ran in this run, by name, with whether they
+/// succeeded; shared with branch contexts
+#[derive(Debug, Clone, Default)]
+pub struct RunOnce(Arc<Mutex<HashMap<String, Arc<Claim>>>>);
+
+impl RunOnce {
+    /// Run the task `name` with `work` unless it already ran in this run
+    ///
+    /// A caller arriving while another thread runs the task waits for it,
+    /// lending its job slot, if it holds one. If the task failed, later
+    /// callers fail too, without running it again.
+    pub fn run(
+        &self,
+        name: &str,
+        slot: Option<&JobSlots>,
+        work: impl FnOnce() -> ExecutionResult<()>,
+    ) -> ExecutionResult<()> {
+        let claim = {
+            let mut tasks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(tasks.entry(name.to_string()).or_default())
+        };
+        if !claim.claimed.swap(true, Ordering::SeqCst) {
+            let _settle = Settle(&claim.succeeded);
+            let result = work();
+            let _ = claim.succeeded.set(result.is_ok());
+            return result;
+        }
+        let succeeded = match slot {
+            Some(slots) => *slots.lend(|| claim.succeeded.wait()),
+            None => *claim.succeeded.wait(),
+        };
+        if succeeded {
+            Ok(())
+        } else {
+            Err(ExecutionError::Subtask {
+                task: name.to_string(),
+                error: "it already failed in this run".to_string(),
+            })
+        }
+    }
+}
+
+/// Run `jobs` in dependency order, each in one of `slots`
+///
+/// While none of its jobs run, the schedule waits for a free slot; a job
+/// that holds a slot of its own lends it first (see [`JobSlots::lend`]).
+/// Under [`FailurePolicy::FailFast`] the first failure cancels running jobs
+/// and nothing new starts. Under [`FailurePolicy::CollectAll`] jobs that do
+/// not depend on a failed one still run, and every failure is reported.
+/// Prerequisites must not form a cycle; jobs in one never start.
+pub fn schedule(
+    jobs: Vec<Job<'_>>,
+    slots: &JobSlots,
+    policy: FailurePolicy,
+) -> ExecutionResult<()> {
+    let mut waiting: Vec<usize> = jobs.iter().map(|job| job.prerequisites.len()).collect();
+    let mut dependents = vec![Vec::new(); jobs.len()];
+    for (index, job) in jobs.iter().enumerate() {
+        for &prerequisite in &job.prerequisites {
+            dependents[prerequisite].push(index);
+        }
+    }
+    let priorities: Vec<i32> = jobs.iter().map(|job| job.priority).collect();
+    let mut work: Vec<Option<Branch>> = jobs.into_iter().map(|job| Some(job.work)).collect();
+    let mut ready: Vec<usize> = (0..work.len())
+        .filter(|&index| waiting[index] == 0)
+        .collect();
+
+    let token = CancelToken::default();
+    let mut failures = Vec::new();
+    thread::scope(|scope| {
+        let (done, finished) = mpsc::channel();
+        let mut running = 0;
+        loop {
+            if !token.is_cancelled() {
+                let ready_priorities: Vec<i32> =
+                    ready.iter().map(|&index| priorities[index]).collect();
+                let order: Vec<usize> = start_order(&ready_priorities)
+                    .into_iter()
+                    .map(|position| ready[position])
+                    .collect();
+                let mut started = 0;
+                for &index in &order {
+                    if running == 0 {
+                        slots.acquire();
+                    } else if !slots.try_acquire() {
+                        break;
+                    }
+                    started += 1;
+                    let Some(branch) = work[index].take() else {
+                        slots.release();
+                        continue;
+                    };
+                    running += 1;
+                    let token = token.clone();
+                    let done = done.clone();
+                    scope.spawn(move || {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| branch(&token)))
+                            .unwrap_or_else(|_| {
+                                Err(ExecutionError::Environment(
+                                    "scheduled task panicked".to_string(),
+                                ))
+                            });
+                        slots.release();
+                        let _ = done.send((index, result));
+                    });
+                }
+                ready = order[started..].to_vec();
+            }
+            if running == 0 {
+                break;
+            }
+
+            // Slots other schedules give back go to the jobs still ready
+            let received = if ready.is_empty() || token.is_cancelled() {
+                finished.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                finished.recv_timeout(SLOT_POLL)
+            };
+            let (index, result) = match received {
+                Ok(received) => received,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            running -= 1;
+            match result {
+                Ok(()) => {
+                    for &dependent in &dependents[index] {
+                        waiting[dependent] -= 1;
+                        if waiting[dependent] == 0 {
+                            ready.push(dependent);
+                        }
+                    }
+                }
+                Err(e) => {
+                    failures.push(e);
+                    if policy == FailurePolicy::FailFast {
+                        token.cancel();
+                    }
+                }
+            }
+        }
+    });
+
+    combine(policy, failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn job<'a>(prerequisites: &[usize], work: Branch<'a>) -> Job<'a> {
+        Job {
+            prerequisites: prerequisites.to_vec(),
+            priority: 0,
+            work,
+        }
+    }
+
+    #[test]
+    fn test_run_once() {
+        let once = RunOnce::default();
+        let ran = AtomicUsize::new(0);
+        let succeed = || {
+            ran.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+        once.run("build", None, succeed).unwrap();
+        once.run("build", None, succeed).unwrap();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        let fail = || Err(ExecutionError::CommandFailed(Some(2)));
+        assert!(matches!(
+            once.run("lint", None, fail),
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+        assert!(matches!(
+            once.run("lint", None, succeed),
+            Err(ExecutionError::Subtask { .. })
+        ));
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_prerequisites_finish_first() {
+        let log = Mutex::new(Vec::new());
+        let record = |name: &'static str| -> Branch {
+            let log = &log;
+            Box::new(move |_| {
+                log.lock().unwrap().push(name);
+                Ok(())
+            })
+        };
+
+        // build <- test, build <- lint, test + lint <- package
+        let jobs = vec![
+            job(&[1, 2], record("package")),
+            job(&[3], record("test")),
+            job(&[3], record("lint")),
+            job(&[], record("build")),
+        ];
+        schedule(jobs, &JobSlots::new(4), FailurePolicy::FailFast).unwrap();
+
+        let log = log.into_inner().unwrap();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[0], "build");
+        assert_eq!(log[3], "package");
+    }
+
+    #[test]
+    fn test_limit_bounds_running_jobs() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let jobs = (0..6)
+            .map(|_| {
+                job(
+                    &[],
+                    Box::new(|_| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(30));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    }),
+                )
+            })
+            .collect();
+
+        schedule(jobs, &JobSlots::new(2), FailurePolicy::FailFast).unwrap();
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_nested_schedules_share_slots() {
+        let slots = JobSlots::new(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let leaf = || -> Branch {
+            Box::new(|_| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(30));
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+        let nested = || -> Branch {
+            Box::new(|_| {
+                let jobs = (0..3).map(|_| job(&[], leaf())).collect();
+                slots.lend(|| schedule(jobs, &slots, FailurePolicy::FailFast))
+            })
+        };
+
+        let jobs = vec![job(&[], nested()), job(&[], nested())];
+        schedule(jobs, &slots, FailurePolicy::FailFast).unwrap();
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_higher_priority_starts_first() {
+        let log = Mutex::new(Vec::new());
+        let jobs = [(1, "low"), (5, "high"), (1, "low2")]
+            .into_iter()
+            .map(|(priority, name)| Job {
+                prerequisites: Vec::new(),
+                priority,
+                work: Box::new({
+                    let log = &log;
+                    move |_| {
+                        log.lock().unwrap().push(name);
+                        Ok(())
+                    }
+                }),
+            })
+            .collect();
+
+        schedule(jobs, &JobSlots::new(1), FailurePolicy::FailFast).unwrap();
+        assert_eq!(log.into_inner().unwrap(), vec!["high", "low", "low2"]);
+    }
+
+    #[test]
+    fn test_failure_skips_dependents() {
+        let ran = AtomicUsize::new(0);
+        let count = || -> Branch {
+            Box::new(|_| {
+                ran.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+        let fail = || -> Branch { Box::new(|_| Err(ExecutionError::CommandFailed(Some(2)))) };
+
+        // 0 fails, 1 depends on it, 2 and 3 are independent
+        let jobs = vec![
+            job(&[], fail()),
+            job(&[0], count()),
+            job(&[], count()),
+            job(&[], fail()),
+        ];
+        match schedule(jobs, &JobSlots::new(1), FailurePolicy::CollectAll) {
+            Err(ExecutionError::ParallelFailed(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected aggregated failure, got {:?}", other),
+        }
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        let jobs = vec![job(&[], fail()), job(&[], count())];
+        let result = schedule(jobs, &JobSlots::new(1), FailurePolicy::FailFast);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,632 @@
+//! Long-running service tasks
+//!
+//! A service task's command is started and then supervised on a background
+//! thread, which restarts it according to the task's [`RestartPolicy`].
+//! Starting a service waits until its readiness check passes. Dropping the
+//! [`Service`] handle stops the process: first politely (SIGTERM on Unix),
+//! then forcibly after a grace period.
+//!
+//! Service processes are spawned directly rather than through the context's
+//! executor, since they outlive the command that started them. The system
+//! executor supervises `background: true` commands as services too.
+
+use crate::config::RestartPolicy;
+use crate::error::{ExecutionError, ExecutionResult};
+use crate::runner::signals::{self, SIGTERM};
+use crate::runner::{exit_code, probe_command, Context, Stream};
+use crate::ui::message;
+use crate::utils::duration::format_duration;
+use crate::utils::net;
+use regex::Regex;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command as StdCommand, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default time a service has to become ready
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often readiness and process state are polled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pause before restarting a service that exited
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a service has to exit after being asked to stop
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// How to tell that a service is ready
+#[derive(Debug, Clone)]
+pub enum Readiness {
+    /// A TCP port on localhost accepts connections
+    Port(u16),
+
+    /// The service prints a line matching the pattern
+    Log(Regex),
+
+    /// A command succeeds
+    Command(String),
+}
+
+impl Readiness {
+    /// Short description for error messages
+    fn describe(&self) -> String {
+        match self {
+            Readiness::Port(port) => format!("port {} is not open", port),
+            Readiness::Log(re) => format!("no output line matched '{}'", re),
+            Readiness::Command(cmd) => format!("'{}' did not succeed", cmd),
+        }
+    }
+
+    /// Check once whether the service is ready
+    fn check(&self, ctx: &Context, log_ready: &Receiver<()>) -> ExecutionResult<bool> {
+        match self {
+            Readiness::Port(port) => Ok(port_open(*port)),
+            Readiness::Log(_) => Ok(log_ready.recv_timeout(POLL_INTERVAL).is_ok()),
+            Readiness::Command(cmd) => probe_command(cmd, ctx),
+        }
+    }
+}
+
+/// Runtime settings of a service task
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    /// Check that must pass before the service counts as started
+    pub ready: Option<Readiness>,
+
+    /// How long to wait for the check to pass
+    pub timeout: Duration,
+
+    /// When the process is restarted after exiting
+    pub restart: RestartPolicy,
+
+    /// Stop restarting after this many restarts
+    pub max_restarts: Option<u32>,
+}
+
+/// How a service's process is started, kept for restarts
+struct Launcher {
+    process: StdCommand,
+    /// Pattern the output is watched for, when readiness comes from a log line
+    watch: Option<Regex>,
+    ready: Sender<()>,
+    /// Whether the service's output is shown
+    echo: bool,
+    /// Label printed before each output line
+    prefix: Option<String>,
+    /// Run in a process group of its own, so stopping it reaches the
+    /// shell's children too
+    own_group: bool,
+}
+
+impl Launcher {
+    fn spawn(&mut self) -> ExecutionResult<Child> {
+        let forwarded = self.watch.is_some() || (self.echo && self.prefix.is_some());
+        let output = || match (forwarded, self.echo) {
+            (true, _) => Stdio::piped(),
+            (false, true) => Stdio::inherit(),
+            (false, false) => Stdio::null(),
+        };
+        self.process.stdin(Stdio::null());
+        #[cfg(unix)]
+        if self.own_group {
+            std::os::unix::process::CommandExt::process_group(&mut self.process, 0);
+        }
+        self.process.stdout(output());
+        self.process.stderr(output());
+
+        let mut child = self
+            .process
+            .spawn()
+            .map_err(|_| ExecutionError::CommandFailed(None))?;
+        if let Some(stdout) = child.stdout.take() {
+            self.forward(stdout, Stream::Stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.forward(stderr, Stream::Stderr);
+        }
+        Ok(child)
+    }
+
+    /// Pass output lines on to the console, signalling readiness on a match
+    fn forward<R: Read + Send + 'static>(&self, reader: R, stream: Stream) {
+        let (ready, echo) = (self.ready.clone(), self.echo);
+        let (watch, prefix) = (self.watch.clone(), self.prefix.clone().unwrap_or_default());
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                if echo {
+                    match stream {
+                        Stream::Stdout => println!("{}{}", prefix, line),
+                        Stream::Stderr => eprintln!("{}{}", prefix, line),
+                    }
+                }
+                if watch.as_ref().is_some_and(|watch| watch.is_match(&line)) {
+                    let _ = ready.send(());
+                }
+            }
+        });
+    }
+}
+
+/// A running service, stopped when dropped
+pub struct Service {
+    name: String,
+    stop: Arc<AtomicBool>,
+    /// Set once the process has exited for good
+    exited: Arc<AtomicBool>,
+    supervisor: Option<JoinHandle<ExecutionResult<()>>>,
+    /// Started for a `background: true` command rather than a service task
+    background: bool,
+}
+
+impl Service {
+    /// Start `process` as the service `name` and wait until it is ready
+    ///
+    /// A service started by a subtask runs in the background in a process
+    /// group of its own; one run directly stays in the terminal's group, so
+    /// Ctrl-C reaches it.
+    pub fn start(
+        name: &str,
+        process: StdCommand,
+        spec: &ServiceSpec,
+        ctx: &Context,
+    ) -> ExecutionResult<Self> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let mut launcher = Launcher {
+            process,
+            watch: match &spec.ready {
+                Some(Readiness::Log(re)) => Some(re.clone()),
+                _ => None,
+            },
+            ready: ready_tx,
+            echo: !ctx.suppresses_child_output(),
+            prefix: ctx.output_prefix.clone(),
+            own_group: ctx.task_stack.len() > 1,
+        };
+        let child = launcher.spawn()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let exited = Arc::new(AtomicBool::new(false));
+        let supervisor = {
+            let (name, spec, printer) = (name.to_string(), spec.clone(), ctx.branch());
+            let (stop, exited) = (Arc::clone(&stop), Arc::clone(&exited));
+            thread::spawn(move || {
+                let result = supervise(&name, child, launcher, &spec, &stop, &printer);
+                exited.store(true, Ordering::SeqCst);
+                result
+            })
+        };
+
+        let service = Service {
+            name: name.to_string(),
+            stop,
+            exited,
+            supervisor: Some(supervisor),
+            background: false,
+        };
+        service.wait_ready(spec, ctx, &ready_rx)?;
+        if spec.ready.is_some() {
+            ctx.print_info(&message("service.ready", &[("service", &name)]));
+        }
+        Ok(service)
+    }
+
+    /// Poll the readiness check until it passes, the process exits for
+    /// good, or the timeout runs out
+    fn wait_ready(
+        &self,
+        spec: &ServiceSpec,
+        ctx: &Context,
+        log_ready: &Receiver<()>,
+    ) -> ExecutionResult<()> {
+        let Some(check) = &spec.ready else {
+            return Ok(());
+        };
+        let not_ready = |reason: String| ExecutionError::ServiceNotReady {
+            name: self.name.clone(),
+            reason,
+        };
+
+        let deadline = Instant::now() + spec.timeout;
+        loop {
+            if check.check(ctx, log_ready)? {
+                return Ok(());
+            }
+            if self.exited.load(Ordering::SeqCst) {
+                return Err(not_ready("it exited before becoming ready".to_string()));
+            }
+            if Instant::now() >= deadline {
+                return Err(not_ready(format!(
+                    "{} after {}",
+                    check.describe(),
+                    format_duration(spec.timeout)
+                )));
+            }
+            if !matches!(check, Readiness::Log(_)) {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// Wait until the service exits for good, failing if its last run failed
+    pub fn wait(mut self) -> ExecutionResult<()> {
+        match self.supervisor.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(ExecutionError::Environment(format!(
+                "supervisor of service '{}' panicked",
+                self.name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Mark the service as one started for a `background: true` command,
+    /// named by the command when it is stopped
+    pub fn as_background_command(mut self) -> Self {
+        self.background = true;
+        self
+    }
+
+    /// Stop the service, saying so if its process is still running
+    pub fn stop(self, ctx: &Context) {
+        if self.exited.load(Ordering::SeqCst) {
+            return;
+        }
+        ctx.print_info(&if self.background {
+            message("background.stopping", &[("command", &self.name)])
+        } else {
+            message("service.stopping", &[("service", &self.name)])
+        });
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let Some(supervisor) = self.supervisor.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = supervisor.join();
+    }
+}
+
+/// Background services started during a run, shared by every context
+/// branched from the one that started them
+#[derive(Clone, Default)]
+pub struct Services(Arc<Mutex<Vec<Service>>>);
+
+impl Services {
+    /// Keep a started service running until [`Services::stop_all`]
+    pub fn add(&self, service: Service) {
+        if let Ok(mut services) = self.0.lock() {
+            services.push(service);
+        }
+    }
+
+    /// Stop every service, most recently started first
+    pub fn stop_all(&self, ctx: &Context) {
+        let services = self
+            .0
+            .lock()
+            .map(|mut services| std::mem::take(&mut *services))
+            .unwrap_or_default();
+        for service in services.into_iter().rev() {
+            service.stop(ctx);
+        }
+    }
+
+    /// Whether any service's process is still running or due a restart
+    pub fn running(&self) -> bool {
+        self.0
+            .lock()
+            .map(|services| {
+                services
+                    .iter()
+                    .any(|service| !service.exited.load(Ordering::SeqCst))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Wait for every service to exit for good, in start order, with each
+    /// service's name and outcome
+    pub fn wait_all(&self) -> Vec<(String, ExecutionResult<()>)> {
+        let services = self
+            .0
+            .lock()
+            .map(|mut services| std::mem::take(&mut *services))
+            .unwrap_or_default();
+        services
+            .into_iter()
+            .map(|service| (service.name.clone(), service.wait()))
+            .collect()
+    }
+}
+
+/// Keep the service's process running per its restart policy until it exits
+/// for good or a stop is requested
+fn supervise(
+    name: &str,
+    mut child: Child,
+    mut launcher: Launcher,
+    spec: &ServiceSpec,
+    stop: &AtomicBool,
+    printer: &Context,
+) -> ExecutionResult<()> {
+    let mut restarts = 0;
+    let interrupts = signals::interrupts();
+    loop {
+        let status = loop {
+            if stop.load(Ordering::SeqCst) {
+                terminate(&mut child, launcher.own_group);
+                return Ok(());
+            }
+            if let Some(signal) = signals::interrupted_since(interrupts) {
+                stop_process(&mut child, launcher.own_group, signal);
+                return Err(ExecutionError::Interrupted(signal));
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(_) => return Err(ExecutionError::CommandFailed(None)),
+            }
+        };
+
+        let restart = match spec.restart {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Always => true,
+        };
+        if !restart || spec.max_restarts.is_some_and(|max| restarts >= max) {
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(ExecutionError::CommandFailed(exit_code(&status)))
+            };
+        }
+
+        restarts += 1;
+        let status = match exit_code(&status) {
+            Some(code) => message("service.exit-code", &[("code", &code)]),
+            None => message("service.exit-signal", &[]),
+        };
+        printer.print_info(&message(
+            "service.restarting",
+            &[
+                ("service", &name),
+                ("status", &status),
+                ("count", &restarts),
+            ],
+        ));
+        let resume_at = Instant::now() + RESTART_DELAY;
+        while Instant::now() < resume_at {
+            if stop.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        child = launcher.spawn()?;
+    }
+}
+
+/// Ask the process to exit, killing it if it is still running after the
+/// grace period
+///
+/// The shell running the command may not pass signals on, so its children
+/// are signalled too: the whole group when the process has one, otherwise
+/// the shell's direct children, with the shell paused meanwhile so it
+/// cannot start new ones.
+pub(crate) fn terminate(child: &mut Child, group: bool) {
+    stop_process(child, group, SIGTERM);
+}
+
+/// Pass `signal` on to the process like [`terminate`] does SIGTERM, killing
+/// it if it is still running after the grace period
+pub(crate) fn stop_process(child: &mut Child, group: bool, signal: i32) {
+    #[cfg(unix)]
+    {
+        // The process is not reaped until it is waited for, so its id (and
+        // that of its group) cannot be reused meanwhile
+        let pid = child.id() as libc::pid_t;
+        let send = |signal: i32| unsafe {
+            if group {
+                libc::killpg(pid, signal);
+            } else {
+                libc::kill(pid, libc::SIGSTOP);
+                for child_pid in child_pids(pid) {
+                    libc::kill(child_pid, signal);
+                }
+                libc::kill(pid, signal);
+                libc::kill(pid, libc::SIGCONT);
+            }
+        };
+        send(signal);
+        let deadline = Instant::now() + STOP_GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        send(libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Ask what is left of the process group led by `child`, which has exited,
+/// to exit too, such as processes its shell started in the background
+pub(crate) fn stop_leftovers(child: &Child) {
+    // A group id is not reused while the group has members
+    #[cfg(unix)]
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, SIGTERM);
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+/// Direct children of the process `pid`
+#[cfg(target_os = "linux")]
+fn child_pids(pid: libc::pid_t) -> Vec<libc::pid_t> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::pid_t>().ok())
+        .filter(|&candidate| parent_pid(candidate) == Some(pid))
+        .collect()
+}
+
+/// Parent of the process `pid`, from the fourth field of `/proc/<pid>/stat`
+#[cfg(target_os = "linux")]
+fn parent_pid(pid: libc::pid_t) -> Option<libc::pid_t> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may itself contain spaces
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Direct children of the process `pid`
+#[cfg(target_vendor = "apple")]
+fn child_pids(pid: libc::pid_t) -> Vec<libc::pid_t> {
+    let mut pids: Vec<libc::pid_t> = vec![0; 1024];
+    let size = (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int;
+    let count = unsafe { libc::proc_listchildpids(pid, pids.as_mut_ptr().cast(), size) };
+    pids.truncate(usize::try_from(count).unwrap_or(0).min(pids.len()));
+    pids
+}
+
+/// Direct children of the process `pid`; not looked up on other systems,
+/// where only the process itself is signalled
+#[cfg(all(unix, not(target_os = "linux"), not(target_vendor = "apple")))]
+fn child_pids(_pid: libc::pid_t) -> Vec<libc::pid_t> {
+    Vec::new()
+}
+
+/// Whether something accepts TCP connections on `port` of localhost
+fn port_open(port: u16) -> bool {
+    net::port_open(&format!("localhost:{}", port), POLL_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::Verbosity;
+    use std::net::TcpListener;
+
+    fn spec(ready: Option<Readiness>, restart: RestartPolicy) -> ServiceSpec {
+        ServiceSpec {
+            ready,
+            timeout: Duration::from_secs(5),
+            restart,
+            max_restarts: None,
+        }
+    }
+
+    fn shell(script: &str) -> StdCommand {
+        let mut process = StdCommand::new("sh");
+        process.arg("-c").arg(script);
+        process
+    }
+
+    #[test]
+    fn test_log_readiness_and_stop() {
+        let ctx = Context::new().with_verbosity(Verbosity::Silent);
+        let ready = Readiness::Log(Regex::new("listening").unwrap());
+        let service = Service::start(
+            "web",
+            shell("echo booting; sleep 0.2; echo listening; sleep 30"),
+            &spec(Some(ready), RestartPolicy::Never),
+            &ctx,
+        )
+        .unwrap();
+
+        let exited = Arc::clone(&service.exited);
+        let started = Instant::now();
+        drop(service);
+        assert!(exited.load(Ordering::SeqCst));
+        assert!(started.elapsed() < STOP_GRACE);
+    }
+
+    #[test]
+    fn test_port_readiness() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(port_open(port));
+        drop(listener);
+        assert!(!port_open(port));
+    }
+
+    #[test]
+    fn test_exit_before_ready_is_an_error() {
+        let ctx = Context::new().with_verbosity(Verbosity::Silent);
+        let ready = Readiness::Command("false".to_string());
+        let result = Service::start(
+            "db",
+            shell("exit 3"),
+            &spec(Some(ready), RestartPolicy::Never),
+            &ctx,
+        );
+        match result {
+            Err(ExecutionError::ServiceNotReady { name, reason }) => {
+                assert_eq!(name, "db");
+                assert!(reason.contains("exited"), "{}", reason);
+            }
+            other => panic!("expected readiness failure, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_restart_on_failure_up_to_limit() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let count = temp_dir.path().join("count");
+        let ctx = Context::new().with_verbosity(Verbosity::Silent);
+        let mut spec = spec(None, RestartPolicy::OnFailure);
+        spec.max_restarts = Some(1);
+
+        let service = Service::start(
+            "flaky",
+            shell(&format!("echo run >> '{}'; exit 4", count.display())),
+            &spec,
+            &ctx,
+        )
+        .unwrap();
+        assert!(matches!(
+            service.wait(),
+            Err(ExecutionError::CommandFailed(Some(4)))
+        ));
+        assert_eq!(std::fs::read_to_string(&count).unwrap(), "run\nrun\n");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_stop_process_reaches_shell_children() {
+        // `; true` keeps the shell from replacing itself with `sleep`
+        let mut child = shell("sleep 30; true").spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let sleeper = loop {
+            if let Some(&sleeper) = child_pids(pid).first() {
+                break sleeper;
+            }
+            assert!(Instant::now() < deadline, "shell never started sleep");
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let started = Instant::now();
+        stop_process(&mut child, false, SIGTERM);
+        assert!(started.elapsed() < STOP_GRACE);
+        // Gone, or a zombie waiting for its new parent to reap it
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", sleeper)).unwrap_or_default();
+        let state = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().next());
+        assert!(matches!(state, None | Some("Z")), "{}", stat);
+    }
+}
@@ -0,0 +1,222 @@
+//! Recorded command sessions
+//!
+//! In record mode every command rtask runs is appended to a session file as
+//! one JSON line: the command, its directory and environment, its exit code
+//! and any captured output. In replay mode commands are not run at all; each
+//! one is answered from the next entry of a recorded session, so a config can
+//! be exercised deterministically and without side effects.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+use std::sync::{Arc, Mutex};
+
+/// One command execution as stored in a session file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    /// The interpolated command string handed to the interpreter
+    pub command: String,
+    pub dir: PathBuf,
+    /// Variables rtask exported to the process
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Exit code, or `None` if the process could not be started or was killed
+    pub exit_code: Option<i32>,
+    /// Output captured into a variable, if the command was captured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+}
+
+impl RecordedCommand {
+    /// Describe a prepared process; the exit code is filled in once it has run
+    pub fn new(command: &StdCommand, dir: &Path) -> Self {
+        RecordedCommand {
+            command: command
+                .get_args()
+                .last()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            dir: dir.to_path_buf(),
+            env: command
+                .get_envs()
+                .filter_map(|(key, value)| {
+                    Some((
+                        key.to_string_lossy().into_owned(),
+                        value?.to_string_lossy().into_owned(),
+                    ))
+                })
+                .collect(),
+            exit_code: None,
+            stdout: None,
+        }
+    }
+
+    /// Record a pipeline as one command, its stages joined with ` | `
+    pub fn pipeline(stages: &[(StdCommand, PathBuf)]) -> Self {
+        let mut entry = match stages.last() {
+            Some((command, dir)) => RecordedCommand::new(command, dir),
+            None => RecordedCommand::new(&StdCommand::new(""), Path::new("")),
+        };
+        entry.command = stages
+            .iter()
+            .map(|(command, dir)| RecordedCommand::new(command, dir).command)
+            .collect::<Vec<_>>()
+            .join(" | ");
+        entry
+    }
+
+    /// Note the outcome of running the command
+    pub fn set_result<T>(&mut self, result: &ExecutionResult<T>) {
+        self.exit_code = match result {
+            Ok(_) => Some(0),
+            Err(ExecutionError::CommandFailed(code)) => *code,
+            Err(_) => None,
+        };
+    }
+
+    /// The outcome the recorded exit code stands for
+    pub fn result(&self) -> ExecutionResult<()> {
+        match self.exit_code {
+            Some(0) => Ok(()),
+            code => Err(ExecutionError::CommandFailed(code)),
+        }
+    }
+}
+
+/// A session file being recorded or replayed, shared by all commands of a run
+#[derive(Debug, Clone)]
+pub enum Session {
+    Record(Arc<Mutex<File>>),
+    Replay(Arc<Mutex<VecDeque<RecordedCommand>>>),
+}
+
+impl Session {
+    /// Start recording to `path`, replacing any previous recording
+    pub fn record(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Session::Record(Arc::new(Mutex::new(File::create(path)?))))
+    }
+
+    /// Load a recording from `path` for replay
+    pub fn replay(path: &Path) -> io::Result<Self> {
+        let commands = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Session::Replay(Arc::new(Mutex::new(commands))))
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Session::Replay(_))
+    }
+
+    /// Append a finished command to the recording (no-op when replaying)
+    pub fn append(&self, entry: &RecordedCommand) -> ExecutionResult<()> {
+        let Session::Record(file) = self else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(entry)
+            .map_err(|e| ExecutionError::Replay(format!("Failed to encode command: {}", e)))?;
+        let mut file = file
+            .lock()
+            .map_err(|_| ExecutionError::Environment("Session file lock poisoned".to_string()))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| ExecutionError::Replay(format!("Failed to write session: {}", e)))
+    }
+
+    /// Take the recorded entry for the command about to run
+    ///
+    /// Commands must be replayed in the order they were recorded; a different
+    /// command string means the config no longer matches the recording.
+    pub fn next(&self, expected: &RecordedCommand) -> ExecutionResult<RecordedCommand> {
+        let Session::Replay(commands) = self else {
+            return Err(ExecutionError::Replay(
+                "Session is not being replayed".to_string(),
+            ));
+        };
+        let mut commands = commands
+            .lock()
+            .map_err(|_| ExecutionError::Environment("Session lock poisoned".to_string()))?;
+
+        match commands.pop_front() {
+            Some(entry) if entry.command == expected.command => Ok(entry),
+            Some(entry) => Err(ExecutionError::Replay(format!(
+                "expected `{}` but the recording has `{}`",
+                expected.command, entry.command
+            ))),
+            None => Err(ExecutionError::Replay(format!(
+                "recording has no entry for `{}`",
+                expected.command
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sh(command: &str) -> RecordedCommand {
+        let mut process = StdCommand::new("sh");
+        process.arg("-c").arg(command).env("name", "world");
+        RecordedCommand::new(&process, Path::new("/work"))
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+
+        let session = Session::record(&path).unwrap();
+        let mut ok = sh("echo ${name}");
+        ok.set_result(&Ok(()));
+        ok.stdout = Some("world".to_string());
+        let mut failed = sh("make");
+        failed.set_result::<()>(&Err(ExecutionError::CommandFailed(Some(2))));
+        session.append(&ok).unwrap();
+        session.append(&failed).unwrap();
+
+        let replay = Session::replay(&path).unwrap();
+        let entry = replay.next(&sh("echo ${name}")).unwrap();
+        assert_eq!(entry, ok);
+        assert_eq!(entry.env.get("name").map(String::as_str), Some("world"));
+        assert!(matches!(
+            replay.next(&sh("make")).unwrap().result(),
+            Err(ExecutionError::CommandFailed(Some(2)))
+        ));
+        assert!(matches!(
+            replay.next(&sh("make")),
+            Err(ExecutionError::Replay(_))
+        ));
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+        Session::record(&path)
+            .unwrap()
+            .append(&sh("echo a"))
+            .unwrap();
+
+        let err = Session::replay(&path)
+            .unwrap()
+            .next(&sh("echo b"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Replay error: expected `echo b` but the recording has `echo a`"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Interrupts
+//!
+//! Once [`install`] has run, SIGINT and SIGTERM no longer end rtask at once.
+//! Commands running when one arrives are passed the signal and waited for,
+//! the task's remaining run items are skipped, and its `finally` items still
+//! run before rtask exits with 128 + the signal number.
+//!
+//! Interrupts are counted, so a command can tell whether one arrived after
+//! it started: `finally` commands started after an interrupt run normally,
+//! and only a further interrupt stops them.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+
+pub const SIGINT: i32 = 2;
+pub const SIGTERM: i32 = 15;
+
+/// Whether interrupts are handled by rtask rather than ending it
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Interrupts received so far
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+/// The most recent interrupt's signal number (0 before the first)
+static LAST: AtomicI32 = AtomicI32::new(0);
+
+/// Handle SIGINT and SIGTERM as interrupts
+pub fn install() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                record(signal);
+            }
+        });
+        INSTALLED.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Whether [`install`] has run, so running commands must watch for interrupts
+pub fn installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst)
+}
+
+/// Record an interrupt by `signal`, also one that reached only a command
+/// holding the terminal
+pub(crate) fn record(signal: i32) {
+    LAST.store(signal, Ordering::SeqCst);
+    RECEIVED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Interrupts received so far, to pass to [`interrupted_since`] later
+pub fn interrupts() -> usize {
+    RECEIVED.load(Ordering::SeqCst)
+}
+
+/// The signal of the latest interrupt, if any arrived after `count` were
+/// received
+pub fn interrupted_since(count: usize) -> Option<i32> {
+    (interrupts() > count).then(|| LAST.load(Ordering::SeqCst))
+}
+
+/// Fail with [`ExecutionError::Interrupted`] if rtask was interrupted
+pub fn check_interrupted() -> ExecutionResult<()> {
+    match interrupted_since(0) {
+        Some(signal) => Err(ExecutionError::Interrupted(signal)),
+        None => Ok(()),
+    }
+}
+
+/// Name of a signal for messages, e.g. `SIGINT`
+pub fn signal_name(signal: i32) -> String {
+    match signal {
+        SIGINT => "SIGINT".to_string(),
+        SIGTERM => "SIGTERM".to_string(),
+        _ => format!("signal {}", signal),
+    }
+}
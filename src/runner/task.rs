@@ -4,8 +4,25 @@
 
 use crate::config;
 use crate::error::{ConfigError, ConfigResult, ExecutionError, ExecutionResult};
-use crate::runner::{evaluate_when_list, execute_command, interpolate, Context};
-use std::collections::HashMap;
+use crate::runner::{
+    cache_key, cache_state, capture_command, clear_checkpoint, describe_combination, describe_when,
+    evaluate_when_list, execute_command, execute_pipeline, interpolate, matrix_combinations,
+    output_prefix, prepare_command, read_checkpoint, record_cache_entry, record_checkpoint,
+    referenced_vars, run_in_order, run_parallel, schedule, signals, start_background, traced,
+    Branch, CacheEntry, CacheState, CancelToken, Checkpoint, Context, EnvPatterns, GroupGuard, Job,
+    Readiness, Service, ServiceSpec, TaskRegistry, Value, Verbosity, DEFAULT_READY_TIMEOUT,
+    EXIT_CODE_VAR, TASK_NAME_VAR,
+};
+use crate::ui::{message, CatalogMessage};
+use crate::utils::duration::{format_duration, parse_duration};
+use crate::utils::url::normalize_url;
+use indexmap::IndexMap;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// Runtime task representation
 ///
@@ -27,15 +44,24 @@ pub struct Task {
     /// Whether this task should run quietly
     pub quiet: bool,
 
-    /// Positional arguments
-    pub args: HashMap<String, Arg>,
+    /// Positional arguments, in definition order
+    pub args: IndexMap<String, Arg>,
 
     /// Named options
     pub options: HashMap<String, TaskOption>,
 
+    /// Conditions that must be met for the task to run
+    pub when: Vec<When>,
+
+    /// Prerequisite tasks, run once per run before the run items
+    pub deps: Vec<String>,
+
     /// Run items to execute
     pub run: Vec<Run>,
 
+    /// Variables whose combinations of values the run items run once for
+    pub matrix: IndexMap<String, Vec<String>>,
+
     /// Finally block
     pub finally: Vec<Run>,
 
@@ -45,6 +71,38 @@ pub struct Task {
     /// Target files for caching
     pub target: Vec<String>,
 
+    /// File that receives a timestamped copy of all command output
+    pub log: Option<String>,
+
+    /// Interpreter for this task's commands, replacing the context's
+    pub interpreter: Option<config::Interpreter>,
+
+    /// Host environment variables this task's commands inherit, replacing
+    /// the context's policy
+    pub inherit_env: Option<config::InheritEnv>,
+
+    /// Patterns of host variables inherited regardless of the policy
+    pub env_allow: Vec<String>,
+
+    /// Patterns of host variables never inherited
+    pub env_deny: Vec<String>,
+
+    /// Service settings, when the task runs a long-running service
+    pub service: Option<ServiceSpec>,
+
+    /// Group of tasks this task never runs at the same time as
+    pub concurrency_group: Option<config::ConcurrencyGroup>,
+
+    /// Start order among tasks ready at the same time; higher goes first
+    pub priority: i32,
+
+    /// Default timeout of this task's commands, replacing the context's
+    pub timeout: Option<Duration>,
+
+    /// Hash of the task's definition, part of its cache key; without it the
+    /// task's runs are not recorded in the cache
+    pub definition: Option<String>,
+
     /// Resolved variable values for this task execution
     pub vars: HashMap<String, String>,
 }
@@ -54,6 +112,16 @@ impl Task {
     pub fn from_config(name: String, config: config::Task) -> ConfigResult<Self> {
         // Validate task configuration
         Self::validate_config(&config)?;
+        let service = Self::service_spec(&config)?;
+        let timeout = config
+            .timeout
+            .as_deref()
+            .map(|timeout| {
+                parse_duration(timeout).map_err(|e| {
+                    ConfigError::Invalid(format!("Invalid timeout '{}': {}", timeout, e))
+                })
+            })
+            .transpose()?;
 
         Ok(Task {
             name,
@@ -71,14 +139,55 @@ impl Task {
                 .into_iter()
                 .map(|(k, v)| (k.clone(), TaskOption::from_config(k, v)))
                 .collect(),
+            when: config.when.into_iter().map(When::from_config).collect(),
+            deps: config.deps,
             run: config.run.into_iter().map(Run::from_config).collect(),
+            matrix: config.matrix,
             finally: config.finally.into_iter().map(Run::from_config).collect(),
             source: config.source,
             target: config.target,
+            log: config.log,
+            interpreter: config.interpreter,
+            inherit_env: config.inherit_env,
+            env_allow: config.env_allow,
+            env_deny: config.env_deny,
+            service,
+            concurrency_group: config.concurrency_group,
+            priority: config.priority.unwrap_or_default(),
+            timeout,
+            definition: None,
             vars: HashMap::new(),
         })
     }
 
+    /// Runtime service settings of a service task
+    fn service_spec(config: &config::Task) -> ConfigResult<Option<ServiceSpec>> {
+        if !config.service {
+            return Ok(None);
+        }
+        let ready = config.ready.clone().unwrap_or_default();
+        let readiness = match (ready.port, ready.log, ready.command) {
+            (Some(port), _, _) => Some(Readiness::Port(port)),
+            (_, Some(log), _) => Some(Readiness::Log(Regex::new(&log).map_err(|e| {
+                ConfigError::Invalid(format!("Invalid ready log pattern '{}': {}", log, e))
+            })?)),
+            (_, _, Some(command)) => Some(Readiness::Command(command)),
+            _ => None,
+        };
+        let timeout = match &ready.timeout {
+            Some(timeout) => parse_duration(timeout).map_err(|e| {
+                ConfigError::Invalid(format!("Invalid ready timeout '{}': {}", timeout, e))
+            })?,
+            None => DEFAULT_READY_TIMEOUT,
+        };
+        Ok(Some(ServiceSpec {
+            ready: readiness,
+            timeout,
+            restart: config.restart.unwrap_or_default(),
+            max_restarts: config.max_restarts,
+        }))
+    }
+
     /// Validate task configuration
     fn validate_config(config: &config::Task) -> ConfigResult<()> {
         // Check source/target consistency
@@ -90,7 +199,7 @@ impl Task {
         }
 
         // Check for duplicate names between args and options
-        for (arg_name, _) in &config.args {
+        for arg_name in config.args.keys() {
             if config.options.contains_key(arg_name) {
                 return Err(ConfigError::DuplicateNames(arg_name.clone()));
             }
@@ -109,6 +218,9 @@ impl Task {
         }
 
         // Add dependencies from when conditions
+        for when in &self.when {
+            deps.extend(when.dependencies());
+        }
         for run in self.run.iter().chain(self.finally.iter()) {
             deps.extend(run.dependencies());
         }
@@ -116,85 +228,1150 @@ impl Task {
         deps
     }
 
+    /// Check option values against their declared types, returning the task
+    /// vars with typed values normalized
+    ///
+    /// Only values given on the command line are in the vars; defaults are
+    /// filled in later by [`Task::option_default`] as run items need them.
+    pub fn resolve_vars(&self, ctx: &Context) -> ExecutionResult<HashMap<String, String>> {
+        let mut vars = self.vars.clone();
+        for (name, option) in &self.options {
+            let Some(value) = vars.get_mut(name).filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            *value = option
+                .option_type
+                .normalize(value, &ctx.working_dir)
+                .map_err(|error| ExecutionError::InvalidOption {
+                    name: name.clone(),
+                    error,
+                })?;
+        }
+
+        // Multi-value args are checked value by value, then joined again
+        for arg in self.args.values() {
+            if arg.arg_type == OptionType::String {
+                continue;
+            }
+            let mut keys: Vec<String> = (1..)
+                .map(|index| format!("{}.{}", arg.name, index))
+                .take_while(|key| arg.variadic && vars.contains_key(key))
+                .collect();
+            let joined = !keys.is_empty();
+            if !joined {
+                keys.push(arg.name.clone());
+            }
+
+            let mut normalized = Vec::new();
+            for key in keys {
+                let Some(value) = vars.get_mut(&key).filter(|v| !v.is_empty()) else {
+                    continue;
+                };
+                *value = arg
+                    .arg_type
+                    .normalize(value, &ctx.working_dir)
+                    .map_err(|error| ExecutionError::InvalidArg {
+                        name: arg.name.clone(),
+                        error,
+                    })?;
+                normalized.push(value.clone());
+            }
+            if joined {
+                vars.insert(arg.name.clone(), normalized.join(" "));
+            }
+        }
+        Ok(vars)
+    }
+
+    /// Variables the task's commands would see, without running anything
+    ///
+    /// Every option default is resolved, and `set-environment` values from
+    /// every run item are applied in order, regardless of `when` conditions.
+    /// Variables captured from command output are not known until the
+    /// commands run, so they are left out.
+    pub fn preview_vars(&self, ctx: &Context) -> ExecutionResult<HashMap<String, String>> {
+        let vars = self.resolve_vars(ctx)?;
+        let mut pending: HashSet<String> = self
+            .options
+            .keys()
+            .filter(|name| !vars.contains_key(*name))
+            .cloned()
+            .collect();
+        let mut scratch = Context::new()
+            .with_working_dir(ctx.working_dir.clone())
+            .with_vars(vars);
+        self.resolve_options(
+            self.options.keys().cloned().collect(),
+            &mut pending,
+            &mut scratch,
+        )?;
+
+        let mut vars = scratch.vars;
+        for run in self.run.iter().chain(&self.finally) {
+            for (key, value) in &run.set_environment {
+                match value {
+                    Some(val) => {
+                        let interpolated = interpolate(val, &vars).unwrap_or_else(|_| val.clone());
+                        vars.insert(key.clone(), interpolated);
+                    }
+                    None => {
+                        vars.remove(key);
+                    }
+                }
+            }
+        }
+        Ok(vars)
+    }
+
+    /// Value of an option that was not given on the command line, from its
+    /// default or environment variable, normalized for its type
+    ///
+    /// Defaults may reference other options and args as `${name}`; those must
+    /// already be in `ctx.vars`.
+    pub fn option_default(&self, name: &str, ctx: &Context) -> ExecutionResult<Option<String>> {
+        let Some(option) = self.options.get(name) else {
+            return Ok(None);
+        };
+        let value = match (&option.default, &option.environment) {
+            (Some(default), _) => {
+                interpolate(default, &ctx.vars).map_err(|e| ExecutionError::InvalidOption {
+                    name: name.to_string(),
+                    error: e.to_string(),
+                })?
+            }
+            (None, Some(env_var)) => std::env::var(env_var).unwrap_or_default(),
+            (None, None) => String::new(),
+        };
+        let value = option.rewrite.clone().unwrap_or(value);
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        option
+            .option_type
+            .normalize(&value, &ctx.working_dir)
+            .map(Some)
+            .map_err(|error| ExecutionError::InvalidOption {
+                name: name.to_string(),
+                error,
+            })
+    }
+
+    /// Resolve the defaults of pending options among `names` into the context
+    fn resolve_options(
+        &self,
+        names: Vec<String>,
+        pending: &mut HashSet<String>,
+        ctx: &mut Context,
+    ) -> ExecutionResult<()> {
+        for name in names {
+            if !pending.remove(&name) {
+                continue;
+            }
+            // Options the default refers to resolve first
+            if let Some(option) = self.options.get(&name) {
+                self.resolve_options(option.dependencies(), pending, ctx)?;
+            }
+            if let Some(value) = self.option_default(&name, ctx)? {
+                ctx.print_trace(&format!("Resolved option '{}' to '{}'", name, value));
+                self.set_var(ctx, name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Set a task variable, with a typed value for comparisons
+    fn set_var(&self, ctx: &mut Context, key: String, value: String) {
+        let typed = match (self.options.get(&key), self.args.get(&key)) {
+            (Some(option), _) => option.option_type.typed_value(&value),
+            (_, Some(arg)) if !arg.variadic => arg.arg_type.typed_value(&value),
+            _ => None,
+        };
+        ctx.set_var(key.clone(), value);
+        if let Some(typed) = typed {
+            ctx.values.insert(key, typed);
+        }
+    }
+
+    /// Merge the task's variables into the context, with typed values for
+    /// comparisons, returning the options left pending
+    ///
+    /// Options not given on the command line are resolved lazily, once a run
+    /// item that references them is about to use them.
+    fn set_vars(&self, ctx: &mut Context, vars: HashMap<String, String>) -> HashSet<String> {
+        let pending = self
+            .options
+            .keys()
+            .filter(|name| !vars.contains_key(*name))
+            .cloned()
+            .collect();
+        for (key, value) in vars {
+            self.set_var(ctx, key, value);
+        }
+        pending
+    }
+
+    /// Limit the host environment the task's commands inherit in `ctx`
+    ///
+    /// An allowlist of patterns starts from nothing, and a subtask keeps its
+    /// parent's denied variables.
+    pub fn limit_env(&self, ctx: &mut Context) {
+        if let Some(inherit_env) = self.inherit_env {
+            ctx.inherit_env = inherit_env;
+        } else if !self.env_allow.is_empty() {
+            ctx.inherit_env = config::InheritEnv::None;
+        }
+        if !self.env_allow.is_empty() {
+            ctx.env_allow = EnvPatterns::new(self.env_allow.clone());
+        }
+        if !self.env_deny.is_empty() {
+            ctx.env_deny = ctx.env_deny.extended(&self.env_deny);
+        }
+    }
+
     /// Execute the task in the given context
     pub fn execute(&self, ctx: &mut Context) -> ExecutionResult<()> {
+        let trace = ctx.trace.clone();
+        traced(
+            trace.as_ref(),
+            "task",
+            || &self.name,
+            || self.execute_task(ctx),
+        )
+    }
+
+    fn execute_task(&self, ctx: &mut Context) -> ExecutionResult<()> {
+        let vars = self.resolve_vars(ctx)?;
+        let interpreter = match &self.interpreter {
+            Some(interpreter) => ctx.interpreter_for(interpreter)?,
+            None => ctx.config_interpreter.clone(),
+        };
+
         // Check for recursion
         if ctx.is_task_in_stack(&self.name) {
-            return Err(ExecutionError::CommandFailed(Some(1)));
+            let mut chain = ctx.task_names();
+            chain.push(self.name.clone());
+            return Err(ExecutionError::RecursiveTask(chain.join(" -> ")));
+        }
+
+        let key = self
+            .definition
+            .as_ref()
+            .map(|definition| cache_key(definition, &vars));
+        let cache_entry = match (&self.definition, &key) {
+            (Some(definition), Some(key)) if !self.source.is_empty() && !self.target.is_empty() => {
+                Some(CacheEntry {
+                    definition: definition.clone(),
+                    key: key.clone(),
+                })
+            }
+            _ => None,
+        };
+
+        // Targets built by this run of the task from the current sources make
+        // it up to date; its prerequisites are skipped along with it
+        if let Some(entry) = cache_entry.as_ref().filter(|_| !ctx.force) {
+            let state = cache_state(
+                &ctx.config_dir(),
+                &self.name,
+                &entry.definition,
+                Some(&entry.key),
+                &self.source,
+                &self.target,
+            );
+            if state == CacheState::Fresh {
+                ctx.print_info(&message("task.up-to-date", &[("task", &self.name)]));
+                return Ok(());
+            }
+        }
+
+        let mut pending = self.set_vars(ctx, vars);
+
+        // A task whose conditions do not hold is skipped, along with its
+        // prerequisites and finally items
+        if !self.conditions_hold(&self.when, ctx, &mut pending)? {
+            ctx.print_task_skip(&self.name, &message("task.condition-not-met", &[]));
+            return Ok(());
         }
 
+        // Wait for other members of the task's concurrency group to finish
+        let group_guard = self.acquire_concurrency_group(ctx)?;
+
         // Push task onto stack
         ctx.push_task(self.name.clone());
 
         // Print task start
         ctx.print_task_start(&self.name);
 
-        // Merge task vars into context
-        for (key, value) in &self.vars {
-            ctx.set_var(key.clone(), value.clone());
+        // Tee all command output to the task log, if configured
+        let outer_tee = ctx.tee_log.clone();
+        if let Some(log) = &self.log {
+            if let Err(e) = self.resolve_options(referenced_vars(log), &mut pending, ctx) {
+                ctx.pop_task();
+                return Err(e);
+            }
+            let path = interpolate(log, &ctx.vars).unwrap_or_else(|_| log.clone());
+            ctx.tee_log = Some(ctx.working_dir.join(path));
+        }
+
+        // Run commands with the task's own interpreter, or the config's; a
+        // subtask does not take on its caller's
+        let outer_interpreter = std::mem::replace(&mut ctx.interpreter, interpreter);
+
+        // Commands without their own timeout get the task's
+        let outer_timeout = ctx.timeout;
+        ctx.timeout = self.timeout.or(outer_timeout);
+
+        let outer_inherit_env = ctx.inherit_env;
+        let outer_env_allow = ctx.env_allow.clone();
+        let outer_env_deny = ctx.env_deny.clone();
+        self.limit_env(ctx);
+
+        // A quiet task hides the echo of all its commands, unless running verbosely
+        let outer_quiet = ctx.quiet;
+        ctx.quiet = outer_quiet || (self.quiet && ctx.verbosity < Verbosity::Verbose);
+
+        // Subtasks in the same concurrency group run inside this task's hold
+        let outer_held_groups = ctx.held_groups.len();
+        if let Some(guard) = &group_guard {
+            ctx.held_groups.push(guard.group().to_string());
         }
 
-        // Execute with finally block handling
-        let result = self.execute_run_items(ctx);
+        // Background commands started by this task end with it
+        let outer_background = std::mem::take(&mut ctx.background);
+
+        // Hooks run around the invoked task, and around subtasks if enabled
+        let hooks = ctx
+            .hooks
+            .clone()
+            .filter(|hooks| hooks.subtasks || ctx.task_stack.len() == 1);
+
+        // The invoked task checkpoints its progress for `--resume`
+        let checkpoint_key = key.as_deref().filter(|_| {
+            ctx.task_stack.len() == 1
+                && self.service.is_none()
+                && self.matrix.is_empty()
+                && !ctx.dry_run
+                && ctx.session.is_none()
+        });
+
+        // Execute with finally block handling; a panic becomes an error so
+        // cleanup still runs
+        let mut result = self.catch_panic(|| {
+            if let Some(hooks) = &hooks {
+                self.execute_hooks(&hooks.before, ctx, &mut pending)?;
+            }
+            let progress = checkpoint_key.map(|key| Progress {
+                key,
+                vars: ctx.vars.clone(),
+            });
+            let progress = progress.as_ref();
+            let resumed = self.resume_point(ctx, progress);
+            // The prerequisites succeeded before the checkpointed run items
+            let start = if resumed > 0 {
+                ctx.print_info(&message(
+                    "task.resuming",
+                    &[("task", &self.name), ("count", &resumed.to_string())],
+                ));
+                resumed
+            } else {
+                let scheduled = self.execute_prerequisites(ctx, &mut pending)?;
+                self.record_progress(ctx, progress, scheduled);
+                scheduled
+            };
+            match &self.service {
+                Some(spec) => self.execute_service(spec, ctx, &mut pending),
+                None if !self.matrix.is_empty() => {
+                    Self::execute_matrix(&self.matrix, &self.name, ctx, |ctx| {
+                        self.execute_run_items(start, ctx, &mut pending, None)
+                    })
+                }
+                None => self.execute_run_items(start, ctx, &mut pending, progress),
+            }
+        });
 
-        // Always run finally blocks
-        if !self.finally.is_empty() {
+        // Always run finally blocks, unless skipped for debugging
+        if !self.finally.is_empty() && ctx.skip_finally {
+            ctx.print_warning(&message("task.finally-skipped", &[("task", &self.name)]));
+        } else if !self.finally.is_empty() {
             ctx.print_debug("Running finally block...");
-            if let Err(e) = self.execute_finally_items(ctx) {
+            let finally = self.catch_panic(|| self.execute_finally_items(ctx, &mut pending));
+            if let Err(e) = finally {
                 // If run succeeded but finally failed, return finally error
                 // If run failed, keep the run error
                 if result.is_ok() {
-                    ctx.pop_task();
-                    return Err(e);
+                    result = Err(e);
                 }
             }
         }
 
-        // Pop task from stack
-        ctx.pop_task();
+        // After-each items see how the task ended as `${exit_code}`
+        if let Some(hooks) = hooks.filter(|hooks| !hooks.after.is_empty()) {
+            let code = result.as_ref().err().map_or(0, ExecutionError::exit_code);
+            ctx.set_value(EXIT_CODE_VAR.to_string(), Value::Integer(code.into()));
+            let after = self.catch_panic(|| self.execute_hooks(&hooks.after, ctx, &mut pending));
+            if let Err(e) = after {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        ctx.background.stop_all(ctx);
+        ctx.background = outer_background;
+        ctx.quiet = outer_quiet;
+        ctx.timeout = outer_timeout;
+        ctx.held_groups.truncate(outer_held_groups);
+        ctx.inherit_env = outer_inherit_env;
+        ctx.env_allow = outer_env_allow;
+        ctx.env_deny = outer_env_deny;
+        ctx.tee_log = outer_tee;
+        ctx.interpreter = outer_interpreter;
+
+        if let Some(Err(e)) = checkpoint_key
+            .filter(|_| result.is_ok())
+            .map(|_| clear_checkpoint(&ctx.config_dir(), &self.name))
+        {
+            ctx.print_warning(&message(
+                "task.checkpoint-not-recorded",
+                &[("task", &self.name), ("error", &e)],
+            ));
+        }
 
         if result.is_ok() {
+            // The targets now come from this definition of the task
+            if let Some(entry) = cache_entry.filter(|_| !ctx.dry_run) {
+                if let Err(e) = record_cache_entry(&ctx.config_dir(), &self.name, entry) {
+                    ctx.print_warning(&message(
+                        "task.cache-not-recorded",
+                        &[("task", &self.name), ("error", &e)],
+                    ));
+                }
+            }
             ctx.print_task_complete(&self.name);
         }
 
+        // Pop task from stack
+        ctx.pop_task();
+
         result
     }
 
+    /// Hold the task's concurrency group for as long as the returned guard
+    /// lives
+    ///
+    /// A subtask of a task already holding the group runs inside it, so it
+    /// gets no guard of its own.
+    fn acquire_concurrency_group(&self, ctx: &Context) -> ExecutionResult<Option<GroupGuard>> {
+        let Some(group) = &self.concurrency_group else {
+            return Ok(None);
+        };
+        let name = group.name();
+        if ctx.held_groups.iter().any(|held| held == name) {
+            return Ok(None);
+        }
+        let config_dir = group.across_processes().then(|| ctx.config_dir());
+        ctx.concurrency
+            .acquire(name, config_dir.as_deref(), || {
+                ctx.print_info(&message(
+                    "task.waiting-for-group",
+                    &[("task", &self.name), ("group", &name)],
+                ));
+            })
+            .map(Some)
+            .map_err(|e| {
+                ExecutionError::Environment(format!(
+                    "Cannot lock concurrency group '{}': {}",
+                    name, e
+                ))
+            })
+    }
+
+    /// Run `f`, reporting a panic inside it as an error for this task
+    fn catch_panic(&self, f: impl FnOnce() -> ExecutionResult<()>) -> ExecutionResult<()> {
+        panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(ExecutionError::Panicked {
+                task: self.name.clone(),
+                message,
+            })
+        })
+    }
+
     /// Execute the main run items
-    fn execute_run_items(&self, ctx: &mut Context) -> ExecutionResult<()> {
-        for run in &self.run {
-            self.execute_run_item(run, ctx)?;
+    ///
+    /// Run items before `start` are skipped. With `progress`, it is
+    /// recorded after each run item.
+    fn execute_run_items(
+        &self,
+        start: usize,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+        progress: Option<&Progress>,
+    ) -> ExecutionResult<()> {
+        // After an interrupt, only finally items still run
+        for (index, run) in self.run.iter().enumerate().skip(start) {
+            ctx.check_interrupted()?;
+            self.execute_run_item(run, ctx, pending)?;
+            self.record_progress(ctx, progress, index + 1);
         }
         Ok(())
     }
 
+    /// Number of run items to skip: those completed in the last run with
+    /// the same key when resuming, otherwise none
+    ///
+    /// Resuming restores the variables the skipped run items set. A run that
+    /// does not resume starts a new checkpoint.
+    fn resume_point(&self, ctx: &mut Context, progress: Option<&Progress>) -> usize {
+        let Some(progress) = progress else {
+            return 0;
+        };
+        let checkpoint = read_checkpoint(&ctx.config_dir(), &self.name, progress.key)
+            .filter(|checkpoint| ctx.resume && checkpoint.completed > 0);
+        if let Some(checkpoint) = checkpoint {
+            for (name, value) in checkpoint.vars {
+                match value {
+                    Some(value) => ctx.set_var(name, value),
+                    None => {
+                        ctx.vars.remove(&name);
+                        ctx.values.remove(&name);
+                    }
+                }
+            }
+            return checkpoint.completed.min(self.resumable_items());
+        }
+        if let Err(e) = clear_checkpoint(&ctx.config_dir(), &self.name) {
+            ctx.print_warning(&message(
+                "task.checkpoint-not-recorded",
+                &[("task", &self.name), ("error", &e)],
+            ));
+        }
+        0
+    }
+
+    /// Record that the first `completed` run items succeeded, with the
+    /// variables they set
+    fn record_progress(&self, ctx: &Context, progress: Option<&Progress>, completed: usize) {
+        let completed = completed.min(self.resumable_items());
+        let Some(progress) = progress.filter(|_| completed > 0) else {
+            return;
+        };
+        let mut vars: BTreeMap<String, Option<String>> = ctx
+            .vars
+            .iter()
+            .filter(|(name, value)| progress.vars.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), Some(value.clone())))
+            .collect();
+        for name in progress.vars.keys() {
+            if !ctx.vars.contains_key(name) {
+                vars.insert(name.clone(), None);
+            }
+        }
+        let checkpoint = Checkpoint {
+            key: progress.key.to_string(),
+            completed,
+            vars,
+        };
+        if let Err(e) = record_checkpoint(&ctx.config_dir(), &self.name, checkpoint) {
+            ctx.print_warning(&message(
+                "task.checkpoint-not-recorded",
+                &[("task", &self.name), ("error", &e)],
+            ));
+        }
+    }
+
+    /// Number of leading run items a resumed run may skip: those before the
+    /// first that starts background commands, which a skipped item could
+    /// not leave running
+    fn resumable_items(&self) -> usize {
+        self.run
+            .iter()
+            .position(Run::starts_background)
+            .unwrap_or(self.run.len())
+    }
+
+    /// Number of leading run items that only start other tasks of this
+    /// config, without conditions or options
+    ///
+    /// With a job limit above one these are prerequisites like `deps`: they
+    /// and their own prerequisites are scheduled together, each running
+    /// once, before the task's other run items.
+    fn prerequisite_items(&self) -> usize {
+        if !self.matrix.is_empty() {
+            return 0;
+        }
+        self.run
+            .iter()
+            .take_while(|run| run.is_prerequisite())
+            .count()
+    }
+
+    /// Run the task's `deps`, each at most once per run, returning how many
+    /// leading run items were run along with them
+    ///
+    /// With a job limit above one the deps, the leading `task:` items and
+    /// their own prerequisites run in dependency order, up to `ctx.jobs` at
+    /// a time; otherwise the deps run one after another.
+    fn execute_prerequisites(
+        &self,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<usize> {
+        let failed = Mutex::new(Vec::new());
+        // Recorded sessions must see the commands in definition order
+        if ctx.jobs <= 1 || ctx.session.is_some() {
+            for dep in &self.deps {
+                if ctx.is_task_in_stack(dep) {
+                    let mut chain = ctx.task_names();
+                    chain.push(dep.clone());
+                    return Err(ExecutionError::RecursiveTask(chain.join(" -> ")));
+                }
+                let deps_run = ctx.deps_run.clone();
+                let slot = ctx.held_job_slot().cloned();
+                let result = deps_run.run(dep, slot.as_ref(), || {
+                    self.execute_subtask(&SubTask::named(dep), ctx, pending)
+                });
+                Self::keep_going_past(ctx, dep, result, &failed)?;
+            }
+            Self::tasks_failed(failed, self.deps.len())?;
+            return Ok(0);
+        }
+
+        let scheduled = self.prerequisite_items();
+        if self.deps.is_empty() && scheduled == 0 {
+            return Ok(0);
+        }
+        let mut found = Vec::new();
+        let mut stack = ctx.task_names();
+        self.collect_prerequisites(ctx, pending, &mut stack, &mut found)?;
+
+        // Several jobs may run at once, so their output is labelled
+        let width = found
+            .iter()
+            .map(|prerequisite| prerequisite.name.chars().count())
+            .max()
+            .unwrap_or_default();
+        let labelled = found.len() > 1;
+        let total = found.len();
+        let failed_jobs = &failed;
+        let mut jobs = Vec::new();
+        for (index, prerequisite) in found.into_iter().enumerate() {
+            let Prerequisite {
+                name,
+                mut task,
+                ctx: mut sub_ctx,
+                prerequisites,
+            } = prerequisite;
+            if labelled {
+                sub_ctx.output_prefix = Some(Self::branch_prefix(ctx, &name, index, width));
+            }
+            // Scheduled here already
+            task.deps.clear();
+            task.run.drain(..task.prerequisite_items());
+            let priority = task.priority;
+            jobs.push(Job {
+                prerequisites,
+                priority,
+                work: Box::new(move |token: &CancelToken| {
+                    sub_ctx.cancel = Some(token.clone());
+                    sub_ctx.holds_job_slot = true;
+                    let deps_run = sub_ctx.deps_run.clone();
+                    let slots = sub_ctx.job_slots.clone();
+                    let result = deps_run.run(&name, Some(&slots), || task.execute(&mut sub_ctx));
+                    // The job still fails, so the jobs depending on it do not start
+                    if let Err(e) = &result {
+                        if Self::keeps_going_past(&sub_ctx, e) {
+                            Self::note_failure(&sub_ctx, &name, e, failed_jobs);
+                        }
+                    }
+                    result
+                }),
+            });
+        }
+        // A job scheduling its own prerequisites lends them its slot
+        let slots = ctx.job_slots.clone();
+        let policy = ctx.failure_policy(None);
+        let result = match ctx.held_job_slot() {
+            Some(held) => held.lend(|| schedule(jobs, &slots, policy)),
+            None => schedule(jobs, &slots, policy),
+        };
+        if let Err(e) = result {
+            if e.interrupted_by().is_none() {
+                Self::tasks_failed(failed, total)?;
+            }
+            return Err(e);
+        }
+        Ok(scheduled)
+    }
+
+    /// Whether `--keep-going` lets the run go on past `error`, the failure
+    /// of one of several independent tasks; an interrupt always stops it
+    fn keeps_going_past(ctx: &Context, error: &ExecutionError) -> bool {
+        ctx.keep_going && error.interrupted_by().is_none() && ctx.check_interrupted().is_ok()
+    }
+
+    /// Report the failure of task `name` that the run goes on past
+    fn note_failure(
+        ctx: &Context,
+        name: &str,
+        error: &ExecutionError,
+        failed: &Mutex<Vec<String>>,
+    ) {
+        ctx.print_error(&message(
+            "task.failed",
+            &[("task", &name), ("error", &error.localized())],
+        ));
+        failed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(name.to_string());
+    }
+
+    /// Pass on the result of task `name`, or, if `--keep-going` lets the run
+    /// go on past its failure, report it and note it in `failed`
+    fn keep_going_past(
+        ctx: &Context,
+        name: &str,
+        result: ExecutionResult<()>,
+        failed: &Mutex<Vec<String>>,
+    ) -> ExecutionResult<()> {
+        match result {
+            Err(e) if Self::keeps_going_past(ctx, &e) => {
+                Self::note_failure(ctx, name, &e, failed);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Run `work` once per combination of `matrix`, with the values of the
+    /// combination set as variables
+    ///
+    /// The matrix variables get their previous values back afterwards.
+    /// `--keep-going` goes on to the next combination after one fails.
+    fn execute_matrix(
+        matrix: &IndexMap<String, Vec<String>>,
+        label: &str,
+        ctx: &mut Context,
+        mut work: impl FnMut(&mut Context) -> ExecutionResult<()>,
+    ) -> ExecutionResult<()> {
+        let saved: Vec<_> = matrix
+            .keys()
+            .map(|name| (name.clone(), ctx.vars.remove(name), ctx.values.remove(name)))
+            .collect();
+        let combinations = matrix_combinations(matrix);
+        let mut failed = Vec::new();
+        let mut result = Ok(());
+        for combination in &combinations {
+            let described = describe_combination(combination);
+            ctx.print_info(&message(
+                "task.matrix-combination",
+                &[("item", &label), ("combination", &described)],
+            ));
+            for (name, value) in combination {
+                ctx.set_var(name.clone(), value.clone());
+            }
+            let name = format!("{} [{}]", label, described);
+            let trace = ctx.trace.clone();
+            let run = ctx
+                .check_interrupted()
+                .and_then(|()| traced(trace.as_ref(), "matrix", || &name, || work(ctx)));
+            result = match run {
+                Err(e) if Self::keeps_going_past(ctx, &e) => {
+                    ctx.print_error(&message(
+                        "task.failed",
+                        &[("task", &name), ("error", &e.localized())],
+                    ));
+                    failed.push(described);
+                    Ok(())
+                }
+                result => result,
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        for (name, var, value) in saved {
+            ctx.vars.remove(&name);
+            ctx.values.remove(&name);
+            if let Some(var) = var {
+                ctx.vars.insert(name.clone(), var);
+            }
+            if let Some(value) = value {
+                ctx.values.insert(name, value);
+            }
+        }
+        result?;
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecutionError::CombinationsFailed {
+                item: label.to_string(),
+                failed,
+                total: combinations.len(),
+            })
+        }
+    }
+
+    /// Summary of the tasks out of `total` the run went on past
+    fn tasks_failed(failed: Mutex<Vec<String>>, total: usize) -> ExecutionResult<()> {
+        let failed = failed.into_inner().unwrap_or_else(|e| e.into_inner());
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecutionError::TasksFailed { failed, total })
+        }
+    }
+
+    /// Add the task's deps and leading `task:` items to `found` (each once,
+    /// after its own prerequisites), returning their indices
+    ///
+    /// Each prerequisite gets its option values from the task declaring it,
+    /// as when the deps run one after another. The prerequisites of one
+    /// whose conditions do not hold are left out, as it is skipped along
+    /// with them.
+    fn collect_prerequisites(
+        &self,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+        stack: &mut Vec<String>,
+        found: &mut Vec<Prerequisite>,
+    ) -> ExecutionResult<Vec<usize>> {
+        let mut indices = Vec::new();
+        let deps: Vec<SubTask> = self.deps.iter().map(|dep| SubTask::named(dep)).collect();
+        let subtasks = self.run[..self.prerequisite_items()]
+            .iter()
+            .flat_map(|run| &run.subtasks);
+        for subtask in deps.iter().chain(subtasks) {
+            let dep = &subtask.name;
+            if stack.contains(dep) {
+                let mut chain = stack.clone();
+                chain.push(dep.clone());
+                return Err(ExecutionError::RecursiveTask(chain.join(" -> ")));
+            }
+            let index = match found.iter().position(|found| &found.name == dep) {
+                Some(index) => index,
+                None => {
+                    let (dependency, sub_ctx) = self.prepare_subtask(subtask, ctx, pending)?;
+                    // Its own prerequisites see its variables, as its run items would
+                    let mut own_ctx = sub_ctx.branch();
+                    let vars = dependency.resolve_vars(&own_ctx)?;
+                    let mut own_pending = dependency.set_vars(&mut own_ctx, vars);
+                    let mut own = Vec::new();
+                    if dependency.holds(&dependency.when, &mut own_ctx, &mut own_pending)? {
+                        stack.push(dep.clone());
+                        own = dependency.collect_prerequisites(
+                            &mut own_ctx,
+                            &mut own_pending,
+                            stack,
+                            found,
+                        )?;
+                        stack.pop();
+                    }
+                    found.push(Prerequisite {
+                        name: dep.clone(),
+                        task: dependency,
+                        ctx: sub_ctx,
+                        prerequisites: own,
+                    });
+                    found.len() - 1
+                }
+            };
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+        Ok(indices)
+    }
+
+    /// Start the task's command as a service
+    ///
+    /// A service started by a subtask (or by `rtask up`) keeps running in the
+    /// background until the run ends; a service task run directly lasts as
+    /// long as its process.
+    fn execute_service(
+        &self,
+        spec: &ServiceSpec,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        let Some(run) = self.run.first() else {
+            return Ok(());
+        };
+        if !self.conditions_hold(&run.when, ctx, pending)? {
+            return Ok(());
+        }
+        self.resolve_options(run.dependencies(), pending, ctx)?;
+        let Some(cmd) = run.commands.first() else {
+            return Ok(());
+        };
+
+        let was_quiet = ctx.quiet;
+        ctx.quiet = was_quiet || run.quiet;
+        let prepared = prepare_command(cmd, ctx);
+        ctx.quiet = was_quiet;
+        let (process, _) = prepared?;
+        if ctx.dry_run {
+            return Ok(());
+        }
+
+        let service = Service::start(&self.name, process, spec, ctx)?;
+        if ctx.detach_services || ctx.task_stack.len() > 1 {
+            ctx.services.add(service);
+            Ok(())
+        } else {
+            service.wait()
+        }
+    }
+
     /// Execute finally items
-    fn execute_finally_items(&self, ctx: &mut Context) -> ExecutionResult<()> {
-        for run in &self.finally {
-            self.execute_run_item(run, ctx)?;
+    fn execute_finally_items(
+        &self,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        // Subtasks run from here are not stopped by the interrupt that may
+        // have ended the run items, only by a further one
+        let outer_handled = ctx.handled_interrupts;
+        ctx.handled_interrupts = signals::interrupts();
+        let result = self
+            .finally
+            .iter()
+            .try_for_each(|run| self.execute_run_item(run, ctx, pending));
+        ctx.handled_interrupts = outer_handled;
+        result
+    }
+
+    /// Execute `before-each` or `after-each` items around this task
+    fn execute_hooks(
+        &self,
+        items: &[Run],
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        // `${task_name}` names the task for the hook items only; a variable
+        // or option of that name is back for the items after them
+        let saved = (
+            ctx.vars.remove(TASK_NAME_VAR),
+            ctx.values.remove(TASK_NAME_VAR),
+            pending.remove(TASK_NAME_VAR),
+        );
+        ctx.set_var(TASK_NAME_VAR.to_string(), self.name.clone());
+        let result = items
+            .iter()
+            .try_for_each(|run| self.execute_run_item(run, ctx, pending));
+        ctx.vars.remove(TASK_NAME_VAR);
+        if let Some(var) = saved.0 {
+            ctx.vars.insert(TASK_NAME_VAR.to_string(), var);
         }
-        Ok(())
+        if let Some(value) = saved.1 {
+            ctx.values.insert(TASK_NAME_VAR.to_string(), value);
+        }
+        if saved.2 {
+            pending.insert(TASK_NAME_VAR.to_string());
+        }
+        result
     }
 
-    /// Execute a single run item
-    fn execute_run_item(&self, run: &Run, ctx: &mut Context) -> ExecutionResult<()> {
-        // Check when conditions
-        if !run.when.is_empty() {
-            let should_run = evaluate_when_list(&run.when, ctx)?;
-            if !should_run {
-                // Skip this run item
-                return Ok(());
+    /// Execute the branches of a parallel group, each on a copy of the context
+    ///
+    /// Variables set inside a branch stay in that branch, but the process
+    /// environment is shared, so `set-environment` in a branch is visible to
+    /// its siblings.
+    fn execute_parallel(
+        &self,
+        run: &Run,
+        ctx: &Context,
+        pending: &HashSet<String>,
+    ) -> ExecutionResult<()> {
+        let names: Vec<String> = run.parallel.iter().map(Run::label).collect();
+        let branches: Vec<Branch> = run
+            .parallel
+            .iter()
+            .zip(Self::branch_contexts(ctx, &names))
+            .map(|(item, mut branch_ctx)| -> Branch {
+                let mut branch_pending = pending.clone();
+                Box::new(move |token: &CancelToken| {
+                    branch_ctx.cancel = Some(token.clone());
+                    self.execute_run_item(item, &mut branch_ctx, &mut branch_pending)
+                })
+            })
+            .collect();
+        Self::join_branches(branches, run.fail_fast, ctx)
+    }
+
+    /// Execute the commands, pipeline and subtasks of a `parallel: true`
+    /// item concurrently, each on a copy of the context
+    fn execute_concurrently(
+        &self,
+        run: &Run,
+        ctx: &Context,
+        pending: &HashSet<String>,
+    ) -> ExecutionResult<()> {
+        let mut names: Vec<String> = run
+            .commands
+            .iter()
+            .map(|cmd| cmd.print().to_string())
+            .collect();
+        if !run.pipe.is_empty() {
+            names.push(Run::pipe_label(&run.pipe));
+        }
+        names.extend(run.subtasks.iter().map(|subtask| subtask.name.clone()));
+        let mut contexts = Self::branch_contexts(ctx, &names).into_iter().peekable();
+
+        let mut branches: Vec<Branch> = Vec::new();
+        for (cmd, mut branch_ctx) in run.commands.iter().zip(contexts.by_ref()) {
+            branches.push(Box::new(move |token: &CancelToken| {
+                branch_ctx.cancel = Some(token.clone());
+                Self::execute_one_command(cmd, &mut branch_ctx)
+            }));
+        }
+        if let Some(mut branch_ctx) = contexts.next_if(|_| !run.pipe.is_empty()) {
+            branches.push(Box::new(move |token: &CancelToken| {
+                branch_ctx.cancel = Some(token.clone());
+                Self::execute_pipe(&run.pipe, &mut branch_ctx)
+            }));
+        }
+        for (subtask, mut branch_ctx) in run.subtasks.iter().zip(contexts) {
+            let mut branch_pending = pending.clone();
+            branches.push(Box::new(move |token: &CancelToken| {
+                branch_ctx.cancel = Some(token.clone());
+                self.execute_subtask(subtask, &mut branch_ctx, &mut branch_pending)
+            }));
+        }
+        Self::join_branches(branches, run.fail_fast, ctx)
+    }
+
+    /// Contexts for concurrent branches named `names`
+    ///
+    /// When there are several, each branch prefixes its output lines with
+    /// its name (after the prefix of the branch it runs in, if any), so
+    /// interleaved lines can be told apart. Names are interpolated.
+    fn branch_contexts(ctx: &Context, names: &[String]) -> Vec<Context> {
+        let names: Vec<String> = names
+            .iter()
+            .map(|name| interpolate(name, &ctx.vars).unwrap_or_else(|_| name.clone()))
+            .collect();
+        let width = names
+            .iter()
+            .map(|name| name.chars().count())
+            .max()
+            .unwrap_or_default();
+        // Recorded sessions run branches one at a time
+        let labelled = names.len() > 1 && ctx.session.is_none();
+        names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let mut branch = ctx.branch();
+                if labelled {
+                    branch.output_prefix = Some(Self::branch_prefix(ctx, name, index, width));
+                }
+                branch
+            })
+            .collect()
+    }
+
+    /// Output prefix of the `index`th concurrent branch, `name`, of `ctx`
+    fn branch_prefix(ctx: &Context, name: &str, index: usize, width: usize) -> String {
+        let outer = ctx.output_prefix.as_deref().unwrap_or_default();
+        format!("{}{}", outer, output_prefix(name, index, width))
+    }
+
+    /// Run branches concurrently, or in order when a session is recorded
+    fn join_branches(
+        branches: Vec<Branch>,
+        fail_fast: Option<bool>,
+        ctx: &Context,
+    ) -> ExecutionResult<()> {
+        let policy = ctx.failure_policy(fail_fast);
+        // Recorded sessions must see the commands in definition order
+        if ctx.session.is_some() {
+            run_in_order(branches, policy)
+        } else {
+            run_parallel(branches, policy)
+        }
+    }
+
+    /// Execute a single run item, continuing past its failure if it allows one
+    fn execute_run_item(
+        &self,
+        run: &Run,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        let trace = ctx.trace.clone();
+        let result = traced(
+            trace.as_ref(),
+            "run",
+            || run.label(),
+            || {
+                if run.matrix.is_empty() {
+                    return self.execute_run_item_body(run, ctx, pending);
+                }
+                Self::execute_matrix(&run.matrix, &run.label(), ctx, |ctx| {
+                    self.execute_run_item_body(run, ctx, pending)
+                })
+            },
+        );
+        match result {
+            Err(error) if run.allow_failure && error.interrupted_by().is_none() => {
+                let failed = ctx
+                    .failed_command
+                    .take()
+                    .unwrap_or_else(|| self.name.clone());
+                ctx.allow_failure(failed, &error);
+                Ok(())
             }
+            result => result,
         }
+    }
 
-        // Execute commands
-        for cmd in &run.commands {
-            execute_command(cmd, ctx)?;
+    /// Check a run item's conditions and run its commands, subtasks and
+    /// parallel branches
+    fn execute_run_item_body(
+        &self,
+        run: &Run,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        if !self.conditions_hold(&run.when, ctx, pending)? {
+            // Skip this run item
+            return Ok(());
+        }
+        self.resolve_options(run.dependencies(), pending, ctx)?;
+
+        // Execute commands, honoring the item-level quiet flag
+        let was_quiet = ctx.quiet;
+        ctx.quiet = was_quiet || run.quiet;
+        let result = if run.concurrent {
+            self.execute_concurrently(run, ctx, pending)
+        } else {
+            run.commands
+                .iter()
+                .try_for_each(|cmd| Self::execute_one_command(cmd, ctx))
+                .and_then(|()| Self::execute_pipe(&run.pipe, ctx))
+        };
+        ctx.quiet = was_quiet;
+        result?;
+
+        if !run.parallel.is_empty() {
+            self.execute_parallel(run, ctx, pending)?;
         }
 
         // Execute subtasks
-        for subtask in &run.subtasks {
-            self.execute_subtask(subtask, ctx)?;
+        if !run.concurrent {
+            let failed = Mutex::new(Vec::new());
+            for subtask in &run.subtasks {
+                let result = self.execute_subtask(subtask, ctx, pending);
+                Self::keep_going_past(ctx, &subtask.name, result, &failed)?;
+            }
+            Self::tasks_failed(failed, run.subtasks.len())?;
         }
 
         // Set environment variables
@@ -218,11 +1395,231 @@ impl Task {
         Ok(())
     }
 
-    /// Execute a subtask (placeholder - will be implemented with full task registry)
-    fn execute_subtask(&self, _subtask: &SubTask, _ctx: &mut Context) -> ExecutionResult<()> {
-        // This will be implemented when we have a task registry in the CLI
-        // For now, just skip subtasks
-        Ok(())
+    /// Whether the conditions hold, without reporting anything
+    fn holds(
+        &self,
+        when: &[When],
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<bool> {
+        let names = when.iter().flat_map(When::dependencies).collect();
+        self.resolve_options(names, pending, ctx)?;
+        evaluate_when_list(when, ctx)
+    }
+
+    /// Check the when conditions of the task or a run item, resolving only
+    /// the options they reference
+    fn conditions_hold(
+        &self,
+        when: &[When],
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<bool> {
+        if when.is_empty() {
+            return Ok(true);
+        }
+        let decision = self.holds(when, ctx, pending)?;
+        if ctx.dry_run {
+            let conditions: Vec<String> =
+                when.iter().map(|when| describe_when(when, ctx)).collect();
+            let unchecked = when
+                .iter()
+                .any(|when| matches!(when.condition, WhenCondition::Command(_)));
+            let id = match (decision, unchecked) {
+                (false, _) => "dry-run.when-skip",
+                (true, true) => "dry-run.when-unchecked",
+                (true, false) => "dry-run.when-run",
+            };
+            ctx.print_info(&message(id, &[("conditions", &conditions.join(", "))]));
+        }
+        Ok(decision)
+    }
+
+    /// Execute a single command, storing its output if it has an output variable
+    fn execute_one_command(cmd: &Command, ctx: &mut Context) -> ExecutionResult<()> {
+        if cmd.is_background() {
+            return start_background(cmd, ctx);
+        }
+        let exec = interpolate(cmd.exec(), &ctx.vars).unwrap_or_else(|_| cmd.exec().to_string());
+        let result = match cmd.output_var() {
+            Some(var) => capture_command(cmd, ctx).map(|output| {
+                ctx.set_var(var.to_string(), output);
+            }),
+            None => execute_command(cmd, ctx),
+        };
+        ctx.set_command_result(exec.clone(), &result);
+        let Err(error) = result else {
+            return Ok(());
+        };
+
+        if cmd.allows_failure() && error.interrupted_by().is_none() {
+            ctx.allow_failure(exec, &error);
+            return Ok(());
+        }
+
+        // Remember the innermost failing command for error reports
+        if ctx.failed_command.is_none() {
+            ctx.failed_command = Some(exec);
+        }
+
+        Err(error)
+    }
+
+    /// Execute a run item's pipeline, if it has one
+    fn execute_pipe(stages: &[Command], ctx: &mut Context) -> ExecutionResult<()> {
+        if stages.is_empty() {
+            return Ok(());
+        }
+        let line: Vec<String> = stages
+            .iter()
+            .map(|cmd| {
+                interpolate(cmd.exec(), &ctx.vars).unwrap_or_else(|_| cmd.exec().to_string())
+            })
+            .collect();
+        let line = line.join(" | ");
+        let result = execute_pipeline(stages, ctx);
+        ctx.set_command_result(line.clone(), &result);
+        if result.is_err() && ctx.failed_command.is_none() {
+            ctx.failed_command = Some(line);
+        }
+        result
+    }
+
+    /// Execute a task referenced by a run item
+    ///
+    /// The subtask runs with its own variables: the options the reference
+    /// gives and those it shares with this task. Variables it sets stay in
+    /// the subtask. A task from another config file runs from that file's
+    /// directory, and its own subtasks are looked up in that file.
+    fn execute_subtask(
+        &self,
+        subtask: &SubTask,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<()> {
+        let (task, mut sub_ctx) = self.prepare_subtask(subtask, ctx, pending)?;
+        let result = task.execute(&mut sub_ctx);
+        if ctx.failed_command.is_none() {
+            ctx.failed_command = sub_ctx.failed_command;
+        }
+        result
+    }
+
+    /// Look up a subtask and give it its option values, returning it with
+    /// the context it runs in
+    fn prepare_subtask(
+        &self,
+        subtask: &SubTask,
+        ctx: &mut Context,
+        pending: &mut HashSet<String>,
+    ) -> ExecutionResult<(Task, Context)> {
+        let subtask_error = |error: String| ExecutionError::Subtask {
+            task: subtask.name.clone(),
+            error,
+        };
+        let mut sub_ctx = ctx.branch();
+        let external = subtask
+            .load_external(&ctx.config_dir())
+            .map_err(|e| subtask_error(e.to_string()))?;
+        let mut task = match external {
+            Some(external) => {
+                sub_ctx.working_dir = external.working_dir;
+                sub_ctx.config_path = Some(external.config_path);
+                sub_ctx.registry = Arc::new(external.registry);
+                external.task
+            }
+            None => ctx
+                .registry
+                .get(&subtask.name)
+                .cloned()
+                .ok_or_else(|| subtask_error("task is not defined".to_string()))?,
+        };
+
+        // Shared options the subtask inherits resolve their defaults here first
+        let shared = task
+            .options
+            .keys()
+            .filter(|name| self.options.contains_key(*name));
+        self.resolve_options(shared.cloned().collect(), pending, ctx)?;
+        task.vars = subtask.option_values(&task, &ctx.vars)?;
+        // Flags and args left out get the values the command line would
+        for option in task.options.values() {
+            let unset = match option.option_type {
+                OptionType::Bool => "false",
+                OptionType::Count => "0",
+                _ => continue,
+            };
+            task.vars
+                .entry(option.name.clone())
+                .or_insert_with(|| unset.to_string());
+        }
+        for arg in task.args.values() {
+            if let Some(default) = &arg.default {
+                task.vars
+                    .entry(arg.name.clone())
+                    .or_insert_with(|| default.clone());
+            }
+        }
+
+        sub_ctx.vars = HashMap::new();
+        sub_ctx.values = HashMap::new();
+        Ok((task, sub_ctx))
+    }
+}
+
+/// A prerequisite task scheduled by `execute_prerequisites`, with the
+/// context it runs in
+struct Prerequisite {
+    name: String,
+    task: Task,
+    ctx: Context,
+    /// Indices of the prerequisites it waits for
+    prerequisites: Vec<usize>,
+}
+
+/// Where the invoked task records its progress for `--resume`
+struct Progress<'a> {
+    /// Cache key of the run
+    key: &'a str,
+    /// Variables before the first run item, to tell which ones the run
+    /// items set
+    vars: HashMap<String, String>,
+}
+
+/// Run items run around tasks: `before-each` ahead of a task's
+/// prerequisites, `after-each` after its finally items
+#[derive(Debug, Clone, Default)]
+pub struct TaskHooks {
+    pub before: Vec<Run>,
+    pub after: Vec<Run>,
+
+    /// Also run around subtasks and deps, not only the invoked task
+    pub subtasks: bool,
+}
+
+impl TaskHooks {
+    /// Create from the top level of a config
+    pub fn from_config(config: &config::Config) -> Self {
+        TaskHooks {
+            before: config
+                .before_each
+                .iter()
+                .cloned()
+                .map(Run::from_config)
+                .collect(),
+            after: config
+                .after_each
+                .iter()
+                .cloned()
+                .map(Run::from_config)
+                .collect(),
+            subtasks: config.each_includes_subtasks.unwrap_or(false),
+        }
+    }
+
+    /// Whether there is nothing to run
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
     }
 }
 
@@ -235,11 +1632,32 @@ pub struct Run {
     /// Commands to execute
     pub commands: Vec<Command>,
 
+    /// Commands connected stdout to stdin
+    pub pipe: Vec<Command>,
+
     /// Subtasks to execute
     pub subtasks: Vec<SubTask>,
 
+    /// Run items executed concurrently after the commands
+    pub parallel: Vec<Run>,
+
+    /// Run the commands, pipeline and subtasks concurrently (`parallel: true`)
+    pub concurrent: bool,
+
+    /// Cancel the other parallel branches when one fails
+    pub fail_fast: Option<bool>,
+
     /// Environment variables to set
     pub set_environment: HashMap<String, Option<String>>,
+
+    /// Suppress the command echo for this item
+    pub quiet: bool,
+
+    /// Keep running the task when this item fails
+    pub allow_failure: bool,
+
+    /// Variables whose combinations of values the item runs once for
+    pub matrix: IndexMap<String, Vec<String>>,
 }
 
 impl Run {
@@ -249,32 +1667,101 @@ impl Run {
             config::Run::SimpleCommand(cmd) => Run {
                 when: Vec::new(),
                 commands: vec![Command::Simple(cmd)],
+                pipe: Vec::new(),
                 subtasks: Vec::new(),
+                parallel: Vec::new(),
+                concurrent: false,
+                fail_fast: None,
                 set_environment: HashMap::new(),
+                quiet: false,
+                allow_failure: false,
+                matrix: IndexMap::new(),
             },
             config::Run::Complex(item) => Run {
                 when: item.when.into_iter().map(When::from_config).collect(),
-                commands: item
-                    .command
-                    .into_iter()
-                    .map(Command::from_config)
-                    .collect(),
-                subtasks: item
-                    .task
-                    .into_iter()
-                    .map(SubTask::from_config)
-                    .collect(),
+                commands: item.command.into_iter().map(Command::from_config).collect(),
+                pipe: item.pipe.into_iter().map(Command::from_config).collect(),
+                subtasks: item.task.into_iter().map(SubTask::from_config).collect(),
+                concurrent: item.parallel.is_flag(),
+                parallel: match item.parallel {
+                    config::Parallel::Group(items) => {
+                        items.into_iter().map(Run::from_config).collect()
+                    }
+                    config::Parallel::Flag(_) => Vec::new(),
+                },
+                fail_fast: item.fail_fast,
                 set_environment: item.set_environment,
+                quiet: item.quiet,
+                allow_failure: item.allow_failure,
+                matrix: item.matrix,
             },
         }
     }
 
-    /// Get dependencies from this run item
+    /// Whether this item only starts tasks of the same config, without
+    /// conditions or options, so it can be scheduled as a prerequisite
+    pub fn is_prerequisite(&self) -> bool {
+        !self.subtasks.is_empty()
+            && self.when.is_empty()
+            && self.commands.is_empty()
+            && self.pipe.is_empty()
+            && self.parallel.is_empty()
+            && self.set_environment.is_empty()
+            && !self.allow_failure
+            && self.matrix.is_empty()
+            && self
+                .subtasks
+                .iter()
+                .all(|subtask| subtask.options.is_empty() && subtask.file.is_none())
+    }
+
+    /// Whether the item starts `background: true` commands, directly or in
+    /// a parallel branch
+    pub fn starts_background(&self) -> bool {
+        self.commands.iter().any(Command::is_background)
+            || self.parallel.iter().any(Run::starts_background)
+    }
+
+    /// Name of the item in output prefixes and traces: its subtasks, or else
+    /// its first command, its pipeline or `parallel` for a parallel group
+    pub fn label(&self) -> String {
+        if !self.subtasks.is_empty() {
+            let names: Vec<&str> = self.subtasks.iter().map(|s| s.name.as_str()).collect();
+            return names.join(", ");
+        }
+        match self.commands.first() {
+            Some(cmd) => cmd.print().to_string(),
+            None if self.pipe.is_empty() && !self.parallel.is_empty() => "parallel".to_string(),
+            None => Self::pipe_label(&self.pipe),
+        }
+    }
+
+    /// Name of a pipeline in output prefixes
+    fn pipe_label(stages: &[Command]) -> String {
+        let stages: Vec<&str> = stages.iter().map(Command::print).collect();
+        stages.join(" | ")
+    }
+
+    /// Get dependencies from this run item: variables its conditions,
+    /// commands, subtask options and environment values reference
+    ///
+    /// Parallel branches resolve their own dependencies when they run.
     pub fn dependencies(&self) -> Vec<String> {
         let mut deps = Vec::new();
         for when in &self.when {
             deps.extend(when.dependencies());
         }
+        for command in self.commands.iter().chain(&self.pipe) {
+            deps.extend(command.dependencies());
+        }
+        for subtask in &self.subtasks {
+            for value in subtask.options.values() {
+                deps.extend(referenced_vars(value));
+            }
+        }
+        for value in self.set_environment.values().flatten() {
+            deps.extend(referenced_vars(value));
+        }
         deps
     }
 }
@@ -286,12 +1773,34 @@ pub enum Command {
     Simple(String),
 
     /// Complex command with options
-    Complex {
-        exec: String,
-        print: String,
-        quiet: bool,
-        dir: Option<String>,
-    },
+    Complex(Box<CommandDetail>),
+}
+
+/// Runtime representation of a detailed command
+#[derive(Debug, Clone, Default)]
+pub struct CommandDetail {
+    pub exec: String,
+    pub print: String,
+    pub quiet: bool,
+    pub dir: Option<String>,
+    pub output_var: Option<String>,
+    pub filter: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub append: bool,
+    pub log: Option<String>,
+    pub heartbeat: Option<String>,
+    pub timeout: Option<String>,
+    pub retries: u32,
+    pub retry_delay: Option<String>,
+    pub allow_failure: bool,
+    pub background: bool,
+    /// Run in a pseudo-terminal
+    pub tty: bool,
+    /// Spawn the words of `exec` directly instead of through the interpreter
+    pub direct: bool,
+    /// Interpreter flags replacing the context's
+    pub shell_opts: Option<Vec<String>>,
 }
 
 impl Command {
@@ -299,12 +1808,48 @@ impl Command {
     pub fn from_config(config: config::Command) -> Self {
         match config {
             config::Command::Simple(cmd) => Command::Simple(cmd),
-            config::Command::Complex(detail) => Command::Complex {
+            config::Command::Complex(detail) => Command::Complex(Box::new(CommandDetail {
                 print: detail.print.clone().unwrap_or_else(|| detail.exec.clone()),
                 exec: detail.exec,
                 quiet: detail.quiet,
                 dir: detail.dir,
-            },
+                output_var: detail.output_var,
+                filter: detail.filter,
+                stdout: detail.stdout,
+                stderr: detail.stderr,
+                append: detail.append,
+                log: detail.log,
+                heartbeat: detail.heartbeat,
+                timeout: detail.timeout,
+                retries: detail.retries.unwrap_or_default(),
+                retry_delay: detail.retry_delay,
+                allow_failure: detail.allow_failure,
+                background: detail.background,
+                tty: detail.tty,
+                direct: detail.shell == Some(false),
+                shell_opts: detail.shell_opts,
+            })),
+        }
+    }
+
+    /// Variables referenced by the command line and its other settings
+    pub fn dependencies(&self) -> Vec<String> {
+        let mut fields = vec![self.exec(), self.print()];
+        if let Some(detail) = self.detail() {
+            fields.extend(
+                [&detail.dir, &detail.stdout, &detail.stderr, &detail.log]
+                    .into_iter()
+                    .filter_map(|field| field.as_deref()),
+            );
+        }
+        fields.into_iter().flat_map(referenced_vars).collect()
+    }
+
+    /// Get the detailed specification, if this is a complex command
+    pub fn detail(&self) -> Option<&CommandDetail> {
+        match self {
+            Command::Simple(_) => None,
+            Command::Complex(detail) => Some(detail),
         }
     }
 
@@ -312,7 +1857,7 @@ impl Command {
     pub fn exec(&self) -> &str {
         match self {
             Command::Simple(cmd) => cmd,
-            Command::Complex { exec, .. } => exec,
+            Command::Complex(detail) => &detail.exec,
         }
     }
 
@@ -320,24 +1865,78 @@ impl Command {
     pub fn print(&self) -> &str {
         match self {
             Command::Simple(cmd) => cmd,
-            Command::Complex { print, .. } => print,
+            Command::Complex(detail) => &detail.print,
         }
     }
 
+    /// Check if this command runs through the interpreter
+    pub fn uses_shell(&self) -> bool {
+        self.detail().is_none_or(|d| !d.direct)
+    }
+
+    /// Interpreter flags set on this command, if any
+    pub fn shell_opts(&self) -> Option<&[String]> {
+        self.detail().and_then(|d| d.shell_opts.as_deref())
+    }
+
     /// Check if this command is quiet
     pub fn is_quiet(&self) -> bool {
-        match self {
-            Command::Simple(_) => false,
-            Command::Complex { quiet, .. } => *quiet,
-        }
+        self.detail().is_some_and(|d| d.quiet)
     }
 
     /// Get the working directory
     pub fn dir(&self) -> Option<&str> {
-        match self {
-            Command::Simple(_) => None,
-            Command::Complex { dir, .. } => dir.as_deref(),
-        }
+        self.detail().and_then(|d| d.dir.as_deref())
+    }
+
+    /// Get the output line filter pattern
+    pub fn filter(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.filter.as_deref())
+    }
+
+    /// Get the tee log file
+    pub fn log(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.log.as_deref())
+    }
+
+    /// Get the heartbeat interval
+    pub fn heartbeat(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.heartbeat.as_deref())
+    }
+
+    /// Get the timeout
+    pub fn timeout(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.timeout.as_deref())
+    }
+
+    /// How many times to re-run the command after it fails
+    pub fn retries(&self) -> u32 {
+        self.detail().map_or(0, |d| d.retries)
+    }
+
+    /// Get the delay before the first retry
+    pub fn retry_delay(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.retry_delay.as_deref())
+    }
+
+    /// Check if the task keeps running when this command fails
+    pub fn allows_failure(&self) -> bool {
+        self.detail().is_some_and(|d| d.allow_failure)
+    }
+
+    /// Check if this command is left running while the task goes on
+    pub fn is_background(&self) -> bool {
+        self.detail().is_some_and(|d| d.background)
+    }
+
+    /// Check if this command runs in a pseudo-terminal
+    pub fn uses_tty(&self) -> bool {
+        self.detail().is_some_and(|d| d.tty)
+    }
+
+    /// Get the variable that receives captured output
+    pub fn output_var(&self) -> Option<&str> {
+        self.detail().and_then(|d| d.output_var.as_deref())
     }
 }
 
@@ -346,21 +1945,105 @@ impl Command {
 pub struct SubTask {
     pub name: String,
     pub options: HashMap<String, String>,
+    /// Config file defining the task, if not the current one
+    pub file: Option<String>,
+}
+
+/// A task loaded from another config file, with the directory it runs in
+#[derive(Debug, Clone)]
+pub struct ExternalTask {
+    pub task: Task,
+    pub working_dir: PathBuf,
+    /// The config file defining the task
+    pub config_path: PathBuf,
+    /// Tasks of that config file, which the task's own subtasks refer to
+    pub registry: TaskRegistry,
 }
 
 impl SubTask {
+    /// Reference to a task of the same config, without options
+    pub fn named(name: &str) -> Self {
+        SubTask {
+            name: name.to_string(),
+            options: HashMap::new(),
+            file: None,
+        }
+    }
+
     pub fn from_config(config: config::SubTask) -> Self {
         match config {
             config::SubTask::Simple(name) => SubTask {
                 name,
                 options: HashMap::new(),
+                file: None,
             },
             config::SubTask::Complex(detail) => SubTask {
                 name: detail.name,
                 options: detail.options,
+                file: detail.file,
             },
         }
     }
+
+    /// Load the referenced task from its own config file, relative to
+    /// `base_dir`, or `None` if it lives in the current config
+    pub fn load_external(&self, base_dir: &Path) -> ConfigResult<Option<ExternalTask>> {
+        let Some(file) = &self.file else {
+            return Ok(None);
+        };
+        let path = base_dir.join(file);
+        let include_error = |error: String| ConfigError::IncludeFile {
+            path: path.clone(),
+            error,
+            location: None,
+        };
+
+        let config =
+            config::parse_config_file(&path).map_err(|e| ConfigError::include_failed(&path, &e))?;
+        config::validate_config(&config).map_err(|e| include_error(e.to_string()))?;
+        let registry = TaskRegistry::from_config(&config)?;
+        let task = registry
+            .get(&self.name)
+            .cloned()
+            .ok_or_else(|| include_error(format!("task '{}' is not defined", self.name)))?;
+
+        Ok(Some(ExternalTask {
+            task,
+            working_dir: path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| base_dir.to_path_buf()),
+            config_path: path,
+            registry,
+        }))
+    }
+
+    /// Option values to run the referenced task with
+    ///
+    /// Options given in the reference win (interpolated against the parent's
+    /// variables). Any other option the target declares that the parent also
+    /// has a value for is inherited from the parent.
+    pub fn option_values(
+        &self,
+        target: &Task,
+        parent_vars: &HashMap<String, String>,
+    ) -> ExecutionResult<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        for name in target.options.keys() {
+            if let Some(value) = parent_vars.get(name) {
+                values.insert(name.clone(), value.clone());
+            }
+        }
+        for (name, value) in &self.options {
+            let value =
+                interpolate(value, parent_vars).map_err(|e| ExecutionError::InvalidOption {
+                    name: name.clone(),
+                    error: e.to_string(),
+                })?;
+            values.insert(name.clone(), value);
+        }
+        Ok(values)
+    }
 }
 
 /// Runtime representation of a when condition
@@ -382,6 +2065,16 @@ impl When {
                 left: ne.left,
                 right: ne.right,
             }
+        } else if let Some(gt) = config.greater_than {
+            WhenCondition::GreaterThan {
+                left: gt.left,
+                right: gt.right,
+            }
+        } else if let Some(lt) = config.less_than {
+            WhenCondition::LessThan {
+                left: lt.left,
+                right: lt.right,
+            }
         } else if let Some(cmd) = config.command {
             WhenCondition::Command(cmd)
         } else if let Some(path) = config.exists {
@@ -394,6 +2087,16 @@ impl When {
             WhenCondition::OptionSet(opt)
         } else if let Some(opt) = config.option_not_set {
             WhenCondition::OptionNotSet(opt)
+        } else if let Some(check) = config.port_open {
+            WhenCondition::PortOpen {
+                address: check.address(),
+                retry: Retry::from_config(&check.wait()),
+            }
+        } else if let Some(check) = config.http_ok {
+            WhenCondition::HttpOk {
+                url: check.url().to_string(),
+                retry: Retry::from_config(&check.wait()),
+            }
         } else {
             // Default to always true if no condition specified
             WhenCondition::Always
@@ -408,7 +2111,21 @@ impl When {
             WhenCondition::OptionSet(name) | WhenCondition::OptionNotSet(name) => {
                 vec![name.clone()]
             }
-            _ => Vec::new(),
+            WhenCondition::Equal { left, right }
+            | WhenCondition::NotEqual { left, right }
+            | WhenCondition::GreaterThan { left, right }
+            | WhenCondition::LessThan { left, right } => {
+                let mut deps = referenced_vars(left);
+                deps.extend(referenced_vars(right));
+                deps
+            }
+            WhenCondition::Command(value)
+            | WhenCondition::Exists(value)
+            | WhenCondition::EnvSet(value)
+            | WhenCondition::EnvNotSet(value)
+            | WhenCondition::PortOpen { address: value, .. }
+            | WhenCondition::HttpOk { url: value, .. } => referenced_vars(value),
+            WhenCondition::Always => Vec::new(),
         }
     }
 }
@@ -418,15 +2135,55 @@ impl When {
 pub enum WhenCondition {
     Equal { left: String, right: String },
     NotEqual { left: String, right: String },
+    GreaterThan { left: String, right: String },
+    LessThan { left: String, right: String },
     Command(String),
     Exists(String),
     EnvSet(String),
     EnvNotSet(String),
     OptionSet(String),
     OptionNotSet(String),
+    PortOpen { address: String, retry: Retry },
+    HttpOk { url: String, retry: Retry },
     Always,
 }
 
+/// How long a network condition keeps retrying before it counts as false
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    /// Total time to keep retrying; zero checks once
+    pub timeout: Duration,
+
+    /// Pause between attempts
+    pub interval: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            timeout: Duration::ZERO,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Retry {
+    /// Retry settings from config; durations were checked when it was validated
+    pub fn from_config(config: &config::ProbeWait) -> Self {
+        let default = Retry::default();
+        let parse = |value: &Option<String>, fallback| {
+            value
+                .as_deref()
+                .and_then(|value| parse_duration(value).ok())
+                .unwrap_or(fallback)
+        };
+        Retry {
+            timeout: parse(&config.timeout, default.timeout),
+            interval: parse(&config.interval, default.interval),
+        }
+    }
+}
+
 /// Runtime representation of an option
 #[derive(Debug, Clone)]
 pub struct TaskOption {
@@ -438,17 +2195,13 @@ pub struct TaskOption {
     pub required: bool,
     pub rewrite: Option<String>,
     pub environment: Option<String>,
+    pub values: Vec<String>,
     pub private: bool,
 }
 
 impl TaskOption {
     pub fn from_config(name: String, config: config::TaskOption) -> Self {
-        let option_type = match config.option_type.as_str() {
-            "bool" | "boolean" => OptionType::Bool,
-            "int" | "integer" => OptionType::Integer,
-            "float" => OptionType::Float,
-            _ => OptionType::String,
-        };
+        let option_type = OptionType::from_name(&config.option_type).unwrap_or(OptionType::String);
 
         TaskOption {
             name,
@@ -459,13 +2212,17 @@ impl TaskOption {
             required: config.required,
             rewrite: config.rewrite,
             environment: config.environment,
+            values: config.values,
             private: config.private,
         }
     }
 
+    /// Options and args referenced by the default value
     pub fn dependencies(&self) -> Vec<String> {
-        // Options don't have dependencies in the basic model
-        Vec::new()
+        self.default
+            .as_deref()
+            .map(referenced_vars)
+            .unwrap_or_default()
     }
 }
 
@@ -476,6 +2233,69 @@ pub enum OptionType {
     Bool,
     Integer,
     Float,
+    /// Any filesystem path (not checked for existence)
+    Path,
+    /// Path to an existing file
+    File,
+    /// Path to an existing directory
+    Dir,
+    /// Number of times a flag was passed
+    Count,
+    /// Human-friendly duration (`30s`, `5m`), normalized to seconds
+    Duration,
+    /// URL with a scheme and host
+    Url,
+}
+
+impl OptionType {
+    /// Parse the `type` key of an option
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(OptionType::String),
+            "bool" | "boolean" => Some(OptionType::Bool),
+            "int" | "integer" => Some(OptionType::Integer),
+            "float" => Some(OptionType::Float),
+            "path" => Some(OptionType::Path),
+            "file" => Some(OptionType::File),
+            "dir" | "directory" => Some(OptionType::Dir),
+            "count" => Some(OptionType::Count),
+            "duration" => Some(OptionType::Duration),
+            "url" => Some(OptionType::Url),
+            _ => None,
+        }
+    }
+
+    /// Check a provided value and return its normalized form, resolving
+    /// relative paths against `base`
+    pub fn normalize(&self, value: &str, base: &Path) -> Result<String, String> {
+        match self {
+            OptionType::File if !base.join(value).is_file() => {
+                Err(format!("file '{}' does not exist", value))
+            }
+            OptionType::Dir if !base.join(value).is_dir() => {
+                Err(format!("directory '{}' does not exist", value))
+            }
+            OptionType::Duration => parse_duration(value).map(format_duration),
+            OptionType::Url => normalize_url(value),
+            OptionType::Integer | OptionType::Count => {
+                Value::parse_integer(value).map(|_| value.to_string())
+            }
+            OptionType::Float => Value::parse_float(value).map(|_| value.to_string()),
+            _ => Ok(value.to_string()),
+        }
+    }
+
+    /// Typed value for option types that when conditions compare by value
+    pub fn typed_value(&self, value: &str) -> Option<Value> {
+        match self {
+            OptionType::Bool => Value::parse_bool(value).ok().map(Value::Bool),
+            OptionType::Integer | OptionType::Count => {
+                Value::parse_integer(value).ok().map(Value::Integer)
+            }
+            OptionType::Float => Value::parse_float(value).ok().map(Value::Float),
+            _ => None,
+        }
+    }
 }
 
 /// Runtime representation of an argument
@@ -486,16 +2306,23 @@ pub struct Arg {
     pub default: Option<String>,
     pub required: bool,
     pub private: bool,
+    pub arg_type: OptionType,
+    pub variadic: bool,
 }
 
 impl Arg {
     pub fn from_config(name: String, config: config::Arg) -> Self {
+        let arg_type = OptionType::from_name(&config.arg_type).unwrap_or(OptionType::String);
+        let variadic = config.is_variadic();
+
         Arg {
             name,
             usage: config.usage,
             default: config.default,
             required: config.required,
             private: config.private,
+            arg_type,
+            variadic,
         }
     }
 }
@@ -503,7 +2330,7 @@ impl Arg {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
+    use indexmap::IndexMap;
 
     #[test]
     fn test_task_validation_source_without_target() {
@@ -512,13 +2339,14 @@ mod tests {
             description: None,
             private: false,
             quiet: false,
-            args: HashMap::new(),
-            options: HashMap::new(),
+            args: IndexMap::new(),
+            options: IndexMap::new(),
             run: vec![],
             finally: vec![],
             source: vec!["src.txt".to_string()],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         let result = Task::validate_config(&config);
@@ -534,7 +2362,7 @@ mod tests {
             private: false,
             quiet: false,
             args: {
-                let mut args = HashMap::new();
+                let mut args = IndexMap::new();
                 args.insert(
                     "name".to_string(),
                     config::Arg {
@@ -542,12 +2370,13 @@ mod tests {
                         default: None,
                         required: false,
                         private: false,
+                        ..Default::default()
                     },
                 );
                 args
             },
             options: {
-                let mut opts = HashMap::new();
+                let mut opts = IndexMap::new();
                 opts.insert(
                     "name".to_string(),
                     config::TaskOption {
@@ -558,6 +2387,7 @@ mod tests {
                         required: false,
                         rewrite: None,
                         environment: None,
+                        values: vec![],
                         private: false,
                     },
                 );
@@ -568,9 +2398,1271 @@ mod tests {
             source: vec![],
             target: vec![],
             include: None,
+            ..Default::default()
         };
 
         let result = Task::validate_config(&config);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_recursive_task_reports_chain() {
+        let config = config::Task {
+            usage: None,
+            description: None,
+            private: false,
+            quiet: false,
+            args: IndexMap::new(),
+            options: IndexMap::new(),
+            run: vec![],
+            finally: vec![],
+            source: vec![],
+            target: vec![],
+            include: None,
+            ..Default::default()
+        };
+        let task = Task::from_config("a".to_string(), config).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.push_task("a".to_string());
+        ctx.push_task("b".to_string());
+
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::RecursiveTask(chain)) => assert_eq!(chain, "a -> b -> a"),
+            other => panic!("expected recursion error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_path_option_types_validate_existence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("present.txt"), "").unwrap();
+
+        assert_eq!(OptionType::from_name("file"), Some(OptionType::File));
+        assert_eq!(OptionType::from_name("directory"), Some(OptionType::Dir));
+
+        assert!(OptionType::File
+            .normalize("present.txt", dir.path())
+            .is_ok());
+        assert!(OptionType::File
+            .normalize("missing.txt", dir.path())
+            .is_err());
+        assert!(OptionType::File.normalize(".", dir.path()).is_err());
+        assert!(OptionType::Dir.normalize(".", dir.path()).is_ok());
+        assert!(OptionType::Dir
+            .normalize("present.txt", dir.path())
+            .is_err());
+        assert!(OptionType::Path
+            .normalize("missing.txt", dir.path())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_duration_and_url_option_types_normalize() {
+        let base = Path::new(".");
+        assert_eq!(
+            OptionType::from_name("duration"),
+            Some(OptionType::Duration)
+        );
+        assert_eq!(
+            OptionType::Duration.normalize("1m30s", base).unwrap(),
+            "90s"
+        );
+        assert!(OptionType::Duration.normalize("soon", base).is_err());
+        assert_eq!(
+            OptionType::Url
+                .normalize("HTTP://Example.com:80/api", base)
+                .unwrap(),
+            "http://example.com/api"
+        );
+        assert!(OptionType::Url.normalize("example.com", base).is_err());
+    }
+
+    #[test]
+    fn test_execute_rejects_missing_file_option() {
+        let mut options = IndexMap::new();
+        options.insert(
+            "config-file".to_string(),
+            config::TaskOption {
+                usage: None,
+                short: None,
+                option_type: "file".to_string(),
+                default: None,
+                required: false,
+                rewrite: None,
+                environment: None,
+                values: vec![],
+                private: false,
+            },
+        );
+        let config = config::Task {
+            options,
+            ..Default::default()
+        };
+        let mut task = Task::from_config("check".to_string(), config).unwrap();
+        task.vars
+            .insert("config-file".to_string(), "does-not-exist.yml".to_string());
+
+        let mut ctx = Context::new();
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::InvalidOption { name, .. }) => assert_eq!(name, "config-file"),
+            other => panic!("expected invalid option error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_execute_runs_commands_through_executor() {
+        let config = config::Task {
+            run: vec![
+                config::Run::SimpleCommand("echo ${greeting}".to_string()),
+                config::Run::SimpleCommand("make".to_string()),
+            ],
+            ..Default::default()
+        };
+        let mut task = Task::from_config("build".to_string(), config).unwrap();
+        task.vars.insert("greeting".to_string(), "hi".to_string());
+
+        let mock = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_verbosity(crate::runner::Verbosity::Silent)
+            .with_executor(mock.clone());
+        task.execute(&mut ctx).unwrap();
+
+        assert_eq!(mock.commands(), vec!["echo hi", "make"]);
+    }
+
+    #[test]
+    fn test_typed_args_are_checked_before_running() {
+        let mut args = IndexMap::new();
+        args.insert(
+            "replicas".to_string(),
+            config::Arg {
+                arg_type: "int".to_string(),
+                ..Default::default()
+            },
+        );
+        args.insert(
+            "ratios".to_string(),
+            config::Arg {
+                arg_type: "float".to_string(),
+                min: Some(0),
+                ..Default::default()
+            },
+        );
+        let config = config::Task {
+            args,
+            run: vec![config::Run::SimpleCommand("make".to_string())],
+            ..Default::default()
+        };
+        let mut task = Task::from_config("scale".to_string(), config).unwrap();
+        for (key, value) in [("replicas", "3"), ("ratios.1", "0.5"), ("ratios.2", "x")] {
+            task.vars.insert(key.to_string(), value.to_string());
+        }
+
+        let mock = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(mock.clone());
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::InvalidArg { name, .. }) => assert_eq!(name, "ratios"),
+            other => panic!("expected invalid arg error, got {:?}", other),
+        }
+        assert!(mock.commands().is_empty());
+
+        task.vars.insert("ratios.2".to_string(), "2".to_string());
+        let vars = task.resolve_vars(&ctx).unwrap();
+        assert_eq!(vars.get("ratios").map(String::as_str), Some("0.5 2"));
+    }
+
+    #[test]
+    fn test_option_defaults_resolve_only_when_referenced() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  deploy:
+    options:
+      manifest:
+        type: file
+        default: missing.yml
+      stage:
+        default: staging
+    run:
+      - when:
+          - equal: {left: "${stage}", right: prod}
+        command: cat ${manifest}
+      - command: echo ${stage}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["deploy"]
+            .clone();
+        let task = Task::from_config("deploy".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(executor.commands(), vec!["echo staging"]);
+        assert!(!ctx.vars.contains_key("manifest"));
+    }
+
+    #[test]
+    fn test_option_defaults_reference_other_options() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  upload:
+    args:
+      project: {}
+    options:
+      bucket:
+        default: ${region}-${project}-bucket
+      region:
+        default: ${zone}
+      zone:
+        default: us-east-1
+    run: aws s3 cp out.tar s3://${bucket}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["upload"]
+            .clone();
+        let mut task = Task::from_config("upload".to_string(), config).unwrap();
+        task.vars.insert("project".to_string(), "web".to_string());
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec!["aws s3 cp out.tar s3://us-east-1-web-bucket"]
+        );
+
+        task.vars
+            .insert("region".to_string(), "eu-west-1".to_string());
+        let vars = task.preview_vars(&Context::new()).unwrap();
+        assert_eq!(vars["bucket"], "eu-west-1-web-bucket");
+        assert_eq!(vars["zone"], "us-east-1");
+    }
+
+    #[test]
+    fn test_task_interpreter_for_host_os() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  hello:
+    interpreter:
+      linux: [rtask-missing-shell, -c]
+      windows: [[rtask-missing-shell, -c], [bash, -c]]
+    run: echo hello
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["hello"]
+            .clone();
+        let task = Task::from_config("hello".to_string(), config).unwrap();
+
+        let mut ctx = Context::new()
+            .with_os("windows")
+            .with_executor(std::sync::Arc::new(crate::runner::MockExecutor::new()));
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(ctx.interpreter, vec!["sh", "-c"]);
+
+        for (os, expected) in [("macos", "'macos'"), ("linux", "'rtask-missing-shell'")] {
+            let mut ctx = Context::new().with_os(os);
+            match task.execute(&mut ctx) {
+                Err(ExecutionError::InterpreterNotFound(message)) => {
+                    assert!(message.contains(expected), "{}", message)
+                }
+                other => panic!("expected missing interpreter, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrency_group_waits_for_holder() {
+        let config = crate::config::parse_config(
+            "tasks:\n  deploy:\n    concurrency-group: deploy\n    run: ship it\n",
+            None,
+        )
+        .unwrap()
+        .tasks["deploy"]
+            .clone();
+        let task = Task::from_config("deploy".to_string(), config).unwrap();
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+
+        // Held by an enclosing task: run inside its hold
+        let holder = ctx.concurrency.acquire("deploy", None, || {}).unwrap();
+        ctx.held_groups.push("deploy".to_string());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(executor.commands(), vec!["ship it"]);
+        assert_eq!(ctx.held_groups, ["deploy"]);
+
+        // Held by another task: wait for it to finish
+        ctx.held_groups.clear();
+        let mut branch = ctx.branch();
+        std::thread::scope(|scope| {
+            let waiting = scope.spawn(|| task.execute(&mut branch));
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            assert_eq!(executor.commands().len(), 1);
+            drop(holder);
+            waiting.join().unwrap().unwrap();
+        });
+        assert_eq!(executor.commands().len(), 2);
+    }
+
+    #[test]
+    fn test_parallel_group_branches() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  release:
+    options:
+      target:
+        default: web
+    run:
+      - parallel:
+          - command: build assets
+          - when:
+              - equal: {left: "${target}", right: cli}
+            command: build cli
+          - command:
+              - exec: git rev-parse HEAD
+                output-var: REV
+          - command: compile ${target}
+      - command: package ${target} ${REV}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["release"]
+            .clone();
+        let task = Task::from_config("release".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+
+        let mut commands = executor.commands();
+        assert_eq!(commands.pop().unwrap(), "package web ${REV}");
+        commands.sort();
+        assert_eq!(
+            commands,
+            vec!["build assets", "compile web", "git rev-parse HEAD"]
+        );
+    }
+
+    #[test]
+    fn test_parallel_group_collects_failures() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  check:
+    run:
+      - parallel: [lint, test, audit]
+        fail-fast: false
+      - command: never
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["check"]
+            .clone();
+        let task = Task::from_config("check".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("lint", 1, "").respond("audit", 2, "");
+        let mut ctx = Context::new().with_executor(executor.clone());
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::ParallelFailed(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected aggregated failure, got {:?}", other),
+        }
+        let mut commands = executor.commands();
+        commands.sort();
+        assert_eq!(commands, vec!["audit", "lint", "test"]);
+    }
+
+    #[test]
+    fn test_allowed_failures_do_not_stop_the_task() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  check:
+    run:
+      - command: {exec: lint, allow-failure: true}
+      - command: [audit, never]
+        allow-failure: true
+      - command: test
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["check"]
+            .clone();
+        let task = Task::from_config("check".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("lint", 1, "").respond("audit", 2, "");
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(executor.commands(), vec!["lint", "audit", "test"]);
+
+        let failures = ctx.allowed_failures.list();
+        let commands: Vec<&str> = failures.iter().map(|f| f.command.as_str()).collect();
+        assert_eq!(commands, vec!["lint", "audit"]);
+        assert!(failures.iter().all(|f| f.task == "check"));
+        assert!(ctx.failed_command.is_none());
+    }
+
+    #[test]
+    fn test_later_steps_see_the_last_exit_code() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  check:
+    run:
+      - command: {exec: flaky, allow-failure: true}
+      - when:
+          - equal: {left: "${exit_code}", right: "3"}
+        command: recover ${last_command}
+      - report ${exit_code}
+      - command: [upload, "notify ${exit_code}"]
+        when:
+          - not-equal: {left: "${exit_code}", right: "0"}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["check"]
+            .clone();
+        let task = Task::from_config("check".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("flaky", 3, "");
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec!["flaky", "recover flaky", "report 0"]
+        );
+        assert_eq!(
+            ctx.get_value(crate::runner::EXIT_CODE_VAR),
+            Some(&Value::Integer(0))
+        );
+    }
+
+    #[test]
+    fn test_matrix_runs_each_combination() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  build:
+    matrix:
+      os: [linux, macos]
+    run:
+      - build ${os}
+      - matrix: {arch: [amd64, arm64]}
+        when:
+          - not-equal: {left: "${os}-${arch}", right: "macos-amd64"}
+        command: package ${os}-${arch}
+    finally:
+      - cleanup ${os}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["build"]
+            .clone();
+        let task = Task::from_config("build".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        ctx.set_var("os".to_string(), "windows".to_string());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec![
+                "build linux",
+                "package linux-amd64",
+                "package linux-arm64",
+                "build macos",
+                "package macos-arm64",
+                "cleanup windows",
+            ]
+        );
+        assert_eq!(ctx.get_var("os").map(String::as_str), Some("windows"));
+
+        // With --keep-going, the other combinations still run
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("build linux", 1, "");
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_keep_going(true);
+        let error = task.execute(&mut ctx).unwrap_err();
+        assert!(executor.commands().contains(&"build macos".to_string()));
+        assert!(matches!(
+            error,
+            ExecutionError::CombinationsFailed { ref item, ref failed, total: 2 }
+                if item == "build" && failed == &["os=linux"]
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_background_commands_stop_when_the_task_ends() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  dev:
+    run:
+      - command: {exec: sleep 10, background: true}
+      - command: "true"
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["dev"]
+            .clone();
+        let task = Task::from_config("dev".to_string(), config).unwrap();
+
+        let mut ctx = Context::new();
+        let started = std::time::Instant::now();
+        task.execute(&mut ctx).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_background_commands_go_through_the_executor() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  dev:
+    run:
+      - command: {exec: serve, background: true}
+      - test
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["dev"]
+            .clone();
+        let task = Task::from_config("dev".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(executor.commands(), vec!["serve", "test"]);
+    }
+
+    #[test]
+    fn test_parallel_flag_runs_commands_and_subtasks_concurrently() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  lint:
+    private: true
+    run: cargo clippy
+  check:
+    run:
+      - parallel: true
+        fail-fast: false
+        command: [cargo fmt --check, cargo test]
+        pipe: [cargo tree, grep openssl]
+        task: lint
+      - command: never
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("check".to_string(), config.tasks["check"].clone()).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor
+            .respond("cargo test", 1, "")
+            .respond("cargo clippy", 2, "");
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(std::sync::Arc::new(
+                TaskRegistry::from_config(&config).unwrap(),
+            ));
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::ParallelFailed(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected aggregated failure, got {:?}", other),
+        }
+        let mut commands = executor.commands();
+        commands.sort();
+        assert_eq!(
+            commands,
+            vec![
+                "cargo clippy",
+                "cargo fmt --check",
+                "cargo test",
+                "cargo tree",
+                "grep openssl"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jobs_schedule_prerequisites_once() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  build:
+    run: cargo build
+  lint:
+    run:
+      - task: build
+      - command: cargo clippy
+  test:
+    run:
+      - task: build
+      - command: cargo test
+  ci:
+    run:
+      - task: [lint, test]
+      - command: upload
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("ci".to_string(), config.tasks["ci"].clone()).unwrap();
+        let registry = std::sync::Arc::new(TaskRegistry::from_config(&config).unwrap());
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry.clone())
+            .with_jobs(4);
+        task.execute(&mut ctx).unwrap();
+
+        let commands = executor.commands();
+        assert_eq!(commands.first().unwrap(), "cargo build");
+        assert_eq!(commands.last().unwrap(), "upload");
+        let mut middle = commands[1..3].to_vec();
+        middle.sort();
+        assert_eq!(middle, vec!["cargo clippy", "cargo test"]);
+
+        // Without a job limit, subtasks run inline as written
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry);
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec![
+                "cargo build",
+                "cargo clippy",
+                "cargo build",
+                "cargo test",
+                "upload"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deps_run_once_per_run() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  setup:
+    run: npm ci
+  lint:
+    deps: setup
+    run: npm run lint
+  test:
+    deps: [setup]
+    run: npm test
+  ci:
+    deps: [lint, test]
+    run: upload
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("ci".to_string(), config.tasks["ci"].clone()).unwrap();
+        let registry = std::sync::Arc::new(TaskRegistry::from_config(&config).unwrap());
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry.clone());
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec!["npm ci", "npm run lint", "npm test", "upload"]
+        );
+
+        // A failed dep fails the tasks depending on it without running again
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("npm ci", 1, "");
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry)
+            .with_jobs(2);
+        assert!(task.execute(&mut ctx).is_err());
+        assert_eq!(executor.commands(), vec!["npm ci"]);
+    }
+
+    #[test]
+    fn test_task_when_skips_the_whole_task() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  build:
+    run: cargo build
+  deploy:
+    options:
+      env:
+        default: staging
+    when:
+      - equal: {left: "${env}", right: "production"}
+    deps: [build]
+    run: ./deploy.sh ${env}
+    finally:
+      - rm -rf tmp
+  release:
+    run:
+      - task: deploy
+      - task: {name: deploy, options: {env: production}}
+"#,
+            None,
+        )
+        .unwrap();
+        let registry = std::sync::Arc::new(TaskRegistry::from_config(&config).unwrap());
+        let task =
+            Task::from_config("release".to_string(), config.tasks["release"].clone()).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry);
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(
+            executor.commands(),
+            vec!["cargo build", "./deploy.sh production", "rm -rf tmp"]
+        );
+    }
+
+    #[test]
+    fn test_hooks_run_around_the_invoked_task() {
+        let mut config = crate::config::parse_config(
+            r#"
+before-each: setup ${task_name}
+after-each:
+  - report ${task_name} ${exit_code}
+tasks:
+  lib:
+    run: make lib
+  app:
+    deps: [lib]
+    run: make app
+    finally:
+      - rm -rf tmp
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("app".to_string(), config.tasks["app"].clone()).unwrap();
+        let run = |config: &config::Config, fail: bool| {
+            let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+            if fail {
+                executor.respond("make app", 2, "");
+            }
+            let mut ctx = Context::new()
+                .with_executor(executor.clone())
+                .with_registry(std::sync::Arc::new(
+                    TaskRegistry::from_config(config).unwrap(),
+                ))
+                .with_hooks(TaskHooks::from_config(config));
+            let result = task.execute(&mut ctx);
+            assert_eq!(result.is_err(), fail);
+            executor.commands()
+        };
+
+        assert_eq!(
+            run(&config, false),
+            vec![
+                "setup app",
+                "make lib",
+                "make app",
+                "rm -rf tmp",
+                "report app 0"
+            ]
+        );
+        assert_eq!(
+            run(&config, true),
+            vec![
+                "setup app",
+                "make lib",
+                "make app",
+                "rm -rf tmp",
+                "report app 2"
+            ]
+        );
+
+        config.each_includes_subtasks = Some(true);
+        assert_eq!(
+            run(&config, false),
+            vec![
+                "setup app",
+                "setup lib",
+                "make lib",
+                "report lib 0",
+                "make app",
+                "rm -rf tmp",
+                "report app 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hooks_leave_task_name_option_alone() {
+        let config = crate::config::parse_config(
+            r#"
+before-each: setup ${task_name}
+tasks:
+  app:
+    options:
+      task_name:
+        default: mine
+    run: make ${task_name}
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("app".to_string(), config.tasks["app"].clone()).unwrap();
+
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_hooks(TaskHooks::from_config(&config));
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(executor.commands(), vec!["setup app", "make mine"]);
+    }
+
+    #[test]
+    fn test_keep_going_runs_independent_deps() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  lint:
+    run: npm run lint
+  test:
+    run: npm test
+  docs:
+    run: npm run docs
+  ci:
+    deps: [lint, test, docs]
+    run: upload
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("ci".to_string(), config.tasks["ci"].clone()).unwrap();
+        let registry = std::sync::Arc::new(TaskRegistry::from_config(&config).unwrap());
+
+        for jobs in [1, 2] {
+            let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+            executor.respond("npm run lint", 1, "");
+            executor.respond("npm run docs", 2, "");
+            let mut ctx = Context::new()
+                .with_executor(executor.clone())
+                .with_registry(registry.clone())
+                .with_jobs(jobs)
+                .with_keep_going(true);
+            match task.execute(&mut ctx).unwrap_err() {
+                ExecutionError::TasksFailed { mut failed, total } => {
+                    failed.sort();
+                    assert_eq!(failed, vec!["docs", "lint"]);
+                    assert_eq!(total, 3);
+                }
+                error => panic!("unexpected error: {}", error),
+            }
+            let mut commands = executor.commands();
+            commands.sort();
+            assert_eq!(commands, vec!["npm run docs", "npm run lint", "npm test"]);
+        }
+
+        // Without it, the first failure stops the run
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        executor.respond("npm run lint", 1, "");
+        let mut ctx = Context::new()
+            .with_executor(executor.clone())
+            .with_registry(registry);
+        assert!(task.execute(&mut ctx).is_err());
+        assert_eq!(executor.commands(), vec!["npm run lint"]);
+    }
+
+    #[test]
+    fn test_recursive_prerequisites_are_an_error() {
+        let config = crate::config::parse_config(
+            "tasks:\n  a:\n    run:\n      - task: b\n  b:\n    run:\n      - task: a\n",
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("a".to_string(), config.tasks["a"].clone()).unwrap();
+        let mut ctx = Context::new()
+            .with_executor(std::sync::Arc::new(crate::runner::MockExecutor::new()))
+            .with_registry(std::sync::Arc::new(
+                TaskRegistry::from_config(&config).unwrap(),
+            ))
+            .with_jobs(2);
+
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::RecursiveTask(chain)) => assert_eq!(chain, "a -> b -> a"),
+            other => panic!("expected recursion error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_up_to_date_task_is_skipped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("in.txt"), "").unwrap();
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  build:
+    source: [in.txt]
+    target: [out.txt]
+    run: cp in.txt out.txt
+"#,
+            None,
+        )
+        .unwrap();
+        let mut task =
+            Task::from_config("build".to_string(), config.tasks["build"].clone()).unwrap();
+        task.definition = crate::runner::definition_hash(&config, "build");
+
+        let run = |ctx: Context| {
+            let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+            let mut ctx = ctx
+                .with_working_dir(dir.path().to_path_buf())
+                .with_executor(executor.clone());
+            task.execute(&mut ctx).unwrap();
+            std::fs::write(dir.path().join("out.txt"), "").unwrap();
+            executor.commands().len()
+        };
+        assert_eq!(run(Context::new()), 1);
+        assert_eq!(run(Context::new()), 0);
+        assert_eq!(run(Context::new().with_force(true)), 1);
+    }
+
+    #[test]
+    fn test_resume_skips_completed_run_items() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = crate::config::parse_config(
+            "tasks:\n  release:\n    run: [build, test, publish]\n    finally: [clean]\n",
+            None,
+        )
+        .unwrap();
+        let mut task =
+            Task::from_config("release".to_string(), config.tasks["release"].clone()).unwrap();
+        task.definition = crate::runner::definition_hash(&config, "release");
+
+        let run = |resume: bool, failing: Option<&str>| {
+            let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+            if let Some(command) = failing {
+                executor.respond(command, 1, "");
+            }
+            let mut ctx = Context::new()
+                .with_working_dir(dir.path().to_path_buf())
+                .with_executor(executor.clone())
+                .with_resume(resume);
+            let result = task.execute(&mut ctx);
+            assert_eq!(result.is_ok(), failing.is_none());
+            executor.commands()
+        };
+        assert_eq!(run(false, Some("test")), vec!["build", "test", "clean"]);
+        assert_eq!(run(true, Some("publish")), vec!["test", "publish", "clean"]);
+        assert_eq!(run(true, None), vec!["publish", "clean"]);
+        assert_eq!(run(true, None), vec!["build", "test", "publish", "clean"]);
+    }
+
+    #[test]
+    fn test_resume_stops_before_background_commands() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  dev:
+    run:
+      - build
+      - command:
+          exec: serve
+          background: true
+      - test
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("dev".to_string(), config.tasks["dev"].clone()).unwrap();
+        assert_eq!(task.resumable_items(), 1);
+    }
+
+    #[test]
+    fn test_dry_run_spawns_nothing() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  deploy:
+    run:
+      - command:
+          exec: touch captured
+          output-var: OUT
+      - when:
+          - command: touch checked
+        command: touch ran-${OUT}
+"#,
+            None,
+        )
+        .unwrap();
+        let task = Task::from_config("deploy".to_string(), config.tasks["deploy"].clone()).unwrap();
+
+        let mut ctx = Context::new()
+            .with_working_dir(temp_dir.path().to_path_buf())
+            .with_dry_run();
+        task.execute(&mut ctx).unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+        assert_eq!(ctx.vars.get("OUT").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_finally_runs_after_panic() {
+        use crate::runner::{CommandExecutor, MockExecutor};
+        use std::path::PathBuf;
+        use std::process::Command as StdCommand;
+
+        /// Records commands like the mock, but panics on `explode`
+        #[derive(Default)]
+        struct Exploding(MockExecutor);
+
+        impl CommandExecutor for Exploding {
+            fn execute(
+                &self,
+                cmd: &Command,
+                ctx: &Context,
+                process: StdCommand,
+                dir: &Path,
+            ) -> ExecutionResult<()> {
+                let result = self.0.execute(cmd, ctx, process, dir);
+                if self.0.commands().last().is_some_and(|c| c == "explode") {
+                    panic!("executor blew up");
+                }
+                result
+            }
+
+            fn capture(
+                &self,
+                cmd: &Command,
+                ctx: &Context,
+                process: StdCommand,
+                dir: &Path,
+            ) -> ExecutionResult<String> {
+                self.0.capture(cmd, ctx, process, dir)
+            }
+
+            fn background(
+                &self,
+                cmd: &Command,
+                ctx: &Context,
+                process: StdCommand,
+                dir: &Path,
+            ) -> ExecutionResult<()> {
+                self.0.background(cmd, ctx, process, dir)
+            }
+
+            fn check(&self, process: StdCommand, dir: &Path) -> ExecutionResult<Option<i32>> {
+                self.0.check(process, dir)
+            }
+
+            fn pipeline(
+                &self,
+                ctx: &Context,
+                stages: Vec<(StdCommand, PathBuf)>,
+            ) -> ExecutionResult<()> {
+                self.0.pipeline(ctx, stages)
+            }
+        }
+
+        let config = crate::config::parse_config(
+            "tasks:\n  t:\n    run: [explode, never]\n    finally: [cleanup]\n",
+            None,
+        )
+        .unwrap()
+        .tasks["t"]
+            .clone();
+        let task = Task::from_config("t".to_string(), config).unwrap();
+
+        let executor = std::sync::Arc::new(Exploding::default());
+        let mut ctx = Context::new().with_executor(executor.clone());
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::Panicked { task, message }) => {
+                assert_eq!(task, "t");
+                assert_eq!(message, "executor blew up");
+            }
+            other => panic!("expected a panic error, got {:?}", other),
+        }
+        assert_eq!(executor.0.commands(), vec!["explode", "cleanup"]);
+        assert!(ctx.task_stack.is_empty());
+    }
+
+    #[test]
+    fn test_preview_vars_applies_set_environment() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  deploy:
+    options:
+      env:
+        default: staging
+    run:
+      - set-environment:
+          URL: https://${env}.example.com
+      - command: echo ${URL}
+"#,
+            None,
+        )
+        .unwrap()
+        .tasks["deploy"]
+            .clone();
+        let mut task = Task::from_config("deploy".to_string(), config).unwrap();
+        task.vars.insert("env".to_string(), "prod".to_string());
+
+        let vars = task.preview_vars(&Context::new()).unwrap();
+        assert_eq!(vars["env"], "prod");
+        assert_eq!(vars["URL"], "https://prod.example.com");
+    }
+
+    #[test]
+    fn test_subtask_inherits_shared_options() {
+        let config = crate::config::parse_config(
+            "tasks:\n  build:\n    options:\n      target: {}\n      profile: {}\n    run: echo\n",
+            None,
+        )
+        .unwrap()
+        .tasks["build"]
+            .clone();
+        let target = Task::from_config("build".to_string(), config).unwrap();
+
+        let mut parent_vars = HashMap::new();
+        parent_vars.insert("target".to_string(), "x86_64".to_string());
+        parent_vars.insert("profile".to_string(), "debug".to_string());
+        parent_vars.insert("unrelated".to_string(), "1".to_string());
+
+        let mut subtask = SubTask::from_config(config::SubTask::Simple("build".to_string()));
+        subtask
+            .options
+            .insert("profile".to_string(), "${target}-release".to_string());
+
+        let values = subtask.option_values(&target, &parent_vars).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["target"], "x86_64");
+        assert_eq!(values["profile"], "x86_64-release");
+    }
+
+    #[test]
+    fn test_subtask_runs_registered_task() {
+        let config = crate::config::parse_config(
+            r#"
+tasks:
+  compile:
+    private: true
+    options:
+      profile:
+        default: debug
+      verbose:
+        type: bool
+    run:
+      - when:
+          - equal: {left: "${verbose}", right: "true"}
+        command: echo verbose
+      - command: cargo build --${profile}
+  ci:
+    options:
+      profile:
+        default: release
+    run:
+      - task: compile
+      - task: {name: compile, options: {profile: test}}
+      - command: echo ${profile}
+"#,
+            None,
+        )
+        .unwrap();
+        let registry = std::sync::Arc::new(TaskRegistry::from_config(&config).unwrap());
+        let task = registry.get("ci").unwrap().clone();
+
+        // The invoked task records its checkpoint in the working directory
+        let dir = tempfile::TempDir::new().unwrap();
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let mut ctx = Context::new()
+            .with_working_dir(dir.path().to_path_buf())
+            .with_verbosity(Verbosity::Silent)
+            .with_executor(executor.clone())
+            .with_registry(registry);
+        task.execute(&mut ctx).unwrap();
+
+        assert_eq!(
+            executor.commands(),
+            vec![
+                "cargo build --release",
+                "cargo build --test",
+                "echo release"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_subtask_is_an_error() {
+        let config =
+            crate::config::parse_config("tasks:\n  ci:\n    run:\n      - task: lint\n", None)
+                .unwrap()
+                .tasks["ci"]
+                .clone();
+        let task = Task::from_config("ci".to_string(), config).unwrap();
+
+        let mut ctx = Context::new().with_verbosity(Verbosity::Silent);
+        match task.execute(&mut ctx) {
+            Err(ExecutionError::Subtask { task, .. }) => assert_eq!(task, "lint"),
+            other => panic!("expected subtask error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_external_subtask() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(
+            shared.join("rtask.yml"),
+            "tasks:\n  build:\n    run: make\n",
+        )
+        .unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+
+        let subtask = SubTask::from_config(config::SubTask::Complex(config::SubTaskDetail {
+            name: "build".to_string(),
+            options: HashMap::new(),
+            file: Some("../shared/rtask.yml".to_string()),
+        }));
+        let external = subtask.load_external(&project).unwrap().unwrap();
+        assert_eq!(external.task.name, "build");
+        assert_eq!(external.working_dir, project.join("../shared"));
+
+        let missing = SubTask {
+            name: "deploy".to_string(),
+            ..subtask.clone()
+        };
+        assert!(matches!(
+            missing.load_external(&project),
+            Err(ConfigError::IncludeFile { .. })
+        ));
+
+        let local = SubTask::from_config(config::SubTask::Simple("build".to_string()));
+        assert!(local.load_external(&project).unwrap().is_none());
+    }
 }
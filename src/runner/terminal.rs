@@ -0,0 +1,153 @@
+//! Handing rtask's terminal to commands
+//!
+//! Commands run in process groups of their own (see
+//! [`own_process_group`](crate::runner::own_process_group)), which the
+//! terminal does not treat as its foreground. While rtask is in the
+//! foreground, a command that runs on its own takes the terminal over as it
+//! starts, as a shell hands it to a job: the command can read from it, and
+//! Ctrl-C and Ctrl-Z reach every process in its group. rtask takes the
+//! terminal back once the command exits, and stops along with a command
+//! stopped by Ctrl-Z until it is resumed.
+//!
+//! Process groups need a unix system; elsewhere commands never take the
+//! terminal.
+
+use std::process::{Child, Command as StdCommand, ExitStatus};
+
+/// Whether rtask's stdin is a terminal with rtask's process group in its
+/// foreground, so a command can be handed the terminal
+#[cfg(unix)]
+pub fn in_foreground() -> bool {
+    // SAFETY: plain queries on stdin and rtask's own process group
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+            && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+#[cfg(not(unix))]
+pub fn in_foreground() -> bool {
+    false
+}
+
+/// Make `command`, spawned as the leader of a new process group, take the
+/// terminal over as it starts
+#[cfg(unix)]
+pub fn take_over(command: &mut StdCommand) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: only async-signal-safe calls between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            hand_to(libc::getpgrp());
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn take_over(_command: &mut StdCommand) {}
+
+/// The terminal, held by the process group of a command spawned after
+/// [`take_over`], until this is dropped
+pub struct Foreground {
+    #[cfg(unix)]
+    group: libc::pid_t,
+}
+
+impl Foreground {
+    /// The terminal, held by the group led by `child`
+    ///
+    /// rtask hands it over too, so the command has it whichever of them gets
+    /// there first.
+    #[cfg(unix)]
+    pub fn new(child: &Child) -> Self {
+        let group = child.id() as libc::pid_t;
+        hand_to(group);
+        Foreground { group }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_child: &Child) -> Self {
+        Foreground {}
+    }
+
+    /// Whether the command was ended by a Ctrl-C typed while it held the
+    /// terminal, which rtask did not receive itself
+    #[cfg(unix)]
+    pub fn interrupted(&self, status: &ExitStatus) -> bool {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal() == Some(libc::SIGINT)
+    }
+
+    #[cfg(not(unix))]
+    pub fn interrupted(&self, _status: &ExitStatus) -> bool {
+        false
+    }
+
+    /// If Ctrl-Z stopped `child`, take the terminal back and stop rtask too;
+    /// once rtask is resumed, the command is too, with the terminal again if
+    /// rtask is back in the foreground
+    #[cfg(unix)]
+    pub fn follow_stop(&self, child: &Child) {
+        // SAFETY: the struct is zeroed, so a reported stop is one that set it
+        let stopped = unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            libc::waitid(
+                libc::P_PID,
+                child.id() as libc::id_t,
+                &mut info,
+                libc::WSTOPPED | libc::WNOHANG,
+            ) == 0
+                && info.si_signo != 0
+        };
+        if !stopped {
+            return;
+        }
+        hand_to(unsafe { libc::getpgrp() });
+        // SAFETY: stops rtask as the terminal's Ctrl-Z would have
+        unsafe { libc::raise(libc::SIGTSTP) };
+        if in_foreground() {
+            hand_to(self.group);
+        }
+        // SAFETY: the group's leader is not reaped yet, so its id is not reused
+        unsafe { libc::killpg(self.group, libc::SIGCONT) };
+    }
+
+    #[cfg(not(unix))]
+    pub fn follow_stop(&self, _child: &Child) {}
+}
+
+impl Drop for Foreground {
+    /// Take the terminal back, unless the command no longer has it (rtask
+    /// was resumed in the background)
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: plain queries on stdin and rtask's own process group
+        unsafe {
+            if libc::tcgetpgrp(libc::STDIN_FILENO) == self.group {
+                hand_to(libc::getpgrp());
+            }
+        }
+    }
+}
+
+/// Make `group` the terminal's foreground process group
+///
+/// SIGTTOU is blocked meanwhile, since a process outside the foreground
+/// group would be stopped by it for changing the foreground. Only
+/// async-signal-safe calls, for use between fork and exec.
+#[cfg(unix)]
+fn hand_to(group: libc::pid_t) {
+    // SAFETY: the signal sets are initialized before use, and the previous
+    // mask is restored
+    unsafe {
+        let mut blocked: libc::sigset_t = std::mem::zeroed();
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut blocked);
+        libc::sigaddset(&mut blocked, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &blocked, &mut previous);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+    }
+}
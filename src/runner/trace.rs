@@ -0,0 +1,206 @@
+//! Timing traces of a run (`--trace-out`)
+//!
+//! A [`Tracer`] records when each task, run item and command of a run starts
+//! and ends. The spans are written in the Chrome trace event format, which
+//! `chrome://tracing` and Perfetto open directly. Each thread rtask runs work
+//! on (parallel branches, scheduled jobs) gets a lane of its own.
+
+use crate::error::ExecutionResult;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+/// One complete span in the Chrome trace event format
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceEvent {
+    pub name: String,
+    /// `task`, `run`, `matrix` or `command`
+    pub cat: &'static str,
+    /// Event type; always `X`, a complete event
+    pub ph: &'static str,
+    /// Start, in microseconds since the trace began
+    pub ts: u64,
+    /// Duration in microseconds
+    pub dur: u64,
+    pub pid: u32,
+    /// Lane of the thread the span ran on
+    pub tid: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub args: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    events: Vec<TraceEvent>,
+    lanes: HashMap<ThreadId, u64>,
+}
+
+/// Spans recorded during a run, shared by all threads of the run
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    origin: Instant,
+    state: Arc<Mutex<TraceState>>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer::new()
+    }
+}
+
+impl Tracer {
+    /// Start a trace; span times count from now
+    pub fn new() -> Self {
+        Tracer {
+            origin: Instant::now(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Run `work` as a span named `name`, noting its error if it fails
+    pub fn span<T>(
+        &self,
+        category: &'static str,
+        name: String,
+        work: impl FnOnce() -> ExecutionResult<T>,
+    ) -> ExecutionResult<T> {
+        let lane = self.lane();
+        let started = Instant::now();
+        let result = work();
+        let mut args = BTreeMap::new();
+        if let Err(e) = &result {
+            args.insert("error", e.to_string());
+        }
+        self.push(TraceEvent {
+            name,
+            cat: category,
+            ph: "X",
+            ts: micros(started.saturating_duration_since(self.origin)),
+            dur: micros(started.elapsed()),
+            pid: std::process::id(),
+            tid: lane,
+            args,
+        });
+        result
+    }
+
+    /// The spans recorded so far, in the order they ended
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.lock().events.clone()
+    }
+
+    /// Write the spans recorded so far to `path` as a Chrome trace
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let trace = serde_json::json!({
+            "traceEvents": self.events(),
+            "displayTimeUnit": "ms",
+        });
+        fs::write(path, serde_json::to_string(&trace)? + "\n")
+    }
+
+    /// Lane of the current thread, numbered in the order threads first
+    /// record a span
+    fn lane(&self) -> u64 {
+        let mut state = self.lock();
+        let next = state.lanes.len() as u64 + 1;
+        *state.lanes.entry(thread::current().id()).or_insert(next)
+    }
+
+    fn push(&self, event: TraceEvent) {
+        self.lock().events.push(event);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TraceState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Run `work`, as a span named by `name` when a trace is being recorded
+pub fn traced<T, N: Display>(
+    trace: Option<&Tracer>,
+    category: &'static str,
+    name: impl FnOnce() -> N,
+    work: impl FnOnce() -> ExecutionResult<T>,
+) -> ExecutionResult<T> {
+    match trace {
+        Some(trace) => trace.span(category, name().to_string(), work),
+        None => work(),
+    }
+}
+
+fn micros(duration: std::time::Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ExecutionError;
+
+    #[test]
+    fn test_spans_nest_and_note_errors() {
+        let tracer = Tracer::new();
+        let result = traced(
+            Some(&tracer),
+            "task",
+            || "build",
+            || {
+                traced(
+                    Some(&tracer),
+                    "command",
+                    || "cargo build",
+                    || Err::<(), _>(ExecutionError::CommandFailed(Some(2))),
+                )
+            },
+        );
+        assert!(result.is_err());
+
+        let events = tracer.events();
+        let names: Vec<&str> = events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["cargo build", "build"]);
+        let (command, task) = (&events[0], &events[1]);
+        assert!(task.ts <= command.ts && command.ts + command.dur <= task.ts + task.dur);
+        assert_eq!(command.tid, task.tid);
+        assert_eq!(
+            command.args.get("error").map(String::as_str),
+            Some("Command failed with exit code Some(2)")
+        );
+
+        // Another thread gets its own lane
+        let other = tracer.clone();
+        thread::spawn(move || traced(Some(&other), "task", || "test", || Ok(())))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_ne!(tracer.events()[2].tid, task.tid);
+
+        // Without a tracer, the work just runs
+        assert_eq!(traced(None, "task", || "build", || Ok(3)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_write_chrome_trace() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("out").join("trace.json");
+        let tracer = Tracer::new();
+        traced(Some(&tracer), "run", || "echo hi", || Ok(())).unwrap();
+        tracer.write(&path).unwrap();
+
+        let trace: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let event = &trace["traceEvents"][0];
+        assert_eq!(event["name"], "echo hi");
+        assert_eq!(event["cat"], "run");
+        assert_eq!(event["ph"], "X");
+        assert!(event.get("args").is_none());
+    }
+}
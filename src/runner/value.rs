@@ -0,0 +1,250 @@
+//! Typed option values
+//!
+//! Options declared as `bool`, `int` or `float` are parsed into a [`Value`] so
+//! when conditions compare them by value (`3` equals `3.0`, `yes` equals
+//! `true`) rather than by their string spelling.
+
+use crate::error::{ExecutionError, ExecutionResult};
+use crate::runner::{interpolate, Context};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A typed option value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+impl Value {
+    /// Name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+        }
+    }
+
+    /// Parse a bool the way option values and YAML literals spell it
+    pub fn parse_bool(raw: &str) -> Result<bool, String> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => Err(format!("expected a bool, got '{}'", raw)),
+        }
+    }
+
+    /// Parse an integer value
+    pub fn parse_integer(raw: &str) -> Result<i64, String> {
+        raw.trim()
+            .parse()
+            .map_err(|_| format!("expected an integer, got '{}'", raw))
+    }
+
+    /// Parse a float value
+    pub fn parse_float(raw: &str) -> Result<f64, String> {
+        raw.trim()
+            .parse()
+            .map_err(|_| format!("expected a number, got '{}'", raw))
+    }
+
+    /// Parse `raw` into a value of the same type as `self`
+    pub fn parse_like(&self, raw: &str) -> Result<Value, String> {
+        match self {
+            Value::String(_) => Ok(Value::String(raw.to_string())),
+            Value::Bool(_) => Self::parse_bool(raw).map(Value::Bool),
+            Value::Integer(_) | Value::Float(_) => Self::parse_float(raw).map(Value::Float),
+        }
+    }
+
+    /// Compare two values of compatible types
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// One side of a comparison: a typed option reference or plain text
+#[derive(Debug, Clone)]
+enum Operand {
+    Typed { name: String, value: Value },
+    Text(String),
+}
+
+impl Operand {
+    /// Resolve `expr`, which is typed only when it is exactly `${name}` of a typed option
+    fn resolve(expr: &str, ctx: &Context) -> Operand {
+        let name = expr
+            .trim()
+            .strip_prefix("${")
+            .and_then(|rest| rest.strip_suffix('}'));
+        if let Some(value) = name.and_then(|name| ctx.get_value(name)) {
+            return Operand::Typed {
+                name: name.unwrap_or_default().to_string(),
+                value: value.clone(),
+            };
+        }
+        Operand::Text(interpolate(expr, &ctx.vars).unwrap_or_else(|_| expr.to_string()))
+    }
+}
+
+/// Check the two sides of a when comparison for equality
+///
+/// When one side is a typed option, the other side is parsed as the same type
+/// and a value that does not parse is reported as a type mismatch. Plain text
+/// is compared as-is.
+pub fn operands_equal(left: &str, right: &str, ctx: &Context) -> ExecutionResult<bool> {
+    let (a, b) = resolve_pair(left, right, ctx, false)?;
+    Ok(compare_values(&a, &b)? == Ordering::Equal)
+}
+
+/// Order the two sides of a when comparison
+///
+/// Typed options are handled as in [`operands_equal`]; plain text is ordered
+/// numerically when both sides are numbers, and as strings otherwise.
+pub fn compare_operands(left: &str, right: &str, ctx: &Context) -> ExecutionResult<Ordering> {
+    let (a, b) = resolve_pair(left, right, ctx, true)?;
+    compare_values(&a, &b)
+}
+
+fn resolve_pair(
+    left: &str,
+    right: &str,
+    ctx: &Context,
+    numeric_text: bool,
+) -> ExecutionResult<(Value, Value)> {
+    let left = Operand::resolve(left, ctx);
+    let right = Operand::resolve(right, ctx);
+
+    Ok(match (left, right) {
+        (Operand::Typed { value: a, .. }, Operand::Typed { value: b, .. }) => (a, b),
+        (Operand::Typed { name, value }, Operand::Text(text)) => {
+            let other = parse_operand(&name, &value, &text)?;
+            (value, other)
+        }
+        (Operand::Text(text), Operand::Typed { name, value }) => {
+            (parse_operand(&name, &value, &text)?, value)
+        }
+        (Operand::Text(a), Operand::Text(b)) => {
+            match (Value::parse_float(&a), Value::parse_float(&b)) {
+                (Ok(x), Ok(y)) if numeric_text => (Value::Float(x), Value::Float(y)),
+                _ => (Value::String(a), Value::String(b)),
+            }
+        }
+    })
+}
+
+fn compare_values(a: &Value, b: &Value) -> ExecutionResult<Ordering> {
+    a.compare(b).ok_or_else(|| {
+        ExecutionError::TypeMismatch(format!(
+            "cannot compare {} '{}' with {} '{}'",
+            a.type_name(),
+            a,
+            b.type_name(),
+            b
+        ))
+    })
+}
+
+fn parse_operand(name: &str, value: &Value, text: &str) -> ExecutionResult<Value> {
+    value.parse_like(text).map_err(|_| {
+        ExecutionError::TypeMismatch(format!(
+            "option '{}' is {} but is compared with '{}'",
+            name,
+            value.type_name(),
+            text
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> Context {
+        let mut ctx = Context::new();
+        ctx.set_value("count".to_string(), Value::Integer(3));
+        ctx.set_value("ratio".to_string(), Value::Float(0.5));
+        ctx.set_value("release".to_string(), Value::Bool(true));
+        ctx.set_var("name".to_string(), "web".to_string());
+        ctx
+    }
+
+    #[test]
+    fn test_numeric_comparison() {
+        let ctx = ctx();
+        assert_eq!(
+            compare_operands("${count}", "3.0", &ctx).unwrap(),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare_operands("${count}", "10", &ctx).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_operands("1", "${ratio}", &ctx).unwrap(),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_operands("10", "9", &ctx).unwrap(),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_bool_comparison() {
+        let ctx = ctx();
+        assert_eq!(
+            compare_operands("${release}", "yes", &ctx).unwrap(),
+            Ordering::Equal
+        );
+        assert_ne!(
+            compare_operands("${release}", "false", &ctx).unwrap(),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let ctx = ctx();
+        assert_eq!(
+            compare_operands("${name}", "web", &ctx).unwrap(),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let ctx = ctx();
+        let err = compare_operands("${count}", "many", &ctx).unwrap_err();
+        assert!(err.to_string().contains("option 'count' is integer"));
+        assert!(compare_operands("${release}", "${count}", &ctx).is_err());
+    }
+}
@@ -3,16 +3,91 @@
 //! This module handles evaluating conditional expressions for run items.
 
 use crate::error::{ExecutionError, ExecutionResult};
-use crate::runner::{check_command, interpolate, Context, When, WhenCondition};
+use crate::runner::{
+    check_command, compare_operands, interpolate, operands_equal, CancelToken, Context, Retry,
+    When, WhenCondition,
+};
+use crate::utils::net;
+use std::cmp::Ordering;
 use std::env;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Most command conditions of one when list that run at the same time
+const MAX_CONCURRENT_CHECKS: usize = 4;
+
+/// Longest a single network probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Evaluate a list of when conditions (all must be true - AND logic)
+///
+/// Conditions that don't spawn a process or touch the network are checked
+/// first, so a false one skips the slow checks entirely. Those then run
+/// concurrently, and no new ones start once any of them is false.
 pub fn evaluate_when_list(when_list: &[When], ctx: &Context) -> ExecutionResult<bool> {
-    for when in when_list {
+    let (commands, others): (Vec<&When>, Vec<&When>) = when_list.iter().partition(|when| {
+        matches!(
+            when.condition,
+            WhenCondition::Command(_)
+                | WhenCondition::PortOpen { .. }
+                | WhenCondition::HttpOk { .. }
+        )
+    });
+
+    for when in others {
         if !evaluate_when(when, ctx)? {
             return Ok(false);
         }
     }
+
+    // Recorded sessions must see the checks in definition order
+    if commands.len() < 2 || ctx.session.is_some() {
+        for when in commands {
+            if !evaluate_when(when, ctx)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    evaluate_concurrently(&commands, ctx)
+}
+
+/// Evaluate conditions on a bounded pool of threads, stopping early on the
+/// first false or failed condition
+fn evaluate_concurrently(when_list: &[&When], ctx: &Context) -> ExecutionResult<bool> {
+    let next = AtomicUsize::new(0);
+    let stop = CancelToken::default();
+    let results: Mutex<Vec<Option<ExecutionResult<bool>>>> =
+        Mutex::new((0..when_list.len()).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..MAX_CONCURRENT_CHECKS.min(when_list.len()) {
+            scope.spawn(|| {
+                while !stop.is_cancelled() {
+                    let index = next.fetch_add(1, atomic::Ordering::SeqCst);
+                    let Some(when) = when_list.get(index) else {
+                        break;
+                    };
+                    let result = evaluate_when(when, ctx);
+                    if !matches!(result, Ok(true)) {
+                        stop.cancel();
+                    }
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    // Report the first condition, in definition order, that didn't hold
+    let results = results.into_inner().unwrap();
+    for result in results.into_iter().flatten() {
+        if !result? {
+            return Ok(false);
+        }
+    }
     Ok(true)
 }
 
@@ -21,16 +96,16 @@ pub fn evaluate_when(when: &When, ctx: &Context) -> ExecutionResult<bool> {
     match &when.condition {
         WhenCondition::Always => Ok(true),
 
-        WhenCondition::Equal { left, right } => {
-            let left_val = interpolate(left, &ctx.vars).unwrap_or_else(|_| left.clone());
-            let right_val = interpolate(right, &ctx.vars).unwrap_or_else(|_| right.clone());
-            Ok(left_val == right_val)
+        WhenCondition::Equal { left, right } => operands_equal(left, right, ctx),
+
+        WhenCondition::NotEqual { left, right } => Ok(!operands_equal(left, right, ctx)?),
+
+        WhenCondition::GreaterThan { left, right } => {
+            Ok(compare_operands(left, right, ctx)? == Ordering::Greater)
         }
 
-        WhenCondition::NotEqual { left, right } => {
-            let left_val = interpolate(left, &ctx.vars).unwrap_or_else(|_| left.clone());
-            let right_val = interpolate(right, &ctx.vars).unwrap_or_else(|_| right.clone());
-            Ok(left_val != right_val)
+        WhenCondition::LessThan { left, right } => {
+            Ok(compare_operands(left, right, ctx)? == Ordering::Less)
         }
 
         WhenCondition::Command(cmd) => {
@@ -40,8 +115,7 @@ pub fn evaluate_when(when: &When, ctx: &Context) -> ExecutionResult<bool> {
 
         WhenCondition::Exists(path) => {
             let path_str = interpolate(path, &ctx.vars).unwrap_or_else(|_| path.clone());
-            let full_path = ctx.working_dir.join(&path_str);
-            Ok(full_path.exists())
+            Ok(ctx.resolve_path(&path_str).exists())
         }
 
         WhenCondition::EnvSet(var_name) => {
@@ -63,6 +137,59 @@ pub fn evaluate_when(when: &When, ctx: &Context) -> ExecutionResult<bool> {
             // Check if the option/variable is not set in context
             Ok(!ctx.vars.contains_key(opt_name))
         }
+
+        WhenCondition::PortOpen { address, retry } => {
+            let address = interpolate(address, &ctx.vars).unwrap_or_else(|_| address.clone());
+            Ok(retry_until(retry, ctx, || {
+                net::port_open(&address, PROBE_TIMEOUT)
+            }))
+        }
+
+        WhenCondition::HttpOk { url, retry } => {
+            let url = interpolate(url, &ctx.vars).unwrap_or_else(|_| url.clone());
+            Ok(retry_until(retry, ctx, || {
+                net::http_ok(&url, PROBE_TIMEOUT)
+            }))
+        }
+    }
+}
+
+/// Describe a condition with its variables filled in, e.g. `web == cli`,
+/// for dry runs
+pub fn describe_when(when: &When, ctx: &Context) -> String {
+    let fill = |text: &String| interpolate(text, &ctx.vars).unwrap_or_else(|_| text.clone());
+    match &when.condition {
+        WhenCondition::Always => "always".to_string(),
+        WhenCondition::Equal { left, right } => format!("{} == {}", fill(left), fill(right)),
+        WhenCondition::NotEqual { left, right } => format!("{} != {}", fill(left), fill(right)),
+        WhenCondition::GreaterThan { left, right } => format!("{} > {}", fill(left), fill(right)),
+        WhenCondition::LessThan { left, right } => format!("{} < {}", fill(left), fill(right)),
+        // Dry runs do not run the command; it counts as passing
+        WhenCondition::Command(cmd) => format!("command `{}` (not checked)", fill(cmd)),
+        WhenCondition::Exists(path) => format!("exists {}", fill(path)),
+        WhenCondition::EnvSet(var) => format!("env-set {}", fill(var)),
+        WhenCondition::EnvNotSet(var) => format!("env-not-set {}", fill(var)),
+        WhenCondition::OptionSet(name) => format!("option-set {}", name),
+        WhenCondition::OptionNotSet(name) => format!("option-not-set {}", name),
+        WhenCondition::PortOpen { address, .. } => format!("port-open {}", fill(address)),
+        WhenCondition::HttpOk { url, .. } => format!("http-ok {}", fill(url)),
+    }
+}
+
+/// Run `probe` until it passes, the retry timeout runs out, or the
+/// context's branch is cancelled
+fn retry_until(retry: &Retry, ctx: &Context, probe: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + retry.timeout;
+    loop {
+        if probe() {
+            return true;
+        }
+        let now = Instant::now();
+        let cancelled = ctx.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        if now >= deadline || cancelled {
+            return false;
+        }
+        thread::sleep(retry.interval.min(deadline - now));
     }
 }
 
@@ -72,10 +199,65 @@ pub fn failed_condition_error(reason: &str) -> ExecutionError {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
 
+    #[test]
+    fn test_describe_when() {
+        let mut vars = HashMap::new();
+        vars.insert("env".to_string(), "staging".to_string());
+        let ctx = Context::new().with_vars(vars);
+        let describe = |condition| describe_when(&When { condition }, &ctx);
+
+        assert_eq!(
+            describe(WhenCondition::Equal {
+                left: "${env}".to_string(),
+                right: "prod".to_string(),
+            }),
+            "staging == prod"
+        );
+        assert_eq!(
+            describe(WhenCondition::Command("test -d ${env}".to_string())),
+            "command `test -d staging` (not checked)"
+        );
+        assert_eq!(
+            describe(WhenCondition::OptionNotSet("force".to_string())),
+            "option-not-set force"
+        );
+    }
+
+    #[test]
+    fn test_command_conditions_run_concurrently() {
+        let when_list: Vec<When> = (0..4)
+            .map(|_| When {
+                condition: WhenCondition::Command("sleep 0.3".to_string()),
+            })
+            .collect();
+
+        let start = std::time::Instant::now();
+        assert!(evaluate_when_list(&when_list, &Context::new()).unwrap());
+        assert!(start.elapsed() < std::time::Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_false_condition_skips_commands() {
+        let executor = std::sync::Arc::new(crate::runner::MockExecutor::new());
+        let ctx = Context::new().with_executor(executor.clone());
+        let when_list = vec![
+            When {
+                condition: WhenCondition::Command("probe".to_string()),
+            },
+            When {
+                condition: WhenCondition::OptionSet("missing".to_string()),
+            },
+        ];
+
+        assert!(!evaluate_when_list(&when_list, &ctx).unwrap());
+        assert!(executor.calls().is_empty());
+    }
+
     #[test]
     fn test_evaluate_always() {
         let ctx = Context::new();
@@ -134,6 +316,20 @@ mod tests {
         assert_eq!(evaluate_when(&when, &ctx).unwrap(), true);
     }
 
+    #[test]
+    fn test_evaluate_greater_than_typed() {
+        let mut ctx = Context::new();
+        ctx.set_value("jobs".to_string(), crate::runner::Value::Integer(8));
+        let when = When {
+            condition: WhenCondition::GreaterThan {
+                left: "${jobs}".to_string(),
+                right: "4".to_string(),
+            },
+        };
+
+        assert!(evaluate_when(&when, &ctx).unwrap());
+    }
+
     #[test]
     fn test_evaluate_command_success() {
         let ctx = Context::new();
@@ -266,4 +462,40 @@ mod tests {
         // First condition is false, so overall result is false
         assert_eq!(evaluate_when_list(&when_list, &ctx).unwrap(), false);
     }
+
+    #[test]
+    fn test_port_open_retries_until_listening() {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let when = |timeout: u64| When {
+            condition: WhenCondition::PortOpen {
+                address: address.to_string(),
+                retry: Retry {
+                    timeout: Duration::from_millis(timeout),
+                    interval: Duration::from_millis(50),
+                },
+            },
+        };
+        let ctx = Context::new();
+        assert!(!evaluate_when(&when(0), &ctx).unwrap());
+
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            let listener = std::net::TcpListener::bind(address).unwrap();
+            let _ = listener.accept();
+        });
+        assert!(evaluate_when(&when(3000), &ctx).unwrap());
+        server.join().unwrap();
+
+        // A cancelled branch stops retrying
+        let token = CancelToken::default();
+        token.cancel();
+        let mut ctx = Context::new();
+        ctx.cancel = Some(token);
+        let started = Instant::now();
+        assert!(!evaluate_when(&when(3000), &ctx).unwrap());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,68 @@
+//! Color control
+//!
+//! Resolves `--color` / `RTASK_COLOR` into a global on/off switch for the
+//! `colored` crate. Status lines go to stderr, so `auto` looks at stderr.
+
+use std::env;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+/// When to use colors in rtask's own output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color when stderr is a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Accepted spellings, for help text and completion
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+
+    /// Turn colors on or off for the rest of the process
+    pub fn apply(self) {
+        let enabled = match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+            }
+        };
+        colored::control::set_override(enabled);
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" | "yes" | "on" => Ok(ColorChoice::Always),
+            "never" | "no" | "off" => Ok(ColorChoice::Never),
+            other => Err(format!(
+                "invalid color choice '{}' (expected one of: {})",
+                other,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color_choice() {
+        assert_eq!(
+            "always".parse::<ColorChoice>().unwrap(),
+            ColorChoice::Always
+        );
+        assert_eq!("NEVER".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+        assert_eq!("auto".parse::<ColorChoice>().unwrap(), ColorChoice::Auto);
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
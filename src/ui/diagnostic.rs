@@ -0,0 +1,100 @@
+//! Source diagnostics
+//!
+//! Renders an error against the config source: the offending line with the
+//! span underlined, followed by an optional hint.
+//!
+//! ```text
+//! error: Unknown key 'not_equal' in when condition of task 'build'
+//!   --> rtask.yml:12:13
+//!    |
+//! 12 |           - not_equal:
+//!    |             ^^^^^^^^^
+//!    = help: did you mean `not-equal`?
+//! ```
+
+use colored::Colorize;
+use std::fmt::Write;
+
+/// An error message tied to a position in a source file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: String,
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column
+    pub column: usize,
+    /// Number of characters to underline (at least one is drawn)
+    pub len: usize,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// Render the diagnostic against `source`
+    pub fn render(&self, source: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} {}", "error:".red().bold(), self.message);
+
+        let gutter = self.line.to_string().len();
+        let pad = " ".repeat(gutter);
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            pad,
+            "-->".blue().bold(),
+            self.file,
+            self.line,
+            self.column
+        );
+
+        if let Some(text) = source.lines().nth(self.line.saturating_sub(1)) {
+            let bar = "|".blue().bold();
+            let _ = writeln!(out, "{} {}", pad, bar);
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                self.line.to_string().blue().bold(),
+                bar,
+                text
+            );
+            let indent = " ".repeat(self.column.saturating_sub(1));
+            let underline = "^".repeat(self.len.max(1));
+            let _ = writeln!(out, "{} {} {}{}", pad, bar, indent, underline.red().bold());
+        }
+
+        if let Some(hint) = &self.hint {
+            let _ = writeln!(out, "{} {} help: {}", pad, "=".blue().bold(), hint);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::strip_ansi;
+
+    #[test]
+    fn test_render() {
+        let diagnostic = Diagnostic {
+            message: "Unknown key 'not_equal'".to_string(),
+            file: "rtask.yml".to_string(),
+            line: 3,
+            column: 9,
+            len: 9,
+            hint: Some("did you mean `not-equal`?".to_string()),
+        };
+        let source = "tasks:\n  a:\n      - not_equal: x\n";
+
+        assert_eq!(
+            strip_ansi(&diagnostic.render(source)),
+            "error: Unknown key 'not_equal'\n \
+             --> rtask.yml:3:9\n  \
+             |\n\
+             3 |       - not_equal: x\n  \
+             |         ^^^^^^^^^\n  \
+             = help: did you mean `not-equal`?\n"
+        );
+    }
+}
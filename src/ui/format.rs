@@ -0,0 +1,109 @@
+//! Output formatting helpers
+//!
+//! This module renders the lines rtask prints around command execution.
+
+/// Default template for the command echo line
+pub const DEFAULT_PRINT_FORMAT: &str = "[RUN] {command}";
+
+/// Values available to a command echo template
+#[derive(Debug, Clone, Default)]
+pub struct CommandEcho<'a> {
+    /// The command as it should be displayed
+    pub command: &'a str,
+
+    /// Working directory the command runs in
+    pub dir: &'a str,
+
+    /// Interpreter the command runs under, joined with spaces
+    pub interpreter: &'a str,
+
+    /// Name of the task the command belongs to
+    pub task: &'a str,
+}
+
+/// Render a command echo line from a template
+///
+/// Supported placeholders are `{command}`, `{dir}`, `{interpreter}` and `{task}`.
+/// Unknown placeholders are left untouched.
+///
+/// The template is scanned once, so placeholder text inside a substituted
+/// value is printed as is.
+pub fn format_command_echo(template: &str, echo: &CommandEcho) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "command" => echo.command,
+                "dir" => echo.dir,
+                "interpreter" => echo.interpreter,
+                "task" => echo.task,
+                _ => return None,
+            };
+            Some((value, end))
+        });
+
+        match value {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format() {
+        let echo = CommandEcho {
+            command: "echo hi",
+            ..Default::default()
+        };
+        assert_eq!(
+            format_command_echo(DEFAULT_PRINT_FORMAT, &echo),
+            "[RUN] echo hi"
+        );
+    }
+
+    #[test]
+    fn test_all_placeholders() {
+        let echo = CommandEcho {
+            command: "make",
+            dir: "/src",
+            interpreter: "sh -c",
+            task: "build",
+        };
+        assert_eq!(
+            format_command_echo("{task}@{dir} ({interpreter}) $ {command} {other}", &echo),
+            "build@/src (sh -c) $ make {other}"
+        );
+    }
+
+    #[test]
+    fn test_placeholders_in_values_are_kept() {
+        let echo = CommandEcho {
+            command: "echo '{task}' {dir}",
+            dir: "/tmp/{command}",
+            interpreter: "sh -c",
+            task: "build",
+        };
+        assert_eq!(
+            format_command_echo("{{task}} {dir}: {command}", &echo),
+            "{build} /tmp/{command}: echo '{task}' {dir}"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Heartbeat output
+//!
+//! Periodically prints a "still running" line for long commands so CI systems
+//! that kill jobs after a period of silence keep seeing activity.
+
+use crate::ui::{format_elapsed, message};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A running heartbeat; stops when dropped
+pub struct Heartbeat {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    /// Start printing a heartbeat line for `label` every `interval`
+    pub fn start(label: &str, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let label = label.to_string();
+        let handle = thread::spawn(move || {
+            let started = Instant::now();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let elapsed = format_elapsed(started.elapsed());
+                eprintln!(
+                    "[INFO] {}",
+                    message(
+                        "heartbeat.still-running",
+                        &[("elapsed", &elapsed), ("command", &label)]
+                    )
+                );
+            }
+        });
+
+        Heartbeat {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread immediately
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_stops_promptly() {
+        let started = Instant::now();
+        let heartbeat = Heartbeat::start("sleep", Duration::from_secs(60));
+        drop(heartbeat);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
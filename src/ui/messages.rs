@@ -0,0 +1,246 @@
+//! Message catalog
+//!
+//! User-facing messages are looked up by a stable ID (e.g. `task.running`) in
+//! a catalog of templates with `{name}` placeholders. The catalogs ship in
+//! `src/ui/messages/<locale>.yml` and are built into the binary; English is
+//! complete and every other catalog falls back to it for missing messages.
+//!
+//! The locale comes from `RTASK_LANG`, then the usual `LC_ALL`,
+//! `LC_MESSAGES` and `LANG`: the first one set decides, so `RTASK_LANG=en`
+//! keeps rtask in English on an otherwise localized system.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Built-in catalogs by locale; the first one is the fallback
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("messages/en.yml")),
+    ("zh", include_str!("messages/zh.yml")),
+];
+
+/// Environment variables that select the locale, most specific first
+pub const LOCALE_VARS: [&str; 4] = ["RTASK_LANG", "LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// Message templates of one locale
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    locale: &'static str,
+    templates: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// The catalog of `locale`, which must be one of [`locales`]; anything
+    /// else gives the English catalog
+    pub fn for_locale(locale: &str) -> Self {
+        let (fallback, source) = CATALOGS[0];
+        let mut catalog = Catalog {
+            locale: fallback,
+            templates: parse_catalog(source),
+        };
+        if let Some((name, source)) = CATALOGS[1..].iter().find(|(name, _)| *name == locale) {
+            catalog.locale = name;
+            catalog.templates.extend(parse_catalog(source));
+        }
+        catalog
+    }
+
+    /// Locale of the catalog
+    pub fn locale(&self) -> &'static str {
+        self.locale
+    }
+
+    /// Render message `id` with `args`; an unknown ID renders as itself
+    pub fn format(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        match self.templates.get(id) {
+            Some(template) => fill(template, args),
+            None => id.to_string(),
+        }
+    }
+}
+
+/// Locales with a built-in catalog
+pub fn locales() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(name, _)| *name)
+}
+
+/// The catalog of the environment's locale
+pub fn catalog() -> &'static Catalog {
+    static CATALOG: OnceLock<Catalog> = OnceLock::new();
+    CATALOG.get_or_init(|| Catalog::for_locale(detect_locale(|name| env::var(name).ok())))
+}
+
+/// Render message `id` in the locale of the environment
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    catalog().format(id, args)
+}
+
+/// Pick the catalog for the first non-empty variable of [`LOCALE_VARS`]
+/// found through `lookup`
+///
+/// Values look like `zh_CN.UTF-8` or `pt-BR`: the full tag is tried before
+/// the language alone, and locales without a catalog (including `C` and
+/// `POSIX`) give English.
+pub fn detect_locale(lookup: impl Fn(&str) -> Option<String>) -> &'static str {
+    let Some(value) = LOCALE_VARS
+        .iter()
+        .filter_map(|name| lookup(name))
+        .find(|value| !value.trim().is_empty())
+    else {
+        return CATALOGS[0].0;
+    };
+    let tag = value
+        .trim()
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('-', "_");
+    let language = tag.split('_').next().unwrap_or_default();
+    let locale = [tag.as_str(), language]
+        .into_iter()
+        .find_map(|wanted| locales().find(|locale| locale.eq_ignore_ascii_case(wanted)));
+    locale.unwrap_or(CATALOGS[0].0)
+}
+
+/// A value that renders as a catalog message
+pub trait CatalogMessage {
+    /// Message ID and placeholder values; values that are messages
+    /// themselves are rendered with `catalog`
+    fn message_parts(&self, catalog: &Catalog) -> (&'static str, Vec<(&'static str, String)>);
+
+    /// Stable ID of the message, for tools matching on messages
+    fn message_id(&self) -> &'static str {
+        self.message_parts(catalog()).0
+    }
+
+    /// The message rendered with `catalog`
+    fn localized_in(&self, catalog: &Catalog) -> String {
+        let (id, args) = self.message_parts(catalog);
+        let args: Vec<(&str, &dyn Display)> = args
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn Display))
+            .collect();
+        catalog.format(id, &args)
+    }
+
+    /// The message in the locale of the environment
+    fn localized(&self) -> String {
+        self.localized_in(catalog())
+    }
+}
+
+/// Parse a catalog file of `id: template` lines
+fn parse_catalog(source: &str) -> HashMap<String, String> {
+    serde_yaml::from_str(source).expect("built-in message catalogs are valid YAML")
+}
+
+/// Replace the `{name}` placeholders of `template`; unknown ones stay as is
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let (_, value) = args.iter().find(|(name, _)| *name == &after[..end])?;
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                out.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Placeholder names used by a template
+    fn placeholders(template: &str) -> BTreeSet<&str> {
+        template
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn test_format_fills_placeholders() {
+        let catalog = Catalog::for_locale("en");
+        assert_eq!(
+            catalog.format(
+                "task.skipped",
+                &[("task", &"lint"), ("reason", &"disabled")]
+            ),
+            "Skipping task 'lint': disabled"
+        );
+        assert_eq!(
+            catalog.format("group.up", &[("group", &"dev")]),
+            "Group 'dev' is up; stop it with `rtask down dev`"
+        );
+        assert_eq!(catalog.format("no.such-message", &[]), "no.such-message");
+        assert_eq!(fill("{a} {b} {", &[("a", &1)]), "1 {b} {");
+    }
+
+    #[test]
+    fn test_locale_falls_back_to_english() {
+        let catalog = Catalog::for_locale("zh");
+        assert_eq!(catalog.locale(), "zh");
+        assert_eq!(
+            catalog.format("task.finished", &[("task", &"a")]),
+            "任务完成：a"
+        );
+        assert_eq!(Catalog::for_locale("xx").locale(), "en");
+    }
+
+    #[test]
+    fn test_detect_locale() {
+        let detect = |vars: &[(&str, &str)]| {
+            detect_locale(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+        assert_eq!(detect(&[]), "en");
+        assert_eq!(detect(&[("LANG", "zh_CN.UTF-8")]), "zh");
+        assert_eq!(detect(&[("LANG", "zh-TW")]), "zh");
+        assert_eq!(detect(&[("LANG", "C")]), "en");
+        assert_eq!(
+            detect(&[("LANG", "zh_CN.UTF-8"), ("LC_ALL", "de_DE")]),
+            "en"
+        );
+        assert_eq!(detect(&[("LC_ALL", "zh_CN"), ("RTASK_LANG", "en")]), "en");
+        assert_eq!(detect(&[("RTASK_LANG", " "), ("LANG", "zh")]), "zh");
+    }
+
+    #[test]
+    fn test_catalogs_match_english() {
+        let english = parse_catalog(CATALOGS[0].1);
+        for (locale, source) in &CATALOGS[1..] {
+            for (id, template) in parse_catalog(source) {
+                let Some(original) = english.get(&id) else {
+                    panic!("{} message '{}' is not in the English catalog", locale, id);
+                };
+                assert_eq!(
+                    placeholders(&template),
+                    placeholders(original),
+                    "{} message '{}' has different placeholders",
+                    locale,
+                    id
+                );
+            }
+        }
+    }
+}
@@ -3,8 +3,20 @@
 //! This module handles terminal output, logging at different verbosity levels,
 //! and colored formatting.
 
+pub mod color;
+pub mod diagnostic;
+pub mod format;
+pub mod heartbeat;
+pub mod messages;
+pub mod spinner;
+
 // Module declarations (to be implemented in later phases)
 // pub mod logger;
-// pub mod format;
 
-// Placeholder for Phase 1
+// Re-export main types
+pub use color::*;
+pub use diagnostic::*;
+pub use format::*;
+pub use heartbeat::*;
+pub use messages::*;
+pub use spinner::*;
@@ -0,0 +1,103 @@
+//! Terminal spinner
+//!
+//! Shows an animated indicator with elapsed time while a command runs without
+//! visible output, so long captures don't look like a hang.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Animation frames
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Delay between frames
+const TICK: Duration = Duration::from_millis(100);
+
+/// A running spinner; stops and clears its line when dropped
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    /// Start a spinner on stderr, or return `None` when stderr is not a terminal
+    pub fn start(label: &str) -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let label = label.to_string();
+        let handle = thread::spawn(move || {
+            let started = Instant::now();
+            let mut frame = 0;
+            while !flag.load(Ordering::Relaxed) {
+                let mut stderr = io::stderr().lock();
+                let _ = write!(
+                    stderr,
+                    "\r\x1b[2K{} {} ({})",
+                    FRAMES[frame % FRAMES.len()],
+                    label,
+                    format_elapsed(started.elapsed())
+                );
+                let _ = stderr.flush();
+                drop(stderr);
+
+                frame += 1;
+                thread::sleep(TICK);
+            }
+            let _ = write!(io::stderr(), "\r\x1b[2K");
+            let _ = io::stderr().flush();
+        });
+
+        Some(Spinner {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stop the spinner and clear its line
+    pub fn stop(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Format an elapsed duration compactly (e.g. `4.2s`, `2m30s`, `1h05m`)
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(format_elapsed(Duration::from_secs(150)), "2m30s");
+        assert_eq!(format_elapsed(Duration::from_secs(3900)), "1h05m");
+    }
+}
@@ -0,0 +1,103 @@
+//! Duration parsing
+//!
+//! Parses human-friendly durations such as `500ms`, `30s`, `5m` or `1h30m`.
+
+use std::time::Duration;
+
+/// Parse a duration string
+///
+/// Accepts one or more `<number><unit>` groups where unit is `ms`, `s`, `m`, `h` or `d`
+/// (e.g. `1h30m`, `2.5s`). A bare number is interpreted as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let s = input.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+
+    if let Ok(secs) = s.parse::<f64>() {
+        return seconds(secs, input);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(|| format!("missing unit in duration '{}'", input))?;
+        if num_len == 0 {
+            return Err(format!("invalid duration '{}'", input));
+        }
+        let value: f64 = rest[..num_len]
+            .parse()
+            .map_err(|_| format!("invalid number in duration '{}'", input))?;
+        rest = &rest[num_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let multiplier = match &rest[..unit_len] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86_400.0,
+            unit => return Err(format!("unknown unit '{}' in duration '{}'", unit, input)),
+        };
+        rest = &rest[unit_len..];
+
+        total += seconds(value * multiplier, input)?;
+    }
+
+    Ok(total)
+}
+
+/// Format a duration in canonical form: whole seconds (`90s`) when exact,
+/// milliseconds (`1500ms`) otherwise
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+/// Convert a non-negative number of seconds to a duration
+fn seconds(secs: f64, input: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("invalid duration '{}'", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86_400));
+    }
+
+    #[test]
+    fn test_parse_compound_and_bare() {
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("2.5s").unwrap(), Duration::from_millis(2500));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(parse_duration("1m30s").unwrap()), "90s");
+        assert_eq!(format_duration(parse_duration("1.5s").unwrap()), "1500ms");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("m5").is_err());
+        assert!(parse_duration("-1").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! File system access
+//!
+//! Config discovery and include loading go through the [`FileSystem`] trait
+//! rather than `std::fs`, so they can run against an in-memory [`MemoryFs`] in
+//! tests and against other sources later. [`OsFs`] is the real file system.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The file operations rtask needs
+pub trait FileSystem: Send + Sync {
+    /// Read a whole file as UTF-8
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Write a file, replacing its contents
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+
+    /// Whether `path` exists and is a regular file
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Last modification time of a file
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+}
+
+/// The operating system's file system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFs;
+
+impl FileSystem for OsFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        fs::metadata(path)?.modified()
+    }
+}
+
+/// An in-memory file system holding files only (directories are implied)
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    files: Mutex<HashMap<PathBuf, (String, SystemTime)>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, modified now
+    pub fn with_file(self, path: impl Into<PathBuf>, contents: &str) -> Self {
+        // Writing to memory cannot fail
+        let _ = self.write(&path.into(), contents);
+        self
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", path.display()),
+        )
+    }
+
+    fn lock(
+        &self,
+    ) -> io::Result<std::sync::MutexGuard<'_, HashMap<PathBuf, (String, SystemTime)>>> {
+        self.files
+            .lock()
+            .map_err(|_| io::Error::other("file system lock poisoned"))
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.lock()?
+            .get(path)
+            .map(|(contents, _)| contents.clone())
+            .ok_or_else(|| Self::not_found(path))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.lock()?.insert(
+            path.to_path_buf(),
+            (contents.to_string(), SystemTime::now()),
+        );
+        Ok(())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.lock().is_ok_and(|files| files.contains_key(path))
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        self.lock()?
+            .get(path)
+            .map(|(_, modified)| *modified)
+            .ok_or_else(|| Self::not_found(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_fs() {
+        let fs = MemoryFs::new().with_file("/work/rtask.yml", "tasks: {}");
+        assert!(fs.is_file(Path::new("/work/rtask.yml")));
+        assert!(!fs.is_file(Path::new("/work")));
+        assert_eq!(
+            fs.read_to_string(Path::new("/work/rtask.yml")).unwrap(),
+            "tasks: {}"
+        );
+        assert_eq!(
+            fs.read_to_string(Path::new("/other.yml"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let before = fs.modified(Path::new("/work/rtask.yml")).unwrap();
+        fs.write(Path::new("/work/rtask.yml"), "tasks: {a: {}}")
+            .unwrap();
+        assert!(fs.modified(Path::new("/work/rtask.yml")).unwrap() >= before);
+    }
+}
@@ -3,8 +3,15 @@
 //! This module contains utility functions for file system operations,
 //! XDG directory handling, and other common operations.
 
+pub mod duration;
+pub mod fs;
+pub mod net;
+pub mod shell;
+pub mod suggest;
+pub mod time;
+pub mod url;
+pub mod watch;
+pub mod which;
+
 // Module declarations (to be implemented in later phases)
 // pub mod xdg;
-// pub mod fs;
-
-// Placeholder for Phase 1
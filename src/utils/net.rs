@@ -0,0 +1,163 @@
+//! Network readiness probes
+//!
+//! Just enough networking to tell whether something is listening: a TCP
+//! connect, and an HTTP/1.1 GET over plain TCP for `http://` URLs. HTTPS would
+//! need a TLS stack, which rtask does not pull in.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// An `http://` URL split into what a request needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpTarget {
+    /// `host:port` to connect to
+    pub address: String,
+    /// Value of the `Host` header
+    pub host: String,
+    /// Path and query, starting with `/`
+    pub path: String,
+}
+
+/// Split an `http://host[:port][/path]` URL
+pub fn parse_http_url(url: &str) -> Result<HttpTarget, String> {
+    let url = url.trim();
+    let rest = url
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+        .map(|_| &url[7..])
+        .ok_or_else(|| format!("'{}' is not an http:// URL", url))?;
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(at) => (&rest[..at], &rest[at..]),
+        None => (rest, "/"),
+    };
+    let path = if path.starts_with('?') {
+        format!("/{}", path)
+    } else {
+        path.to_string()
+    };
+
+    // The port follows the last colon, unless that colon is inside an IPv6 address
+    let has_port = authority
+        .rfind(':')
+        .is_some_and(|colon| !authority[colon..].contains(']'));
+    let address = if has_port {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let valid = !authority.is_empty()
+        && !authority.contains(char::is_whitespace)
+        && address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+    if !valid {
+        return Err(format!("'{}' is not a valid URL", url));
+    }
+
+    Ok(HttpTarget {
+        address,
+        host: authority.to_string(),
+        path,
+    })
+}
+
+/// Whether something at `address` (`host:port`) accepts TCP connections
+pub fn port_open(address: &str, timeout: Duration) -> bool {
+    connect(address, timeout).is_some()
+}
+
+/// Whether a GET of `url` answers with a 2xx status
+pub fn http_ok(url: &str, timeout: Duration) -> bool {
+    let Ok(target) = parse_http_url(url) else {
+        return false;
+    };
+    let Some(mut stream) = connect(&target.address, timeout) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(timeout));
+    let _ = stream.set_write_timeout(Some(timeout));
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rtask\r\nConnection: close\r\n\r\n",
+        target.path, target.host
+    );
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+    let mut status_line = String::new();
+    if BufReader::new(stream).read_line(&mut status_line).is_err() {
+        return false;
+    }
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .is_some_and(|status| (200..300).contains(&status))
+}
+
+/// Connect to the first of the addresses `address` resolves to that accepts
+fn connect(address: &str, timeout: Duration) -> Option<TcpStream> {
+    address
+        .to_socket_addrs()
+        .ok()?
+        .find_map(|addr| TcpStream::connect_timeout(&addr, timeout).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse_http_url() {
+        let target = parse_http_url("http://localhost:8080/health?full=1").unwrap();
+        assert_eq!(target.address, "localhost:8080");
+        assert_eq!(target.host, "localhost:8080");
+        assert_eq!(target.path, "/health?full=1");
+
+        let target = parse_http_url("HTTP://example.com").unwrap();
+        assert_eq!(target.address, "example.com:80");
+        assert_eq!(target.path, "/");
+
+        assert_eq!(
+            parse_http_url("http://[::1]:9000/").unwrap().address,
+            "[::1]:9000"
+        );
+        assert!(parse_http_url("https://example.com").is_err());
+        assert!(parse_http_url("http://").is_err());
+        assert!(parse_http_url("http://host:port/").is_err());
+    }
+
+    /// Serve one request with the given status line
+    fn serve_once(status: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer);
+            let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+        });
+        format!("http://{}/health", address)
+    }
+
+    #[test]
+    fn test_http_ok_checks_status() {
+        let timeout = Duration::from_secs(2);
+        assert!(http_ok(&serve_once("200 OK"), timeout));
+        assert!(!http_ok(&serve_once("503 Service Unavailable"), timeout));
+    }
+
+    #[test]
+    fn test_port_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(port_open(&address, Duration::from_secs(1)));
+        drop(listener);
+        assert!(!port_open(&address, Duration::from_secs(1)));
+    }
+}
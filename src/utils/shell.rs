@@ -0,0 +1,128 @@
+//! Shell word quoting and splitting
+
+use std::path::Path;
+
+/// POSIX-style shells, which take flags such as `-eu -o pipefail`
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh", "mksh", "ash", "yash"];
+
+/// Quote a word so a POSIX shell reads it back unchanged
+pub fn quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Join words into a single shell command line
+pub fn join<S: AsRef<str>>(words: &[S]) -> String {
+    words
+        .iter()
+        .map(|word| quote(word.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a command line into words the way a POSIX shell would, honoring
+/// quotes and backslash escapes but not expanding anything
+pub fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Whether `program`, a name or a path, is a POSIX-style shell
+pub fn is_posix_shell(program: &str) -> bool {
+    Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| POSIX_SHELLS.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("ls"), "ls");
+        assert_eq!(quote("--flag=a/b.txt"), "--flag=a/b.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("two words"), "'two words'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join(&["echo", "a b", "c"]), "echo 'a b' c");
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("  ls -la  /tmp ").unwrap(), vec!["ls", "-la", "/tmp"]);
+        assert_eq!(
+            split(r#"echo 'a b' "c \"d\" $e" f\ g ''"#).unwrap(),
+            vec!["echo", "a b", "c \"d\" $e", "f g", ""]
+        );
+        assert!(split("echo 'open").is_err());
+        assert!(split("echo \"open").is_err());
+    }
+
+    #[test]
+    fn test_split_round_trips_join() {
+        let words = ["grep", "-e", "it's a $var", "", "a\"b"];
+        assert_eq!(split(&join(&words)).unwrap(), words);
+    }
+
+    #[test]
+    fn test_is_posix_shell() {
+        assert!(is_posix_shell("sh"));
+        assert!(is_posix_shell("/usr/local/bin/bash"));
+        assert!(!is_posix_shell("python3"));
+        assert!(!is_posix_shell("pwsh"));
+    }
+}
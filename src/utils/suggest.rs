@@ -0,0 +1,28 @@
+//! "Did you mean" suggestions
+
+/// Find the candidate closest to `input`, if any is close enough to be a
+/// plausible typo
+pub fn closest<'a, I>(input: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(input, candidate), candidate))
+        .filter(|(score, _)| *score >= 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest() {
+        let keys = ["equal", "not-equal", "command", "exists"];
+        assert_eq!(closest("not_equal", keys), Some("not-equal"));
+        assert_eq!(closest("comand", keys), Some("command"));
+        assert_eq!(closest("zzz", keys), None);
+    }
+}
@@ -0,0 +1,63 @@
+//! URL validation
+//!
+//! A deliberately small check for `scheme://host[:port][/path]` URLs, enough to
+//! catch typos in options without pulling in a full URL parser.
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^([A-Za-z][A-Za-z0-9+.-]*)://([^\s/?#@]+@)?([^\s/?#:]+)(:\d+)?([/?#]\S*)?$")
+            .expect("valid URL regex")
+    })
+}
+
+/// Validate and normalize a URL
+///
+/// The scheme and host are lowercased and default ports for http(s) are dropped.
+pub fn normalize_url(input: &str) -> Result<String, String> {
+    let caps = url_regex()
+        .captures(input.trim())
+        .ok_or_else(|| format!("'{}' is not a valid URL", input))?;
+
+    let scheme = caps[1].to_ascii_lowercase();
+    let userinfo = caps.get(2).map_or("", |m| m.as_str());
+    let host = caps[3].to_ascii_lowercase();
+    let port = match (scheme.as_str(), caps.get(4).map(|m| m.as_str())) {
+        ("http", Some(":80")) | ("https", Some(":443")) => "",
+        (_, port) => port.unwrap_or(""),
+    };
+    let rest = caps.get(5).map_or("", |m| m.as_str());
+
+    Ok(format!("{}://{}{}{}{}", scheme, userinfo, host, port, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("HTTPS://Example.COM:443/Path?q=1").unwrap(),
+            "https://example.com/Path?q=1"
+        );
+        assert_eq!(
+            normalize_url("postgres://user:pw@db:5432/app").unwrap(),
+            "postgres://user:pw@db:5432/app"
+        );
+        assert_eq!(
+            normalize_url("http://localhost:80").unwrap(),
+            "http://localhost"
+        );
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        assert!(normalize_url("example.com").is_err());
+        assert!(normalize_url("http://").is_err());
+        assert!(normalize_url("http://exa mple.com").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! File watching by polling
+//!
+//! A [`FileWatcher`] remembers the size and modification time of every file
+//! matching its patterns and reports the files that were added, changed or
+//! removed since the previous scan. Polling keeps rtask free of platform
+//! notification APIs, at the cost of walking the watched directories on each
+//! scan; only the directories the patterns can match are walked.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directories never walked
+const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// What a file looked like at the last scan
+type Stamp = (Option<SystemTime>, u64);
+
+/// Reports changes to the files under a root that match a set of glob patterns
+#[derive(Debug)]
+pub struct FileWatcher {
+    root: PathBuf,
+    include: GlobSet,
+    exclude: GlobSet,
+    /// Directories skipped entirely: excluded ones, and `dir` for each `dir/**`
+    prune: GlobSet,
+    /// Directories (relative to the root) the include patterns can match under
+    bases: Vec<PathBuf>,
+    files: HashMap<PathBuf, Stamp>,
+}
+
+impl FileWatcher {
+    /// Watch files under `root` matching `paths` but none of `exclude`
+    ///
+    /// Patterns are relative to `root`. The files present now are the
+    /// baseline for the first [`FileWatcher::changes`].
+    pub fn new(root: &Path, paths: &[String], exclude: &[String]) -> Result<Self, String> {
+        let prune_patterns: Vec<String> = exclude
+            .iter()
+            .flat_map(|pattern| {
+                let dir = pattern.strip_suffix("/**").map(str::to_string);
+                std::iter::once(pattern.clone()).chain(dir)
+            })
+            .collect();
+
+        let mut watcher = FileWatcher {
+            root: root.to_path_buf(),
+            include: glob_set(paths)?,
+            exclude: glob_set(exclude)?,
+            prune: glob_set(&prune_patterns)?,
+            bases: paths
+                .iter()
+                .map(|pattern| literal_prefix(pattern))
+                .collect(),
+            files: HashMap::new(),
+        };
+        watcher.files = watcher.scan();
+        Ok(watcher)
+    }
+
+    /// Files added, changed or removed since the last call, relative to the
+    /// root and sorted
+    pub fn changes(&mut self) -> Vec<PathBuf> {
+        let files = self.scan();
+        let mut changed: Vec<PathBuf> = files
+            .iter()
+            .filter(|(path, stamp)| self.files.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .chain(
+                self.files
+                    .keys()
+                    .filter(|path| !files.contains_key(*path))
+                    .cloned(),
+            )
+            .collect();
+        changed.sort();
+        self.files = files;
+        changed
+    }
+
+    /// Stamp every watched file
+    fn scan(&self) -> HashMap<PathBuf, Stamp> {
+        let mut files = HashMap::new();
+        for base in &self.bases {
+            self.walk(base, &mut files);
+        }
+        files
+    }
+
+    /// Collect the watched files at or below `rel`
+    fn walk(&self, rel: &Path, files: &mut HashMap<PathBuf, Stamp>) {
+        let path = self.root.join(rel);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return;
+        };
+
+        if metadata.is_dir() {
+            let skipped = rel
+                .file_name()
+                .is_some_and(|name| SKIPPED_DIRS.iter().any(|dir| name == *dir));
+            if skipped || (!rel.as_os_str().is_empty() && self.prune.is_match(rel)) {
+                return;
+            }
+            let Ok(entries) = fs::read_dir(&path) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                self.walk(&rel.join(entry.file_name()), files);
+            }
+        } else if self.include.is_match(rel) && !self.exclude.is_match(rel) {
+            // Follow symlinked files, so a change to the target is noticed
+            let metadata = fs::metadata(&path).unwrap_or(metadata);
+            files.insert(
+                rel.to_path_buf(),
+                (metadata.modified().ok(), metadata.len()),
+            );
+        }
+    }
+}
+
+/// Exclude patterns for the rules of a `.gitignore` file at the watch root
+///
+/// Covers the common forms: comments, anchored (`/build`, `docs/out`) and
+/// unanchored (`*.log`) rules, and directory rules (`target/`). Negations
+/// (`!keep.log`) cannot be expressed as exclusions and are left out.
+pub fn gitignore_patterns(contents: &str) -> Vec<String> {
+    let mut patterns = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        let line = line.strip_suffix('/').unwrap_or(line);
+        // A slash anywhere but at the end ties the rule to the root
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        if line.is_empty() {
+            continue;
+        }
+        let pattern = if anchored {
+            line.to_string()
+        } else {
+            format!("**/{}", line)
+        };
+        patterns.push(format!("{}/**", pattern));
+        patterns.push(pattern);
+    }
+    patterns
+}
+
+/// Compile glob patterns into one set
+fn glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Leading path components of a pattern that contain no glob syntax
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| {
+            matches!(component, Component::Normal(part)
+                if !part.to_string_lossy().contains(['*', '?', '[', '{']))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix("src/**/*.rs"), PathBuf::from("src"));
+        assert_eq!(literal_prefix("Cargo.toml"), PathBuf::from("Cargo.toml"));
+        assert_eq!(literal_prefix("**/*.md"), PathBuf::new());
+    }
+
+    #[test]
+    fn test_changes_follow_patterns() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "src/main.rs", "fn main() {}");
+        write(dir.path(), "src/gen/out.rs", "");
+        let mut watcher = FileWatcher::new(
+            dir.path(),
+            &["src/**".to_string(), "Cargo.toml".to_string()],
+            &["src/gen/**".to_string()],
+        )
+        .unwrap();
+        assert!(watcher.changes().is_empty());
+
+        write(dir.path(), "src/main.rs", "fn main() { run() }");
+        write(dir.path(), "src/lib.rs", "");
+        write(dir.path(), "src/gen/out.rs", "changed");
+        write(dir.path(), "README.md", "ignored");
+        assert_eq!(
+            watcher.changes(),
+            [PathBuf::from("src/lib.rs"), PathBuf::from("src/main.rs")]
+        );
+        assert!(watcher.changes().is_empty());
+
+        write(dir.path(), "Cargo.toml", "[package]");
+        fs::remove_file(dir.path().join("src/lib.rs")).unwrap();
+        assert_eq!(
+            watcher.changes(),
+            [PathBuf::from("Cargo.toml"), PathBuf::from("src/lib.rs")]
+        );
+    }
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let patterns =
+            gitignore_patterns("# build output\n/target/\n*.log\ndocs/out\n!keep.log\n\n");
+        assert_eq!(
+            patterns,
+            [
+                "target/**",
+                "target",
+                "**/*.log/**",
+                "**/*.log",
+                "docs/out/**",
+                "docs/out"
+            ]
+        );
+
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "src/main.rs", "");
+        let mut watcher = FileWatcher::new(dir.path(), &["**".to_string()], &patterns).unwrap();
+        write(dir.path(), "target/debug/app", "changed");
+        write(dir.path(), "src/debug.log", "changed");
+        write(dir.path(), "src/main.rs", "changed");
+        assert_eq!(watcher.changes(), [PathBuf::from("src/main.rs")]);
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let dir = TempDir::new().unwrap();
+        assert!(FileWatcher::new(dir.path(), &["src/[".to_string()], &[]).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Locating programs on the PATH
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Find the executable that `program` names, the way a shell would
+///
+/// Names containing a path separator are checked as paths; bare names are
+/// looked up in each `PATH` directory (trying `PATHEXT` extensions on Windows).
+pub fn which(program: &str) -> Option<PathBuf> {
+    if program.contains(std::path::is_separator) {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .flat_map(|dir| candidates(&dir, program))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(windows)]
+fn candidates(dir: &Path, program: &str) -> Vec<PathBuf> {
+    let exts = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    std::iter::once(dir.join(program))
+        .chain(
+            exts.split(';')
+                .map(|ext| dir.join(format!("{}{}", program, ext))),
+        )
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn candidates(dir: &Path, program: &str) -> Vec<PathBuf> {
+    vec![dir.join(program)]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_which() {
+        assert!(which("sh").is_some());
+        assert_eq!(which("/bin/sh"), Some(PathBuf::from("/bin/sh")));
+        assert!(which("rtask-no-such-program").is_none());
+        assert!(which("./rtask-no-such-program").is_none());
+    }
+}
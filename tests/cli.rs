@@ -0,0 +1,2008 @@
+//! Integration tests for the rtask binary
+
+mod common;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use common::create_test_config;
+use predicates::prelude::*;
+
+#[test]
+fn test_failing_command_exit_code_is_propagated() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  fail:
+    run: exit 3
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("fail")
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_unknown_task_is_a_usage_error() {
+    let (dir, _) = create_test_config("tasks:\n  ok:\n    run: echo ok\n");
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("missing")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_config_error_exits_with_one() {
+    let (dir, _) = create_test_config("tasks:\n  ok:\n    run: true\n");
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("ok")
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_multiple_tasks_stop_at_first_failure() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  fail:
+    run: exit 4
+  after:
+    run: echo after-ran
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["fail", "after"])
+        .assert()
+        .code(4)
+        .stdout(predicates::str::contains("after-ran").not());
+}
+
+#[test]
+fn test_keep_going_runs_remaining_tasks() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  fail:
+    run: exit 4
+  after:
+    run: echo after-ran
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--keep-going", "fail", "after"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("after-ran"))
+        .stderr(predicates::str::contains("1 of 2 tasks failed: fail"));
+}
+
+#[test]
+fn test_keep_going_runs_independent_deps() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  lint:
+    run: exit 4
+  test:
+    run: echo test-ran
+  ci:
+    deps: [lint, test]
+    run: echo uploaded
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--keep-going", "ci"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("test-ran"))
+        .stdout(predicates::str::contains("uploaded").not())
+        .stderr(predicates::str::contains("Task 'lint' failed"))
+        .stderr(predicates::str::contains("1 of 2 tasks failed: lint"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("ci")
+        .assert()
+        .code(4)
+        .stdout(predicates::str::contains("test-ran").not());
+}
+
+#[test]
+fn test_messages_follow_locale() {
+    let (dir, _) = create_test_config("tasks:\n  fail:\n    run: exit 4\n");
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .env("RTASK_LANG", "zh_CN.UTF-8")
+        .arg("fail")
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains("正在运行任务：fail"))
+        .stderr(predicates::str::contains("命令失败"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .env("RTASK_LANG", "zh")
+        .args(["--error-format", "json", "fail"])
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains(
+            r#""id":"execution.command-failed""#,
+        ));
+}
+
+#[test]
+fn test_stats_reports_recorded_runs() {
+    let (dir, _) =
+        create_test_config("tasks:\n  ok:\n    run: echo ok\n  fail:\n    run: exit 1\n");
+
+    for task in ["ok", "ok", "fail"] {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .arg(task)
+            .assert();
+    }
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["stats", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"task\": \"fail\""))
+        .stdout(predicate::str::contains("\"runs\": 2"))
+        .stdout(predicate::str::contains("\"failure_rate\": 1.0"));
+}
+
+#[test]
+fn test_graph_json() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    source: ["src/*.c"]
+    target: ["app"]
+    run: cc -o app src/*.c
+  ci:
+    options:
+      fast:
+        type: bool
+    run:
+      - task: build
+"#,
+    );
+
+    let output = cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["graph", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let graph: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(graph["version"], 1);
+    assert_eq!(graph["nodes"][0]["name"], "build");
+    assert_eq!(graph["nodes"][0]["cache"]["state"], "stale");
+    assert_eq!(graph["nodes"][1]["options"][0]["type"], "bool");
+    assert_eq!(
+        graph["edges"],
+        serde_json::json!([{"from": "ci", "to": "build", "kind": "subtask"}])
+    );
+}
+
+#[test]
+fn test_trace_out_writes_chrome_trace() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    run: echo built
+  ci:
+    deps: build
+    run:
+      - exit 3
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--trace-out", "out/trace.json", "ci"])
+        .assert()
+        .code(3);
+
+    let trace = std::fs::read_to_string(dir.path().join("out/trace.json")).unwrap();
+    let trace: serde_json::Value = serde_json::from_str(&trace).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let spans: Vec<(&str, &str)> = events
+        .iter()
+        .map(|e| (e["cat"].as_str().unwrap(), e["name"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            ("command", "echo built"),
+            ("run", "echo built"),
+            ("task", "build"),
+            ("command", "exit 3"),
+            ("run", "exit 3"),
+            ("task", "ci"),
+        ]
+    );
+    assert!(events.iter().all(|e| e["ph"] == "X" && e["dur"].is_u64()));
+    assert_eq!(
+        events[5]["args"]["error"],
+        "Command failed with exit code Some(3)"
+    );
+}
+
+#[test]
+fn test_editing_task_makes_targets_stale() {
+    let config = |command: &str| {
+        format!(
+            "tasks:\n  build:\n    source: [\"main.c\"]\n    target: [app]\n    run: {}\n",
+            command
+        )
+    };
+    let (dir, config_path) = create_test_config(&config("cp main.c app"));
+    std::fs::write(dir.path().join("main.c"), "int main;").unwrap();
+    let state = || {
+        let output = cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .args(["graph", "--format", "json"])
+            .output()
+            .unwrap();
+        let graph: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        graph["nodes"][0]["cache"]["state"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(state(), "stale");
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("build")
+        .assert()
+        .success();
+    assert_eq!(state(), "fresh");
+
+    std::fs::write(&config_path, config("cp -p main.c app")).unwrap();
+    assert_eq!(state(), "stale");
+}
+
+#[test]
+fn test_config_tests_are_run() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  greet:
+    run: echo hello
+tests:
+  greets:
+    task: greet
+    expect:
+      stdout-contains: hello
+  wrong-greeting:
+    task: greet
+    expect:
+      stdout-contains: goodbye
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["test", "greets"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("test greets ... ok"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("test")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("test wrong-greeting ... FAILED"))
+        .stdout(predicate::str::contains(
+            "stdout does not contain \"goodbye\"",
+        ));
+}
+
+#[test]
+fn test_replay_stubs_recorded_commands() {
+    let (dir, _) = create_test_config("tasks:\n  make:\n    run: touch made.txt && exit 5\n");
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--record", "session.jsonl", "make"])
+        .assert()
+        .code(5);
+    std::fs::remove_file(dir.path().join("made.txt")).unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--replay", "session.jsonl", "make"])
+        .assert()
+        .code(5);
+    assert!(!dir.path().join("made.txt").exists());
+}
+
+#[test]
+fn test_record_keeps_exit_code_of_when_command() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    run:
+      - when:
+          - command: exit 3
+        command: echo deploying
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--record", "session.jsonl", "deploy"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying").not());
+    let session = std::fs::read_to_string(dir.path().join("session.jsonl")).unwrap();
+    assert!(session.contains("\"exit_code\":3"), "{}", session);
+}
+
+#[test]
+fn test_positional_args_bind_in_definition_order() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  copy:
+    args:
+      src: {}
+      dst: {}
+      mode: {}
+      owner: {}
+      group: {}
+    run: echo "${src} ${dst} ${mode} ${owner} ${group}"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["copy", "a.txt", "b.txt", "644", "root", "wheel"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt b.txt 644 root wheel"));
+}
+
+#[test]
+fn test_private_task_requires_include_private() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  secret:
+    private: true
+    run: echo hidden
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("secret")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--include-private"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--include-private", "secret"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hidden"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("secret").not());
+}
+
+#[test]
+fn test_exec_runs_command_with_project_interpreter() {
+    let (dir, _) = create_test_config(
+        r#"
+interpreter: [bash, -c]
+tasks:
+  hello:
+    run: echo hello
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["exec", "--", "echo", "$BASH_VERSION", "two words"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("$BASH_VERSION two words"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["exec", "--", "bash", "-c", "exit 4"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_shell_exports_task_variables() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    options:
+      mode:
+        default: debug
+    run: echo ${mode}
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["shell", "build", "--mode", "release"])
+        .write_stdin("echo \"mode is $mode\"\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("mode is release"));
+}
+
+#[test]
+fn test_shell_limits_environment_like_task() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    env-allow: [PATH, KEPT_*]
+    run: echo building
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .env("KEPT_TOKEN", "kept")
+        .env("LEAKED_TOKEN", "leaked")
+        .args(["shell", "build"])
+        .write_stdin("echo \"seen: $KEPT_TOKEN $LEAKED_TOKEN\"\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("seen: kept \n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_heartbeat_shows_interpolated_command() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  wait:
+    options:
+      pause:
+        default: "0.5"
+    run:
+      - command:
+          exec: sleep ${pause}
+          heartbeat: 100ms
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("wait")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Still running"))
+        .stderr(predicate::str::contains("sleep 0.5"))
+        .stderr(predicate::str::contains("${pause}").not());
+}
+
+#[test]
+fn test_env_prints_resolved_variables() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    options:
+      stage:
+        default: staging
+    run:
+      - set-environment:
+          URL: https://${stage}.example.com
+      - command: echo ${URL}
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["env", "deploy", "--", "--stage", "prod"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "export URL=https://prod.example.com",
+        ))
+        .stdout(predicate::str::contains("export stage=prod"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["env", "deploy", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""stage": "staging""#));
+}
+
+#[test]
+fn test_config_dump_merges_includes() {
+    let (dir, _) = create_test_config(
+        r#"
+include:
+  - {path: ci.yml, as: ci}
+tasks:
+  build:
+    run: echo build
+"#,
+    );
+    std::fs::write(
+        dir.path().join("ci.yml"),
+        "tasks:\n  lint:\n    run: echo lint\n",
+    )
+    .unwrap();
+
+    let output = cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["config", "dump"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ci:lint:"))
+        .stdout(predicate::str::contains("include").not())
+        .get_output()
+        .stdout
+        .clone();
+
+    // The dump loads on its own
+    std::fs::write(dir.path().join("dumped.yml"), output).unwrap();
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--file", "dumped.yml", "ci:lint"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lint"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_per_os_interpreter() {
+    let (dir, _) = create_test_config(
+        r#"
+interpreter:
+  windows: [powershell, -Command]
+  default: [sh, -c]
+tasks:
+  plain:
+    run: echo "running in $0"
+  bashy:
+    interpreter:
+      windows: [bash.exe, -c]
+      default: [bash, -c]
+    run: echo "running in $0"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("plain")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("running in sh"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("bashy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("running in bash"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_subtask_does_not_take_callers_interpreter() {
+    let (dir, _) = create_test_config(
+        r#"
+interpreter: [sh, -c]
+tasks:
+  plain:
+    run: echo "plain in $0"
+  bashy:
+    interpreter: [bash, -c]
+    run:
+      - echo "bashy in $0"
+      - task: plain
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("bashy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bashy in bash"))
+        .stdout(predicate::str::contains("plain in sh"));
+}
+
+#[test]
+fn test_command_without_shell() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  greet:
+    options:
+      name:
+        default: "world; echo injected"
+    run:
+      - command:
+          exec: [printf, "hello %s\n", "${name}"]
+          shell: false
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("greet")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("hello world; echo injected\n"));
+}
+
+#[test]
+fn test_pipe_run_item() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  newest:
+    run:
+      - pipe:
+          - printf 'b\na\nc\n'
+          - sort -r
+          - head -n 1
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("newest")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("c\n"))
+        .stderr(predicate::str::contains(
+            "printf 'b\\na\\nc\\n' | sort -r | head -n 1",
+        ));
+}
+
+#[test]
+fn test_parallel_group_run_item() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  release:
+    run:
+      - parallel:
+          - touch assets.done
+          - touch binary.done
+      - command: ls assets.done binary.done
+      - parallel: [exit 3, exit 4]
+        fail-fast: false
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("release")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("assets.done\nbinary.done"));
+}
+
+#[test]
+fn test_parallel_flag_cancels_running_siblings() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  check:
+    run:
+      - parallel: true
+        command:
+          - sleep 5 && touch late.done
+          - sleep 0.2 && exit 3
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("check")
+        .assert()
+        .code(3);
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+    assert!(!dir.path().join("late.done").exists());
+}
+
+#[test]
+fn test_jobs_run_prerequisites_concurrently() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  left:
+    run: sleep 1 && touch left.done
+  right:
+    run: sleep 1 && touch right.done
+  both:
+    run:
+      - task: [left, right]
+      - command: ls left.done right.done
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["-j", "2", "both"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("left.done\nright.done"));
+    assert!(started.elapsed() < std::time::Duration::from_millis(1900));
+}
+
+#[test]
+fn test_jobs_skip_deps_of_skipped_dependency() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    run: echo building
+  deploy:
+    when:
+      - equal: {left: "staging", right: "production"}
+    deps: [build]
+    run: echo deploying
+  lint:
+    run: echo linting
+  release:
+    deps: [deploy, lint]
+    run: echo releasing
+"#,
+    );
+
+    for jobs in ["1", "4"] {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .args(["-j", jobs, "release"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("linting"))
+            .stdout(predicate::str::contains("releasing"))
+            .stdout(predicate::str::contains("building").not())
+            .stdout(predicate::str::contains("deploying").not());
+    }
+}
+
+#[test]
+fn test_jobs_resolve_options_from_declaring_task() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  compile:
+    options:
+      mode:
+        default: debug
+    run: echo compiling ${mode}
+  package:
+    options:
+      mode:
+        default: release
+    deps: [compile]
+    run: echo packaging ${mode}
+  ci:
+    deps: [package]
+    run: echo done
+"#,
+    );
+
+    for jobs in ["1", "4"] {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .args(["-j", jobs, "ci"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("compiling release"))
+            .stdout(predicate::str::contains("packaging release"));
+    }
+}
+
+#[test]
+fn test_deps_run_once() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  setup:
+    run: echo setting up
+  lint:
+    deps: setup
+    run: echo linting
+  ci:
+    deps: [setup, lint]
+    run: echo uploading
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("ci")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("setting up\nlinting\nuploading\n"));
+}
+
+#[test]
+fn test_dry_run_prints_without_running() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    options:
+      env:
+        default: staging
+    run:
+      - command: touch deployed-${env}
+      - when:
+          - equal: {left: "${env}", right: prod}
+        command: touch prod-only
+      - when:
+          - command: test -d ${env}
+        command: touch ${env}-dir
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--dry-run", "deploy"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("touch deployed-staging"))
+        .stderr(predicate::str::contains(
+            "Conditions do not hold, skipping: staging == prod",
+        ))
+        .stderr(predicate::str::contains(
+            "running as if they hold: command `test -d staging` (not checked)",
+        ));
+    assert!(!dir.path().join("deployed-staging").exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_interrupted_task_runs_finally_subtasks() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  cleanup:
+    run: echo subtask-cleanup
+  main:
+    run:
+      - kill -TERM $PPID; sleep 5
+      - echo never
+    finally:
+      - echo inline-cleanup
+      - task: cleanup
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("main")
+        .assert()
+        .code(143)
+        .stdout(predicate::str::contains(
+            "inline-cleanup\nsubtask-cleanup\n",
+        ))
+        .stdout(predicate::str::contains("never").not());
+}
+
+#[test]
+fn test_timeout_stops_command() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  slow:
+    timeout: 200ms
+    run:
+      - sleep 10
+      - touch finished
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("slow")
+        .assert()
+        .code(124)
+        .stderr(predicate::str::contains("Command timed out after 200ms"));
+    assert!(!dir.path().join("finished").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_timeout_stops_processes_the_shell_left_behind() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  slow:
+    timeout: 300ms
+    run: "sh -c 'sleep 30 >/dev/null 2>&1 & echo $! > orphan.pid'; sleep 30"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("slow")
+        .write_stdin("")
+        .assert()
+        .code(124);
+
+    // Gone, or a zombie waiting for init to reap it
+    let pid = std::fs::read_to_string(dir.path().join("orphan.pid")).unwrap();
+    let stopped = (0..40).any(|_| {
+        let ps = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", pid.trim()])
+            .output()
+            .unwrap();
+        let state = String::from_utf8_lossy(&ps.stdout);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        state.trim().is_empty() || state.starts_with('Z')
+    });
+    assert!(stopped, "process {} is still running", pid.trim());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_finished_command_stops_what_its_shell_left_behind() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  spawn:
+    run: "sh -c 'sleep 30 >/dev/null 2>&1 & echo $! > orphan.pid'"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("spawn")
+        .assert()
+        .success();
+
+    // Gone, or a zombie waiting for init to reap it
+    let pid = std::fs::read_to_string(dir.path().join("orphan.pid")).unwrap();
+    let stopped = (0..40).any(|_| {
+        let ps = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", pid.trim()])
+            .output()
+            .unwrap();
+        let state = String::from_utf8_lossy(&ps.stdout);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        state.trim().is_empty() || state.starts_with('Z')
+    });
+    assert!(stopped, "process {} is still running", pid.trim());
+}
+
+#[test]
+fn test_retries_rerun_flaky_command() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  flaky:
+    run:
+      - command:
+          exec: echo x >> tries; test $(wc -l < tries) -ge 2
+          retries: 2
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("flaky")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("retrying (1 of 2)"));
+}
+
+#[test]
+fn test_allowed_failures_are_summarized() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  check:
+    run:
+      - command:
+          exec: exit 3
+          allow-failure: true
+      - touch finished
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("check")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Allowed failures (1):"))
+        .stderr(predicate::str::contains("check: exit 3: Command failed"));
+    assert!(dir.path().join("finished").exists());
+}
+
+#[test]
+fn test_exit_code_of_last_command_is_a_variable() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  check:
+    run:
+      - command:
+          exec: exit 3
+          allow-failure: true
+      - when:
+          - equal: {left: "${exit_code}", right: "3"}
+        command: echo "recovering from '${last_command}'"
+      - exit 5
+    finally:
+      - echo "cleanup after ${exit_code}"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("check")
+        .assert()
+        .code(5)
+        .stdout(predicate::str::contains("recovering from 'exit 3'"))
+        .stdout(predicate::str::contains("cleanup after 5"));
+}
+
+#[test]
+fn test_background_command_runs_until_the_task_ends() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  dev:
+    run:
+      - command:
+          exec: touch started && sleep 10
+          background: true
+      - sleep 0.5
+      - test -f started
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("dev")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Stopping background command 'touch started && sleep 10'",
+        ));
+    assert!(started.elapsed() < std::time::Duration::from_secs(8));
+}
+
+#[test]
+fn test_task_when_skips_the_task() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    options:
+      env:
+        default: staging
+    when:
+      - equal: {left: "${env}", right: "production"}
+    run: echo "deploying ${env}"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying").not())
+        .stderr(predicate::str::contains(
+            "Skipping task 'deploy': condition not met",
+        ));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["deploy", "--env", "production"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying production"));
+}
+
+#[test]
+fn test_before_and_after_each_hooks() {
+    let (dir, _) = create_test_config(
+        r#"
+before-each: echo "setting up ${task_name}"
+after-each:
+  - echo "${task_name} exited with ${exit_code}"
+tasks:
+  lint:
+    run: echo linting
+  check:
+    deps: [lint]
+    run: exit 4
+"#,
+    );
+
+    let output = cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("check")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec!["setting up check", "linting", "check exited with 4"]
+    );
+}
+
+#[test]
+fn test_matrix_runs_each_combination() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    matrix:
+      os: [linux, macos]
+      arch: [amd64, arm64]
+    run:
+      - echo "built ${os}-${arch}"
+"#,
+    );
+
+    let output = cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("build")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let built: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("built "))
+        .collect();
+    assert_eq!(
+        built,
+        vec![
+            "built linux-amd64",
+            "built linux-arm64",
+            "built macos-amd64",
+            "built macos-arm64"
+        ]
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Running 'build' with os=macos, arch=arm64"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tty_command_sees_a_terminal() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  check:
+    run:
+      - command:
+          exec: test -t 1 && test -t 2 && echo on-a-tty
+          tty: true
+          output-var: SEEN
+      - echo "seen=${SEEN}"
+      - command:
+          exec: read -r line && echo "read $line"
+          tty: true
+      - test -t 1 || echo piped
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("check")
+        .write_stdin("typed\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("seen=on-a-tty"))
+        .stdout(predicate::str::contains("read typed"))
+        .stdout(predicate::str::contains("piped"));
+}
+
+#[test]
+fn test_parallel_output_is_prefixed_with_branch_names() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  lint:
+    run: echo linted
+  check:
+    run:
+      - task: [lint]
+        command:
+          exec: echo tested >&2
+          print: test
+        parallel: true
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--color", "never", "check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lint | linted"))
+        .stderr(predicate::str::contains("test | tested"));
+}
+
+#[test]
+fn test_up_to_date_task_is_skipped_unless_forced() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    source: [in.txt]
+    target: [out.txt]
+    run: cp in.txt out.txt && echo built >> log.txt
+"#,
+    );
+    std::fs::write(dir.path().join("in.txt"), "v1").unwrap();
+
+    let build = |args: &[&str]| {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .arg("build")
+            .args(args)
+            .assert()
+            .success()
+    };
+    build(&[]);
+    build(&[]).stderr(predicate::str::contains("Task 'build' is up to date"));
+    build(&["--force"]);
+
+    let log = std::fs::read_to_string(dir.path().join("log.txt")).unwrap();
+    assert_eq!(log.lines().count(), 2);
+}
+
+#[test]
+fn test_bench_runs_cached_tasks_every_iteration() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    source: [main.c]
+    target: [app]
+    run: cp main.c app && echo run >> runs.txt
+"#,
+    );
+    std::fs::write(dir.path().join("main.c"), "int main;").unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["bench", "build", "-n", "3"])
+        .assert()
+        .success();
+    let runs = std::fs::read_to_string(dir.path().join("runs.txt")).unwrap();
+    assert_eq!(runs, "run\nrun\nrun\n");
+}
+
+#[test]
+fn test_resume_skips_steps_that_succeeded() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    run:
+      - echo step >> log.txt
+      - test -f ready
+      - echo done >> log.txt
+"#,
+    );
+
+    let deploy = |args: &[&str]| {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .arg("deploy")
+            .args(args)
+            .assert()
+    };
+    deploy(&[]).code(1);
+    std::fs::write(dir.path().join("ready"), "").unwrap();
+    deploy(&["--resume"])
+        .success()
+        .stderr(predicate::str::contains(
+            "Resuming task 'deploy' after 1 completed run items",
+        ));
+
+    let log = std::fs::read_to_string(dir.path().join("log.txt")).unwrap();
+    assert_eq!(log, "step\ndone\n");
+}
+
+#[test]
+fn test_resume_restores_variables_of_skipped_steps() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  release:
+    run:
+      - command:
+          exec: echo 1.2
+          output-var: VERSION
+      - set-environment:
+          CHANNEL: stable
+      - test -f ready
+      - echo "publishing v${VERSION} to $CHANNEL"
+"#,
+    );
+
+    let release = |args: &[&str]| {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .arg("release")
+            .args(args)
+            .assert()
+    };
+    release(&[]).code(1);
+    std::fs::write(dir.path().join("ready"), "").unwrap();
+    release(&["--resume"])
+        .success()
+        .stdout(predicate::str::contains("publishing v1.2 to stable"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigterm_runs_finally_and_exits_with_signal_code() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  slow:
+    run:
+      - touch started; sleep 30
+      - touch never
+    finally:
+      - touch cleaned
+"#,
+    );
+
+    let mut rtask = std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+        .current_dir(dir.path())
+        .arg("slow")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if dir.path().join("started").exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let started = std::time::Instant::now();
+    std::process::Command::new("kill")
+        .args(["-TERM", &rtask.id().to_string()])
+        .status()
+        .unwrap();
+    let status = rtask.wait().unwrap();
+
+    assert_eq!(status.code(), Some(143));
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(dir.path().join("cleaned").exists());
+    assert!(!dir.path().join("never").exists());
+}
+
+#[test]
+fn test_subtasks_run_with_their_options() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  greet:
+    private: true
+    options:
+      name:
+        default: World
+    run: echo "Hello, ${name}!"
+  ci:
+    run:
+      - task: greet
+      - task: {name: greet, options: {name: CI}}
+      - task: {name: where, file: tools/rtask.yml}
+"#,
+    );
+    let tools = dir.path().join("tools");
+    std::fs::create_dir(&tools).unwrap();
+    std::fs::write(tools.join("rtask.yml"), "tasks:\n  where:\n    run: pwd\n").unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("ci")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello, World!\nHello, CI!\n"))
+        .stdout(predicate::str::contains("tools\n"));
+}
+
+#[test]
+fn test_quiet_task_hides_command_echo() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  hush:
+    quiet: true
+    run: echo visible-output
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("hush")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("visible-output"))
+        .stderr(predicate::str::contains("echo visible-output").not());
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--verbose", "hush"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("echo visible-output"));
+}
+
+#[test]
+fn test_quiet_run_item_hides_command_echo() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  setup:
+    run:
+      - quiet: true
+        command:
+          - echo "hushed-one"
+          - echo "hushed-two"
+      - echo "loud"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hushed-one\nhushed-two\nloud\n"))
+        .stderr(predicate::str::contains("echo \"hushed").not())
+        .stderr(predicate::str::contains("echo \"loud\""));
+}
+
+#[test]
+fn test_skip_finally_leaves_state_behind() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  itest:
+    run:
+      - touch scratch.db
+      - exit 2
+    finally:
+      - rm scratch.db
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--skip-finally", "itest"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Skipping finally block of task 'itest'",
+        ));
+    assert!(dir.path().join("scratch.db").exists());
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("itest")
+        .assert()
+        .code(2);
+    assert!(!dir.path().join("scratch.db").exists());
+}
+
+#[test]
+fn test_inherit_env_policies() {
+    let (dir, _) = create_test_config(
+        r#"
+inherit-env: allowlist
+tasks:
+  show:
+    run: echo "secret=[$RTASK_TEST_SECRET] home=[$HOME]"
+  bare:
+    inherit-env: none
+    options:
+      who:
+        default: me
+    run: echo "home=[$HOME] who=[$who]"
+  full:
+    inherit-env: all
+    run: echo "secret=[$RTASK_TEST_SECRET]"
+"#,
+    );
+
+    for (task, expected) in [
+        ("show", "secret=[] home=[/home/tester]\n"),
+        ("bare", "home=[] who=[me]\n"),
+        ("full", "secret=[hunter2]\n"),
+    ] {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .env("RTASK_TEST_SECRET", "hunter2")
+            .env("HOME", "/home/tester")
+            .arg(task)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(expected));
+    }
+}
+
+#[test]
+fn test_env_allow_and_deny_patterns() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    env-allow: [PATH, AWS_*]
+    env-deny: [AWS_SECRET_*]
+    run: echo "region=[$AWS_REGION] secret=[$AWS_SECRET_ACCESS_KEY] home=[$HOME]"
+  lint:
+    env-deny: [AWS_*]
+    run: echo "region=[$AWS_REGION] home=[$HOME]"
+"#,
+    );
+
+    for (task, expected) in [
+        ("deploy", "region=[eu-west-1] secret=[] home=[]\n"),
+        ("lint", "region=[] home=[/home/tester]\n"),
+    ] {
+        cargo_bin_cmd!("rtask")
+            .current_dir(dir.path())
+            .env("AWS_REGION", "eu-west-1")
+            .env("AWS_SECRET_ACCESS_KEY", "hunter2")
+            .env("HOME", "/home/tester")
+            .arg(task)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(expected));
+    }
+}
+
+#[test]
+fn test_when_command_sees_filtered_environment() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  lint:
+    env-deny: [AWS_*]
+    run:
+      - when:
+          - command: test -z "$AWS_REGION"
+        command: echo "region hidden"
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .env("AWS_REGION", "eu-west-1")
+        .arg("lint")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("region hidden"));
+}
+
+#[test]
+fn test_commands_run_from_config_dir_by_default() {
+    let (dir, config_path) = create_test_config("tasks:\n  where:\n    run: pwd -P\n");
+    let sub = dir.path().join("src").join("nested");
+    std::fs::create_dir_all(&sub).unwrap();
+    let root = dir.path().canonicalize().unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(&sub)
+        .arg("where")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(format!("{}\n", root.display())));
+
+    std::fs::write(
+        &config_path,
+        "run-from: cwd\ntasks:\n  where:\n    run: pwd -P\n",
+    )
+    .unwrap();
+    cargo_bin_cmd!("rtask")
+        .current_dir(&sub)
+        .arg("where")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(format!(
+            "{}\n",
+            sub.canonicalize().unwrap().display()
+        )));
+}
+
+#[test]
+fn test_exists_and_dir_path_bases() {
+    let (dir, _) = create_test_config(
+        r#"
+run-from: cwd
+tasks:
+  ship:
+    run:
+      - when:
+          - exists: "{config}/dist"
+        command:
+          exec: ls
+          dir: "{config}/dist"
+      - when:
+          - exists: dist
+        command: echo relative-to-cwd
+      - when:
+          - exists: "{cwd}/local.txt"
+        command: echo found-local
+"#,
+    );
+    std::fs::create_dir(dir.path().join("dist")).unwrap();
+    std::fs::write(dir.path().join("dist").join("app.tar"), "").unwrap();
+    let sub = dir.path().join("sub");
+    std::fs::create_dir(&sub).unwrap();
+    std::fs::write(sub.join("local.txt"), "").unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(&sub)
+        .arg("ship")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("app.tar\nfound-local\n"));
+}
+
+#[test]
+fn test_service_task_readiness_and_restart() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  web:
+    service: true
+    ready:
+      log: listening
+      timeout: 5s
+    run: sh -c 'echo listening; sleep 0.2; echo done'
+  flaky:
+    service: true
+    restart: on-failure
+    max-restarts: 1
+    run: sh -c 'echo attempt >> attempts.txt; exit 3'
+  stuck:
+    service: true
+    ready:
+      command: test -f never-created
+      timeout: 300ms
+    run: sleep 10
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("web")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("listening\ndone\n"))
+        .stderr(predicate::str::contains("Service 'web' is ready"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("flaky")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("Restarting service 'flaky'"));
+    let attempts = std::fs::read_to_string(dir.path().join("attempts.txt")).unwrap();
+    assert_eq!(attempts, "attempt\nattempt\n");
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("stuck")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Service 'stuck' did not become ready",
+        ));
+}
+
+#[test]
+fn test_quiet_hides_service_messages() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  web:
+    service: true
+    run: sleep 10
+  dev:
+    run:
+      - task: web
+      - echo up
+"#,
+    );
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .arg("dev")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Stopping service 'web'"));
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--quiet", "dev"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Stopping service").not());
+}
+
+#[test]
+fn test_up_and_down_service_group() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  db:
+    service: true
+    ready:
+      log: db ready
+    run: echo db ready; exec sleep 30
+  web:
+    service: true
+    ready:
+      command: test -f started.txt
+    run: touch started.txt; exec sleep 30
+groups:
+  dev:
+    services:
+      web: {after: [db]}
+      db:
+"#,
+    );
+
+    let up = std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+        .current_dir(dir.path())
+        .args(["up", "dev"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid_file = dir.path().join(".rtask/groups/dev.pid");
+    for _ in 0..100 {
+        if dir.path().join("started.txt").exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(pid_file.exists());
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["down", "dev"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Group 'dev' is down"));
+    let output = up.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(!pid_file.exists());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("db  | db ready"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let db = stderr.find("Running task: db").unwrap();
+    let web = stderr.find("Running task: web").unwrap();
+    assert!(db < web, "{}", stderr);
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["down", "dev"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Group 'dev' is not up"));
+}
+
+#[test]
+fn test_watch_reruns_task_on_change() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    watch:
+      paths: ["src/**"]
+      exclude: ["src/*.tmp"]
+    run: echo run >> runs.txt
+"#,
+    );
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+    let mut watch = std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+        .current_dir(dir.path())
+        .args(["watch", "build"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let runs = || std::fs::read_to_string(dir.path().join("runs.txt")).unwrap_or_default();
+    let wait_for = |count: usize| {
+        for _ in 0..100 {
+            if runs().lines().count() >= count {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        false
+    };
+
+    assert!(wait_for(1));
+    std::fs::write(dir.path().join("src/scratch.tmp"), "ignored").unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() { run() }").unwrap();
+    let rerun = wait_for(2);
+    std::thread::sleep(std::time::Duration::from_millis(1200));
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+
+    assert!(rerun);
+    assert_eq!(runs(), "run\nrun\n");
+}
+
+#[test]
+fn test_watch_true_watches_source_when_run() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  build:
+    watch: true
+    source: ["src/**"]
+    target: [out]
+    run: echo run >> runs.txt
+"#,
+    );
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+    cargo_bin_cmd!("rtask")
+        .current_dir(dir.path())
+        .args(["--no-watch", "build"])
+        .assert()
+        .success();
+    let runs = || std::fs::read_to_string(dir.path().join("runs.txt")).unwrap_or_default();
+    assert_eq!(runs(), "run\n");
+
+    let mut watch = std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+        .current_dir(dir.path())
+        .arg("build")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let wait_for = |count: usize| {
+        for _ in 0..100 {
+            if runs().lines().count() >= count {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        false
+    };
+
+    assert!(wait_for(2));
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() { run() }").unwrap();
+    let rerun = wait_for(3);
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    assert!(rerun);
+}
+
+#[test]
+fn test_watch_restarts_run_in_flight() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  serve:
+    watch:
+      paths: ["src/**"]
+      debounce: 100ms
+      in-flight: restart
+    run: |
+      echo start >> runs.txt
+      sleep 2
+      echo end >> runs.txt
+"#,
+    );
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+    let mut watch = std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+        .current_dir(dir.path())
+        .args(["watch", "serve"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let runs = || std::fs::read_to_string(dir.path().join("runs.txt")).unwrap_or_default();
+    let wait_for = |expected: &str| {
+        for _ in 0..100 {
+            if runs() == expected {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        false
+    };
+
+    assert!(wait_for("start\n"));
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() { run() }").unwrap();
+    let finished = wait_for("start\nstart\nend\n");
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+
+    assert!(finished, "unexpected runs: {:?}", runs());
+}
+
+#[test]
+fn test_concurrency_group_across_processes() {
+    let (dir, _) = create_test_config(
+        r#"
+tasks:
+  deploy:
+    concurrency-group: {name: deploy, across-processes: true}
+    run: |
+      echo start >> deploys.txt
+      sleep 1
+      echo end >> deploys.txt
+"#,
+    );
+
+    let spawn = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_rtask"))
+            .current_dir(dir.path())
+            .arg("deploy")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap()
+    };
+    let mut first = spawn();
+    let mut second = spawn();
+    assert!(first.wait().unwrap().success());
+    assert!(second.wait().unwrap().success());
+
+    let deploys = std::fs::read_to_string(dir.path().join("deploys.txt")).unwrap();
+    assert_eq!(deploys, "start\nend\nstart\nend\n");
+}
@@ -1,7 +1,7 @@
 //! Integration tests for task execution
 
 use rtask::config::{parse_config, validate_config};
-use rtask::runner::{Context, Task};
+use rtask::runner::{CaptureLimits, Context, Task};
 
 #[test]
 fn test_execute_simple_task() {
@@ -267,3 +267,88 @@ tasks:
 
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_quiet_run_item() {
+    let yaml = r#"
+tasks:
+  setup:
+    run:
+      - quiet: true
+        command:
+          - echo "one"
+          - echo "two"
+      - echo "loud"
+"#;
+
+    let config = parse_config(yaml, None).unwrap();
+    let task_config = config.tasks.get("setup").unwrap();
+    let task = Task::from_config("setup".to_string(), task_config.clone()).unwrap();
+
+    assert!(task.run[0].quiet);
+    assert!(!task.run[1].quiet);
+
+    let mut ctx = Context::new();
+    let result = task.execute(&mut ctx);
+
+    assert!(result.is_ok());
+    // The item-level flag must not leak into later items
+    assert!(!ctx.quiet);
+}
+
+#[test]
+fn test_output_var_capture() {
+    let yaml = r#"
+capture:
+  max-size: 5
+tasks:
+  capture:
+    run:
+      - command:
+          exec: echo "hello world"
+          output-var: greeting
+"#;
+
+    let config = parse_config(yaml, None).unwrap();
+    let task_config = config.tasks.get("capture").unwrap();
+    let task = Task::from_config("capture".to_string(), task_config.clone()).unwrap();
+
+    let capture = config.capture.as_ref().unwrap();
+    let mut ctx = Context::new().with_capture_limits(CaptureLimits {
+        max_bytes: capture.max_size.unwrap(),
+        ..Default::default()
+    });
+    task.execute(&mut ctx).unwrap();
+
+    assert_eq!(ctx.get_var("greeting"), Some(&"hello".to_string()));
+}
+
+#[test]
+fn test_task_log_tees_output() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let yaml = r#"
+tasks:
+  logged:
+    log: task.log
+    run:
+      - echo "first"
+      - echo "second"
+"#;
+
+    let config = parse_config(yaml, None).unwrap();
+    let task_config = config.tasks.get("logged").unwrap();
+    let task = Task::from_config("logged".to_string(), task_config.clone()).unwrap();
+
+    let mut ctx = Context::new().with_working_dir(temp_dir.path().to_path_buf());
+    task.execute(&mut ctx).unwrap();
+
+    let contents = fs::read_to_string(temp_dir.path().join("task.log")).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("] first"));
+    assert!(lines[1].ends_with("] second"));
+    assert!(ctx.tee_log.is_none());
+}